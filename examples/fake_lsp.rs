@@ -0,0 +1,163 @@
+// A scripted language server for the LSP integration test. It speaks plain
+// Content-Length framing on stdin/stdout and writes nothing else there.
+
+use std::io::{self, BufRead, Write};
+
+use ropey::Rope;
+use serde_json::{json, Value};
+
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let Some(length) = length else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"));
+    };
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map(Some).map_err(io::Error::from)
+}
+
+fn write_message(writer: &mut impl Write, msg: &Value) -> io::Result<()> {
+    let body = msg.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+fn reply(id: &Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+fn range(start: (usize, usize), end: (usize, usize)) -> Value {
+    json!({
+        "start": { "line": start.0, "character": start.1 },
+        "end": { "line": end.0, "character": end.1 },
+    })
+}
+
+fn workspace_edit(uri: &Value, at: Value, new_text: Value) -> Value {
+    let mut changes = serde_json::Map::new();
+    let file = uri.as_str().unwrap_or("").to_string();
+    changes.insert(file, json!([{ "range": at, "newText": new_text }]));
+    json!({ "changes": changes })
+}
+
+fn char_index(text: &Rope, pos: &Value) -> usize {
+    let line = (pos["line"].as_u64().unwrap_or(0) as usize).min(text.len_lines().saturating_sub(1));
+    let start = text.line_to_char(line);
+    let units = text.char_to_utf16_cu(start) + pos["character"].as_u64().unwrap_or(0) as usize;
+    text.utf16_cu_to_char(units.min(text.len_utf16_cu()))
+}
+
+fn main() {
+    let stdin = io::stdin();
+    let mut reader = stdin.lock();
+    let mut stdout = io::stdout();
+    let mut text = Rope::new();
+    let mut uri = Value::Null;
+    while let Ok(Some(msg)) = read_message(&mut reader) {
+        let id = msg["id"].clone();
+        let params = &msg["params"];
+        let out = match msg["method"].as_str().unwrap_or("") {
+            "initialize" => reply(
+                &id,
+                json!({ "capabilities": {
+                    "textDocumentSync": 2,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "renameProvider": true,
+                    "codeActionProvider": { "resolveProvider": true },
+                    "documentFormattingProvider": true,
+                    "completionProvider": {},
+                } }),
+            ),
+            "textDocument/didOpen" => {
+                text = Rope::from_str(params["textDocument"]["text"].as_str().unwrap_or(""));
+                uri = params["textDocument"]["uri"].clone();
+                json!({ "jsonrpc": "2.0", "method": "textDocument/publishDiagnostics", "params": {
+                    "uri": uri,
+                    "diagnostics": [{
+                        "range": range((1, 0), (1, 3)),
+                        "severity": 1,
+                        "message": "bad line",
+                    }],
+                } })
+            }
+            "textDocument/didChange" => {
+                for change in params["contentChanges"].as_array().into_iter().flatten() {
+                    let new_text = change["text"].as_str().unwrap_or("");
+                    if change.get("range").is_none() {
+                        text = Rope::from_str(new_text);
+                        continue;
+                    }
+                    let start = char_index(&text, &change["range"]["start"]);
+                    let end = char_index(&text, &change["range"]["end"]);
+                    text.remove(start..end);
+                    text.insert(start, new_text);
+                }
+                continue;
+            }
+            "textDocument/hover" => reply(
+                &id,
+                json!({ "contents": { "kind": "plaintext", "value": format!("mirror:\n{}", text) } }),
+            ),
+            "textDocument/definition" => reply(&id, json!({ "uri": uri, "range": range((0, 3), (0, 7)) })),
+            "textDocument/references" => reply(
+                &id,
+                json!([
+                    { "uri": uri, "range": range((1, 3), (1, 7)) },
+                    { "uri": uri, "range": range((2, 4), (2, 7)) },
+                ]),
+            ),
+            "textDocument/rename" => reply(
+                &id,
+                workspace_edit(&uri, range((2, 4), (2, 7)), params["newName"].clone()),
+            ),
+            "textDocument/codeAction" => reply(
+                &id,
+                json!([
+                    {
+                        "title": "Add header",
+                        "edit": workspace_edit(&uri, range((0, 0), (0, 0)), json!("// header\n")),
+                    },
+                    { "title": "Add footer", "data": 1 },
+                ]),
+            ),
+            "codeAction/resolve" => {
+                let end = text.len_lines().saturating_sub(1);
+                let mut action = params.clone();
+                action["edit"] = workspace_edit(&uri, range((end, 0), (end, 0)), json!("// footer\n"));
+                reply(&id, action)
+            }
+            "textDocument/formatting" => reply(
+                &id,
+                json!([{ "range": range((2, 0), (2, 4)), "newText": "\t" }]),
+            ),
+            "textDocument/completion" => reply(
+                &id,
+                json!([{ "label": "alpha" }, { "label": "alphabet" }, { "label": "beta" }]),
+            ),
+            "shutdown" => reply(&id, Value::Null),
+            "exit" => break,
+            _ if id.is_null() => continue,
+            _ => reply(&id, Value::Null),
+        };
+        if write_message(&mut stdout, &out).is_err() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,137 @@
+;; Sample rvim plugin. Load it with
+;;
+;;   [plugins]
+;;   enabled = ["examples/plugins/sample.wat"]
+;;
+;;   [plugin_settings.sample]
+;;   greeting = "hi there"
+;;
+;; Strings cross the boundary as (pointer, length) pairs in the exported memory.
+;; Host functions that return text (get_buffer_text, get_setting, list_buffers)
+;; return its length (or -1) and keep it until the plugin copies it out with
+;; read_reply into memory obtained from its own `alloc` export.
+(module
+  (import "rvim" "set_status" (func $set_status (param i32 i32)))
+  (import "rvim" "register_command" (func $register_command (param i32 i32 i32 i32)))
+  (import "rvim" "register_keymap" (func $register_keymap (param i32 i32 i32 i32 i32 i32)))
+  (import "rvim" "register_status" (func $register_status (param i32 i32 i32)))
+  (import "rvim" "current_buffer" (func $current_buffer (result i32)))
+  (import "rvim" "get_buffer_text" (func $get_buffer_text (param i32 i32 i32) (result i32)))
+  (import "rvim" "apply_edit" (func $apply_edit (param i32 i32 i32 i32 i32) (result i32)))
+  (import "rvim" "get_setting" (func $get_setting (param i32 i32) (result i32)))
+  (import "rvim" "read_reply" (func $read_reply (param i32)))
+  (import "rvim" "status_text" (func $status_text (param i32 i32)))
+
+  (memory (export "memory") 1)
+  (global $heap (mut i32) (i32.const 1024))
+  (global $runs (mut i32) (i32.const 0))
+
+  (data (i32.const 0) "Upcase")
+  (data (i32.const 16) "uppercase the first line")
+  (data (i32.const 48) "Greet")
+  (data (i32.const 64) "show the greeting setting")
+  (data (i32.const 96) "Spin")
+  (data (i32.const 112) "loop forever")
+  (data (i32.const 128) "Crash")
+  (data (i32.const 144) "trap")
+  (data (i32.const 160) "n")
+  (data (i32.const 176) "<leader>u")
+  (data (i32.const 192) "runs")
+  (data (i32.const 208) "greeting")
+  (data (i32.const 224) "hello from wasm")
+  (data (i32.const 240) "runs:0")
+  (data (i32.const 256) "Hog")
+  (data (i32.const 272) "grow memory past the limit")
+
+  ;; Plugin API 0.1, encoded as major << 16 | minor.
+  (func (export "rvim_api_version") (result i32)
+    (i32.const 1))
+
+  (func $alloc (export "alloc") (param $len i32) (result i32)
+    (local $ptr i32)
+    (if (i32.gt_u (i32.add (global.get $heap) (local.get $len)) (i32.const 65536))
+      (then (global.set $heap (i32.const 1024))))
+    (local.set $ptr (global.get $heap))
+    (global.set $heap (i32.add (global.get $heap) (local.get $len)))
+    (local.get $ptr))
+
+  (func (export "init")
+    (call $register_command (i32.const 0) (i32.const 6) (i32.const 16) (i32.const 24))
+    (call $register_command (i32.const 48) (i32.const 5) (i32.const 64) (i32.const 25))
+    (call $register_command (i32.const 96) (i32.const 4) (i32.const 112) (i32.const 12))
+    (call $register_command (i32.const 128) (i32.const 5) (i32.const 144) (i32.const 4))
+    (call $register_command (i32.const 256) (i32.const 3) (i32.const 272) (i32.const 26))
+    (call $register_keymap
+      (i32.const 160) (i32.const 1) (i32.const 176) (i32.const 9) (i32.const 0) (i32.const 6))
+    (call $register_status (i32.const 192) (i32.const 4) (i32.const 5)))
+
+  (func $upcase (result i32)
+    (local $buf i32) (local $len i32) (local $ptr i32) (local $i i32) (local $ch i32)
+    (local.set $buf (call $current_buffer))
+    (local.set $len (call $get_buffer_text (local.get $buf) (i32.const 0) (i32.const 1)))
+    (if (i32.lt_s (local.get $len) (i32.const 0))
+      (then (return (i32.const 1))))
+    (local.set $ptr (call $alloc (local.get $len)))
+    (call $read_reply (local.get $ptr))
+    (block $done
+      (loop $next
+        (br_if $done (i32.ge_u (local.get $i) (local.get $len)))
+        (local.set $ch (i32.load8_u (i32.add (local.get $ptr) (local.get $i))))
+        (if (i32.and
+              (i32.ge_u (local.get $ch) (i32.const 97))
+              (i32.le_u (local.get $ch) (i32.const 122)))
+          (then
+            (i32.store8
+              (i32.add (local.get $ptr) (local.get $i))
+              (i32.sub (local.get $ch) (i32.const 32)))))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $next)))
+    (call $apply_edit
+      (local.get $buf) (i32.const 0) (i32.const 1) (local.get $ptr) (local.get $len)))
+
+  (func $greet
+    (local $len i32) (local $ptr i32)
+    (local.set $len (call $get_setting (i32.const 208) (i32.const 8)))
+    (if (i32.lt_s (local.get $len) (i32.const 0))
+      (then
+        (call $set_status (i32.const 224) (i32.const 15))
+        (return)))
+    (local.set $ptr (call $alloc (local.get $len)))
+    (call $read_reply (local.get $ptr))
+    (call $set_status (local.get $ptr) (local.get $len)))
+
+  ;; Commands are told apart by their first letter; 0 means success.
+  (func (export "run_command")
+    (param $name i32) (param $name_len i32) (param $args i32) (param $args_len i32) (result i32)
+    (local $first i32)
+    (global.set $runs (i32.add (global.get $runs) (i32.const 1)))
+    (local.set $first (i32.load8_u (local.get $name)))
+    (if (i32.eq (local.get $first) (i32.const 85))
+      (then (return (call $upcase))))
+    (if (i32.eq (local.get $first) (i32.const 71))
+      (then
+        (call $greet)
+        (return (i32.const 0))))
+    (if (i32.eq (local.get $first) (i32.const 83))
+      (then (loop $spin (br $spin))))
+    (if (i32.eq (local.get $first) (i32.const 67))
+      (then unreachable))
+    (if (i32.eq (local.get $first) (i32.const 72))
+      (then
+        (if (i32.eq (memory.grow (i32.const 1024)) (i32.const -1))
+          (then unreachable))))
+    (i32.const 2))
+
+  ;; Echo the path of every written file; BufWritePost is the only 12-byte event name.
+  (func (export "on_event")
+    (param $name i32) (param $name_len i32) (param $subject i32) (param $subject_len i32) (result i32)
+    (if (i32.eq (local.get $name_len) (i32.const 12))
+      (then (call $set_status (local.get $subject) (local.get $subject_len))))
+    (i32.const 0))
+
+  (func (export "status") (param $id i32) (param $len i32)
+    (i32.store8
+      (i32.const 245)
+      (i32.add (i32.const 48) (i32.rem_u (global.get $runs) (i32.const 10))))
+    (call $status_text (i32.const 240) (i32.const 6)))
+)
@@ -0,0 +1,683 @@
+use std::collections::HashMap;
+
+use ropey::{Rope, RopeSlice};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct TextEdit {
+    pub(crate) char_idx: usize,
+    pub(crate) removed: String,
+    pub(crate) inserted: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ByteEdit {
+    pub(crate) start_byte: usize,
+    pub(crate) old_end_byte: usize,
+    pub(crate) new_end_byte: usize,
+    pub(crate) start_point: (usize, usize),
+    pub(crate) old_end_point: (usize, usize),
+    pub(crate) new_end_point: (usize, usize),
+}
+
+const BYTE_EDIT_LIMIT: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct LspChange {
+    pub(crate) start: (usize, usize),
+    pub(crate) end: (usize, usize),
+    pub(crate) text: String,
+}
+
+const LSP_CHANGE_LIMIT: usize = 1024;
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TextBuffer {
+    rope: Rope,
+    changes: Vec<TextEdit>,
+    byte_edits: Option<Vec<ByteEdit>>,
+    lsp_changes: Option<Vec<LspChange>>,
+    tracked: Option<Vec<Option<usize>>>,
+    marks: HashMap<char, usize>,
+    anchors: HashMap<usize, usize>,
+}
+
+impl TextBuffer {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn from_text(content: &str) -> Self {
+        Self {
+            rope: Rope::from_str(content),
+            changes: Vec::new(),
+            byte_edits: None,
+            lsp_changes: None,
+            tracked: None,
+            marks: HashMap::new(),
+            anchors: HashMap::new(),
+        }
+    }
+
+    // Shares the rope (a cheap clone) but none of the edit history or marks.
+    pub(crate) fn snapshot(&self) -> Self {
+        Self {
+            rope: self.rope.clone(),
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn line_count(&self) -> usize {
+        self.rope.len_lines()
+    }
+
+    pub(crate) fn len_bytes(&self) -> usize {
+        self.rope.len_bytes()
+    }
+
+    pub(crate) fn line_slice(&self, row: usize) -> Option<RopeSlice<'_>> {
+        let slice = self.rope.get_line(row)?;
+        let len = slice.len_chars();
+        if len > 0 && slice.char(len - 1) == '\n' {
+            Some(slice.slice(..len - 1))
+        } else {
+            Some(slice)
+        }
+    }
+
+    pub(crate) fn line(&self, row: usize) -> Option<String> {
+        self.line_slice(row).map(String::from)
+    }
+
+    pub(crate) fn line_len(&self, row: usize) -> usize {
+        self.line_slice(row).map(|s| s.len_chars()).unwrap_or(0)
+    }
+
+    pub(crate) fn char_at(&self, row: usize, col: usize) -> Option<char> {
+        let line = self.line_slice(row)?;
+        line.get_char(col)
+    }
+
+    fn pos_to_char(&self, row: usize, col: usize) -> usize {
+        let row = row.min(self.line_count().saturating_sub(1));
+        self.rope.line_to_char(row) + col.min(self.line_len(row))
+    }
+
+    pub(crate) fn insert(&mut self, row: usize, col: usize, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        let idx = self.pos_to_char(row, col);
+        self.rope_insert(idx, text);
+    }
+
+    pub(crate) fn insert_char(&mut self, row: usize, col: usize, ch: char) {
+        let idx = self.pos_to_char(row, col);
+        let mut buf = [0u8; 4];
+        self.rope_insert(idx, ch.encode_utf8(&mut buf));
+    }
+
+    pub(crate) fn remove(&mut self, start: (usize, usize), end: (usize, usize)) {
+        let start_idx = self.pos_to_char(start.0, start.1);
+        let end_idx = self.pos_to_char(end.0, end.1);
+        if start_idx < end_idx {
+            self.rope_remove(start_idx, end_idx);
+        }
+    }
+
+    pub(crate) fn replace_all(&mut self, text: &str) {
+        let marks = self.mark_positions();
+        let anchors: Vec<(usize, (usize, usize))> = self
+            .anchors
+            .keys()
+            .filter_map(|id| Some((*id, self.anchor(*id)?)))
+            .collect();
+        let len = self.rope.len_chars();
+        if len > 0 {
+            self.rope_remove(0, len);
+        }
+        self.rope_insert(0, text);
+        for (name, pos) in marks {
+            self.set_mark(name, pos);
+        }
+        for (id, pos) in anchors {
+            self.set_anchor(id, pos);
+        }
+    }
+
+    pub(crate) fn slice_to_string(&self, start: (usize, usize), end: (usize, usize)) -> String {
+        let start_idx = self.pos_to_char(start.0, start.1);
+        let end_idx = self.pos_to_char(end.0, end.1);
+        if start_idx >= end_idx {
+            return String::new();
+        }
+        self.rope.slice(start_idx..end_idx).to_string()
+    }
+
+    pub(crate) fn set_line(&mut self, row: usize, text: &str) {
+        if row >= self.line_count() {
+            return;
+        }
+        let len = self.line_len(row);
+        self.remove((row, 0), (row, len));
+        self.insert(row, 0, text);
+    }
+
+    pub(crate) fn insert_line(&mut self, row: usize, text: &str) {
+        if row >= self.line_count() {
+            let end = self.rope.len_chars();
+            self.rope_insert(end, &format!("\n{}", text));
+            return;
+        }
+        let idx = self.rope.line_to_char(row);
+        self.rope_insert(idx, &format!("{}\n", text));
+    }
+
+    pub(crate) fn insert_lines<S: AsRef<str>>(&mut self, row: usize, lines: &[S]) {
+        if lines.is_empty() {
+            return;
+        }
+        let mut joined = String::new();
+        for (idx, line) in lines.iter().enumerate() {
+            if idx > 0 {
+                joined.push('\n');
+            }
+            joined.push_str(line.as_ref());
+        }
+        self.insert_line(row, &joined);
+    }
+
+    pub(crate) fn remove_lines(&mut self, start_row: usize, end_row: usize) {
+        let count = self.line_count();
+        if start_row >= count {
+            return;
+        }
+        let end_row = end_row.min(count - 1);
+        if end_row + 1 < count {
+            let start = self.rope.line_to_char(start_row);
+            let end = self.rope.line_to_char(end_row + 1);
+            self.rope_remove(start, end);
+        } else if start_row > 0 {
+            let start = self.rope.line_to_char(start_row) - 1;
+            let end = self.rope.len_chars();
+            self.rope_remove(start, end);
+        } else {
+            let end = self.rope.len_chars();
+            self.rope_remove(0, end);
+        }
+    }
+
+    pub(crate) fn lines(&self, start: usize, end: Option<usize>) -> Vec<String> {
+        let end = end.unwrap_or(usize::MAX).min(self.line_count());
+        (start.min(end)..end).filter_map(|row| self.line(row)).collect()
+    }
+
+    pub(crate) fn replace_lines(&mut self, start: usize, end: usize, lines: &[String]) {
+        let count = self.line_count();
+        if end < count {
+            self.remove((start, 0), (end, 0));
+            let text: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+            self.insert(start, 0, &text);
+        } else if start > 0 {
+            let last = count - 1;
+            self.remove((start - 1, self.line_len(start - 1)), (last, self.line_len(last)));
+            let text: String = lines.iter().map(|line| format!("\n{}", line)).collect();
+            self.insert(start - 1, self.line_len(start - 1), &text);
+        } else {
+            self.replace_all(&lines.join("\n"));
+        }
+    }
+
+    pub(crate) fn split_line(&mut self, row: usize, col: usize) {
+        self.insert_char(row, col, '\n');
+    }
+
+    pub(crate) fn join_line(&mut self, row: usize) {
+        if row + 1 >= self.line_count() {
+            return;
+        }
+        let idx = self.rope.line_to_char(row + 1) - 1;
+        self.rope_remove(idx, idx + 1);
+    }
+
+    pub(crate) fn apply_edit(&mut self, edit: &TextEdit) {
+        let end = edit.char_idx + edit.removed.chars().count();
+        self.rope_remove(edit.char_idx, end);
+        self.rope_insert(edit.char_idx, &edit.inserted);
+    }
+
+    pub(crate) fn revert_edit(&mut self, edit: &TextEdit) {
+        let end = edit.char_idx + edit.inserted.chars().count();
+        self.rope_remove(edit.char_idx, end);
+        self.rope_insert(edit.char_idx, &edit.removed);
+    }
+
+    pub(crate) fn track_lines(&mut self, rows: &[usize]) {
+        let starts = rows
+            .iter()
+            .map(|row| Some(self.rope.line_to_char(*row)))
+            .collect();
+        self.tracked = Some(starts);
+    }
+
+    pub(crate) fn tracked_line(&self, idx: usize) -> Option<usize> {
+        let start = (*self.tracked.as_ref()?.get(idx)?)?;
+        Some(self.rope.char_to_line(start))
+    }
+
+    pub(crate) fn is_tracking(&self) -> bool {
+        self.tracked.is_some()
+    }
+
+    pub(crate) fn stop_tracking(&mut self) {
+        self.tracked = None;
+    }
+
+    fn shift_tracked_for_insert(&mut self, idx: usize, text: &str) {
+        let Some(tracked) = self.tracked.as_mut() else {
+            return;
+        };
+        let len = text.chars().count();
+        for pos in tracked.iter_mut().flatten() {
+            if *pos > idx || (*pos == idx && text.ends_with('\n')) {
+                *pos += len;
+            }
+        }
+    }
+
+    fn shift_tracked_for_remove(&mut self, start: usize, end: usize, removed: &str) {
+        let Some(tracked) = self.tracked.as_mut() else {
+            return;
+        };
+        let at_line_start = start == 0 || self.rope.char(start - 1) == '\n';
+        for slot in tracked.iter_mut() {
+            let Some(pos) = *slot else {
+                continue;
+            };
+            *slot = if pos < start || (pos == start && !removed.contains('\n')) {
+                Some(pos)
+            } else if pos > end {
+                Some(pos - (end - start))
+            } else if pos == end && at_line_start {
+                Some(start)
+            } else {
+                None
+            };
+        }
+    }
+
+    pub(crate) fn set_mark(&mut self, name: char, (row, col): (usize, usize)) {
+        let idx = self.pos_to_char(row, col);
+        self.marks.insert(name, idx);
+    }
+
+    pub(crate) fn mark(&self, name: char) -> Option<(usize, usize)> {
+        let idx = (*self.marks.get(&name)?).min(self.rope.len_chars());
+        let row = self.rope.char_to_line(idx);
+        Some((row, idx - self.rope.line_to_char(row)))
+    }
+
+    pub(crate) fn remove_mark(&mut self, name: char) {
+        self.marks.remove(&name);
+    }
+
+    pub(crate) fn mark_positions(&self) -> Vec<(char, (usize, usize))> {
+        let mut marks: Vec<(char, (usize, usize))> = self
+            .marks
+            .keys()
+            .filter_map(|name| Some((*name, self.mark(*name)?)))
+            .collect();
+        marks.sort_by_key(|(name, _)| *name);
+        marks
+    }
+
+    // Anchors are positions kept for views of this buffer, such as windows that are not
+    // current. They move with edits like marks but are never deleted by them.
+    pub(crate) fn set_anchor(&mut self, id: usize, (row, col): (usize, usize)) {
+        let idx = self.pos_to_char(row, col);
+        self.anchors.insert(id, idx);
+    }
+
+    pub(crate) fn anchor(&self, id: usize) -> Option<(usize, usize)> {
+        let idx = (*self.anchors.get(&id)?).min(self.rope.len_chars());
+        let row = self.rope.char_to_line(idx);
+        Some((row, idx - self.rope.line_to_char(row)))
+    }
+
+    fn shift_marks_for_insert(&mut self, idx: usize, text: &str) {
+        let len = text.chars().count();
+        for pos in self.marks.values_mut().chain(self.anchors.values_mut()) {
+            if *pos > idx || (*pos == idx && text.ends_with('\n')) {
+                *pos += len;
+            }
+        }
+    }
+
+    fn shift_marks_for_remove(&mut self, start: usize, end: usize) {
+        for pos in self.anchors.values_mut() {
+            if *pos >= end {
+                *pos -= end - start;
+            } else if *pos > start {
+                *pos = start;
+            }
+        }
+        let rope = &self.rope;
+        self.marks.retain(|_, pos| {
+            if *pos < start {
+                return true;
+            }
+            if *pos >= end {
+                *pos -= end - start;
+                return true;
+            }
+            let row = rope.char_to_line(*pos);
+            let line_start = rope.line_to_char(row);
+            let line_end = if row + 1 < rope.len_lines() {
+                rope.line_to_char(row + 1)
+            } else {
+                rope.len_chars()
+            };
+            if start <= line_start && end >= line_end {
+                return false;
+            }
+            *pos = start;
+            true
+        });
+    }
+
+    pub(crate) fn take_changes(&mut self) -> Vec<TextEdit> {
+        std::mem::take(&mut self.changes)
+    }
+
+    pub(crate) fn take_byte_edits(&mut self) -> Option<Vec<ByteEdit>> {
+        self.byte_edits.replace(Vec::new())
+    }
+
+    pub(crate) fn take_lsp_changes(&mut self) -> Option<Vec<LspChange>> {
+        self.lsp_changes.replace(Vec::new())
+    }
+
+    fn utf16_point(&self, char_idx: usize) -> (usize, usize) {
+        let row = self.rope.char_to_line(char_idx);
+        let line_start = self.rope.line_to_char(row);
+        (
+            row,
+            self.rope.char_to_utf16_cu(char_idx) - self.rope.char_to_utf16_cu(line_start),
+        )
+    }
+
+    pub(crate) fn pos_to_utf16(&self, (row, col): (usize, usize)) -> (usize, usize) {
+        self.utf16_point(self.pos_to_char(row, col))
+    }
+
+    pub(crate) fn utf16_to_pos(&self, (row, character): (usize, usize)) -> (usize, usize) {
+        if row >= self.line_count() {
+            let last = self.line_count().saturating_sub(1);
+            return (last, self.line_len(last));
+        }
+        let Some(line) = self.line_slice(row) else {
+            return (row, 0);
+        };
+        (row, line.utf16_cu_to_char(character.min(line.len_utf16_cu())))
+    }
+
+    fn push_lsp_change(&mut self, change: LspChange) {
+        if let Some(changes) = self.lsp_changes.as_mut() {
+            if changes.len() >= LSP_CHANGE_LIMIT {
+                self.lsp_changes = None;
+            } else {
+                changes.push(change);
+            }
+        }
+    }
+
+    fn byte_point(&self, char_idx: usize) -> (usize, (usize, usize)) {
+        let byte = self.rope.char_to_byte(char_idx);
+        let row = self.rope.char_to_line(char_idx);
+        (byte, (row, byte - self.rope.line_to_byte(row)))
+    }
+
+    fn push_byte_edit(&mut self, edit: ByteEdit) {
+        if let Some(edits) = self.byte_edits.as_mut() {
+            if edits.len() >= BYTE_EDIT_LIMIT {
+                self.byte_edits = None;
+            } else {
+                edits.push(edit);
+            }
+        }
+    }
+
+    fn rope_insert(&mut self, idx: usize, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        if self.byte_edits.is_some() {
+            let (start_byte, start_point) = self.byte_point(idx);
+            let new_end_point = match text.rfind('\n') {
+                Some(pos) => (
+                    start_point.0 + text.matches('\n').count(),
+                    text.len() - pos - 1,
+                ),
+                None => (start_point.0, start_point.1 + text.len()),
+            };
+            self.push_byte_edit(ByteEdit {
+                start_byte,
+                old_end_byte: start_byte,
+                new_end_byte: start_byte + text.len(),
+                start_point,
+                old_end_point: start_point,
+                new_end_point,
+            });
+        }
+        if self.lsp_changes.is_some() {
+            let start = self.utf16_point(idx);
+            self.push_lsp_change(LspChange {
+                start,
+                end: start,
+                text: text.to_string(),
+            });
+        }
+        self.rope.insert(idx, text);
+        self.shift_tracked_for_insert(idx, text);
+        self.shift_marks_for_insert(idx, text);
+        if let Some(last) = self.changes.last_mut()
+            && last.char_idx + last.inserted.chars().count() == idx
+        {
+            last.inserted.push_str(text);
+            return;
+        }
+        self.changes.push(TextEdit {
+            char_idx: idx,
+            removed: String::new(),
+            inserted: text.to_string(),
+        });
+    }
+
+    fn rope_remove(&mut self, start: usize, end: usize) {
+        if start >= end {
+            return;
+        }
+        if self.byte_edits.is_some() {
+            let (start_byte, start_point) = self.byte_point(start);
+            let (old_end_byte, old_end_point) = self.byte_point(end);
+            self.push_byte_edit(ByteEdit {
+                start_byte,
+                old_end_byte,
+                new_end_byte: start_byte,
+                start_point,
+                old_end_point,
+                new_end_point: start_point,
+            });
+        }
+        if self.lsp_changes.is_some() {
+            let change = LspChange {
+                start: self.utf16_point(start),
+                end: self.utf16_point(end),
+                text: String::new(),
+            };
+            self.push_lsp_change(change);
+        }
+        let removed = self.rope.slice(start..end).to_string();
+        self.shift_marks_for_remove(start, end);
+        self.rope.remove(start..end);
+        self.shift_tracked_for_remove(start, end, &removed);
+        self.changes.push(TextEdit {
+            char_idx: start,
+            removed,
+            inserted: String::new(),
+        });
+    }
+
+    pub(crate) fn line_to_byte(&self, row: usize) -> usize {
+        if row >= self.line_count() {
+            return self.rope.len_bytes();
+        }
+        self.rope.line_to_byte(row)
+    }
+
+    pub(crate) fn chunk_from_byte(&self, byte_idx: usize) -> &[u8] {
+        if byte_idx >= self.rope.len_bytes() {
+            return &[];
+        }
+        let (chunk, start, _, _) = self.rope.chunk_at_byte(byte_idx);
+        &chunk.as_bytes()[byte_idx - start..]
+    }
+
+    pub(crate) fn byte_range_chunks(
+        &self,
+        start: usize,
+        end: usize,
+    ) -> impl Iterator<Item = &[u8]> + '_ {
+        let len = self.rope.len_bytes();
+        let start = start.min(len);
+        let end = end.clamp(start, len);
+        self.rope.byte_slice(start..end).chunks().map(str::as_bytes)
+    }
+
+    pub(crate) fn byte_range_to_string(&self, start: usize, end: usize) -> String {
+        let len = self.rope.len_bytes();
+        let start = start.min(len);
+        let end = end.clamp(start, len);
+        self.rope.byte_slice(start..end).to_string()
+    }
+}
+
+impl std::fmt::Display for TextBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for chunk in self.rope.chunks() {
+            f.write_str(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_text_keeps_text_exactly() {
+        let buf = TextBuffer::from_text("a\nbb\nccc");
+        assert_eq!(buf.line_count(), 3);
+        assert_eq!(buf.to_string(), "a\nbb\nccc");
+        assert_eq!(TextBuffer::from_text("").line_count(), 1);
+        assert_eq!(TextBuffer::from_text("x\n").line_count(), 2);
+    }
+
+    #[test]
+    fn line_edits_keep_single_empty_line_minimum() {
+        let mut buf = TextBuffer::from_text("one\ntwo\nthree");
+        buf.remove_lines(1, 2);
+        assert_eq!(buf.to_string(), "one");
+        buf.remove_lines(0, 0);
+        assert_eq!(buf.line_count(), 1);
+        assert_eq!(buf.line(0).as_deref(), Some(""));
+        buf.insert_line(1, "tail");
+        buf.insert_line(0, "head");
+        assert_eq!(buf.to_string(), "head\n\ntail");
+    }
+
+    #[test]
+    fn split_join_and_remove_across_lines() {
+        let mut buf = TextBuffer::from_text("hello world\nsecond");
+        buf.split_line(0, 5);
+        assert_eq!(buf.line(1).as_deref(), Some(" world"));
+        buf.join_line(0);
+        assert_eq!(buf.line(0).as_deref(), Some("hello world"));
+        buf.remove((0, 6), (1, 3));
+        assert_eq!(buf.to_string(), "hello ond");
+        assert_eq!(buf.char_at(0, 6), Some('o'));
+        assert_eq!(buf.line_len(0), 9);
+    }
+
+    #[test]
+    fn recorded_changes_revert_in_reverse_order() {
+        let mut buf = TextBuffer::from_text("abc\ndef");
+        buf.insert(0, 3, "xy");
+        buf.insert(0, 5, "z");
+        buf.join_line(0);
+        buf.remove_lines(0, 0);
+        let changes = buf.take_changes();
+        assert_eq!(changes[0].inserted, "xyz");
+        for edit in changes.iter().rev() {
+            buf.revert_edit(edit);
+        }
+        assert_eq!(buf.to_string(), "abc\ndef");
+        for edit in &changes {
+            buf.apply_edit(edit);
+        }
+        assert_eq!(buf.to_string(), "");
+    }
+
+    #[test]
+    fn byte_edits_track_points_after_first_take() {
+        let mut buf = TextBuffer::from_text("héllo\nworld");
+        assert!(buf.take_byte_edits().is_none());
+        buf.insert(1, 0, "a\nbc");
+        buf.remove((0, 1), (0, 2));
+        let edits = buf.take_byte_edits().unwrap();
+        assert_eq!(edits[0].start_byte, 7);
+        assert_eq!(edits[0].start_point, (1, 0));
+        assert_eq!(edits[0].new_end_point, (2, 2));
+        assert_eq!(edits[1].old_end_byte, 3);
+        assert_eq!(edits[1].old_end_point, (0, 3));
+        assert_eq!(buf.take_byte_edits(), Some(Vec::new()));
+    }
+
+    #[test]
+    fn lsp_changes_use_utf16_columns() {
+        let mut buf = TextBuffer::from_text("a\u{1F600}b\nxyz");
+        assert!(buf.take_lsp_changes().is_none());
+        buf.insert(0, 2, "c");
+        buf.remove((0, 3), (1, 1));
+        let changes = buf.take_lsp_changes().unwrap();
+        assert_eq!(changes[0].start, (0, 3));
+        assert_eq!(changes[0].text, "c");
+        assert_eq!(changes[1].start, (0, 4));
+        assert_eq!(changes[1].end, (1, 1));
+        assert_eq!(buf.utf16_to_pos((0, 3)), (0, 2));
+        assert_eq!(buf.pos_to_utf16((0, 2)), (0, 3));
+        assert_eq!(buf.utf16_to_pos((9, 0)), (0, 5));
+    }
+
+    #[test]
+    fn marks_follow_edits_and_drop_with_their_line() {
+        let mut buf = TextBuffer::from_text("one\ntwo\nthree\nfour");
+        buf.set_mark('a', (1, 2));
+        buf.set_mark('b', (2, 1));
+        buf.set_mark('c', (3, 0));
+        buf.insert_line(0, "zero");
+        assert_eq!(buf.mark('a'), Some((2, 2)));
+        buf.split_line(2, 1);
+        assert_eq!(buf.mark('a'), Some((3, 1)));
+        buf.remove((3, 0), (3, 1));
+        assert_eq!(buf.mark('a'), Some((3, 0)));
+        buf.remove_lines(4, 4);
+        assert_eq!(buf.mark('b'), None);
+        assert_eq!(buf.mark('c'), Some((4, 0)));
+        buf.join_line(3);
+        assert_eq!(buf.mark('c'), Some((3, 1)));
+        buf.replace_all("x");
+        assert_eq!(buf.mark('c'), Some((0, 1)));
+    }
+}
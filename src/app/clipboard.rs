@@ -0,0 +1,234 @@
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result, bail};
+use crossterm::execute;
+use crossterm::style::Print;
+
+use super::config::ClipboardConfig;
+use super::registers::Register;
+use super::types::YankType;
+use super::App;
+
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) enum ClipboardProvider {
+    #[default]
+    Internal,
+    Command {
+        copy: Vec<String>,
+        paste: Option<Vec<String>>,
+    },
+    Osc52,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Clipboard {
+    pub(crate) provider: ClipboardProvider,
+    last: Option<Register>,
+}
+
+impl ClipboardProvider {
+    pub(crate) fn from_config(config: &ClipboardConfig) -> Result<Self, String> {
+        let copy = config.copy.clone().filter(|argv| !argv.is_empty());
+        let paste = config.paste.clone().filter(|argv| !argv.is_empty());
+        match config.provider.as_deref() {
+            Some("osc52") => Ok(Self::Osc52),
+            Some("internal") => Ok(Self::Internal),
+            Some("command") | None => match copy {
+                Some(copy) => Ok(Self::Command { copy, paste }),
+                None if config.provider.is_some() => {
+                    Err("clipboard_provider: command needs `copy`".to_string())
+                }
+                None => Ok(Self::Internal),
+            },
+            Some(other) => Err(format!(
+                "Unknown clipboard provider: {} (use command|osc52|internal)",
+                other
+            )),
+        }
+    }
+}
+
+impl Clipboard {
+    fn copy(&mut self, register: &Register) -> Result<()> {
+        self.last = Some(register.clone());
+        match &self.provider {
+            ClipboardProvider::Internal => Ok(()),
+            ClipboardProvider::Command { copy, .. } => {
+                run_copy_command(copy, &clipboard_text(register))
+            }
+            ClipboardProvider::Osc52 => {
+                let tmux = std::env::var_os("TMUX").is_some();
+                execute!(io::stdout(), Print(osc52_sequence(&clipboard_text(register), tmux)))?;
+                Ok(())
+            }
+        }
+    }
+
+    fn paste(&self) -> Result<Option<Register>> {
+        match &self.provider {
+            ClipboardProvider::Command {
+                paste: Some(paste), ..
+            } => Ok(Some(self.register_from_text(run_paste_command(paste)?))),
+            _ => Ok(self.last.clone()),
+        }
+    }
+
+    fn register_from_text(&self, text: String) -> Register {
+        if let Some(last) = &self.last
+            && clipboard_text(last) == text
+        {
+            return last.clone();
+        }
+        match text.strip_suffix('\n') {
+            Some(stripped) => Register {
+                text: stripped.to_string(),
+                kind: YankType::Line,
+            },
+            None => Register {
+                text,
+                kind: YankType::Char,
+            },
+        }
+    }
+}
+
+fn clipboard_text(register: &Register) -> String {
+    match register.kind {
+        YankType::Line => format!("{}\n", register.text),
+        _ => register.text.clone(),
+    }
+}
+
+fn run_copy_command(argv: &[String], text: &str) -> Result<()> {
+    let mut child = Command::new(&argv[0])
+        .args(&argv[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("cannot run {}", argv[0]))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("{} exited with {}", argv[0], status);
+    }
+    Ok(())
+}
+
+fn run_paste_command(argv: &[String]) -> Result<String> {
+    let output = Command::new(&argv[0])
+        .args(&argv[1..])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .with_context(|| format!("cannot run {}", argv[0]))?;
+    if !output.status.success() {
+        bail!("{} exited with {}", argv[0], output.status);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).replace("\r\n", "\n"))
+}
+
+// tmux drops OSC 52 from applications unless it arrives through DCS passthrough,
+// which needs every ESC inside the payload doubled.
+fn osc52_sequence(text: &str, tmux: bool) -> String {
+    let sequence = format!("\x1b]52;c;{}\x07", base64_encode(text.as_bytes()));
+    if tmux {
+        format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"))
+    } else {
+        sequence
+    }
+}
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                out.push(BASE64_CHARS[(n >> (18 - idx * 6)) as usize & 63] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+impl App {
+    pub(super) fn clipboard_copy(&mut self, register: &Register) {
+        if let Err(err) = self.clipboard.copy(register) {
+            self.set_status(format!("Clipboard: {}", err));
+        }
+    }
+
+    pub(super) fn clipboard_paste(&self) -> Result<Option<Register>> {
+        self.clipboard.paste()
+    }
+
+    pub(super) fn set_clipboard_option(&mut self, value: &str) -> Result<(), String> {
+        self.clipboard_unnamed = match value {
+            "" => false,
+            "unnamed" | "unnamedplus" => true,
+            _ => return Err(format!("Unknown clipboard value: {} (use unnamedplus)", value)),
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_osc52_payload() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foobar"), "Zm9vYmFy");
+        assert_eq!(osc52_sequence("hi\n", false), "\x1b]52;c;aGkK\x07");
+        assert_eq!(osc52_sequence("hi\n", true), "\x1bPtmux;\x1b\x1b]52;c;aGkK\x07\x1b\\");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn round_trips_through_provider_command() {
+        let dir = std::env::temp_dir().join(format!("rvim-clipboard-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("clip");
+        let path_str = path.display().to_string();
+        let calls = dir.join("calls");
+        let mut app = App::new(None, "one\ntwo".to_string());
+        app.clipboard.provider = ClipboardProvider::Command {
+            copy: vec!["sh".into(), "-c".into(), format!("cat > '{}'", path_str)],
+            paste: Some(vec![
+                "sh".into(),
+                "-c".into(),
+                format!("printf x >> '{}'; cat '{}'", calls.display(), path_str),
+            ]),
+        };
+
+        app.pending_register = Some('+');
+        app.yank_line(0);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "one\n");
+
+        std::fs::write(&path, "from outside").unwrap();
+        app.cursor_row = 1;
+        app.pending_register = Some('*');
+        app.paste_after();
+        assert_eq!(app.text.line(1).unwrap(), "tfrom outsidewo");
+        assert_eq!(std::fs::read_to_string(&calls).unwrap(), "x");
+
+        app.set_clipboard_option("unnamedplus").unwrap();
+        app.yank_line(1);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "tfrom outsidewo\n");
+        app.paste_after();
+        assert_eq!(app.text.line(2).unwrap(), "tfrom outsidewo");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,12 +1,21 @@
-use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::Result;
+use encoding_rs::Encoding;
 
+use super::buffer::TextBuffer;
+use super::events::EditorEvent;
+use super::fileio::{
+    encode_bytes, encoding_for_name, encoding_name, read_file, write_file, FileFormat,
+    FileOptions, WriteOptions,
+};
 use super::types::{BufferSlot, BufferState, CommandPrompt, SearchSpec};
 use super::input::expand_tilde_path;
+use super::undo::UndoTree;
+use super::window::SplitDir;
 use super::App;
 use super::Theme;
+use crate::logging::append_log;
 
 impl App {
     fn find_buffer_id_by_path(&self, path: &PathBuf) -> Option<usize> {
@@ -18,7 +27,7 @@ impl App {
         None
     }
 
-    fn sorted_buffer_ids(&self) -> Vec<usize> {
+    pub(super) fn sorted_buffer_ids(&self) -> Vec<usize> {
         let mut ids = Vec::with_capacity(self.buffers.len() + 1);
         ids.push(self.current_buffer_id);
         for slot in &self.buffers {
@@ -28,7 +37,18 @@ impl App {
         ids
     }
 
-    fn switch_to_buffer(&mut self, id: usize) -> bool {
+    pub(super) fn switch_to_buffer(&mut self, id: usize) -> bool {
+        let previous = self.current_buffer_id;
+        if !self.enter_buffer(id, true) {
+            return false;
+        }
+        if id != previous {
+            self.fire_event(EditorEvent::BufEnter(self.file_path.clone()));
+        }
+        true
+    }
+
+    pub(super) fn enter_buffer(&mut self, id: usize, record_jump: bool) -> bool {
         if id == self.current_buffer_id {
             return true;
         }
@@ -39,7 +59,11 @@ impl App {
                 return false;
             }
         };
+        if record_jump {
+            self.push_jump();
+        }
         let target = self.buffers.swap_remove(idx);
+        self.maybe_write_swap(true);
         let current_state = self.capture_buffer_state();
         let current_id = self.current_buffer_id;
         self.buffers.push(BufferSlot {
@@ -84,50 +108,120 @@ impl App {
         }
     }
 
-    fn open_or_switch_buffer(&mut self, path: PathBuf) -> Result<()> {
+    pub(super) fn open_or_switch_buffer(&mut self, path: PathBuf, encoding: Option<&'static Encoding>) {
         if self.file_path.as_ref() == Some(&path) {
-            self.reload(&path)?;
-            self.set_status(format!("Opened {}", path.display()));
-            return Ok(());
+            self.open_current_file(encoding);
+            return;
         }
         if let Some(id) = self.find_buffer_id_by_path(&path) {
             if self.switch_to_buffer(id) {
-                self.set_status(format!("Buffer {}", id));
+                if encoding.is_some() && !self.dirty {
+                    self.open_current_file(encoding);
+                } else {
+                    self.set_status(format!("Buffer {}", id));
+                }
             }
-            return Ok(());
-        }
-        let content = fs::read_to_string(&path).unwrap_or_default();
-        let mut lines: Vec<String> = content.lines().map(|s| s.to_string()).collect();
-        if lines.is_empty() {
-            lines.push(String::new());
+            return;
         }
+        let (text, file_options) = match read_file(&path, encoding) {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                self.set_status(format!("Can't open {}: {:#}", path.display(), err));
+                return;
+            }
+        };
+        let undo_tree = self.load_undo_history(&path, &text);
+        self.push_jump();
         let new_state = BufferState {
-            lines,
+            text,
             cursor_row: 0,
             cursor_col: 0,
             scroll_row: 0,
             scroll_col: 0,
             file_path: Some(path.clone()),
+            file_options,
             dirty: false,
-            undo_stack: Vec::new(),
-            redo_stack: Vec::new(),
+            undo_tree,
             line_undo: None,
-            is_restoring: false,
             change_tick: 0,
             edit_tick: 0,
+            swap_tick: 0,
+            read_only: false,
         };
+        self.install_new_buffer(new_state);
+        self.set_opened_status(&path);
+        self.check_swap_file();
+        self.fire_event(EditorEvent::BufRead(Some(path.clone())));
+        self.fire_event(EditorEvent::BufEnter(Some(path)));
+    }
+
+    pub(super) fn open_empty_buffer(&mut self) {
+        self.install_new_buffer(BufferState {
+            text: TextBuffer::new(),
+            cursor_row: 0,
+            cursor_col: 0,
+            scroll_row: 0,
+            scroll_col: 0,
+            file_path: None,
+            file_options: FileOptions::default(),
+            dirty: false,
+            undo_tree: UndoTree::new(),
+            line_undo: None,
+            change_tick: 0,
+            edit_tick: 0,
+            swap_tick: 0,
+            read_only: false,
+        });
+    }
+
+    fn install_new_buffer(&mut self, state: BufferState) {
+        self.maybe_write_swap(true);
         let current_state = self.capture_buffer_state();
         let current_id = self.current_buffer_id;
         self.buffers.push(BufferSlot {
             id: current_id,
             state: current_state,
         });
-        self.load_buffer_state(new_state);
+        self.load_buffer_state(state);
         self.current_buffer_id = self.next_buffer_id;
         self.next_buffer_id += 1;
         self.reset_transient_for_switch();
-        self.set_status(format!("Opened {}", path.display()));
-        Ok(())
+    }
+
+    pub fn open_current_file(&mut self, encoding: Option<&'static Encoding>) {
+        let Some(path) = self.file_path.clone() else {
+            return;
+        };
+        match self.reload(&path, encoding) {
+            Ok(()) => {
+                self.set_opened_status(&path);
+                self.check_swap_file();
+                self.fire_event(EditorEvent::BufRead(Some(path)));
+            }
+            Err(err) => self.set_status(format!("Can't open {}: {:#}", path.display(), err)),
+        }
+    }
+
+    fn set_opened_status(&mut self, path: &Path) {
+        let encoding = encoding_name(self.file_options.encoding);
+        if self.file_options.decode_failed {
+            self.set_status(format!(
+                "Opened {} [conversion error: not valid {}; writing it back is disabled]",
+                path.display(),
+                encoding
+            ));
+        } else if encoding != "utf-8" {
+            self.set_status(format!("Opened {} [{}]", path.display(), encoding));
+        } else {
+            self.set_status(format!("Opened {}", path.display()));
+        }
+    }
+
+    fn set_write_path(&mut self, path: PathBuf) {
+        if self.file_path.as_ref() != Some(&path) {
+            self.file_options.decode_failed = false;
+        }
+        self.file_path = Some(path);
     }
 
     fn close_buffer(&mut self, id: Option<usize>, force: bool) {
@@ -137,20 +231,26 @@ impl App {
                 self.set_status("No write since last change (add ! to override)");
                 return;
             }
+            if let Some(path) = self.file_path.clone() {
+                self.remove_swap_file(&path);
+            }
+            let text = std::mem::take(&mut self.text);
+            self.forget_buffer_marks(target_id, &text);
             if self.buffers.is_empty() {
-                self.lines = vec![String::new()];
+                self.text = TextBuffer::new();
                 self.cursor_row = 0;
                 self.cursor_col = 0;
                 self.scroll_row = 0;
                 self.scroll_col = 0;
                 self.file_path = None;
+                self.file_options = FileOptions::default();
                 self.dirty = false;
-                self.undo_stack.clear();
-                self.redo_stack.clear();
+                self.undo_tree = UndoTree::new();
                 self.line_undo = None;
-                self.is_restoring = false;
                 self.change_tick = 0;
                 self.edit_tick = 0;
+                self.swap_tick = 0;
+                self.read_only = false;
                 self.syntax_by_buffer.remove(&target_id);
                 self.reset_transient_for_switch();
                 self.set_status("Closed buffer (new empty)");
@@ -170,6 +270,7 @@ impl App {
             self.load_buffer_state(replacement.state);
             self.current_buffer_id = replacement.id;
             self.syntax_by_buffer.remove(&target_id);
+            self.forget_buffer_windows(target_id);
             self.reset_transient_for_switch();
             self.set_status(format!("Closed buffer {}, now {}", target_id, replacement_id));
             return;
@@ -185,35 +286,84 @@ impl App {
             self.set_status("No write since last change (add ! to override)");
             return;
         }
-        self.buffers.swap_remove(idx);
+        let slot = self.buffers.swap_remove(idx);
+        if let Some(path) = slot.state.file_path.as_ref() {
+            self.remove_swap_file(path);
+        }
+        self.forget_buffer_marks(target_id, &slot.state.text);
         self.syntax_by_buffer.remove(&target_id);
+        self.forget_buffer_windows(target_id);
         self.set_status(format!("Closed buffer {}", target_id));
     }
 
-    pub(super) fn save(&mut self) -> Result<()> {
+    pub(super) fn save(&mut self) -> bool {
+        self.save_with(false)
+    }
+
+    pub(super) fn save_with(&mut self, force: bool) -> bool {
         let Some(path) = self.file_path.clone() else {
             self.set_status("No file name (open with a path)");
-            return Ok(());
+            return false;
+        };
+        if self.read_only && !force {
+            self.set_status("'readonly' option is set (add ! to override)");
+            return false;
+        }
+        if self.file_options.decode_failed {
+            self.set_status(format!(
+                "Not overwriting {}: it did not decode as {} (write elsewhere or :e ++enc=<name>)",
+                path.display(),
+                encoding_name(self.file_options.encoding)
+            ));
+            return false;
+        }
+        let buffer = self.current_buffer_id;
+        self.fire_event(EditorEvent::BufWritePre(path.clone()));
+        if self.current_buffer_id != buffer || self.file_path.as_ref() != Some(&path) {
+            self.set_status(format!("Not writing {}: BufWritePre switched buffers", path.display()));
+            return false;
+        }
+        let opts = WriteOptions {
+            backup: self.backup,
+            backup_dir: self.backup_dir.as_deref(),
         };
-        let content = self.lines.join("\n");
-        fs::write(&path, content)?;
+        let written = encode_bytes(&self.text, &self.file_options)
+            .and_then(|data| write_file(&path, &data, opts));
+        if let Err(err) = written {
+            append_log(&format!("write: {}: {:#}", path.display(), err));
+            self.set_status(format!("Write failed: {:#}", err));
+            return false;
+        }
+        self.read_only = false;
+        self.remove_swap_file(&path);
+        self.commit_undo();
+        self.undo_tree.mark_saved();
         self.dirty = false;
-        self.set_status(format!("Wrote {}", path.display()));
-        Ok(())
+        self.lsp_did_save();
+        match self.save_undo_history(&path) {
+            Err(err) => self.set_status(format!("Wrote {} (undofile: {})", path.display(), err)),
+            Ok(()) => self.set_status(format!("Wrote {}", path.display())),
+        }
+        self.fire_event(EditorEvent::BufWritePost(path));
+        true
     }
 
-    pub(super) fn reload(&mut self, path: &PathBuf) -> Result<()> {
-        let content = fs::read_to_string(path).unwrap_or_default();
-        self.lines = content.lines().map(|s| s.to_string()).collect();
-        if self.lines.is_empty() {
-            self.lines.push(String::new());
+    pub(super) fn reload(&mut self, path: &Path, encoding: Option<&'static Encoding>) -> Result<()> {
+        let marks = self.text.mark_positions();
+        (self.text, self.file_options) = read_file(path, encoding)?;
+        for (name, pos) in marks {
+            self.text.set_mark(name, pos);
         }
+        self.undo_tree = self.load_undo_history(path, &self.text);
+        self.line_undo = None;
         self.cursor_row = 0;
         self.cursor_col = 0;
         self.scroll_row = 0;
         self.scroll_col = 0;
         self.dirty = false;
+        self.read_only = false;
         self.edit_tick = self.edit_tick.wrapping_add(1);
+        self.swap_tick = self.edit_tick;
         Ok(())
     }
 
@@ -231,50 +381,209 @@ impl App {
             self.command_history.push(input.clone());
         }
         self.command_history_index = None;
+        let quit = self.run_ex_command(&input)?;
+        let name = input.split_whitespace().next().unwrap_or("").trim_end_matches('!');
+        self.fire_event(EditorEvent::ExCommand(name.to_string()));
+        Ok(quit)
+    }
+
+    pub(super) fn run_ex_command(&mut self, input: &str) -> Result<bool> {
+        let (range, rest) = match self.parse_line_range(input) {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                self.set_status(err);
+                return Ok(false);
+            }
+        };
+        let rest = rest.trim_start();
+        if let Some(args) = rest
+            .strip_prefix("substitute")
+            .or_else(|| rest.strip_prefix('s'))
+            .filter(|args| !args.starts_with(|ch: char| ch.is_alphanumeric() || ch == ' '))
+        {
+            let range = range.unwrap_or((self.cursor_row, self.cursor_row));
+            self.substitute(range, args);
+            return Ok(false);
+        }
+        if let Some((_, row)) = range
+            && rest.is_empty()
+        {
+            self.push_jump();
+            self.clear_line_undo();
+            self.cursor_row = row;
+            self.move_line_first_non_blank();
+            return Ok(false);
+        }
+        if range.is_none() && self.run_plugin_command(rest) {
+            return Ok(false);
+        }
+        if self.execute_line_command(range, rest)? {
+            return Ok(false);
+        }
+        if range.is_none() && self.execute_quickfix_command(rest)? {
+            return Ok(false);
+        }
+        if range.is_some() {
+            self.set_status(format!("Not an editor command: {}", rest));
+            return Ok(false);
+        }
 
         let mut parts = input.split_whitespace();
         let cmd = parts.next().unwrap_or("");
         let arg = parts.next().map(|s| s.to_string());
 
         match cmd {
-            "w" | "write" => {
+            "w" | "write" | "w!" | "write!" => {
+                let force = cmd.ends_with('!');
                 if let Some(path) = arg.as_deref().map(PathBuf::from) {
-                    self.file_path = Some(path.clone());
-                    self.save()?;
+                    self.set_write_path(path);
+                    self.save_with(force);
                 } else if self.file_path.is_none() {
                     self.set_status("Usage: :w <path>");
                 } else {
-                    self.save()?;
+                    self.save_with(force);
                 }
             }
             "q" | "quit" => {
-                if self.dirty {
+                if self.window_count() == 1 && self.tabs.len() == 1 && self.dirty {
                     self.set_status("No write since last change (add ! to override)");
                     return Ok(false);
                 }
-                return Ok(true);
+                return Ok(self.quit_window());
             }
             "q!" | "quit!" => {
+                return Ok(self.quit_window());
+            }
+            "qa" | "qall" | "quitall" => {
+                if self.dirty || self.buffers.iter().any(|slot| slot.state.dirty) {
+                    self.set_status("No write since last change (add ! to override)");
+                    return Ok(false);
+                }
+                return Ok(true);
+            }
+            "qa!" | "qall!" | "quitall!" => {
                 return Ok(true);
             }
             "wq" | "x" => {
                 if let Some(path) = arg.as_deref().map(PathBuf::from) {
-                    self.file_path = Some(path.clone());
-                    self.save()?;
-                    return Ok(true);
-                }
-                if self.file_path.is_none() {
+                    self.set_write_path(path);
+                } else if self.file_path.is_none() {
                     self.set_status("Usage: :wq <path>");
                     return Ok(false);
                 }
-                self.save()?;
-                return Ok(true);
+                return Ok(self.save() && self.quit_window());
             }
-            "e" | "edit" => {
-                if let Some(path) = arg.map(expand_tilde_path).map(PathBuf::from) {
-                    self.open_or_switch_buffer(path)?;
+            "sp" | "split" | "vs" | "vsplit" => {
+                let dir = if cmd.starts_with('v') {
+                    SplitDir::Vertical
+                } else {
+                    SplitDir::Horizontal
+                };
+                let path = arg.as_deref().map(|arg| PathBuf::from(expand_tilde_path(arg)));
+                self.split_window(dir, path, false);
+            }
+            "new" | "vne" | "vnew" => {
+                let dir = if cmd == "new" {
+                    SplitDir::Horizontal
                 } else {
-                    self.set_status("Usage: :e <path>");
+                    SplitDir::Vertical
+                };
+                self.split_window(dir, None, true);
+            }
+            "clo" | "close" => {
+                self.close_window(self.current_window_id());
+            }
+            "on" | "only" => {
+                self.only_window();
+            }
+            "res" | "resize" => {
+                self.resize_command(SplitDir::Horizontal, arg.as_deref());
+            }
+            "vert" | "vertical" => match parts.next() {
+                Some("res" | "resize") => {
+                    self.resize_command(SplitDir::Vertical, parts.next());
+                }
+                _ => self.set_status("Usage: :vertical resize [+-]N"),
+            },
+            "winc" | "wincmd" => match arg.as_deref().and_then(|arg| arg.chars().next()) {
+                Some(ch) => return self.window_command(ch, 0),
+                None => self.set_status("Usage: :wincmd {key}"),
+            },
+            "tabnew" | "tabe" | "tabedit" => {
+                let path = arg.as_deref().map(|arg| PathBuf::from(expand_tilde_path(arg)));
+                self.new_tab(path);
+            }
+            "tabn" | "tabnext" => match arg.as_deref().map(str::parse::<usize>) {
+                Some(Ok(n)) if n > 0 => self.next_tab(n, true),
+                Some(_) => self.set_status("Usage: :tabnext [N]"),
+                None => self.next_tab(0, true),
+            },
+            "tabp" | "tabprevious" | "tabN" | "tabNext" => {
+                let count = arg.as_deref().and_then(|arg| arg.parse().ok()).unwrap_or(1);
+                self.next_tab(count, false);
+            }
+            "tabc" | "tabclose" => {
+                self.close_tab(self.current_tab);
+            }
+            "tabo" | "tabonly" => {
+                self.only_tab();
+            }
+            "tabs" => {
+                let lines = self.tab_list_lines();
+                self.show_list(lines);
+            }
+            "LspInfo" => {
+                self.show_list(self.lsp_info_lines());
+            }
+            "LspRestart" => {
+                self.lsp_restart();
+            }
+            "LspRename" => match arg.as_deref() {
+                Some(name) => self.lsp_rename(name),
+                None => self.set_status("Usage: :LspRename <new name>"),
+            },
+            "LspCodeAction" => match arg.as_deref().map(str::parse::<usize>) {
+                Some(Ok(index)) => self.lsp_code_action(Some(index)),
+                Some(Err(_)) => self.set_status("Usage: :LspCodeAction [N]"),
+                None => self.lsp_code_action(None),
+            },
+            "LspFormat" => {
+                self.lsp_format();
+            }
+            "Files" => {
+                self.open_file_picker();
+            }
+            "Explore" | "Ex" | "Lexplore" => {
+                self.explore_command(arg.as_deref().unwrap_or(""));
+            }
+            "LspDiagnostics" => {
+                self.show_list(self.diagnostic_list_lines());
+            }
+            "e" | "edit" => {
+                let mut encoding = None;
+                let mut target = None;
+                for part in input.split_whitespace().skip(1) {
+                    if let Some(name) = part
+                        .strip_prefix("++enc=")
+                        .or_else(|| part.strip_prefix("++e="))
+                    {
+                        match encoding_for_name(name) {
+                            Some(enc) => encoding = Some(enc),
+                            None => {
+                                self.set_status(format!("Unknown encoding: {}", name));
+                                return Ok(false);
+                            }
+                        }
+                    } else if target.is_none() {
+                        target = Some(PathBuf::from(expand_tilde_path(part)));
+                    }
+                }
+                match target {
+                    Some(path) => self.open_or_switch_buffer(path, encoding),
+                    None if encoding.is_some() && self.file_path.is_some() => {
+                        self.open_current_file(encoding);
+                    }
+                    None => self.set_status("Usage: :e [++enc=<name>] <path>"),
                 }
             }
             "ls" | "buffers" => {
@@ -300,12 +609,39 @@ impl App {
                 self.switch_prev_buffer();
             }
             "map" => {
-                self.completion_candidates = self.keymaps.describe_lines();
-                self.completion_index = Some(0);
-                self.completion_cmd_prefix = Some("<map>".to_string());
-                self.completion_anchor_fixed = true;
-                self.completion_anchor_col = Some(0);
-                self.command_keep_open = true;
+                self.show_list(self.keymaps.describe_lines());
+            }
+            "au" | "autocmd" => {
+                self.show_list(self.autocmd_list_lines());
+            }
+            "plugins" => {
+                self.show_list(self.plugin_list_lines());
+            }
+            "undol" | "undolist" => {
+                self.show_list(self.undo_list_lines());
+            }
+            "let" => {
+                self.let_command(rest["let".len()..].trim());
+            }
+            "marks" => {
+                let filter: String = input.split_whitespace().skip(1).collect();
+                self.show_list(self.marks_list_lines(&filter));
+            }
+            "ju" | "jumps" => {
+                self.show_list(self.jumps_list_lines());
+            }
+            "reg" | "registers" | "di" | "display" => {
+                let filter: String = input.split_whitespace().skip(1).collect();
+                self.show_list(self.register_list_lines(&filter));
+            }
+            "earlier" | "later" => {
+                let forward = cmd == "later";
+                let spec = arg.as_deref().unwrap_or("1");
+                match parse_undo_time(spec) {
+                    Some(UndoSpan::Steps(steps)) => self.undo_chrono(steps, forward),
+                    Some(UndoSpan::Seconds(secs)) => self.undo_by_time(secs, forward),
+                    None => self.set_status(format!("Usage: :{} {{count}}[s|m|h|d]", cmd)),
+                }
             }
             "perf" => {
                 let subcmd = arg.as_deref().unwrap_or("avg");
@@ -401,6 +737,107 @@ impl App {
                         }
                         return Ok(false);
                     }
+                    if let Some(value) = setting
+                        .strip_prefix("directory=")
+                        .or_else(|| setting.strip_prefix("dir="))
+                    {
+                        self.swap_dir = if value.is_empty() {
+                            None
+                        } else {
+                            Some(PathBuf::from(expand_tilde_path(value)))
+                        };
+                        self.set_status(format!("directory={}", value));
+                        return Ok(false);
+                    }
+                    if let Some(value) = setting
+                        .strip_prefix("updatetime=")
+                        .or_else(|| setting.strip_prefix("ut="))
+                    {
+                        match value.parse::<u64>() {
+                            Ok(ms) => {
+                                self.update_time = ms;
+                                self.set_status(format!("updatetime={}", ms));
+                            }
+                            Err(_) => self.set_status("updatetime expects a number"),
+                        }
+                        return Ok(false);
+                    }
+                    if let Some(value) = setting
+                        .strip_prefix("updatecount=")
+                        .or_else(|| setting.strip_prefix("uc="))
+                    {
+                        match value.parse::<u64>() {
+                            Ok(count) => {
+                                self.update_count = count;
+                                self.set_status(format!("updatecount={}", count));
+                            }
+                            Err(_) => self.set_status("updatecount expects a number"),
+                        }
+                        return Ok(false);
+                    }
+                    if let Some(value) = setting
+                        .strip_prefix("fileencoding=")
+                        .or_else(|| setting.strip_prefix("fenc="))
+                    {
+                        match encoding_for_name(value) {
+                            Some(encoding) => {
+                                self.set_file_options(FileOptions {
+                                    encoding,
+                                    ..self.file_options
+                                });
+                                self.set_status(format!("fileencoding={}", encoding_name(encoding)));
+                            }
+                            None => self.set_status(format!("Unknown encoding: {}", value)),
+                        }
+                        return Ok(false);
+                    }
+                    if let Some(value) = setting
+                        .strip_prefix("fileformat=")
+                        .or_else(|| setting.strip_prefix("ff="))
+                    {
+                        match FileFormat::from_name(value) {
+                            Some(format) => {
+                                self.set_file_options(FileOptions {
+                                    format,
+                                    ..self.file_options
+                                });
+                                self.set_status(format!("fileformat={}", value));
+                            }
+                            None => self.set_status("Unknown fileformat (use unix|dos|mac)"),
+                        }
+                        return Ok(false);
+                    }
+                    if let Some(value) = setting
+                        .strip_prefix("backupdir=")
+                        .or_else(|| setting.strip_prefix("bdir="))
+                    {
+                        self.backup_dir = if value.is_empty() {
+                            None
+                        } else {
+                            Some(PathBuf::from(expand_tilde_path(value)))
+                        };
+                        self.set_status(format!("backupdir={}", value));
+                        return Ok(false);
+                    }
+                    if let Some(value) = setting
+                        .strip_prefix("clipboard=")
+                        .or_else(|| setting.strip_prefix("cb="))
+                    {
+                        match self.set_clipboard_option(value) {
+                            Ok(()) => self.set_status(format!("clipboard={}", value)),
+                            Err(err) => self.set_status(err),
+                        }
+                        return Ok(false);
+                    }
+                    if let Some(value) = setting.strip_prefix("undodir=") {
+                        self.undo_dir = if value.is_empty() {
+                            None
+                        } else {
+                            Some(PathBuf::from(expand_tilde_path(value)))
+                        };
+                        self.set_status(format!("undodir={}", value));
+                        return Ok(false);
+                    }
                     match setting {
                         "findcross" => {
                             self.find_cross_line = true;
@@ -418,6 +855,54 @@ impl App {
                             };
                             self.set_status(value);
                         }
+                        "ignorecase" | "ic" => {
+                            self.ignore_case = true;
+                            self.set_status("ignorecase");
+                        }
+                        "noignorecase" | "noic" => {
+                            self.ignore_case = false;
+                            self.set_status("noignorecase");
+                        }
+                        "ignorecase?" | "ic?" => {
+                            let value = if self.ignore_case {
+                                "ignorecase"
+                            } else {
+                                "noignorecase"
+                            };
+                            self.set_status(value);
+                        }
+                        "smartcase" | "scs" => {
+                            self.smart_case = true;
+                            self.set_status("smartcase");
+                        }
+                        "nosmartcase" | "noscs" => {
+                            self.smart_case = false;
+                            self.set_status("nosmartcase");
+                        }
+                        "smartcase?" | "scs?" => {
+                            let value = if self.smart_case {
+                                "smartcase"
+                            } else {
+                                "nosmartcase"
+                            };
+                            self.set_status(value);
+                        }
+                        "incsearch" | "is" => {
+                            self.incsearch = true;
+                            self.set_status("incsearch");
+                        }
+                        "noincsearch" | "nois" => {
+                            self.incsearch = false;
+                            self.set_status("noincsearch");
+                        }
+                        "incsearch?" | "is?" => {
+                            let value = if self.incsearch {
+                                "incsearch"
+                            } else {
+                                "noincsearch"
+                            };
+                            self.set_status(value);
+                        }
                         "relativenumber" | "rnu" => {
                             self.relative_number = true;
                             self.set_status("relativenumber");
@@ -451,6 +936,122 @@ impl App {
                             self.indent_colon = false;
                             self.set_status("noindentcolon");
                         }
+                        "undofile" | "udf" => {
+                            self.undo_file = true;
+                            self.set_status("undofile");
+                        }
+                        "noundofile" | "noudf" => {
+                            self.undo_file = false;
+                            self.set_status("noundofile");
+                        }
+                        "clipboard?" | "cb?" => {
+                            let value = if self.clipboard_unnamed { "unnamedplus" } else { "" };
+                            self.set_status(format!("clipboard={}", value));
+                        }
+                        "undodir?" => {
+                            let dir = self
+                                .undo_dir
+                                .clone()
+                                .or_else(super::undofile::default_undo_dir)
+                                .map(|dir| dir.display().to_string())
+                                .unwrap_or_default();
+                            self.set_status(format!("undodir={}", dir));
+                        }
+                        "swapfile" | "swf" => {
+                            self.swap_file = true;
+                            self.set_status("swapfile");
+                        }
+                        "noswapfile" | "noswf" => {
+                            self.swap_file = false;
+                            self.set_status("noswapfile");
+                        }
+                        "swapfile?" | "swf?" => {
+                            let value = if self.swap_file { "swapfile" } else { "noswapfile" };
+                            self.set_status(value);
+                        }
+                        "directory?" | "dir?" => {
+                            let dir = self
+                                .swap_dir
+                                .clone()
+                                .or_else(super::swap::default_swap_dir)
+                                .map(|dir| dir.display().to_string())
+                                .unwrap_or_default();
+                            self.set_status(format!("directory={}", dir));
+                        }
+                        "updatetime?" | "ut?" => {
+                            self.set_status(format!("updatetime={}", self.update_time));
+                        }
+                        "updatecount?" | "uc?" => {
+                            self.set_status(format!("updatecount={}", self.update_count));
+                        }
+                        "readonly" | "ro" => {
+                            self.read_only = true;
+                            self.set_status("readonly");
+                        }
+                        "noreadonly" | "noro" => {
+                            self.read_only = false;
+                            self.set_status("noreadonly");
+                        }
+                        "fileencoding?" | "fenc?" => {
+                            self.set_status(format!(
+                                "fileencoding={}",
+                                encoding_name(self.file_options.encoding)
+                            ));
+                        }
+                        "fileformat?" | "ff?" => {
+                            self.set_status(format!(
+                                "fileformat={}",
+                                self.file_options.format.name()
+                            ));
+                        }
+                        "eol" | "endofline" | "noeol" | "noendofline" => {
+                            let eol = !setting.starts_with("no");
+                            self.set_file_options(FileOptions {
+                                eol,
+                                ..self.file_options
+                            });
+                            self.set_status(if eol { "eol" } else { "noeol" });
+                        }
+                        "eol?" | "endofline?" => {
+                            let value = if self.file_options.eol { "eol" } else { "noeol" };
+                            self.set_status(value);
+                        }
+                        "bomb" | "nobomb" => {
+                            let bomb = setting == "bomb";
+                            self.set_file_options(FileOptions {
+                                bomb,
+                                ..self.file_options
+                            });
+                            self.set_status(setting);
+                        }
+                        "bomb?" => {
+                            let value = if self.file_options.bomb { "bomb" } else { "nobomb" };
+                            self.set_status(value);
+                        }
+                        "backup" | "bk" => {
+                            self.backup = true;
+                            self.set_status("backup");
+                        }
+                        "nobackup" | "nobk" => {
+                            self.backup = false;
+                            self.set_status("nobackup");
+                        }
+                        "backup?" | "bk?" => {
+                            let value = if self.backup { "backup" } else { "nobackup" };
+                            self.set_status(value);
+                        }
+                        "backupdir?" | "bdir?" => {
+                            let dir = self
+                                .backup_dir
+                                .as_ref()
+                                .map(|dir| dir.display().to_string())
+                                .unwrap_or_else(|| ".".to_string());
+                            self.set_status(format!("backupdir={}", dir));
+                        }
+                        "undofile?" | "udf?" => {
+                            let value = if self.undo_file { "undofile" } else { "noundofile" };
+                            self.set_status(value);
+                        }
                         "indentcolon?" => {
                             let value = if self.indent_colon {
                                 "indentcolon"
@@ -475,7 +1076,28 @@ impl App {
         Ok(false)
     }
 
+    fn set_file_options(&mut self, options: FileOptions) {
+        if options != self.file_options {
+            self.file_options = options;
+            self.dirty = true;
+        }
+    }
+
+    pub(super) fn show_list(&mut self, lines: Vec<String>) {
+        self.completion_candidates = lines;
+        self.completion_index = Some(0);
+        self.completion_cmd_prefix = Some("<list>".to_string());
+        self.completion_anchor_fixed = true;
+        self.completion_anchor_col = Some(0);
+        self.command_keep_open = true;
+    }
+
     pub(super) fn execute_search(&mut self) -> Result<bool> {
+        if let Some((row, col)) = self.incsearch_origin.take() {
+            self.cursor_row = row;
+            self.cursor_col = col;
+        }
+        self.incsearch_pattern = None;
         let pattern = self.command_buffer.clone();
         if pattern.is_empty() {
             return Ok(false);
@@ -490,11 +1112,15 @@ impl App {
         }
         self.search_history_index = None;
         let reverse = matches!(self.command_prompt, CommandPrompt::SearchBackward);
-        let found = if reverse {
-            self.search_backward(&pattern)
-        } else {
-            self.search_forward(&pattern)
+        let compiled = match self.compile_search(&pattern) {
+            Ok(compiled) => compiled,
+            Err(err) => {
+                self.set_status(format!("Invalid pattern: {}", err));
+                return Ok(false);
+            }
         };
+        self.push_jump();
+        let found = self.search_with(&compiled, reverse);
         if !found {
             self.set_status(format!(
                 "Pattern not found: {}{}",
@@ -507,3 +1133,21 @@ impl App {
         Ok(false)
     }
 }
+
+enum UndoSpan {
+    Steps(usize),
+    Seconds(u64),
+}
+
+fn parse_undo_time(spec: &str) -> Option<UndoSpan> {
+    let unit = spec.chars().last()?;
+    let scale = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        _ => return spec.parse::<usize>().ok().map(UndoSpan::Steps),
+    };
+    let value = spec[..spec.len() - 1].parse::<u64>().ok()?;
+    Some(UndoSpan::Seconds(value * scale))
+}
@@ -0,0 +1,390 @@
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use super::App;
+use super::fuzzy::fuzzy_rank;
+use super::input::expand_tilde_path;
+use super::types::{CharClass, char_class};
+
+const KEYWORD_LIMIT: usize = 5000;
+const WHOLE_LINE_LIMIT: usize = 1000;
+
+// Rows by distance from `center`, nearer rows first and the row above before the row below.
+fn rows_outward(center: usize, count: usize) -> impl Iterator<Item = usize> {
+    let center = center.min(count.saturating_sub(1));
+    (0..count).flat_map(move |offset| {
+        let above = center.checked_sub(offset).filter(|_| offset > 0);
+        let below = Some(center + offset).filter(|row| *row < count);
+        above.into_iter().chain(below)
+    })
+}
+
+pub(crate) trait CompletionSource {
+    fn name(&self) -> &'static str;
+
+    fn start_col(&self, line: &[char], col: usize) -> usize {
+        let mut start = col.min(line.len());
+        while start > 0 && char_class(line[start - 1]) == CharClass::Word {
+            start -= 1;
+        }
+        start
+    }
+
+    fn candidates(&self, app: &App, line: &[char], start: usize) -> Vec<String>;
+}
+
+pub(crate) struct InsertCompletion {
+    sources: Vec<Rc<dyn CompletionSource>>,
+    row: usize,
+    start_col: usize,
+    original: String,
+    pool: Vec<String>,
+}
+
+pub(crate) struct BufferKeywords;
+
+impl CompletionSource for BufferKeywords {
+    fn name(&self) -> &'static str {
+        "Keyword"
+    }
+
+    fn candidates(&self, app: &App, _line: &[char], _start: usize) -> Vec<String> {
+        let current =
+            rows_outward(app.cursor_row, app.text.line_count()).filter_map(|row| app.text.line(row));
+        let others = app
+            .buffers
+            .iter()
+            .flat_map(|slot| (0..slot.state.text.line_count()).filter_map(|row| slot.state.text.line(row)));
+        let mut seen = HashSet::new();
+        let mut out = Vec::new();
+        for line in current.chain(others) {
+            for word in line.split(|ch| char_class(ch) != CharClass::Word) {
+                if word.chars().count() >= 2 && seen.insert(word.to_string()) {
+                    out.push(word.to_string());
+                    if out.len() >= KEYWORD_LIMIT {
+                        return out;
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+pub(crate) struct FilePaths;
+
+impl FilePaths {
+    fn token_start(line: &[char], col: usize) -> usize {
+        let mut start = col.min(line.len());
+        while start > 0 && !line[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        start
+    }
+}
+
+impl CompletionSource for FilePaths {
+    fn name(&self) -> &'static str {
+        "File name"
+    }
+
+    fn start_col(&self, line: &[char], col: usize) -> usize {
+        let col = col.min(line.len());
+        let token = Self::token_start(line, col);
+        line[token..col]
+            .iter()
+            .rposition(|&ch| ch == '/')
+            .map(|idx| token + idx + 1)
+            .unwrap_or(token)
+    }
+
+    fn candidates(&self, _app: &App, line: &[char], start: usize) -> Vec<String> {
+        let dir: String = line[Self::token_start(line, start)..start].iter().collect();
+        let dir = if dir.is_empty() { ".".to_string() } else { expand_tilde_path(&dir) };
+        let hidden = line.get(start) == Some(&'.');
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            return Vec::new();
+        };
+        let mut out: Vec<String> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let mut name = entry.file_name().to_string_lossy().into_owned();
+                if name.starts_with('.') && !hidden {
+                    return None;
+                }
+                if entry.path().is_dir() {
+                    name.push('/');
+                }
+                Some(name)
+            })
+            .collect();
+        out.sort();
+        out
+    }
+}
+
+pub(crate) struct WholeLines;
+
+impl CompletionSource for WholeLines {
+    fn name(&self) -> &'static str {
+        "Whole line"
+    }
+
+    fn start_col(&self, line: &[char], col: usize) -> usize {
+        line.iter()
+            .position(|ch| !ch.is_whitespace())
+            .unwrap_or(line.len())
+            .min(col)
+    }
+
+    fn candidates(&self, app: &App, _line: &[char], _start: usize) -> Vec<String> {
+        let current = rows_outward(app.cursor_row, app.text.line_count())
+            .filter(|&row| row != app.cursor_row)
+            .filter_map(|row| app.text.line(row));
+        let others = app
+            .buffers
+            .iter()
+            .flat_map(|slot| (0..slot.state.text.line_count()).filter_map(|row| slot.state.text.line(row)));
+        let mut seen = HashSet::new();
+        current
+            .chain(others)
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty() && seen.insert(line.clone()))
+            .take(WHOLE_LINE_LIMIT)
+            .collect()
+    }
+}
+
+pub(crate) struct Words {
+    pub(crate) name: &'static str,
+    pub(crate) words: Vec<String>,
+}
+
+impl CompletionSource for Words {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn candidates(&self, _app: &App, _line: &[char], _start: usize) -> Vec<String> {
+        self.words.clone()
+    }
+}
+
+impl App {
+    fn cursor_line_chars(&self) -> Vec<char> {
+        self.text.line(self.cursor_row).unwrap_or_default().chars().collect()
+    }
+
+    pub(crate) fn start_insert_completion(&mut self, sources: Vec<Rc<dyn CompletionSource>>, forward: bool) {
+        let Some(first) = sources.first() else {
+            return;
+        };
+        let line = self.cursor_line_chars();
+        let col = self.cursor_col.min(line.len());
+        let start_col = first.start_col(&line, col);
+        let mut seen = HashSet::new();
+        let pool: Vec<String> = sources
+            .iter()
+            .flat_map(|source| source.candidates(self, &line, start_col))
+            .filter(|item| seen.insert(item.clone()))
+            .collect();
+        let original: String = line[start_col..col].iter().collect();
+        let name = first.name();
+        self.insert_completion = Some(InsertCompletion {
+            sources,
+            row: self.cursor_row,
+            start_col,
+            original,
+            pool,
+        });
+        if !self.rank_insert_completion() {
+            self.set_status(format!("-- {} completion: Pattern not found", name));
+            return;
+        }
+        self.insert_completion_step(forward);
+    }
+
+    fn rank_insert_completion(&mut self) -> bool {
+        let Some(state) = &self.insert_completion else {
+            return false;
+        };
+        let pool = state.pool.iter().filter(|item| **item != state.original);
+        let ranked = fuzzy_rank(&state.original, pool);
+        if ranked.is_empty() {
+            self.close_insert_completion();
+            return false;
+        }
+        self.completion_candidates = ranked;
+        self.completion_index = None;
+        true
+    }
+
+    pub(crate) fn insert_completion_step(&mut self, forward: bool) {
+        if self.insert_completion.is_none() {
+            let sources = self.completion_sources.clone();
+            self.start_insert_completion(sources, forward);
+            return;
+        }
+        let total = self.completion_candidates.len();
+        self.completion_index = match (self.completion_index, forward) {
+            (None, true) => Some(0),
+            (None, false) => Some(total - 1),
+            (Some(idx), true) if idx + 1 < total => Some(idx + 1),
+            (Some(idx), false) if idx > 0 => Some(idx - 1),
+            _ => None,
+        };
+        let text = match self.completion_index {
+            Some(idx) => self.completion_candidates[idx].clone(),
+            None => self.insert_completion.as_ref().map(|s| s.original.clone()).unwrap_or_default(),
+        };
+        self.replace_completion_text(&text);
+        self.show_insert_completion_status();
+    }
+
+    fn replace_completion_text(&mut self, text: &str) {
+        let Some(state) = &self.insert_completion else {
+            return;
+        };
+        let (row, start) = (state.row, state.start_col);
+        let end = self.cursor_col.max(start);
+        self.record_undo();
+        self.touch_edit();
+        self.clear_line_undo();
+        self.text.remove((row, start), (row, end));
+        self.text.insert(row, start, text);
+        self.cursor_col = start + text.chars().count();
+        self.dirty = true;
+    }
+
+    fn show_insert_completion_status(&mut self) {
+        let Some(state) = &self.insert_completion else {
+            return;
+        };
+        let name = state.sources[0].name();
+        let total = self.completion_candidates.len();
+        match self.completion_index {
+            Some(idx) => self.set_status(format!("-- {} completion match {} of {}", name, idx + 1, total)),
+            None => self.set_status(format!("-- {} completion Back at original", name)),
+        }
+    }
+
+    pub(crate) fn refresh_insert_completion(&mut self) {
+        let Some(state) = &self.insert_completion else {
+            return;
+        };
+        let line = self.cursor_line_chars();
+        let col = self.cursor_col.min(line.len());
+        if self.cursor_row != state.row || col < state.start_col {
+            self.close_insert_completion();
+            return;
+        }
+        let start_col = state.sources[0].start_col(&line, col);
+        if start_col != state.start_col {
+            let sources = state.sources.clone();
+            self.close_insert_completion();
+            self.start_insert_completion(sources, true);
+            return;
+        }
+        if let Some(state) = &mut self.insert_completion {
+            state.original = line[start_col..col].iter().collect();
+        }
+        if self.rank_insert_completion() {
+            self.show_insert_completion_status();
+        }
+    }
+
+    pub(crate) fn close_insert_completion(&mut self) {
+        if self.insert_completion.take().is_some() {
+            self.clear_completion();
+        }
+    }
+
+    pub(crate) fn cancel_insert_completion(&mut self) {
+        if let Some(original) = self.insert_completion.as_ref().map(|s| s.original.clone()) {
+            self.replace_completion_text(&original);
+        }
+        self.close_insert_completion();
+    }
+
+    pub(crate) fn insert_completion_anchor(&self) -> Option<(usize, usize)> {
+        self.insert_completion.as_ref().map(|state| (state.row, state.start_col))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::handle_key;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    fn press(app: &mut App, code: KeyCode, modifiers: KeyModifiers) {
+        handle_key(app, KeyEvent::new(code, modifiers)).unwrap();
+    }
+
+    fn type_text(app: &mut App, text: &str) {
+        for ch in text.chars() {
+            press(app, KeyCode::Char(ch), KeyModifiers::NONE);
+        }
+    }
+
+    #[test]
+    fn keyword_line_and_path_completion() {
+        let dir = std::env::temp_dir().join(format!("rvim-complete-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+        std::fs::write(dir.join(".hidden"), "").unwrap();
+
+        let mut app = App::new(None, "let counter = 1;\ncount\n".to_string());
+        app.cursor_row = 1;
+        app.cursor_col = 5;
+        app.mode = crate::app::Mode::Insert;
+        press(&mut app, KeyCode::Char('n'), KeyModifiers::CONTROL);
+        assert_eq!(app.text.line(1).unwrap(), "counter");
+        assert_eq!(app.completion_candidates, vec!["counter"]);
+        press(&mut app, KeyCode::Char('n'), KeyModifiers::CONTROL);
+        assert_eq!(app.text.line(1).unwrap(), "count");
+        press(&mut app, KeyCode::Char('p'), KeyModifiers::CONTROL);
+        assert_eq!(app.text.line(1).unwrap(), "counter");
+        press(&mut app, KeyCode::Char('y'), KeyModifiers::CONTROL);
+        assert!(app.insert_completion.is_none());
+        assert_eq!(app.cursor_col, 7);
+
+        press(&mut app, KeyCode::Enter, KeyModifiers::NONE);
+        type_text(&mut app, "  le");
+        press(&mut app, KeyCode::Char('x'), KeyModifiers::CONTROL);
+        press(&mut app, KeyCode::Char('l'), KeyModifiers::CONTROL);
+        assert_eq!(app.text.line(2).unwrap(), "  let counter = 1;");
+        press(&mut app, KeyCode::Char('e'), KeyModifiers::CONTROL);
+        assert_eq!(app.text.line(2).unwrap(), "  le");
+
+        press(&mut app, KeyCode::Enter, KeyModifiers::NONE);
+        type_text(&mut app, &format!("{}/", dir.display()));
+        press(&mut app, KeyCode::Char('x'), KeyModifiers::CONTROL);
+        press(&mut app, KeyCode::Char('f'), KeyModifiers::CONTROL);
+        assert_eq!(app.completion_candidates, vec!["notes.txt", "src/"]);
+        assert_eq!(app.text.line(3).unwrap(), format!("  {}/notes.txt", dir.display()));
+        press(&mut app, KeyCode::Char('p'), KeyModifiers::CONTROL);
+        type_text(&mut app, "s");
+        assert_eq!(app.completion_candidates, vec!["src/", "notes.txt"]);
+        press(&mut app, KeyCode::Char('n'), KeyModifiers::CONTROL);
+        assert_eq!(app.text.line(3).unwrap(), format!("  {}/src/", dir.display()));
+        press(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+        assert!(app.completion_candidates.is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn sources_walk_outward_from_the_cursor_and_stop_at_their_limit() {
+        assert_eq!(rows_outward(2, 5).collect::<Vec<_>>(), vec![2, 1, 3, 0, 4]);
+        assert_eq!(rows_outward(0, 3).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(rows_outward(9, 2).collect::<Vec<_>>(), vec![1, 0]);
+
+        let text: String = (0..KEYWORD_LIMIT * 2).map(|idx| format!("word{}\n", idx)).collect();
+        let mut app = App::new(None, text);
+        app.cursor_row = KEYWORD_LIMIT;
+        let words = BufferKeywords.candidates(&app, &[], 0);
+        assert_eq!(words.len(), KEYWORD_LIMIT);
+        assert_eq!(words[..3], ["word5000", "word4999", "word5001"]);
+        assert_eq!(WholeLines.candidates(&app, &[], 0).len(), WHOLE_LINE_LIMIT);
+    }
+}
@@ -14,6 +14,26 @@ pub struct Config {
     pub(crate) themes: Option<HashMap<String, ThemeOverride>>,
     pub(crate) keymap: Option<KeymapConfig>,
     pub(crate) keymap_debug: Option<bool>,
+    pub(crate) undofile: Option<bool>,
+    pub(crate) undodir: Option<String>,
+    pub(crate) backup: Option<bool>,
+    pub(crate) backupdir: Option<String>,
+    pub(crate) swapfile: Option<bool>,
+    pub(crate) directory: Option<String>,
+    pub(crate) updatetime: Option<u64>,
+    pub(crate) updatecount: Option<u64>,
+    pub(crate) ignorecase: Option<bool>,
+    pub(crate) smartcase: Option<bool>,
+    pub(crate) incsearch: Option<bool>,
+    pub(crate) clipboard: Option<String>,
+    pub(crate) clipboard_provider: Option<ClipboardConfig>,
+    pub(crate) lsp: Option<HashMap<String, LspServerConfig>>,
+    pub(crate) grepprg: Option<String>,
+    pub(crate) makeprg: Option<String>,
+    pub(crate) errorformat: Option<Vec<String>>,
+    pub(crate) plugins: Option<PluginsConfig>,
+    pub(crate) plugin_settings: Option<HashMap<String, toml::Table>>,
+    pub(crate) autocmd: Option<Vec<AutocmdConfig>>,
 }
 
 pub fn load_config() -> Result<Config> {
@@ -59,10 +79,45 @@ pub(crate) struct ThemeOverride {
     pub(crate) syntax_macro: Option<String>,
     pub(crate) syntax_attribute: Option<String>,
     pub(crate) syntax_punctuation: Option<String>,
+    pub(crate) diagnostic_error: Option<String>,
+    pub(crate) diagnostic_warning: Option<String>,
+    pub(crate) diagnostic_info: Option<String>,
+    pub(crate) diagnostic_hint: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Default, Clone)]
+pub(crate) struct ClipboardConfig {
+    pub(crate) provider: Option<String>,
+    pub(crate) copy: Option<Vec<String>>,
+    pub(crate) paste: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize, Default, Clone)]
+pub(crate) struct LspServerConfig {
+    pub(crate) command: Vec<String>,
+    pub(crate) root_markers: Option<Vec<String>>,
+    pub(crate) env: Option<HashMap<String, String>>,
+    pub(crate) settings: Option<toml::Value>,
+}
+
+#[derive(Debug, Deserialize, Default, Clone)]
+pub(crate) struct PluginsConfig {
+    pub(crate) enabled: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize, Default, Clone)]
+pub(crate) struct AutocmdConfig {
+    pub(crate) event: String,
+    pub(crate) pattern: Option<String>,
+    pub(crate) command: Option<String>,
+    pub(crate) shell: Option<String>,
+    pub(crate) filter: Option<bool>,
+    pub(crate) timeout_ms: Option<u64>,
 }
 
 #[derive(Debug, Deserialize, Default, Clone)]
 pub(crate) struct KeymapConfig {
+    pub(crate) leader: Option<String>,
     pub(crate) normal: Option<HashMap<String, String>>,
     pub(crate) insert: Option<HashMap<String, String>>,
     pub(crate) visual: Option<HashMap<String, String>>,
@@ -144,6 +199,18 @@ pub(crate) fn apply_theme_overrides(theme: &mut Theme, overrides: &ThemeOverride
     if let Some(color) = overrides.syntax_punctuation.as_deref().and_then(parse_color) {
         theme.syntax_punctuation = color;
     }
+    if let Some(color) = overrides.diagnostic_error.as_deref().and_then(parse_color) {
+        theme.diagnostic_error = color;
+    }
+    if let Some(color) = overrides.diagnostic_warning.as_deref().and_then(parse_color) {
+        theme.diagnostic_warning = color;
+    }
+    if let Some(color) = overrides.diagnostic_info.as_deref().and_then(parse_color) {
+        theme.diagnostic_info = color;
+    }
+    if let Some(color) = overrides.diagnostic_hint.as_deref().and_then(parse_color) {
+        theme.diagnostic_hint = color;
+    }
 }
 
 fn parse_color(value: &str) -> Option<Color> {
@@ -1,36 +1,42 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fs;
 use std::path::PathBuf;
 
 use super::motion::char_count_in_range;
 use crossterm::event::{KeyCode, KeyModifiers};
 
+use super::clipboard::{Clipboard, ClipboardProvider};
+use super::completion::BufferKeywords;
+use super::plugin::PluginHost;
+use super::quickfix::DEFAULT_ERROR_FORMAT;
+use super::fileio::decode_text;
+use super::registers::Registers;
+use super::swap::SwapWriter;
+use super::undo::{UndoStep, UndoTree, now_secs};
+use super::window::{DEFAULT_WINDOW_AREA, TabPage, Window};
 use super::types::{
     char_class, char_to_byte_idx, char_to_screen_col, normalize_range, screen_col_to_char_idx,
-    CharClass, CommandPrompt, EditorState, LastVisual, LineUndo, Mode, Operator,
+    CharClass, CommandPrompt, LastVisual, LineUndo, Mode, Operator,
     RepeatKey, VisualSelection, VisualSelectionKind, YankType, char_display_width,
 };
 use super::App;
 
 impl App {
     pub fn new(file_path: Option<std::path::PathBuf>, content: String) -> Self {
-        let mut lines: Vec<String> = content.lines().map(|s| s.to_string()).collect();
-        if lines.is_empty() {
-            lines.push(String::new());
-        }
+        let (text, file_options) = decode_text(&content);
         Self {
-            lines,
+            text,
             cursor_row: 0,
             cursor_col: 0,
             scroll_row: 0,
             scroll_col: 0,
             mode: Mode::Normal,
             file_path,
+            file_options,
             dirty: false,
             status_message: String::new(),
             command_buffer: String::new(),
-            undo_stack: Vec::new(),
-            redo_stack: Vec::new(),
+            undo_tree: UndoTree::new(),
             find_cross_line: true,
             shift_width: 4,
             indent_colon: false,
@@ -39,12 +45,32 @@ impl App {
             theme: super::theme::Theme::default_theme(),
             theme_name: "light".to_string(),
             theme_overrides: None,
-            yank_buffer: String::new(),
-            yank_type: YankType::Char,
+            registers: Registers::default(),
+            clipboard: Clipboard::default(),
+            clipboard_unnamed: false,
+            pending_register: None,
+            awaiting_register: false,
+            insert_register_pending: false,
+            insert_capture: String::new(),
             visual_start: None,
             block_insert: None,
             last_visual: None,
+            global_marks: HashMap::new(),
+            jumplist: Vec::new(),
+            jump_index: 0,
+            pending_mark_jump: None,
+            pending_mark: false,
+            pending_macro_record: false,
+            pending_macro_play: None,
+            macro_recording: None,
+            macro_keys: Vec::new(),
+            macro_queue: VecDeque::new(),
+            macro_running: false,
+            macro_failed: false,
+            macro_expansions: 0,
+            last_macro: None,
             insert_undo_snapshot: false,
+            undo_grouped: false,
             pending_find: None,
             pending_g: false,
             operator_pending: None,
@@ -53,8 +79,21 @@ impl App {
             quit_confirm: false,
             status_time: None,
             undo_limit: 200,
+            undo_file: false,
+            undo_dir: None,
+            backup: false,
+            backup_dir: None,
+            swap_file: true,
+            swap_dir: None,
+            update_time: 4000,
+            update_count: 200,
+            swap_tick: 0,
+            swap_prompt: None,
+            swap_writer: SwapWriter::default(),
+            substitute_confirm: None,
+            last_input: std::time::Instant::now(),
+            read_only: false,
             line_undo: None,
-            is_restoring: false,
             command_prompt: CommandPrompt::Command,
             command_history: Vec::new(),
             command_history_index: None,
@@ -74,6 +113,12 @@ impl App {
             last_search: None,
             search_history: Vec::new(),
             search_history_index: None,
+            search_count: None,
+            ignore_case: false,
+            smart_case: false,
+            incsearch: true,
+            incsearch_origin: None,
+            incsearch_pattern: None,
             repeat_recording: false,
             repeat_replaying: false,
             repeat_changed: false,
@@ -90,6 +135,33 @@ impl App {
             completion_anchor_col: None,
             edit_tick: 0,
             syntax_by_buffer: HashMap::new(),
+            windows: vec![Window::new(1, 1)],
+            tabs: vec![TabPage::new(1)],
+            current_tab: 0,
+            next_window_id: 2,
+            window_area: DEFAULT_WINDOW_AREA,
+            pending_window: None,
+            lsp_servers: HashMap::new(),
+            lsp_clients: HashMap::new(),
+            lsp_failed: HashMap::new(),
+            lsp_code_actions: Vec::new(),
+            diagnostics: HashMap::new(),
+            pending_ctrl_x: false,
+            completion_sources: vec![std::rc::Rc::new(BufferKeywords)],
+            insert_completion: None,
+            file_picker: None,
+            quickfix: Vec::new(),
+            quickfix_index: 0,
+            quickfix_title: String::new(),
+            quickfix_job: None,
+            quickfix_buffer: None,
+            grepprg: None,
+            makeprg: "make".to_string(),
+            errorformat: DEFAULT_ERROR_FORMAT.iter().map(|format| format.to_string()).collect(),
+            explorer: None,
+            plugins: PluginHost::default(),
+            autocmds: Vec::new(),
+            firing_event: false,
         }
     }
 
@@ -142,46 +214,41 @@ impl App {
 
     pub fn capture_buffer_state(&self) -> super::types::BufferState {
         super::types::BufferState {
-            lines: self.lines.clone(),
+            text: self.text.clone(),
             cursor_row: self.cursor_row,
             cursor_col: self.cursor_col,
             scroll_row: self.scroll_row,
             scroll_col: self.scroll_col,
             file_path: self.file_path.clone(),
+            file_options: self.file_options,
             dirty: self.dirty,
-            undo_stack: self.undo_stack.clone(),
-            redo_stack: self.redo_stack.clone(),
+            undo_tree: self.undo_tree.clone(),
             line_undo: self.line_undo.clone(),
-            is_restoring: self.is_restoring,
             change_tick: self.change_tick,
             edit_tick: self.edit_tick,
+            swap_tick: self.swap_tick,
+            read_only: self.read_only,
         }
     }
 
     pub fn load_buffer_state(&mut self, state: super::types::BufferState) {
-        self.lines = state.lines;
-        if self.lines.is_empty() {
-            self.lines.push(String::new());
-        }
-        self.cursor_row = state.cursor_row.min(self.lines.len().saturating_sub(1));
-        let current_line_len = self
-            .lines
-            .get(self.cursor_row)
-            .map(|line| line.chars().count())
-            .unwrap_or(0);
+        self.text = state.text;
+        self.cursor_row = state.cursor_row.min(self.text.line_count().saturating_sub(1));
+        let current_line_len = self.text.line_len(self.cursor_row);
         self.cursor_col = state.cursor_col.min(current_line_len);
         self.scroll_row = state
             .scroll_row
-            .min(self.lines.len().saturating_sub(1));
+            .min(self.text.line_count().saturating_sub(1));
         self.scroll_col = state.scroll_col;
         self.file_path = state.file_path;
+        self.file_options = state.file_options;
         self.dirty = state.dirty;
-        self.undo_stack = state.undo_stack;
-        self.redo_stack = state.redo_stack;
+        self.undo_tree = state.undo_tree;
         self.line_undo = state.line_undo;
-        self.is_restoring = state.is_restoring;
         self.change_tick = state.change_tick;
         self.edit_tick = state.edit_tick;
+        self.swap_tick = state.swap_tick;
+        self.read_only = state.read_only;
     }
 
     pub fn reset_transient_for_switch(&mut self) {
@@ -191,6 +258,9 @@ impl App {
         self.command_cursor = 0;
         self.command_keep_open = false;
         self.search_history_index = None;
+        self.search_count = None;
+        self.incsearch_origin = None;
+        self.incsearch_pattern = None;
         self.pending_count = None;
         self.visual_start = None;
         self.block_insert = None;
@@ -205,11 +275,13 @@ impl App {
         self.repeat_replaying = false;
         self.repeat_changed = false;
         self.repeat_buffer.clear();
+        self.insert_completion = None;
         self.clear_completion();
     }
 
     pub(crate) fn touch_edit(&mut self) {
         self.edit_tick = self.edit_tick.wrapping_add(1);
+        self.text.set_mark('.', (self.cursor_row, self.cursor_col));
     }
 
     pub fn clear_completion(&mut self) {
@@ -271,22 +343,68 @@ impl App {
 
     pub fn apply_config(&mut self, config: &super::config::Config) {
         self.theme_overrides = config.themes.clone();
-        if let Some(name) = config.theme.as_deref() {
-            if let Some(theme) = super::theme::Theme::from_name(name) {
+        if let Some(name) = config.theme.as_deref()
+            && let Some(theme) = super::theme::Theme::from_name(name) {
                 self.set_theme_named(name, theme);
             }
-        }
         self.keymap_debug = config.keymap_debug.unwrap_or(false);
+        self.undo_file = config.undofile.unwrap_or(false);
+        self.undo_dir = config
+            .undodir
+            .as_deref()
+            .map(|dir| PathBuf::from(super::input::expand_tilde_path(dir)));
+        self.backup = config.backup.unwrap_or(false);
+        self.backup_dir = config
+            .backupdir
+            .as_deref()
+            .map(|dir| PathBuf::from(super::input::expand_tilde_path(dir)));
+        self.swap_file = config.swapfile.unwrap_or(true);
+        self.swap_dir = config
+            .directory
+            .as_deref()
+            .map(|dir| PathBuf::from(super::input::expand_tilde_path(dir)));
+        if let Some(ms) = config.updatetime {
+            self.update_time = ms;
+        }
+        if let Some(count) = config.updatecount {
+            self.update_count = count;
+        }
+        self.ignore_case = config.ignorecase.unwrap_or(false);
+        self.smart_case = config.smartcase.unwrap_or(false);
+        self.incsearch = config.incsearch.unwrap_or(true);
+        if let Err(err) = self.set_clipboard_option(config.clipboard.as_deref().unwrap_or("")) {
+            self.set_status(err);
+        }
+        if let Some(provider) = config.clipboard_provider.as_ref() {
+            match ClipboardProvider::from_config(provider) {
+                Ok(provider) => self.clipboard.provider = provider,
+                Err(err) => self.set_status(err),
+            }
+        }
+        if let Some(servers) = config.lsp.clone() {
+            self.lsp_servers = servers;
+        }
+        self.grepprg = config.grepprg.clone().filter(|prg| !prg.trim().is_empty());
+        if let Some(prg) = config.makeprg.clone() {
+            self.makeprg = prg;
+        }
+        if let Some(formats) = config.errorformat.clone() {
+            self.errorformat = formats;
+        }
+        self.plugins.configure(config);
+        self.set_autocmds(config.autocmd.as_deref().unwrap_or_default());
         if self.keymap_debug {
             self.set_status("Keymap debug: on");
         }
         let (keymaps, errors) = super::keymap::Keymaps::from_config(config.keymap.as_ref());
         self.keymaps = keymaps;
+        self.apply_plugin_keymaps();
         if let Some(err) = errors.first() {
             self.set_status(format!("Keymap error: {}", err));
         }
     }
 
+    #[allow(dead_code)]
     pub fn keymap_entries(&self, mode: Option<&'static str>) -> Vec<super::keymap::KeymapEntry> {
         self.keymaps.entries(mode)
     }
@@ -314,12 +432,13 @@ impl App {
     }
 
     pub fn clear_status_if_stale(&mut self) {
-        if let Some(t) = self.status_time {
-            if t.elapsed() > std::time::Duration::from_secs(5) {
+        if let Some(t) = self.status_time
+            && self.swap_prompt.is_none()
+            && self.substitute_confirm.is_none()
+            && t.elapsed() > std::time::Duration::from_secs(5) {
                 self.status_message.clear();
                 self.status_time = None;
             }
-        }
     }
 
     pub fn push_perf_sample(&mut self, micros: u128) {
@@ -425,14 +544,14 @@ impl App {
             self.scroll_row = self.cursor_row.saturating_sub(viewport_rows - 1);
         }
 
-        let line = self.lines.get(self.cursor_row).map(|s| s.as_str()).unwrap_or("");
-        let cursor_screen = char_to_screen_col(line, self.cursor_col, self.shift_width);
-        let scroll_screen = char_to_screen_col(line, self.scroll_col, self.shift_width);
+        let line = self.text.line(self.cursor_row).unwrap_or_default();
+        let cursor_screen = char_to_screen_col(&line, self.cursor_col, self.shift_width);
+        let scroll_screen = char_to_screen_col(&line, self.scroll_col, self.shift_width);
         if cursor_screen < scroll_screen {
-            self.scroll_col = screen_col_to_char_idx(line, cursor_screen, self.shift_width);
+            self.scroll_col = screen_col_to_char_idx(&line, cursor_screen, self.shift_width);
         } else if cursor_screen >= scroll_screen + viewport_cols {
             let target = cursor_screen.saturating_sub(viewport_cols - 1);
-            self.scroll_col = screen_col_to_char_idx(line, target, self.shift_width);
+            self.scroll_col = screen_col_to_char_idx(&line, target, self.shift_width);
         }
     }
 
@@ -470,9 +589,9 @@ impl App {
             VisualSelectionKind::Line(start, end) => format!("{} lines", end - start + 1),
             VisualSelectionKind::Block { start, end } => {
                 let rows = end.0 - start.0 + 1;
-                let base_line = self.lines.get(start.0).map(|s| s.as_str()).unwrap_or("");
-                let start_sc = char_to_screen_col(base_line, start.1, self.shift_width);
-                let end_sc = char_to_screen_col(base_line, end.1, self.shift_width);
+                let base_line = self.text.line(start.0).unwrap_or_default();
+                let start_sc = char_to_screen_col(&base_line, start.1, self.shift_width);
+                let end_sc = char_to_screen_col(&base_line, end.1, self.shift_width);
                 let end_char = base_line.chars().nth(end.1).unwrap_or(' ');
                 let end_w = char_display_width(end_char, end_sc, self.shift_width);
                 let (a, b) = if start_sc <= end_sc {
@@ -518,7 +637,7 @@ impl App {
     }
 
     pub(super) fn textobj_word_range(&self) -> Option<((usize, usize), (usize, usize))> {
-        let line = self.lines.get(self.cursor_row)?;
+        let line = self.text.line(self.cursor_row)?;
         let chars: Vec<char> = line.chars().collect();
         if self.cursor_col >= chars.len() {
             return None;
@@ -542,7 +661,7 @@ impl App {
         if row != row2 {
             return Some(((row, start), (row2, end)));
         }
-        let line = self.lines.get(row)?;
+        let line = self.text.line(row)?;
         let chars: Vec<char> = line.chars().collect();
         let len = chars.len();
         let mut new_start = start;
@@ -569,16 +688,8 @@ impl App {
         let (r_row, r_col) = self.find_matching_close_from(open, close, l_row, l_col)?;
         match kind {
             super::types::TextObjectKind::Inner => {
-                let start = if let Some(next) = self.advance_pos(l_row, l_col) {
-                    next
-                } else {
-                    return None;
-                };
-                let end = if let Some(prev) = self.prev_pos(r_row, r_col) {
-                    prev
-                } else {
-                    return None;
-                };
+                let start = self.advance_pos(l_row, l_col)?;
+                let end = self.prev_pos(r_row, r_col)?;
                 Some((start, end))
             }
             super::types::TextObjectKind::Around => Some(((l_row, l_col), (r_row, r_col))),
@@ -594,16 +705,8 @@ impl App {
         let (r_row, r_col) = self.find_matching_quote_from(quote, l_row, l_col)?;
         match kind {
             super::types::TextObjectKind::Inner => {
-                let start = if let Some(next) = self.advance_pos(l_row, l_col) {
-                    next
-                } else {
-                    return None;
-                };
-                let end = if let Some(prev) = self.prev_pos(r_row, r_col) {
-                    prev
-                } else {
-                    return None;
-                };
+                let start = self.advance_pos(l_row, l_col)?;
+                let end = self.prev_pos(r_row, r_col)?;
                 Some((start, end))
             }
             super::types::TextObjectKind::Around => Some(((l_row, l_col), (r_row, r_col))),
@@ -660,13 +763,9 @@ impl App {
         let mut depth = 0i32;
         let mut r = row;
         let mut c = col;
-        loop {
-            if let Some((nr, nc)) = self.advance_pos(r, c) {
-                r = nr;
-                c = nc;
-            } else {
-                break;
-            }
+        while let Some((nr, nc)) = self.advance_pos(r, c) {
+            r = nr;
+            c = nc;
             if let Some(ch) = self.char_at(r, c) {
                 if ch == open {
                     depth += 1;
@@ -685,9 +784,9 @@ impl App {
         let mut r = self.cursor_row;
         let mut c = self.cursor_col;
         loop {
-            if let Some(ch) = self.char_at(r, c) {
-                if ch == '<' {
-                    if let Some(open) = self.parse_tag_at(r, c) {
+            if let Some(ch) = self.char_at(r, c)
+                && ch == '<'
+                    && let Some(open) = self.parse_tag_at(r, c) {
                         if open.is_closing || open.is_self_closing {
                             // skip closing/self-closing
                         } else {
@@ -706,8 +805,6 @@ impl App {
                             }
                         }
                     }
-                }
-            }
             if let Some((pr, pc)) = self.prev_pos(r, c) {
                 r = pr;
                 c = pc;
@@ -722,16 +819,12 @@ impl App {
         let mut depth = 0i32;
         let mut r = start.0;
         let mut c = start.1;
-        loop {
-            if let Some((nr, nc)) = self.advance_pos(r, c) {
-                r = nr;
-                c = nc;
-            } else {
-                break;
-            }
-            if let Some(ch) = self.char_at(r, c) {
-                if ch == '<' {
-                    if let Some(tag) = self.parse_tag_at(r, c) {
+        while let Some((nr, nc)) = self.advance_pos(r, c) {
+            r = nr;
+            c = nc;
+            if let Some(ch) = self.char_at(r, c)
+                && ch == '<'
+                    && let Some(tag) = self.parse_tag_at(r, c) {
                         if tag.name != name {
                             continue;
                         }
@@ -750,8 +843,6 @@ impl App {
                             depth += 1;
                         }
                     }
-                }
-            }
         }
         None
     }
@@ -822,41 +913,26 @@ impl App {
         end: (usize, usize),
     ) -> Option<String> {
         let (start, end) = super::types::normalize_range(start, end);
+        if end.0 >= self.text.line_count() {
+            return None;
+        }
         if start.0 == end.0 {
-            let line = self.lines.get(start.0)?;
-            let chars: Vec<char> = line.chars().collect();
-            if start.1 >= chars.len() || end.1 > chars.len() {
+            let len = self.text.line_len(start.0);
+            if start.1 >= len || end.1 > len {
                 return None;
             }
-            return Some(chars[start.1..end.1].iter().collect());
-        }
-        let mut out = String::new();
-        let first = self.lines.get(start.0)?;
-        let first_chars: Vec<char> = first.chars().collect();
-        if start.1 < first_chars.len() {
-            out.extend(first_chars[start.1..].iter());
-        }
-        out.push('\n');
-        for row in (start.0 + 1)..end.0 {
-            out.push_str(self.lines.get(row)?);
-            out.push('\n');
         }
-        let last = self.lines.get(end.0)?;
-        let last_chars: Vec<char> = last.chars().collect();
-        let end_idx = end.1.min(last_chars.len());
-        out.extend(last_chars[..end_idx].iter());
-        Some(out)
+        Some(self.text.slice_to_string(start, end))
     }
 
     fn find_enclosing_quote_left(&self, quote: char) -> Option<(usize, usize)> {
         let mut r = self.cursor_row;
         let mut c = self.cursor_col;
         loop {
-            if let Some(ch) = self.char_at(r, c) {
-                if ch == quote && !self.is_escaped_at(r, c) {
+            if let Some(ch) = self.char_at(r, c)
+                && ch == quote && !self.is_escaped_at(r, c) {
                     return Some((r, c));
                 }
-            }
             if let Some((pr, pc)) = self.prev_pos(r, c) {
                 r = pr;
                 c = pc;
@@ -875,24 +951,19 @@ impl App {
     ) -> Option<(usize, usize)> {
         let mut r = row;
         let mut c = col;
-        loop {
-            if let Some((nr, nc)) = self.advance_pos(r, c) {
-                r = nr;
-                c = nc;
-            } else {
-                break;
-            }
-            if let Some(ch) = self.char_at(r, c) {
-                if ch == quote && !self.is_escaped_at(r, c) {
+        while let Some((nr, nc)) = self.advance_pos(r, c) {
+            r = nr;
+            c = nc;
+            if let Some(ch) = self.char_at(r, c)
+                && ch == quote && !self.is_escaped_at(r, c) {
                     return Some((r, c));
                 }
-            }
         }
         None
     }
 
     fn is_escaped_at(&self, row: usize, col: usize) -> bool {
-        let line = match self.lines.get(row) {
+        let line = match self.text.line(row) {
             Some(l) => l,
             None => return false,
         };
@@ -908,89 +979,179 @@ impl App {
         self.status_time = Some(std::time::Instant::now());
     }
 
-    pub(super) fn snapshot(&self) -> EditorState {
-        EditorState {
-            lines: self.lines.clone(),
-            cursor_row: self.cursor_row,
-            cursor_col: self.cursor_col,
-            scroll_row: self.scroll_row,
-            scroll_col: self.scroll_col,
-            dirty: self.dirty,
+    pub(super) fn record_undo(&mut self) {
+        if self.undo_grouped || (self.mode == Mode::Insert && self.insert_undo_snapshot) {
+            return;
+        }
+        self.change_tick = self.change_tick.wrapping_add(1);
+        self.commit_undo();
+        self.undo_tree.begin((self.cursor_row, self.cursor_col));
+        if self.mode == Mode::Insert {
+            self.insert_undo_snapshot = true;
         }
     }
 
-    pub(super) fn restore(&mut self, state: EditorState) {
-        self.is_restoring = true;
-        self.lines = state.lines;
-        if self.lines.is_empty() {
-            self.lines.push(String::new());
+    pub(super) fn commit_undo(&mut self) {
+        if self.undo_grouped {
+            return;
         }
-        self.cursor_row = state.cursor_row.min(self.lines.len().saturating_sub(1));
-        let len = self.line_len(self.cursor_row);
-        self.cursor_col = state.cursor_col.min(len);
-        self.scroll_row = state.scroll_row;
-        self.scroll_col = state.scroll_col;
-        self.dirty = state.dirty;
-        self.pending_g = false;
-        self.pending_find = None;
-        self.operator_pending = None;
-        self.block_insert = None;
-        self.visual_start = None;
-        self.line_undo = None;
-        self.is_restoring = false;
+        let edits = self.text.take_changes();
+        self.undo_tree
+            .commit(edits, (self.cursor_row, self.cursor_col), self.undo_limit);
     }
 
-    pub(super) fn record_undo(&mut self) {
-        if self.is_restoring {
-            return;
+    pub(super) fn begin_undo_group(&mut self) -> bool {
+        if self.undo_grouped {
+            return false;
         }
-        if self.mode == Mode::Insert && self.insert_undo_snapshot {
-            return;
+        self.record_undo();
+        self.undo_grouped = true;
+        true
+    }
+
+    pub(super) fn end_undo_group(&mut self) {
+        self.undo_grouped = false;
+        self.commit_undo();
+    }
+
+    pub(super) fn undo(&mut self) {
+        self.commit_undo();
+        match self.undo_tree.undo_target() {
+            Some(target) => {
+                self.undo_to(target);
+            }
+            None => self.set_status("Already at oldest change"),
         }
-        self.change_tick = self.change_tick.wrapping_add(1);
-        self.undo_stack.push(self.snapshot());
-        if self.undo_stack.len() > self.undo_limit {
-            let overflow = self.undo_stack.len() - self.undo_limit;
-            self.undo_stack.drain(0..overflow);
+    }
+
+    pub(super) fn redo(&mut self) {
+        self.commit_undo();
+        match self.undo_tree.redo_target() {
+            Some(target) => {
+                self.undo_to(target);
+            }
+            None => self.set_status("Already at newest change"),
         }
-        self.redo_stack.clear();
-        if self.mode == Mode::Insert {
-            self.insert_undo_snapshot = true;
+    }
+
+    pub(super) fn undo_chrono(&mut self, steps: usize, forward: bool) {
+        self.commit_undo();
+        let mut target = self.undo_tree.current();
+        for _ in 0..steps {
+            let next = if forward {
+                self.undo_tree.next_seq_from(target)
+            } else {
+                self.undo_tree.prev_seq_from(target)
+            };
+            match next {
+                Some(seq) => target = seq,
+                None => break,
+            }
+        }
+        if target == self.undo_tree.current() {
+            let edge = if forward { "newest" } else { "oldest" };
+            self.set_status(format!("Already at {} change", edge));
+            return;
         }
+        self.undo_to(target);
+        self.set_status(format!("Undo state #{}", target));
     }
 
-    pub(super) fn undo(&mut self) {
-        if let Some(state) = self.undo_stack.pop() {
-            let current = self.snapshot();
-            self.redo_stack.push(current);
-            self.restore(state);
-            self.insert_undo_snapshot = false;
-            self.touch_edit();
+    pub(super) fn undo_by_time(&mut self, secs: u64, forward: bool) {
+        self.commit_undo();
+        let base = self
+            .undo_tree
+            .node(self.undo_tree.current())
+            .map(|node| node.time)
+            .unwrap_or_else(now_secs);
+        let time = if forward {
+            base.saturating_add(secs)
+        } else {
+            base.saturating_sub(secs)
+        };
+        let target = self.undo_tree.seq_before_time(time);
+        if target == self.undo_tree.current() {
+            let edge = if forward { "newest" } else { "oldest" };
+            self.set_status(format!("Already at {} change", edge));
+            return;
         }
+        self.undo_to(target);
+        self.set_status(format!("Undo state #{}", target));
     }
 
-    pub(super) fn redo(&mut self) {
-        if let Some(state) = self.redo_stack.pop() {
-            let current = self.snapshot();
-            self.undo_stack.push(current);
-            self.restore(state);
-            self.insert_undo_snapshot = false;
-            self.touch_edit();
+    fn undo_to(&mut self, target: usize) -> bool {
+        let steps = self.undo_tree.steps_to(target);
+        if steps.is_empty() {
+            return false;
+        }
+        let mut cursor = (self.cursor_row, self.cursor_col);
+        for step in steps {
+            let (seq, revert) = match step {
+                UndoStep::Revert(seq) => (seq, true),
+                UndoStep::Apply(seq) => (seq, false),
+            };
+            let Some(node) = self.undo_tree.node(seq) else {
+                continue;
+            };
+            if revert {
+                for edit in node.edits.iter().rev() {
+                    self.text.revert_edit(edit);
+                }
+            } else {
+                for edit in &node.edits {
+                    self.text.apply_edit(edit);
+                }
+            }
+            cursor = node.cursor;
+        }
+        self.text.take_changes();
+        self.undo_tree.set_current(target);
+        self.cursor_row = cursor.0.min(self.text.line_count().saturating_sub(1));
+        self.cursor_col = cursor.1.min(self.line_len(self.cursor_row));
+        self.dirty = !self.undo_tree.is_saved();
+        self.pending_g = false;
+        self.pending_find = None;
+        self.operator_pending = None;
+        self.block_insert = None;
+        self.visual_start = None;
+        self.line_undo = None;
+        self.insert_undo_snapshot = false;
+        self.touch_edit();
+        true
+    }
+
+    pub(super) fn undo_list_lines(&self) -> Vec<String> {
+        let now = now_secs();
+        let mut out = vec!["number changes  when".to_string()];
+        for node in self.undo_tree.leaves() {
+            let mut changes = 0;
+            let mut seq = Some(node.seq);
+            while let Some(cur) = seq.and_then(|seq| self.undo_tree.node(seq)) {
+                if cur.parent.is_some() {
+                    changes += 1;
+                }
+                seq = cur.parent;
+            }
+            let age = now.saturating_sub(node.time);
+            let when = if age < 100 {
+                format!("{} seconds ago", age)
+            } else {
+                chrono::DateTime::from_timestamp(node.time as i64, 0)
+                    .map(|t| t.with_timezone(&chrono::Local).format("%H:%M:%S").to_string())
+                    .unwrap_or_default()
+            };
+            let marker = if node.seq == self.undo_tree.current() { "*" } else { " " };
+            out.push(format!("{:>6}{}{:>7}  {}", node.seq, marker, changes, when));
         }
+        out
     }
 
     pub(super) fn set_line_undo(&mut self, row: usize) {
-        if row >= self.lines.len() {
+        if matches!(self.line_undo, Some(ref lu) if lu.row == row) {
             return;
         }
-        match self.line_undo {
-            Some(ref lu) if lu.row == row => {}
-            _ => {
-                self.line_undo = Some(LineUndo {
-                    row,
-                    line: self.lines[row].clone(),
-                });
-            }
+        if let Some(line) = self.text.line(row) {
+            self.line_undo = Some(LineUndo { row, line });
         }
     }
 
@@ -1003,12 +1164,12 @@ impl App {
             self.set_status("No line undo");
             return;
         };
-        if lu.row >= self.lines.len() {
+        if lu.row >= self.text.line_count() {
             return;
         }
         self.record_undo();
         self.touch_edit();
-        self.lines[lu.row] = lu.line;
+        self.text.set_line(lu.row, &lu.line);
         self.cursor_row = lu.row;
         let len = self.line_len(self.cursor_row);
         self.cursor_col = self.cursor_col.min(len);
@@ -1018,77 +1179,52 @@ impl App {
     pub(super) fn insert_char(&mut self, ch: char) {
         self.record_undo();
         self.touch_edit();
-        if let Some(block) = &mut self.block_insert {
-            for row in block.start_row..=block.end_row {
-                if row >= self.lines.len() {
-                    break;
-                }
-                let line = &mut self.lines[row];
-                let col = if block.append {
-                    line.chars().count()
-                } else {
-                    block.col
-                };
-                let len = line.chars().count();
-                if col > len {
-                    line.push_str(&" ".repeat(col - len));
-                }
-                let byte_idx = char_to_byte_idx(line, col);
-                line.insert(byte_idx, ch);
-            }
-            block.col += 1;
-            self.cursor_col = block.col;
-            self.dirty = true;
+        if self.block_insert.is_some() {
+            self.block_insert_char(ch);
             return;
         }
         self.set_line_undo(self.cursor_row);
-        let line = &mut self.lines[self.cursor_row];
-        let byte_idx = char_to_byte_idx(line, self.cursor_col);
-        line.insert(byte_idx, ch);
+        self.text.insert_char(self.cursor_row, self.cursor_col, ch);
         self.cursor_col += 1;
         self.dirty = true;
     }
 
     pub(super) fn insert_char_raw(&mut self, ch: char) {
-        if let Some(block) = &mut self.block_insert {
-            for row in block.start_row..=block.end_row {
-                if row >= self.lines.len() {
-                    break;
-                }
-                let line = &mut self.lines[row];
-                let col = if block.append {
-                    line.chars().count()
-                } else {
-                    block.col
-                };
-                let len = line.chars().count();
-                if col > len {
-                    line.push_str(&" ".repeat(col - len));
-                }
-                let byte_idx = char_to_byte_idx(line, col);
-                line.insert(byte_idx, ch);
-            }
-            block.col += 1;
-            self.cursor_col = block.col;
-            self.dirty = true;
+        if self.block_insert.is_some() {
+            self.block_insert_char(ch);
             return;
         }
-        let line = &mut self.lines[self.cursor_row];
-        let byte_idx = char_to_byte_idx(line, self.cursor_col);
-        line.insert(byte_idx, ch);
+        self.text.insert_char(self.cursor_row, self.cursor_col, ch);
         self.cursor_col += 1;
         self.dirty = true;
     }
 
+    fn block_insert_char(&mut self, ch: char) {
+        let Some(block) = &mut self.block_insert else {
+            return;
+        };
+        for row in block.start_row..=block.end_row {
+            if row >= self.text.line_count() {
+                break;
+            }
+            let len = self.text.line_len(row);
+            let col = if block.append { len } else { block.col };
+            if col > len {
+                self.text.insert(row, len, &" ".repeat(col - len));
+            }
+            self.text.insert_char(row, col, ch);
+        }
+        block.col += 1;
+        self.cursor_col = block.col;
+        self.dirty = true;
+    }
+
     pub(super) fn insert_newline_raw(&mut self) {
         if self.block_insert.is_some() {
             self.block_insert_newline();
             return;
         }
-        let line = &mut self.lines[self.cursor_row];
-        let byte_idx = char_to_byte_idx(line, self.cursor_col);
-        let right = line.split_off(byte_idx);
-        self.lines.insert(self.cursor_row + 1, right);
+        self.text.split_line(self.cursor_row, self.cursor_col);
         self.cursor_row += 1;
         self.cursor_col = 0;
         self.dirty = true;
@@ -1102,22 +1238,22 @@ impl App {
             return;
         }
         self.clear_line_undo();
-        let line = &mut self.lines[self.cursor_row];
-        let byte_idx = char_to_byte_idx(line, self.cursor_col);
-        let right = line.split_off(byte_idx);
-        let mut indent = Self::leading_whitespace(line);
-        if Self::should_increase_indent(line, self.indent_colon) {
+        let line = self.text.line(self.cursor_row).unwrap_or_default();
+        let byte_idx = char_to_byte_idx(&line, self.cursor_col);
+        let (left, right) = line.split_at(byte_idx);
+        let mut indent = Self::leading_whitespace(left);
+        if Self::should_increase_indent(left, self.indent_colon) {
             indent = Self::increase_indent(&indent, self.shift_width);
-        } else if Self::should_decrease_indent(&right) {
+        } else if Self::should_decrease_indent(right) {
             if let Some(target) = self.matching_indent_for_closer(self.cursor_row + 1, 0) {
                 indent = " ".repeat(target);
             } else {
                 indent = Self::decrease_indent(&indent, self.shift_width);
             }
         }
-        let mut new_line = indent.clone();
-        new_line.push_str(&right);
-        self.lines.insert(self.cursor_row + 1, new_line);
+        let mut inserted = String::from("\n");
+        inserted.push_str(&indent);
+        self.text.insert(self.cursor_row, self.cursor_col, &inserted);
         self.cursor_row += 1;
         self.cursor_col = indent.chars().count();
         self.dirty = true;
@@ -1132,17 +1268,13 @@ impl App {
             }
             let target_col = block.col - 1;
             for row in block.start_row..=block.end_row {
-                if row >= self.lines.len() {
+                if row >= self.text.line_count() {
                     break;
                 }
-                let line = &mut self.lines[row];
-                let len = line.chars().count();
-                if target_col >= len {
+                if target_col >= self.text.line_len(row) {
                     continue;
                 }
-                let byte_idx = char_to_byte_idx(line, target_col);
-                let next_idx = char_to_byte_idx(line, target_col + 1);
-                line.replace_range(byte_idx..next_idx, "");
+                self.text.remove((row, target_col), (row, target_col + 1));
             }
             block.col -= 1;
             self.cursor_col = block.col;
@@ -1151,20 +1283,17 @@ impl App {
         }
         if self.cursor_col > 0 {
             self.set_line_undo(self.cursor_row);
-            let line = &mut self.lines[self.cursor_row];
-            let byte_idx = char_to_byte_idx(line, self.cursor_col);
-            let prev_idx = char_to_byte_idx(line, self.cursor_col - 1);
-            line.replace_range(prev_idx..byte_idx, "");
+            self.text.remove(
+                (self.cursor_row, self.cursor_col - 1),
+                (self.cursor_row, self.cursor_col),
+            );
             self.cursor_col -= 1;
             self.dirty = true;
         } else if self.cursor_row > 0 {
             self.clear_line_undo();
-            let current = self.lines.remove(self.cursor_row);
             self.cursor_row -= 1;
-            let prev_line = &mut self.lines[self.cursor_row];
-            let prev_len = prev_line.chars().count();
-            prev_line.push_str(&current);
-            self.cursor_col = prev_len;
+            self.cursor_col = self.text.line_len(self.cursor_row);
+            self.text.join_line(self.cursor_row);
             self.dirty = true;
         }
     }
@@ -1177,18 +1306,19 @@ impl App {
         }
         let len = self.line_len(self.cursor_row);
         if self.cursor_col < len {
-            self.yank_range((self.cursor_row, self.cursor_col), (self.cursor_row, self.cursor_col));
+            if self.mode != Mode::Insert {
+                let cursor = (self.cursor_row, self.cursor_col);
+                self.register_range(cursor, cursor, true);
+            }
             self.set_line_undo(self.cursor_row);
-            let line = &mut self.lines[self.cursor_row];
-            let byte_idx = char_to_byte_idx(line, self.cursor_col);
-            let next_idx = char_to_byte_idx(line, self.cursor_col + 1);
-            line.replace_range(byte_idx..next_idx, "");
+            self.text.remove(
+                (self.cursor_row, self.cursor_col),
+                (self.cursor_row, self.cursor_col + 1),
+            );
             self.dirty = true;
-        } else if self.cursor_row + 1 < self.lines.len() {
+        } else if self.cursor_row + 1 < self.text.line_count() {
             self.clear_line_undo();
-            let next = self.lines.remove(self.cursor_row + 1);
-            let line = &mut self.lines[self.cursor_row];
-            line.push_str(&next);
+            self.text.join_line(self.cursor_row);
             self.dirty = true;
         }
     }
@@ -1197,12 +1327,12 @@ impl App {
         self.record_undo();
         self.touch_edit();
         self.clear_line_undo();
-        let line = &self.lines[self.cursor_row];
-        let mut indent = Self::leading_whitespace(line);
-        if Self::should_increase_indent(line, self.indent_colon) {
+        let line = self.text.line(self.cursor_row).unwrap_or_default();
+        let mut indent = Self::leading_whitespace(&line);
+        if Self::should_increase_indent(&line, self.indent_colon) {
             indent = Self::increase_indent(&indent, self.shift_width);
         }
-        self.lines.insert(self.cursor_row + 1, indent.clone());
+        self.text.insert_line(self.cursor_row + 1, &indent);
         self.cursor_row += 1;
         self.cursor_col = indent.chars().count();
         self.dirty = true;
@@ -1212,16 +1342,16 @@ impl App {
         self.record_undo();
         self.touch_edit();
         self.clear_line_undo();
-        let line = &self.lines[self.cursor_row];
-        let mut indent = Self::leading_whitespace(line);
-        if Self::should_decrease_indent(line) {
+        let line = self.text.line(self.cursor_row).unwrap_or_default();
+        let mut indent = Self::leading_whitespace(&line);
+        if Self::should_decrease_indent(&line) {
             if let Some(target) = self.matching_indent_for_closer(self.cursor_row, 0) {
                 indent = " ".repeat(target);
             } else {
                 indent = Self::decrease_indent(&indent, self.shift_width);
             }
         }
-        self.lines.insert(self.cursor_row, indent.clone());
+        self.text.insert_line(self.cursor_row, &indent);
         self.cursor_col = indent.chars().count();
         self.dirty = true;
     }
@@ -1233,39 +1363,23 @@ impl App {
         if start.0 == end.0 {
             self.set_line_undo(start.0);
             let row = start.0;
-            let line = &mut self.lines[row];
-            let len = line.chars().count();
+            let len = self.text.line_len(row);
             if len == 0 {
                 return;
             }
-            let start_idx = char_to_byte_idx(line, start.1);
             let end_col = end.1.min(len.saturating_sub(1));
-            let end_idx = if end_col + 1 <= len {
-                char_to_byte_idx(line, end_col + 1)
-            } else {
-                line.len()
-            };
-            line.replace_range(start_idx..end_idx, "");
+            self.text.remove((row, start.1), (row, end_col + 1));
         } else {
             self.clear_line_undo();
-            let start_line = &self.lines[start.0];
-            let start_prefix = &start_line[..char_to_byte_idx(start_line, start.1)];
-            let end_line = &self.lines[end.0];
-            let end_len = end_line.chars().count();
-            let end_col = end.1.min(end_len.saturating_sub(1));
-            let end_suffix = if end_len == 0 {
-                ""
+            let end_len = self.text.line_len(end.0);
+            let end_col = if end_len == 0 {
+                0
             } else {
-                &end_line[char_to_byte_idx(end_line, end_col + 1)..]
+                end.1.min(end_len - 1) + 1
             };
-            let merged = format!("{}{}", start_prefix, end_suffix);
-            self.lines[start.0] = merged;
-            self.lines.drain(start.0 + 1..=end.0);
+            self.text.remove(start, (end.0, end_col));
         }
-        if self.lines.is_empty() {
-            self.lines.push(String::new());
-        }
-        self.cursor_row = start.0.min(self.lines.len() - 1);
+        self.cursor_row = start.0.min(self.text.line_count() - 1);
         let len = self.line_len(self.cursor_row);
         self.cursor_col = start.1.min(len);
         self.dirty = true;
@@ -1275,16 +1389,10 @@ impl App {
         self.record_undo();
         self.touch_edit();
         self.clear_line_undo();
-        if self.lines.is_empty() {
-            return;
-        }
-        let start = start_row.min(self.lines.len() - 1);
-        let end = end_row.min(self.lines.len() - 1);
-        self.lines.drain(start..=end);
-        if self.lines.is_empty() {
-            self.lines.push(String::new());
-        }
-        self.cursor_row = start.min(self.lines.len() - 1);
+        let start = start_row.min(self.text.line_count() - 1);
+        let end = end_row.min(self.text.line_count() - 1);
+        self.text.remove_lines(start, end);
+        self.cursor_row = start.min(self.text.line_count() - 1);
         self.cursor_col = 0;
         self.dirty = true;
     }
@@ -1295,97 +1403,68 @@ impl App {
         self.clear_line_undo();
         let (start, end) = normalize_range(start, end);
         for row in start.0..=end.0 {
-            if row >= self.lines.len() {
+            if row >= self.text.line_count() {
                 break;
             }
-            let line = &mut self.lines[row];
-            let len = line.chars().count();
+            let len = self.text.line_len(row);
             if len == 0 || start.1 >= len {
                 continue;
             }
             let end_col = end.1.min(len.saturating_sub(1));
-            let start_idx = char_to_byte_idx(line, start.1);
-            let end_idx = if end_col + 1 <= len {
-                char_to_byte_idx(line, end_col + 1)
-            } else {
-                line.len()
-            };
-            line.replace_range(start_idx..end_idx, "");
+            self.text.remove((row, start.1), (row, end_col + 1));
         }
-        self.cursor_row = start.0.min(self.lines.len().saturating_sub(1));
+        self.cursor_row = start.0.min(self.text.line_count().saturating_sub(1));
         self.cursor_col = start.1.min(self.line_len(self.cursor_row));
         self.dirty = true;
     }
 
     pub(super) fn yank_range(&mut self, start: (usize, usize), end: (usize, usize)) {
+        self.register_range(start, end, false);
+    }
+
+    pub(super) fn register_range(&mut self, start: (usize, usize), end: (usize, usize), delete: bool) {
         let (start, end) = normalize_range(start, end);
-        let mut out = String::new();
-        if start.0 == end.0 {
-            let line = &self.lines[start.0];
-            let len = line.chars().count();
-            if len == 0 {
-                self.yank_buffer.clear();
-                self.yank_type = YankType::Char;
-                return;
-            }
-            let start_idx = char_to_byte_idx(line, start.1);
-            let end_col = end.1.min(len.saturating_sub(1));
-            let end_idx = if end_col + 1 <= len {
-                char_to_byte_idx(line, end_col + 1)
-            } else {
-                line.len()
-            };
-            out.push_str(&line[start_idx..end_idx]);
-        } else {
-            let start_line = &self.lines[start.0];
-            out.push_str(&start_line[char_to_byte_idx(start_line, start.1)..]);
-            out.push('\n');
-            for row in (start.0 + 1)..end.0 {
-                out.push_str(&self.lines[row]);
-                out.push('\n');
-            }
-            let end_line = &self.lines[end.0];
-            let end_len = end_line.chars().count();
-            let end_col = end.1.min(end_len.saturating_sub(1));
-            let end_idx = if end_len == 0 {
-                0
-            } else {
-                char_to_byte_idx(end_line, end_col + 1)
-            };
-            out.push_str(&end_line[..end_idx]);
+        if start.0 == end.0 && self.text.line_len(start.0) == 0 {
+            self.store_register(String::new(), YankType::Char, delete);
+            return;
         }
-        self.yank_buffer = out;
-        self.yank_type = YankType::Char;
+        let end_len = self.text.line_len(end.0);
+        let end_col = if end_len == 0 {
+            0
+        } else {
+            end.1.min(end_len - 1) + 1
+        };
+        let out = self.text.slice_to_string(start, (end.0, end_col));
+        self.set_change_marks(start, (end.0, end_col.saturating_sub(1)));
+        self.store_register(out, YankType::Char, delete);
     }
 
     pub(super) fn yank_lines(&mut self, start_row: usize, end_row: usize) {
-        if self.lines.is_empty() {
-            self.yank_buffer.clear();
-            self.yank_type = YankType::Line;
-            return;
-        }
-        let start = start_row.min(self.lines.len() - 1);
-        let end = end_row.min(self.lines.len() - 1);
-        let mut out = String::new();
-        for row in start..=end {
-            out.push_str(&self.lines[row]);
-            if row != end {
-                out.push('\n');
-            }
-        }
-        self.yank_buffer = out;
-        self.yank_type = YankType::Line;
+        self.register_lines(start_row, end_row, false);
+    }
+
+    pub(super) fn register_lines(&mut self, start_row: usize, end_row: usize, delete: bool) {
+        let start = start_row.min(self.text.line_count() - 1);
+        let end = end_row.min(self.text.line_count() - 1);
+        let out = self
+            .text
+            .slice_to_string((start, 0), (end, self.text.line_len(end)));
+        self.set_change_marks((start, 0), (end, 0));
+        self.store_register(out, YankType::Line, delete);
     }
 
     pub(super) fn yank_block(&mut self, start: (usize, usize), end: (usize, usize)) {
+        self.register_block(start, end, false);
+    }
+
+    pub(super) fn register_block(&mut self, start: (usize, usize), end: (usize, usize), delete: bool) {
         let (start, end) = normalize_range(start, end);
         let mut out = String::new();
         for row in start.0..=end.0 {
-            if row >= self.lines.len() {
+            if row >= self.text.line_count() {
                 break;
             }
-            let line = &self.lines[row];
-            let len = line.chars().count();
+            let len = self.text.line_len(row);
             if len == 0 || start.1 >= len {
                 if row != end.0 {
                     out.push('\n');
@@ -1393,139 +1472,122 @@ impl App {
                 continue;
             }
             let end_col = end.1.min(len.saturating_sub(1));
-            let start_idx = char_to_byte_idx(line, start.1);
-            let end_idx = if end_col + 1 <= len {
-                char_to_byte_idx(line, end_col + 1)
-            } else {
-                line.len()
-            };
-            out.push_str(&line[start_idx..end_idx]);
+            out.push_str(&self.text.slice_to_string((row, start.1), (row, end_col + 1)));
             if row != end.0 {
                 out.push('\n');
             }
         }
-        self.yank_buffer = out;
-        self.yank_type = YankType::Block;
+        self.set_change_marks(start, end);
+        self.store_register(out, YankType::Block, delete);
     }
 
     pub(super) fn delete_line(&mut self, row: usize) {
         self.record_undo();
         self.touch_edit();
         self.clear_line_undo();
-        if self.lines.is_empty() {
-            return;
-        }
-        self.lines.remove(row.min(self.lines.len() - 1));
-        if self.lines.is_empty() {
-            self.lines.push(String::new());
-        }
-        self.cursor_row = row.min(self.lines.len() - 1);
+        let row = row.min(self.text.line_count() - 1);
+        self.text.remove_lines(row, row);
+        self.cursor_row = row.min(self.text.line_count() - 1);
         self.cursor_col = 0;
         self.dirty = true;
     }
 
     pub(super) fn yank_line(&mut self, row: usize) {
-        let row = row.min(self.lines.len().saturating_sub(1));
-        self.yank_buffer = self.lines.get(row).cloned().unwrap_or_default();
-        self.yank_type = YankType::Line;
+        self.register_lines(row, row, false);
     }
 
     pub(super) fn apply_operator(&mut self, op: Operator, start: (usize, usize), end: (usize, usize)) {
         match op {
-            Operator::Delete => {
-                self.yank_range(start, end);
+            Operator::Delete | Operator::Change => {
+                self.register_range(start, end, true);
                 self.delete_range(start, end);
             }
             Operator::Yank => self.yank_range(start, end),
-            Operator::Change => {
-                self.yank_range(start, end);
-                self.delete_range(start, end);
-            }
         }
     }
 
     pub fn undo_len(&self) -> usize {
-        self.undo_stack.len()
+        self.undo_tree.undo_depth()
     }
 
     pub fn redo_len(&self) -> usize {
-        self.redo_stack.len()
+        self.undo_tree.redo_depth()
     }
 
     pub(super) fn paste_after(&mut self) {
+        let Some(register) = self.take_paste_register() else {
+            return;
+        };
         self.record_undo();
         self.touch_edit();
-        if self.yank_buffer.is_empty() {
-            return;
-        }
-        match self.yank_type {
+        let put_at = match register.kind {
+            YankType::Line => ((self.cursor_row + 1).min(self.text.line_count()), 0),
+            YankType::Char => (self.cursor_row, (self.cursor_col + 1).min(self.line_len(self.cursor_row))),
+            YankType::Block => (self.cursor_row, self.cursor_col),
+        };
+        match register.kind {
             YankType::Line => {
                 self.clear_line_undo();
-                let insert_at = (self.cursor_row + 1).min(self.lines.len());
-                let lines: Vec<String> =
-                    self.yank_buffer.split('\n').map(|s| s.to_string()).collect();
-                self.lines.splice(insert_at..insert_at, lines);
+                let insert_at = (self.cursor_row + 1).min(self.text.line_count());
+                let lines: Vec<&str> = register.text.split('\n').collect();
+                self.text.insert_lines(insert_at, &lines);
                 self.cursor_row = insert_at;
                 self.cursor_col = 0;
             }
             YankType::Block => {
                 self.clear_line_undo();
-                self.paste_block_at(self.cursor_row, self.cursor_col);
+                self.paste_block_at(&register.text, self.cursor_row, self.cursor_col);
             }
             YankType::Char => {
                 self.set_line_undo(self.cursor_row);
-                let line = &mut self.lines[self.cursor_row];
-                let byte_idx = char_to_byte_idx(line, self.cursor_col + 1);
-                line.insert_str(byte_idx, &self.yank_buffer);
-                self.cursor_col += self.yank_buffer.chars().count();
+                self.text
+                    .insert(self.cursor_row, self.cursor_col + 1, &register.text);
+                self.cursor_col += register.text.chars().count();
             }
         }
+        self.mark_put(&register, put_at);
         self.dirty = true;
     }
 
     pub(super) fn paste_before(&mut self) {
+        let Some(register) = self.take_paste_register() else {
+            return;
+        };
         self.record_undo();
         self.touch_edit();
-        if self.yank_buffer.is_empty() {
-            return;
-        }
-        match self.yank_type {
+        let put_at = (self.cursor_row, self.cursor_col);
+        match register.kind {
             YankType::Line => {
                 self.clear_line_undo();
-                let insert_at = self.cursor_row.min(self.lines.len());
-                let lines: Vec<String> =
-                    self.yank_buffer.split('\n').map(|s| s.to_string()).collect();
-                self.lines.splice(insert_at..insert_at, lines);
+                let insert_at = self.cursor_row.min(self.text.line_count());
+                let lines: Vec<&str> = register.text.split('\n').collect();
+                self.text.insert_lines(insert_at, &lines);
                 self.cursor_row = insert_at;
                 self.cursor_col = 0;
             }
             YankType::Block => {
                 self.clear_line_undo();
-                self.paste_block_at(self.cursor_row, self.cursor_col);
+                self.paste_block_at(&register.text, self.cursor_row, self.cursor_col);
             }
             YankType::Char => {
                 self.set_line_undo(self.cursor_row);
-                let line = &mut self.lines[self.cursor_row];
-                let byte_idx = char_to_byte_idx(line, self.cursor_col);
-                line.insert_str(byte_idx, &self.yank_buffer);
-                self.cursor_col += self.yank_buffer.chars().count();
+                self.text
+                    .insert(self.cursor_row, self.cursor_col, &register.text);
+                self.cursor_col += register.text.chars().count();
             }
         }
+        self.mark_put(&register, put_at);
         self.dirty = true;
     }
 
-    pub(super) fn paste_block_at(&mut self, row: usize, col: usize) {
+    pub(super) fn paste_block_at(&mut self, text: &str, row: usize, col: usize) {
         self.touch_edit();
-        let mut r = row;
-        for line_text in self.yank_buffer.split('\n') {
-            if r >= self.lines.len() {
-                self.lines.push(String::new());
+        for (offset, line_text) in text.split('\n').enumerate() {
+            let r = row + offset;
+            if r >= self.text.line_count() {
+                self.text.insert_line(r, "");
             }
-            let line = &mut self.lines[r];
-            let insert_col = col.min(line.chars().count());
-            let byte_idx = char_to_byte_idx(line, insert_col);
-            line.insert_str(byte_idx, line_text);
-            r += 1;
+            self.text.insert(r, col, line_text);
         }
     }
 
@@ -1540,15 +1602,15 @@ impl App {
         self.clear_line_undo();
         let (start, end) = normalize_range(start, end);
         if start.0 == end.0 {
-            if let Some(line) = self.lines.get(start.0).cloned() {
-                self.lines[start.0] = change_case_in_line(&line, start.1, end.1, to_upper);
+            if let Some(line) = self.text.line(start.0) {
+                self.text.set_line(start.0, &change_case_in_line(&line, start.1, end.1, to_upper));
             }
         } else {
             for row in start.0..=end.0 {
-                if row >= self.lines.len() {
+                if row >= self.text.line_count() {
                     break;
                 }
-                let line = self.lines[row].clone();
+                let line = self.text.line(row).unwrap_or_default();
                 let len = line.chars().count();
                 if len == 0 {
                     continue;
@@ -1560,7 +1622,7 @@ impl App {
                 } else {
                     (0, len.saturating_sub(1))
                 };
-                self.lines[row] = change_case_in_line(&line, s, e, to_upper);
+                self.text.set_line(row, &change_case_in_line(&line, s, e, to_upper));
             }
         }
         self.dirty = true;
@@ -1570,18 +1632,15 @@ impl App {
         self.record_undo();
         self.touch_edit();
         self.clear_line_undo();
-        if self.lines.is_empty() {
-            return;
-        }
-        let start = start_row.min(self.lines.len() - 1);
-        let end = end_row.min(self.lines.len() - 1);
+        let start = start_row.min(self.text.line_count() - 1);
+        let end = end_row.min(self.text.line_count() - 1);
         for row in start..=end {
-            let line = self.lines[row].clone();
+            let line = self.text.line(row).unwrap_or_default();
             let len = line.chars().count();
             if len == 0 {
                 continue;
             }
-            self.lines[row] = change_case_in_line(&line, 0, len.saturating_sub(1), to_upper);
+            self.text.set_line(row, &change_case_in_line(&line, 0, len.saturating_sub(1), to_upper));
         }
         self.dirty = true;
     }
@@ -1597,16 +1656,16 @@ impl App {
         self.clear_line_undo();
         let (start, end) = normalize_range(start, end);
         for row in start.0..=end.0 {
-            if row >= self.lines.len() {
+            if row >= self.text.line_count() {
                 break;
             }
-            let line = self.lines[row].clone();
+            let line = self.text.line(row).unwrap_or_default();
             let len = line.chars().count();
             if len == 0 || start.1 >= len {
                 continue;
             }
             let end_col = end.1.min(len.saturating_sub(1));
-            self.lines[row] = change_case_in_line(&line, start.1, end_col, to_upper);
+            self.text.set_line(row, &change_case_in_line(&line, start.1, end_col, to_upper));
         }
         self.dirty = true;
     }
@@ -1617,15 +1676,15 @@ impl App {
         self.clear_line_undo();
         let (start, end) = normalize_range(start, end);
         if start.0 == end.0 {
-            if let Some(line) = self.lines.get(start.0).cloned() {
-                self.lines[start.0] = toggle_case_in_line(&line, start.1, end.1);
+            if let Some(line) = self.text.line(start.0) {
+                self.text.set_line(start.0, &toggle_case_in_line(&line, start.1, end.1));
             }
         } else {
             for row in start.0..=end.0 {
-                if row >= self.lines.len() {
+                if row >= self.text.line_count() {
                     break;
                 }
-                let line = self.lines[row].clone();
+                let line = self.text.line(row).unwrap_or_default();
                 let len = line.chars().count();
                 if len == 0 {
                     continue;
@@ -1637,7 +1696,7 @@ impl App {
                 } else {
                     (0, len.saturating_sub(1))
                 };
-                self.lines[row] = toggle_case_in_line(&line, s, e);
+                self.text.set_line(row, &toggle_case_in_line(&line, s, e));
             }
         }
         self.dirty = true;
@@ -1647,18 +1706,15 @@ impl App {
         self.record_undo();
         self.touch_edit();
         self.clear_line_undo();
-        if self.lines.is_empty() {
-            return;
-        }
-        let start = start_row.min(self.lines.len() - 1);
-        let end = end_row.min(self.lines.len() - 1);
+        let start = start_row.min(self.text.line_count() - 1);
+        let end = end_row.min(self.text.line_count() - 1);
         for row in start..=end {
-            let line = self.lines[row].clone();
+            let line = self.text.line(row).unwrap_or_default();
             let len = line.chars().count();
             if len == 0 {
                 continue;
             }
-            self.lines[row] = toggle_case_in_line(&line, 0, len.saturating_sub(1));
+            self.text.set_line(row, &toggle_case_in_line(&line, 0, len.saturating_sub(1)));
         }
         self.dirty = true;
     }
@@ -1669,16 +1725,16 @@ impl App {
         self.clear_line_undo();
         let (start, end) = normalize_range(start, end);
         for row in start.0..=end.0 {
-            if row >= self.lines.len() {
+            if row >= self.text.line_count() {
                 break;
             }
-            let line = self.lines[row].clone();
+            let line = self.text.line(row).unwrap_or_default();
             let len = line.chars().count();
             if len == 0 || start.1 >= len {
                 continue;
             }
             let end_col = end.1.min(len.saturating_sub(1));
-            self.lines[row] = toggle_case_in_line(&line, start.1, end_col);
+            self.text.set_line(row, &toggle_case_in_line(&line, start.1, end_col));
         }
         self.dirty = true;
     }
@@ -1688,22 +1744,15 @@ impl App {
         self.touch_edit();
         let (start, end) = normalize_range(start, end);
         for row in start.0..=end.0 {
-            if row >= self.lines.len() {
+            if row >= self.text.line_count() {
                 break;
             }
-            let line = &mut self.lines[row];
-            let len = line.chars().count();
+            let len = self.text.line_len(row);
             if len == 0 || start.1 >= len {
                 continue;
             }
             let end_col = end.1.min(len.saturating_sub(1));
-            let start_idx = char_to_byte_idx(line, start.1);
-            let end_idx = if end_col + 1 <= len {
-                char_to_byte_idx(line, end_col + 1)
-            } else {
-                line.len()
-            };
-            line.replace_range(start_idx..end_idx, "");
+            self.text.remove((row, start.1), (row, end_col + 1));
         }
     }
 
@@ -1713,38 +1762,22 @@ impl App {
         let (start, end) = normalize_range(start, end);
         if start.0 == end.0 {
             let row = start.0;
-            let line = &mut self.lines[row];
-            let len = line.chars().count();
+            let len = self.text.line_len(row);
             if len == 0 {
                 return;
             }
-            let start_idx = char_to_byte_idx(line, start.1);
             let end_col = end.1.min(len.saturating_sub(1));
-            let end_idx = if end_col + 1 <= len {
-                char_to_byte_idx(line, end_col + 1)
-            } else {
-                line.len()
-            };
-            line.replace_range(start_idx..end_idx, "");
+            self.text.remove((row, start.1), (row, end_col + 1));
         } else {
-            let start_line = &self.lines[start.0];
-            let start_prefix = &start_line[..char_to_byte_idx(start_line, start.1)];
-            let end_line = &self.lines[end.0];
-            let end_len = end_line.chars().count();
-            let end_col = end.1.min(end_len.saturating_sub(1));
-            let end_suffix = if end_len == 0 {
-                ""
+            let end_len = self.text.line_len(end.0);
+            let end_col = if end_len == 0 {
+                0
             } else {
-                &end_line[char_to_byte_idx(end_line, end_col + 1)..]
+                end.1.min(end_len - 1) + 1
             };
-            let merged = format!("{}{}", start_prefix, end_suffix);
-            self.lines[start.0] = merged;
-            self.lines.drain(start.0 + 1..=end.0);
+            self.text.remove(start, (end.0, end_col));
         }
-        if self.lines.is_empty() {
-            self.lines.push(String::new());
-        }
-        self.cursor_row = start.0.min(self.lines.len() - 1);
+        self.cursor_row = start.0.min(self.text.line_count() - 1);
         let len = self.line_len(self.cursor_row);
         self.cursor_col = start.1.min(len);
     }
@@ -1752,16 +1785,10 @@ impl App {
     #[allow(dead_code)]
     pub(super) fn delete_lines_no_undo(&mut self, start_row: usize, end_row: usize) {
         self.touch_edit();
-        if self.lines.is_empty() {
-            return;
-        }
-        let start = start_row.min(self.lines.len() - 1);
-        let end = end_row.min(self.lines.len() - 1);
-        self.lines.drain(start..=end);
-        if self.lines.is_empty() {
-            self.lines.push(String::new());
-        }
-        self.cursor_row = start.min(self.lines.len() - 1);
+        let start = start_row.min(self.text.line_count() - 1);
+        let end = end_row.min(self.text.line_count() - 1);
+        self.text.remove_lines(start, end);
+        self.cursor_row = start.min(self.text.line_count() - 1);
         self.cursor_col = 0;
     }
 
@@ -1772,18 +1799,14 @@ impl App {
         };
         let mut row = block.end_row;
         loop {
-            if row >= self.lines.len() {
+            if row >= self.text.line_count() {
                 if row == 0 {
                     break;
                 }
                 row -= 1;
                 continue;
             }
-            let line = &mut self.lines[row];
-            let col = block.col.min(line.chars().count());
-            let byte_idx = char_to_byte_idx(line, col);
-            let right = line.split_off(byte_idx);
-            self.lines.insert(row + 1, right);
+            self.text.split_line(row, block.col);
             if row == block.start_row {
                 break;
             }
@@ -1795,10 +1818,7 @@ impl App {
     }
 
     pub(super) fn line_len(&self, row: usize) -> usize {
-        self.lines
-            .get(row)
-            .map(|l| l.chars().count())
-            .unwrap_or(0)
+        self.text.line_len(row)
     }
 }
 
@@ -1830,6 +1850,53 @@ fn default_command_candidates() -> Vec<String> {
         "perf",
         "map",
         "syntax",
+        "undolist",
+        "registers",
+        "marks",
+        "jumps",
+        "let",
+        "display",
+        "earlier",
+        "later",
+        "qa",
+        "qall",
+        "split",
+        "vsplit",
+        "new",
+        "vnew",
+        "close",
+        "only",
+        "resize",
+        "wincmd",
+        "tabnew",
+        "tabedit",
+        "tabnext",
+        "tabprevious",
+        "tabclose",
+        "tabonly",
+        "tabs",
+        "LspInfo",
+        "LspRestart",
+        "LspRename",
+        "LspCodeAction",
+        "LspFormat",
+        "LspDiagnostics",
+        "Files",
+        "grep",
+        "vimgrep",
+        "make",
+        "copen",
+        "cclose",
+        "clist",
+        "cnext",
+        "cprevious",
+        "cfirst",
+        "clast",
+        "cdo",
+        "cfdo",
+        "Explore",
+        "plugins",
+        "autocmd",
     ]
     .into_iter()
     .map(|s| s.to_string())
@@ -1885,19 +1952,15 @@ impl App {
         let mut depth = 0i32;
         let mut r = row;
         let mut c = col;
-        loop {
-            if let Some((pr, pc)) = self.prev_pos(r, c) {
-                r = pr;
-                c = pc;
-            } else {
-                break;
-            }
+        while let Some((pr, pc)) = self.prev_pos(r, c) {
+            r = pr;
+            c = pc;
             if let Some(ch2) = self.char_at(r, c) {
                 if ch2 == close {
                     depth += 1;
                 } else if ch2 == open {
                     if depth == 0 {
-                        let indent = Self::leading_whitespace(&self.lines[r]).chars().count();
+                        let indent = Self::leading_whitespace(&self.text.line(r).unwrap_or_default()).chars().count();
                         return Some(indent);
                     } else {
                         depth -= 1;
@@ -0,0 +1,447 @@
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+
+use super::config::AutocmdConfig;
+use super::plugin::PluginApi;
+use super::types::Mode;
+use super::App;
+use crate::logging::append_log;
+
+const EVENT_NAMES: &[&str] = &[
+    "BufRead",
+    "BufEnter",
+    "BufWritePre",
+    "BufWritePost",
+    "ModeChanged",
+    "KeyPressed",
+    "ExCommand",
+];
+
+const SHELL_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum EditorEvent {
+    BufRead(Option<PathBuf>),
+    BufEnter(Option<PathBuf>),
+    BufWritePre(PathBuf),
+    BufWritePost(PathBuf),
+    ModeChanged(Mode, Mode),
+    KeyPressed(String),
+    ExCommand(String),
+}
+
+impl EditorEvent {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            EditorEvent::BufRead(_) => "BufRead",
+            EditorEvent::BufEnter(_) => "BufEnter",
+            EditorEvent::BufWritePre(_) => "BufWritePre",
+            EditorEvent::BufWritePost(_) => "BufWritePost",
+            EditorEvent::ModeChanged(..) => "ModeChanged",
+            EditorEvent::KeyPressed(_) => "KeyPressed",
+            EditorEvent::ExCommand(_) => "ExCommand",
+        }
+    }
+
+    pub(crate) fn subject(&self) -> String {
+        match self {
+            EditorEvent::BufRead(path) | EditorEvent::BufEnter(path) => path
+                .as_ref()
+                .map(|path| path.display().to_string())
+                .unwrap_or_default(),
+            EditorEvent::BufWritePre(path) | EditorEvent::BufWritePost(path) => {
+                path.display().to_string()
+            }
+            EditorEvent::ModeChanged(from, to) => format!("{}:{}", mode_name(*from), mode_name(*to)),
+            EditorEvent::KeyPressed(key) | EditorEvent::ExCommand(key) => key.clone(),
+        }
+    }
+
+    fn path(&self) -> Option<&PathBuf> {
+        match self {
+            EditorEvent::BufRead(path) | EditorEvent::BufEnter(path) => path.as_ref(),
+            EditorEvent::BufWritePre(path) | EditorEvent::BufWritePost(path) => Some(path),
+            _ => None,
+        }
+    }
+}
+
+fn mode_name(mode: Mode) -> &'static str {
+    match mode {
+        Mode::Normal => "n",
+        Mode::Insert => "i",
+        Mode::Command => "c",
+        Mode::VisualChar => "v",
+        Mode::VisualLine => "V",
+        Mode::VisualBlock => "^V",
+    }
+}
+
+enum AutocmdAction {
+    Ex(String),
+    Shell {
+        command: String,
+        filter: bool,
+        timeout: Duration,
+    },
+}
+
+pub(crate) struct Autocmd {
+    events: Vec<&'static str>,
+    pattern: String,
+    globs: Vec<Regex>,
+    action: AutocmdAction,
+}
+
+impl Autocmd {
+    pub(crate) fn from_config(config: &AutocmdConfig) -> Result<Self, String> {
+        let mut events = Vec::new();
+        for name in config.event.split(',').map(str::trim) {
+            let Some(event) = EVENT_NAMES.iter().find(|event| event.eq_ignore_ascii_case(name)) else {
+                return Err(format!("unknown event: {}", name));
+            };
+            events.push(*event);
+        }
+        let pattern = config.pattern.clone().unwrap_or_else(|| "*".to_string());
+        let globs = split_patterns(&pattern)
+            .iter()
+            .map(|glob| glob_regex(glob))
+            .collect::<Result<_, _>>()?;
+        let action = match (&config.command, &config.shell) {
+            (Some(command), None) => AutocmdAction::Ex(command.trim_start_matches(':').to_string()),
+            (None, Some(command)) => AutocmdAction::Shell {
+                command: command.clone(),
+                filter: config.filter.unwrap_or(false),
+                timeout: config.timeout_ms.map_or(SHELL_TIMEOUT, Duration::from_millis),
+            },
+            _ => {
+                return Err(format!(
+                    "{} {}: set exactly one of command or shell",
+                    config.event, pattern
+                ));
+            }
+        };
+        Ok(Self {
+            events,
+            pattern,
+            globs,
+            action,
+        })
+    }
+
+    fn matches(&self, event: &str, subject: &str) -> bool {
+        self.events.contains(&event) && self.globs.iter().any(|glob| glob.is_match(subject))
+    }
+
+    fn describe(&self) -> String {
+        let action = match &self.action {
+            AutocmdAction::Ex(command) => format!(":{}", command),
+            AutocmdAction::Shell { command, filter: false, .. } => format!("!{}", command),
+            AutocmdAction::Shell { command, filter: true, .. } => format!("!{} (filter)", command),
+        };
+        format!("{:<16} {:<12} {}", self.events.join(","), self.pattern, action)
+    }
+}
+
+fn split_patterns(pattern: &str) -> Vec<String> {
+    let mut out = vec![String::new()];
+    let mut depth = 0usize;
+    for ch in pattern.chars() {
+        match ch {
+            ',' if depth == 0 => out.push(String::new()),
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        if ch != ',' || depth > 0 {
+            out.last_mut().unwrap().push(ch);
+        }
+    }
+    out.into_iter().map(|glob| glob.trim().to_string()).filter(|glob| !glob.is_empty()).collect()
+}
+
+// A glob without a slash matches the last path component, one with a slash matches
+// a trailing run of components: "*.rs" and "src/*.rs" both match "/repo/src/main.rs".
+fn glob_regex(glob: &str) -> Result<Regex, String> {
+    let mut re = String::from("(?:^|/)");
+    let mut chars = glob.chars().peekable();
+    let mut depth = 0usize;
+    while let Some(ch) = chars.next() {
+        match ch {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                re.push_str(".*");
+            }
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            '{' => {
+                depth += 1;
+                re.push_str("(?:");
+            }
+            '}' if depth > 0 => {
+                depth -= 1;
+                re.push(')');
+            }
+            ',' if depth > 0 => re.push('|'),
+            _ => re.push_str(&regex::escape(&ch.to_string())),
+        }
+    }
+    if depth > 0 {
+        return Err(format!("unclosed {{ in pattern: {}", glob));
+    }
+    re.push('$');
+    Regex::new(&re).map_err(|err| err.to_string())
+}
+
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+// Only a standalone % is the file name, so `date +%s` survives; \% is a literal %.
+fn expand_percent(command: &str, path: &str) -> String {
+    let mut out = String::with_capacity(command.len());
+    let mut prev: Option<char> = None;
+    let mut chars = command.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch == '\\' && chars.peek() == Some(&'%') {
+            out.push(chars.next().unwrap());
+            prev = Some('%');
+            continue;
+        }
+        let standalone = prev.is_none_or(char::is_whitespace)
+            && chars.peek().is_none_or(|next| next.is_whitespace());
+        if ch == '%' && standalone {
+            out.push_str(path);
+        } else {
+            out.push(ch);
+        }
+        prev = Some(ch);
+    }
+    out
+}
+
+fn read_all(mut pipe: impl Read + Send + 'static) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut out = Vec::new();
+        let _ = pipe.read_to_end(&mut out);
+        out
+    })
+}
+
+// Runs on the UI thread, so a command that hangs (a filter waiting on a terminal, say)
+// is killed once its timeout passes instead of freezing the editor.
+fn run_shell(command: &str, input: Option<String>, timeout: Duration) -> Result<String, String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("{}: {}", command, err))?;
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        thread::spawn(move || {
+            let _ = stdin.write_all(input.as_bytes());
+        });
+    }
+    let stdout = child.stdout.take().map(read_all);
+    let stderr = child.stderr.take().map(read_all);
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("{}: timed out after {}ms, killed", command, timeout.as_millis()));
+            }
+            Ok(None) => thread::sleep(Duration::from_millis(5)),
+            Err(err) => return Err(format!("{}: {}", command, err)),
+        }
+    };
+    let collect = |pipe: Option<thread::JoinHandle<Vec<u8>>>| {
+        pipe.and_then(|handle| handle.join().ok()).unwrap_or_default()
+    };
+    let (stdout, stderr) = (collect(stdout), collect(stderr));
+    if !status.success() {
+        let stderr = String::from_utf8_lossy(&stderr);
+        return Err(match stderr.lines().find(|line| !line.trim().is_empty()) {
+            Some(line) => format!("{}: {}", command, line.trim()),
+            None => format!("{}: exited with {}", command, status),
+        });
+    }
+    Ok(String::from_utf8_lossy(&stdout).replace("\r\n", "\n"))
+}
+
+impl App {
+    pub(crate) fn fire_event(&mut self, event: EditorEvent) {
+        // Commands run by autocmds and plugin handlers do not fire further events.
+        if self.firing_event || (self.autocmds.is_empty() && self.plugins.is_empty()) {
+            return;
+        }
+        self.firing_event = true;
+        self.dispatch_plugin_event(&event);
+        let name = event.name();
+        let subject = event.subject();
+        let autocmds = std::mem::take(&mut self.autocmds);
+        for autocmd in autocmds.iter().filter(|autocmd| autocmd.matches(name, &subject)) {
+            if let Err(err) = self.run_autocmd(autocmd, &event) {
+                append_log(&format!("autocmd: {} {}: {}", name, autocmd.pattern, err));
+                self.set_status(format!("Autocmd {} {}: {}", name, autocmd.pattern, err));
+            }
+        }
+        self.autocmds = autocmds;
+        self.firing_event = false;
+    }
+
+    fn run_autocmd(&mut self, autocmd: &Autocmd, event: &EditorEvent) -> Result<(), String> {
+        match &autocmd.action {
+            AutocmdAction::Ex(command) => {
+                self.run_ex_command(command).map_err(|err| err.to_string())?;
+            }
+            AutocmdAction::Shell { command, filter, timeout } => {
+                let path = event.path().map(|path| shell_quote(&path.display().to_string()));
+                let command = expand_percent(command, path.as_deref().unwrap_or("''"));
+                if !filter {
+                    run_shell(&command, None, *timeout)?;
+                    return Ok(());
+                }
+                let buffer = self.current_buffer_id;
+                let lines = self.get_buffer_text(buffer, 0, None).unwrap_or_default();
+                let input: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+                let output = run_shell(&command, Some(input), *timeout)?;
+                let filtered: Vec<String> = output.split_terminator('\n').map(str::to_string).collect();
+                if filtered.is_empty() && lines.iter().any(|line| !line.is_empty()) {
+                    return Err(format!("{}: no output, buffer left unchanged", command));
+                }
+                if filtered != lines {
+                    self.apply_edit(buffer, 0, self.text.line_count(), &filtered)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn set_autocmds(&mut self, configs: &[AutocmdConfig]) {
+        self.autocmds.clear();
+        for config in configs {
+            match Autocmd::from_config(config) {
+                Ok(autocmd) => self.autocmds.push(autocmd),
+                Err(err) => self.set_status(format!("Autocmd error: {}", err)),
+            }
+        }
+    }
+
+    pub(super) fn autocmd_list_lines(&self) -> Vec<String> {
+        if self.autocmds.is_empty() {
+            return vec!["No autocommands".to_string()];
+        }
+        self.autocmds.iter().map(Autocmd::describe).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::handle_key;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    fn autocmd(event: &str, pattern: &str, command: Option<&str>, shell: Option<&str>) -> AutocmdConfig {
+        AutocmdConfig {
+            event: event.to_string(),
+            pattern: Some(pattern.to_string()),
+            command: command.map(str::to_string),
+            shell: shell.map(str::to_string),
+            filter: shell.map(|_| true),
+            timeout_ms: None,
+        }
+    }
+
+    #[test]
+    fn autocmds_run_for_matching_events() {
+        let dir = std::env::temp_dir().join(format!("rvim-autocmd-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("main.rs");
+        std::fs::write(&path, "fn main() {}  \n").unwrap();
+
+        let mut app = App::new(None, String::new());
+        app.set_autocmds(&[
+            autocmd("BufWritePre", "*.{rs,toml}", None, Some("tr a-z A-Z")),
+            autocmd("BufWritePost", "main.rs,*.md", Some("w"), None),
+            autocmd("bufread", "*.rs", Some("s/  $//"), None),
+            autocmd("ModeChanged", "i:n", Some("s/^/>/"), None),
+            autocmd("ExCommand", "nohl*", Some("s/$/!/"), None),
+            autocmd("BufRead", "*", None, Some("exit 3")),
+            autocmd("Nope", "*", Some("w"), None),
+        ]);
+        assert_eq!(app.status_message, "Autocmd error: unknown event: Nope");
+        assert_eq!(app.autocmds.len(), 6);
+        assert!(app.autocmd_list_lines()[0].ends_with("!tr a-z A-Z (filter)"));
+        let glob = glob_regex("src/**/*.rs").unwrap();
+        assert!(glob.is_match("/repo/src/app/mod.rs"));
+        assert!(!glob.is_match("/repo/tests/main.rs"));
+
+        app.open_or_switch_buffer(path.clone(), None);
+        assert_eq!(app.text.line(0).unwrap(), "fn main() {}");
+        assert_eq!(app.status_message, "Autocmd BufRead *: exit 3: exited with exit status: 3");
+
+        assert!(app.save());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "FN MAIN() {}\n");
+        app.undo();
+        assert_eq!(app.text.line(0).unwrap(), "fn main() {}");
+
+        for code in [KeyCode::Char('i'), KeyCode::Esc] {
+            handle_key(&mut app, KeyEvent::new(code, KeyModifiers::NONE)).unwrap();
+        }
+        assert_eq!(app.text.line(0).unwrap(), ">fn main() {}");
+        app.command_buffer = "nohlsearch".to_string();
+        app.execute_command().unwrap();
+        assert_eq!(app.text.line(0).unwrap(), ">fn main() {}!");
+
+        assert_eq!(expand_percent("rustfmt % && date +%s", "'a b.rs'"), "rustfmt 'a b.rs' && date +%s");
+        assert_eq!(expand_percent("printf '%s' \\% %", "f"), "printf '%s' % f");
+        app.set_autocmds(&[autocmd("BufWritePre", "*.rs", None, Some("cat >/dev/null"))]);
+        assert!(app.save());
+        assert_eq!(app.text.line(0).unwrap(), ">fn main() {}!");
+        assert!(app.status_message.starts_with("Wrote"), "{}", app.status_message);
+
+        let other = dir.join("other.txt");
+        std::fs::write(&other, "other\n").unwrap();
+        app.set_autocmds(&[autocmd("BufWritePre", "*.txt", Some("bn"), None)]);
+        app.open_or_switch_buffer(other.clone(), None);
+        app.text.set_line(0, "changed");
+        assert!(!app.save());
+        assert!(app.status_message.contains("BufWritePre switched buffers"));
+        assert_eq!(std::fs::read_to_string(&other).unwrap(), "other\n");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), ">fn main() {}!\n");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn key_events_fire_and_hung_shell_commands_are_killed() {
+        let mut app = App::new(None, "a".to_string());
+        app.set_autocmds(&[
+            autocmd("KeyPressed", "<C-l>", Some("s/$/!/"), None),
+            AutocmdConfig {
+                timeout_ms: Some(100),
+                ..autocmd("KeyPressed", "j", None, Some("sleep 5"))
+            },
+        ]);
+        handle_key(&mut app, KeyEvent::new(KeyCode::Char('l'), KeyModifiers::CONTROL)).unwrap();
+        assert_eq!(app.text.to_string(), "a!");
+        handle_key(&mut app, KeyEvent::new(KeyCode::Char('l'), KeyModifiers::NONE)).unwrap();
+        assert_eq!(app.text.to_string(), "a!");
+
+        let started = Instant::now();
+        handle_key(&mut app, KeyEvent::new(KeyCode::Char('j'), KeyModifiers::NONE)).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(3));
+        assert_eq!(app.status_message, "Autocmd KeyPressed j: sleep 5: timed out after 100ms, killed");
+        assert_eq!(app.text.to_string(), "a!");
+    }
+}
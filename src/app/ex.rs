@@ -0,0 +1,424 @@
+use anyhow::Result;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use super::input::handle_key;
+use super::range::LineRange;
+use super::substitute::split_delimited;
+use super::types::{CommandPrompt, Mode, SearchSpec};
+use super::App;
+
+pub(super) fn split_command(input: &str) -> (&str, bool, &str) {
+    let len = match input.chars().next() {
+        Some(ch @ ('>' | '<')) => input.chars().take_while(|c| *c == ch).count(),
+        _ => input
+            .chars()
+            .take_while(|c| c.is_ascii_alphabetic())
+            .count(),
+    };
+    let (name, rest) = input.split_at(len);
+    match rest.strip_prefix('!') {
+        Some(rest) => (name, true, rest.trim()),
+        None => (name, false, rest.trim()),
+    }
+}
+
+impl App {
+    pub(super) fn execute_line_command(
+        &mut self,
+        range: Option<LineRange>,
+        input: &str,
+    ) -> Result<bool> {
+        let (name, bang, args) = split_command(input);
+        let current = (self.cursor_row, self.cursor_row);
+        match name {
+            "d" | "de" | "del" | "delete" => {
+                let args = self.register_arg(args);
+                if let Some((start, end)) = self.counted_range(range.unwrap_or(current), args) {
+                    self.register_lines(start, end, true);
+                    self.delete_lines(start, end);
+                    self.move_line_first_non_blank();
+                }
+            }
+            "y" | "ya" | "yank" => {
+                let args = self.register_arg(args);
+                if let Some((start, end)) = self.counted_range(range.unwrap_or(current), args) {
+                    self.yank_lines(start, end);
+                    let count = end - start + 1;
+                    self.set_status(format!(
+                        "{} line{} yanked",
+                        count,
+                        if count == 1 { "" } else { "s" }
+                    ));
+                }
+            }
+            "m" | "mo" | "move" => match self.parse_target_line(args) {
+                Ok(target) => self.move_lines(range.unwrap_or(current), target),
+                Err(err) => self.set_status(err),
+            },
+            "t" | "co" | "copy" => match self.parse_target_line(args) {
+                Ok(target) => self.copy_lines(range.unwrap_or(current), target),
+                Err(err) => self.set_status(err),
+            },
+            _ if name.starts_with('>') || name.starts_with('<') => {
+                if let Some(range) = self.counted_range(range.unwrap_or(current), args) {
+                    self.shift_lines(range, name.len(), name.starts_with('>'));
+                }
+            }
+            "j" | "join" => {
+                let (start, end) = range.unwrap_or(current);
+                let range = if args.is_empty() {
+                    Some((start, end.max(start + 1)))
+                } else {
+                    self.counted_range((end, end), args)
+                        .map(|(start, end)| (start, end.max(start + 1)))
+                };
+                if let Some((start, end)) = range {
+                    self.join_lines(start, end, !bang);
+                }
+            }
+            "norm" | "normal" => {
+                if args.is_empty() {
+                    self.set_status("Argument required");
+                } else {
+                    self.run_normal(range, args)?;
+                }
+            }
+            "g" | "global" => self.global(range, args, bang)?,
+            "v" | "vglobal" => self.global(range, args, true)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn global(&mut self, range: Option<LineRange>, args: &str, invert: bool) -> Result<()> {
+        if self.text.is_tracking() {
+            self.set_status("Cannot do :global recursive");
+            return Ok(());
+        }
+        let mut chars = args.chars();
+        let delim = match chars.next() {
+            Some(ch) if !ch.is_alphanumeric() && !ch.is_whitespace() && ch != '\\' && ch != '"' => ch,
+            _ => {
+                self.set_status("Usage: :[range]g/pattern/command");
+                return Ok(());
+            }
+        };
+        let (pattern, command) = split_delimited(chars.as_str(), delim);
+        let source = if pattern.is_empty() {
+            match self.last_search.as_ref() {
+                Some(last) => last.pattern.clone(),
+                None => {
+                    self.set_status("No previous regular expression");
+                    return Ok(());
+                }
+            }
+        } else {
+            pattern
+        };
+        let compiled = match self.compile_search(&source) {
+            Ok(compiled) => compiled,
+            Err(err) => {
+                self.set_status(format!("Invalid pattern: {}", err));
+                return Ok(());
+            }
+        };
+        self.last_search = Some(SearchSpec {
+            pattern: source.clone(),
+            reverse: false,
+        });
+        let (start, end) = range.unwrap_or((0, self.text.line_count().saturating_sub(1)));
+        let rows: Vec<usize> = (start..=end)
+            .filter(|row| {
+                let line = self.text.line(*row).unwrap_or_default();
+                compiled.find_from(&line, 0).is_some() != invert
+            })
+            .collect();
+        if rows.is_empty() {
+            self.set_status(format!("Pattern not found: {}", source));
+            return Ok(());
+        }
+        let command = command.unwrap_or("").trim();
+        if command.is_empty() || command == "p" || command == "print" {
+            let lines = rows
+                .iter()
+                .map(|row| format!("{:>5} {}", row + 1, self.text.line(*row).unwrap_or_default()))
+                .collect();
+            self.show_list(lines);
+            return Ok(());
+        }
+        let grouped = self.begin_undo_group();
+        self.text.track_lines(&rows);
+        let mut result = Ok(());
+        for idx in 0..rows.len() {
+            let Some(row) = self.text.tracked_line(idx) else {
+                continue;
+            };
+            self.cursor_row = row;
+            self.cursor_col = 0;
+            if let Err(err) = self.run_ex_command(command) {
+                result = Err(err);
+                break;
+            }
+        }
+        self.text.stop_tracking();
+        if grouped {
+            self.end_undo_group();
+        }
+        self.cursor_row = self.cursor_row.min(self.text.line_count().saturating_sub(1));
+        self.cursor_col = self.cursor_col.min(self.line_len(self.cursor_row));
+        result
+    }
+
+    fn register_arg<'a>(&mut self, args: &'a str) -> &'a str {
+        match args.chars().next() {
+            Some(ch) if ch.is_ascii_alphabetic() || matches!(ch, '"' | '-' | '_' | '+' | '*') => {
+                self.pending_register = Some(ch);
+                args[1..].trim_start()
+            }
+            _ => args,
+        }
+    }
+
+    fn counted_range(&mut self, (start, end): LineRange, args: &str) -> Option<LineRange> {
+        if args.is_empty() {
+            return Some((start, end));
+        }
+        match args.parse::<usize>() {
+            Ok(count) if count > 0 => {
+                let last = self.text.line_count().saturating_sub(1);
+                Some((end, (end + count - 1).min(last)))
+            }
+            _ => {
+                self.set_status(format!("Trailing characters: {}", args));
+                None
+            }
+        }
+    }
+
+    fn range_lines(&self, (start, end): LineRange) -> Vec<String> {
+        (start..=end)
+            .map(|row| self.text.line(row).unwrap_or_default())
+            .collect()
+    }
+
+    fn move_lines(&mut self, (start, end): LineRange, target: usize) {
+        if target > start && target <= end {
+            self.set_status("Cannot move a range of lines into itself");
+            return;
+        }
+        let count = end - start + 1;
+        let lines = self.range_lines((start, end));
+        self.record_undo();
+        self.touch_edit();
+        self.clear_line_undo();
+        if target != start && target != end + 1 {
+            self.text.remove_lines(start, end);
+            let insert_at = if target > end { target - count } else { target };
+            self.text.insert_lines(insert_at, &lines);
+            self.cursor_row = insert_at + count - 1;
+            self.dirty = true;
+        } else {
+            self.cursor_row = end;
+        }
+        self.move_line_first_non_blank();
+        self.set_status(format!(
+            "{} line{} moved",
+            count,
+            if count == 1 { "" } else { "s" }
+        ));
+    }
+
+    fn copy_lines(&mut self, range: LineRange, target: usize) {
+        let lines = self.range_lines(range);
+        self.record_undo();
+        self.touch_edit();
+        self.clear_line_undo();
+        self.text.insert_lines(target, &lines);
+        self.cursor_row = target + lines.len() - 1;
+        self.move_line_first_non_blank();
+        self.dirty = true;
+    }
+
+    fn shift_lines(&mut self, (start, end): LineRange, amount: usize, right: bool) {
+        self.record_undo();
+        self.touch_edit();
+        self.clear_line_undo();
+        for row in start..=end {
+            let line = self.text.line(row).unwrap_or_default();
+            if line.trim().is_empty() {
+                continue;
+            }
+            let indent = App::leading_whitespace(&line);
+            let mut shifted = indent.clone();
+            for _ in 0..amount {
+                shifted = if right {
+                    App::increase_indent(&shifted, self.shift_width)
+                } else {
+                    App::decrease_indent(&shifted, self.shift_width)
+                };
+            }
+            if shifted != indent {
+                self.text
+                    .set_line(row, &format!("{}{}", shifted, &line[indent.len()..]));
+            }
+        }
+        self.cursor_row = end;
+        self.move_line_first_non_blank();
+        self.dirty = true;
+    }
+
+    pub(super) fn join_lines(&mut self, start: usize, end: usize, spaces: bool) {
+        let end = end.min(self.text.line_count().saturating_sub(1));
+        if end <= start {
+            return;
+        }
+        self.record_undo();
+        self.touch_edit();
+        self.clear_line_undo();
+        let mut join_col = 0;
+        for _ in start..end {
+            let line = self.text.line(start).unwrap_or_default();
+            let next = self.text.line(start + 1).unwrap_or_default();
+            join_col = line.chars().count();
+            if spaces {
+                let lead = next.chars().take_while(|ch| ch.is_whitespace()).count();
+                self.text.remove((start + 1, 0), (start + 1, lead));
+                let rest = &next.trim_start();
+                let needs_space = !line.is_empty()
+                    && !line.ends_with(char::is_whitespace)
+                    && !rest.is_empty()
+                    && !rest.starts_with(')');
+                self.text.join_line(start);
+                if needs_space {
+                    self.text.insert(start, join_col, " ");
+                }
+            } else {
+                self.text.join_line(start);
+            }
+        }
+        self.cursor_row = start;
+        self.cursor_col = join_col.min(self.line_len(start).saturating_sub(1));
+        self.dirty = true;
+    }
+
+    fn run_normal(&mut self, range: Option<LineRange>, keys: &str) -> Result<()> {
+        let grouped = self.begin_undo_group();
+        let mut result = Ok(());
+        match range {
+            None => result = self.feed_normal_keys(keys),
+            Some((start, end)) => {
+                let mut row = start as isize;
+                let mut end = end as isize;
+                while row <= end && (row as usize) < self.text.line_count() {
+                    let before = self.text.line_count() as isize;
+                    self.cursor_row = row as usize;
+                    self.cursor_col = 0;
+                    result = self.feed_normal_keys(keys);
+                    if result.is_err() {
+                        break;
+                    }
+                    let delta = self.text.line_count() as isize - before;
+                    end += delta;
+                    row += 1 + delta.max(-1);
+                }
+            }
+        }
+        if grouped {
+            self.end_undo_group();
+        }
+        result
+    }
+
+    fn feed_normal_keys(&mut self, keys: &str) -> Result<()> {
+        self.mode = Mode::Normal;
+        self.command_prompt = CommandPrompt::Command;
+        self.command_buffer.clear();
+        self.command_cursor = 0;
+        for ch in keys.chars() {
+            handle_key(self, KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE))?;
+        }
+        if self.mode != Mode::Normal {
+            handle_key(self, KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE))?;
+        }
+        self.mode = Mode::Normal;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(app: &mut App, command: &str) {
+        app.command_buffer = command.to_string();
+        app.execute_command().unwrap();
+    }
+
+    #[test]
+    fn line_commands_edit_ranges() {
+        let mut app = App::new(None, "one\ntwo\nthree\nfour".to_string());
+        run(&mut app, "1m$");
+        assert_eq!(app.text.to_string(), "two\nthree\nfour\none");
+        run(&mut app, "2,3t0");
+        assert_eq!(app.text.to_string(), "three\nfour\ntwo\nthree\nfour\none");
+        run(&mut app, "/two/,$d");
+        assert_eq!(app.text.to_string(), "three\nfour");
+        run(&mut app, "%>");
+        assert_eq!(app.text.to_string(), "    three\n    four");
+        run(&mut app, "1j");
+        assert_eq!(app.text.to_string(), "    three four");
+        assert!(app.text.line_count() == 1);
+        run(&mut app, "2m0");
+        assert_eq!(app.status_message, "Invalid range");
+    }
+
+    #[test]
+    fn earlier_uses_the_time_each_change_was_made() {
+        let mut app = App::new(None, "a".to_string());
+        for (idx, text) in ["b", "c", "d"].iter().enumerate() {
+            app.record_undo();
+            app.text.insert(0, idx + 1, text);
+            app.commit_undo();
+        }
+        app.undo_tree.set_time(0, 0);
+        app.undo_tree.set_time(1, 60);
+        app.undo_tree.set_time(2, 600);
+        app.undo_tree.set_time(3, 660);
+        run(&mut app, "earlier 5m");
+        assert_eq!(app.text.to_string(), "ab");
+        run(&mut app, "earlier 1m");
+        assert_eq!(app.text.to_string(), "a");
+        run(&mut app, "later 10m");
+        assert_eq!(app.text.to_string(), "abc");
+    }
+
+    #[test]
+    fn normal_runs_keys_per_line_as_one_undo_step() {
+        let mut app = App::new(None, "a\nb\nc".to_string());
+        run(&mut app, "%normal A;");
+        assert_eq!(app.text.to_string(), "a;\nb;\nc;");
+        run(&mut app, "1,2normal dd");
+        assert_eq!(app.text.to_string(), "c;");
+        app.undo();
+        assert_eq!(app.text.to_string(), "a;\nb;\nc;");
+        app.undo();
+        assert_eq!(app.text.to_string(), "a\nb\nc");
+    }
+
+    #[test]
+    fn global_tracks_lines_through_deletions() {
+        let mut app = App::new(None, "TODO a\nb\nTODO c\nTODO d\ne".to_string());
+        run(&mut app, "g/TODO/+1d");
+        assert_eq!(app.text.to_string(), "TODO a\nTODO c\ne");
+        app.undo();
+        assert_eq!(app.text.to_string(), "TODO a\nb\nTODO c\nTODO d\ne");
+        run(&mut app, "v/TODO/normal A!");
+        assert_eq!(app.text.to_string(), "TODO a\nb!\nTODO c\nTODO d\ne!");
+        run(&mut app, "g/TODO/m0");
+        assert_eq!(app.text.to_string(), "TODO d\nTODO c\nTODO a\nb!\ne!");
+        run(&mut app, "g/TODO/j");
+        assert_eq!(app.text.to_string(), "TODO d TODO c\nTODO a b!\ne!");
+        app.undo();
+        assert_eq!(app.text.to_string(), "TODO d\nTODO c\nTODO a\nb!\ne!");
+    }
+}
@@ -0,0 +1,523 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ignore::WalkBuilder;
+
+use super::App;
+use super::input::expand_tilde_path;
+use super::lsp::absolute_path;
+use super::lsp_features::display_path;
+
+pub(crate) struct Explorer {
+    pub(crate) root: PathBuf,
+    pub(crate) entries: Vec<ExplorerEntry>,
+    pub(crate) selected: usize,
+    pub(crate) scroll: usize,
+    pub(crate) focused: bool,
+    pub(crate) prompt: Option<ExplorerPrompt>,
+    expanded: HashSet<PathBuf>,
+    synced: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ExplorerEntry {
+    pub(crate) path: PathBuf,
+    pub(crate) name: String,
+    pub(crate) depth: usize,
+    pub(crate) is_dir: bool,
+    pub(crate) ignored: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ExplorerAction {
+    Create,
+    Rename,
+    Delete,
+}
+
+pub(crate) struct ExplorerPrompt {
+    pub(crate) action: ExplorerAction,
+    pub(crate) input: String,
+    target: PathBuf,
+}
+
+fn list_dir(dir: &Path, depth: usize, parent_ignored: bool) -> Vec<ExplorerEntry> {
+    let Ok(read) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let visible: HashSet<PathBuf> = WalkBuilder::new(dir)
+        .max_depth(Some(1))
+        .hidden(false)
+        .git_ignore(true)
+        .require_git(false)
+        .build()
+        .flatten()
+        .map(|entry| entry.into_path())
+        .collect();
+    let mut entries: Vec<ExplorerEntry> = read
+        .flatten()
+        .filter(|entry| entry.file_name() != ".git")
+        .map(|entry| {
+            let path = entry.path();
+            ExplorerEntry {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                is_dir: path.is_dir(),
+                ignored: parent_ignored || !visible.contains(&path),
+                depth,
+                path,
+            }
+        })
+        .collect();
+    entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+    entries
+}
+
+impl Explorer {
+    fn new(root: PathBuf) -> Self {
+        let mut explorer = Self {
+            root,
+            entries: Vec::new(),
+            selected: 0,
+            scroll: 0,
+            focused: true,
+            prompt: None,
+            expanded: HashSet::new(),
+            synced: None,
+        };
+        explorer.rebuild();
+        explorer
+    }
+
+    fn rebuild(&mut self) {
+        let selected = self.entries.get(self.selected).map(|entry| entry.path.clone());
+        let mut out = Vec::new();
+        self.push_children(&self.root.clone(), 0, false, &mut out);
+        self.entries = out;
+        self.select_path(selected.as_deref());
+    }
+
+    fn push_children(&mut self, dir: &Path, depth: usize, ignored: bool, out: &mut Vec<ExplorerEntry>) {
+        for entry in list_dir(dir, depth, ignored) {
+            let expand = entry.is_dir && self.expanded.contains(&entry.path);
+            let (path, ignored) = (entry.path.clone(), entry.ignored);
+            out.push(entry);
+            if expand {
+                self.push_children(&path, depth + 1, ignored, out);
+            }
+        }
+    }
+
+    fn select_path(&mut self, path: Option<&Path>) {
+        if let Some(idx) = path.and_then(|path| self.entries.iter().position(|entry| entry.path == path)) {
+            self.selected = idx;
+        }
+        self.selected = self.selected.min(self.entries.len().saturating_sub(1));
+    }
+
+    pub(crate) fn is_expanded(&self, path: &Path) -> bool {
+        self.expanded.contains(path)
+    }
+
+    fn selected_entry(&self) -> Option<&ExplorerEntry> {
+        self.entries.get(self.selected)
+    }
+
+    fn selected_dir(&self) -> PathBuf {
+        match self.selected_entry() {
+            Some(entry) if entry.is_dir => entry.path.clone(),
+            Some(entry) => entry.path.parent().map(Path::to_path_buf).unwrap_or_else(|| self.root.clone()),
+            None => self.root.clone(),
+        }
+    }
+
+    fn reveal(&mut self, path: &Path) {
+        let Ok(rel) = path.strip_prefix(&self.root) else {
+            return;
+        };
+        let mut dir = self.root.clone();
+        let components: Vec<_> = rel.components().collect();
+        for component in components.iter().take(components.len().saturating_sub(1)) {
+            dir.push(component);
+            self.expanded.insert(dir.clone());
+        }
+        self.rebuild();
+        self.select_path(Some(path));
+    }
+
+    fn display(&self, path: &Path) -> String {
+        match path.strip_prefix(&self.root) {
+            Ok(rel) if rel.as_os_str().is_empty() => ".".to_string(),
+            Ok(rel) => rel.display().to_string(),
+            Err(_) => path.display().to_string(),
+        }
+    }
+}
+
+impl App {
+    pub(crate) fn explorer_focused(&self) -> bool {
+        self.explorer.as_ref().is_some_and(|explorer| explorer.focused)
+    }
+
+    pub(super) fn focus_explorer(&mut self, focused: bool) -> bool {
+        match &mut self.explorer {
+            Some(explorer) => {
+                explorer.focused = focused;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn toggle_explorer(&mut self) {
+        if self.explorer.take().is_none() {
+            self.open_explorer(PathBuf::from("."));
+        }
+    }
+
+    pub(super) fn explore_command(&mut self, args: &str) {
+        if args.is_empty() {
+            self.toggle_explorer();
+            return;
+        }
+        let root = PathBuf::from(expand_tilde_path(args));
+        if !root.is_dir() {
+            self.set_status(format!("Not a directory: {}", root.display()));
+            return;
+        }
+        self.open_explorer(root);
+    }
+
+    fn open_explorer(&mut self, root: PathBuf) {
+        self.explorer = Some(Explorer::new(absolute_path(&root)));
+        self.sync_explorer();
+    }
+
+    pub(crate) fn sync_explorer(&mut self) {
+        let path = self.file_path.as_deref().map(absolute_path);
+        let Some(explorer) = &mut self.explorer else {
+            return;
+        };
+        if explorer.synced == path {
+            return;
+        }
+        if let Some(path) = &path {
+            explorer.reveal(path);
+        }
+        explorer.synced = path;
+    }
+
+    pub(crate) fn explorer_prompt_line(&self) -> Option<String> {
+        let explorer = self.explorer.as_ref()?;
+        let prompt = explorer.prompt.as_ref()?;
+        Some(match prompt.action {
+            ExplorerAction::Create => format!("Create in {}/: {}", explorer.display(&prompt.target), prompt.input),
+            ExplorerAction::Rename => format!("Move {} to: {}", explorer.display(&prompt.target), prompt.input),
+            ExplorerAction::Delete => format!("Delete {}? (y/n)", explorer.display(&prompt.target)),
+        })
+    }
+
+    pub(crate) fn handle_explorer_key(&mut self, key: KeyEvent) -> bool {
+        let Some(explorer) = &mut self.explorer else {
+            return false;
+        };
+        if explorer.prompt.is_some() {
+            self.handle_explorer_prompt_key(key);
+            return true;
+        }
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let last = explorer.entries.len().saturating_sub(1);
+        match key.code {
+            KeyCode::Char('w') if ctrl => return false,
+            KeyCode::Char(':') => return false,
+            KeyCode::Esc => explorer.focused = false,
+            KeyCode::Char('q') => self.explorer = None,
+            KeyCode::Char('j') | KeyCode::Down => explorer.selected = (explorer.selected + 1).min(last),
+            KeyCode::Char('k') | KeyCode::Up => explorer.selected = explorer.selected.saturating_sub(1),
+            KeyCode::Char('g') | KeyCode::Home => explorer.selected = 0,
+            KeyCode::Char('G') | KeyCode::End => explorer.selected = last,
+            KeyCode::Char('R') => {
+                explorer.rebuild();
+                self.set_status("Explorer refreshed");
+            }
+            KeyCode::Enter | KeyCode::Char('o') | KeyCode::Char('l') | KeyCode::Right => {
+                let Some(entry) = explorer.selected_entry().cloned() else {
+                    return true;
+                };
+                if !entry.is_dir {
+                    explorer.focused = false;
+                    self.open_or_switch_buffer(display_path(&entry.path), None);
+                    self.sync_explorer();
+                } else {
+                    let expand = matches!(key.code, KeyCode::Char('l') | KeyCode::Right)
+                        || !explorer.expanded.contains(&entry.path);
+                    if expand {
+                        explorer.expanded.insert(entry.path);
+                    } else {
+                        explorer.expanded.remove(&entry.path);
+                    }
+                    explorer.rebuild();
+                }
+            }
+            KeyCode::Char('h') | KeyCode::Left => {
+                let Some(entry) = explorer.selected_entry().cloned() else {
+                    return true;
+                };
+                if entry.is_dir && explorer.expanded.remove(&entry.path) {
+                    explorer.rebuild();
+                } else if let Some(parent) = entry.path.parent() {
+                    explorer.select_path(Some(parent));
+                }
+            }
+            KeyCode::Char('a') => {
+                explorer.prompt = Some(ExplorerPrompt {
+                    action: ExplorerAction::Create,
+                    input: String::new(),
+                    target: explorer.selected_dir(),
+                });
+            }
+            KeyCode::Char('r') | KeyCode::Char('m') => {
+                if let Some(entry) = explorer.selected_entry() {
+                    explorer.prompt = Some(ExplorerPrompt {
+                        action: ExplorerAction::Rename,
+                        input: explorer.display(&entry.path),
+                        target: entry.path.clone(),
+                    });
+                }
+            }
+            KeyCode::Char('d') => {
+                if let Some(entry) = explorer.selected_entry() {
+                    explorer.prompt = Some(ExplorerPrompt {
+                        action: ExplorerAction::Delete,
+                        input: String::new(),
+                        target: entry.path.clone(),
+                    });
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn handle_explorer_prompt_key(&mut self, key: KeyEvent) {
+        let Some(prompt) = self.explorer.as_mut().and_then(|explorer| explorer.prompt.as_mut()) else {
+            return;
+        };
+        if prompt.action == ExplorerAction::Delete {
+            let confirmed = key.code == KeyCode::Char('y');
+            if let Some(prompt) = self.explorer.as_mut().and_then(|explorer| explorer.prompt.take())
+                && confirmed
+            {
+                self.explorer_delete(&prompt.target);
+            }
+            return;
+        }
+        match key.code {
+            KeyCode::Esc => prompt.input.clear(),
+            KeyCode::Backspace => {
+                prompt.input.pop();
+                return;
+            }
+            KeyCode::Char(ch) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                prompt.input.push(ch);
+                return;
+            }
+            KeyCode::Enter => {}
+            _ => return,
+        }
+        let Some(prompt) = self.explorer.as_mut().and_then(|explorer| explorer.prompt.take()) else {
+            return;
+        };
+        if prompt.input.trim().is_empty() {
+            return;
+        }
+        match prompt.action {
+            ExplorerAction::Create => self.explorer_create(&prompt.target, prompt.input.trim()),
+            _ => self.explorer_rename(&prompt.target, prompt.input.trim()),
+        }
+    }
+
+    fn explorer_refresh(&mut self, select: Option<&Path>) {
+        if let Some(explorer) = &mut self.explorer {
+            explorer.rebuild();
+            if let Some(path) = select {
+                explorer.reveal(path);
+            }
+        }
+    }
+
+    fn explorer_create(&mut self, dir: &Path, name: &str) {
+        let path = dir.join(name.trim_end_matches('/'));
+        if path.exists() {
+            self.set_status(format!("{} already exists", display_path(&path).display()));
+            return;
+        }
+        let result = if name.ends_with('/') {
+            fs::create_dir_all(&path)
+        } else {
+            path.parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::File::create_new(&path).map(|_| ()))
+        };
+        match result {
+            Ok(()) => {
+                self.set_status(format!("Created {}", display_path(&path).display()));
+                self.explorer_refresh(Some(&path));
+            }
+            Err(err) => self.set_status(format!("Can't create {}: {}", path.display(), err)),
+        }
+    }
+
+    fn explorer_rename(&mut self, from: &Path, input: &str) {
+        let Some(root) = self.explorer.as_ref().map(|explorer| explorer.root.clone()) else {
+            return;
+        };
+        let to = root.join(expand_tilde_path(input));
+        if to == from {
+            return;
+        }
+        if to.exists() {
+            self.set_status(format!("{} already exists", display_path(&to).display()));
+            return;
+        }
+        let result = to
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::rename(from, &to));
+        match result {
+            Ok(()) => {
+                self.retarget_buffers(from, &to);
+                self.set_status(format!(
+                    "Moved {} to {}",
+                    display_path(from).display(),
+                    display_path(&to).display()
+                ));
+                self.explorer_refresh(Some(&to));
+            }
+            Err(err) => self.set_status(format!("Can't move {}: {}", from.display(), err)),
+        }
+    }
+
+    fn explorer_delete(&mut self, path: &Path) {
+        let result = if path.is_dir() {
+            fs::remove_dir_all(path)
+        } else {
+            fs::remove_file(path)
+        };
+        match result {
+            Ok(()) => {
+                self.set_status(format!("Deleted {}", display_path(path).display()));
+                self.explorer_refresh(None);
+            }
+            Err(err) => self.set_status(format!("Can't delete {}: {}", path.display(), err)),
+        }
+    }
+
+    fn retarget_buffers(&mut self, from: &Path, to: &Path) {
+        let retarget = |path: &mut Option<PathBuf>| {
+            let Some(current) = path.as_deref().map(absolute_path) else {
+                return;
+            };
+            if let Ok(rest) = current.strip_prefix(from) {
+                let moved = if rest.as_os_str().is_empty() { to.to_path_buf() } else { to.join(rest) };
+                *path = Some(display_path(&moved));
+            }
+        };
+        retarget(&mut self.file_path);
+        for slot in &mut self.buffers {
+            retarget(&mut slot.state.file_path);
+        }
+        if let Some(explorer) = &mut self.explorer {
+            explorer.synced = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::handle_key;
+
+    fn press(app: &mut App, keys: &str) {
+        for ch in keys.chars() {
+            let code = match ch {
+                '\n' => KeyCode::Enter,
+                _ => KeyCode::Char(ch),
+            };
+            handle_key(app, KeyEvent::new(code, KeyModifiers::NONE)).unwrap();
+        }
+    }
+
+    fn names(app: &App) -> Vec<String> {
+        app.explorer
+            .as_ref()
+            .unwrap()
+            .entries
+            .iter()
+            .map(|entry| {
+                let ignored = if entry.ignored { " (ignored)" } else { "" };
+                format!("{}{}{}", "  ".repeat(entry.depth), entry.name, ignored)
+            })
+            .collect()
+    }
+
+    fn selected_name(app: &App) -> String {
+        let explorer = app.explorer.as_ref().unwrap();
+        explorer.entries[explorer.selected].name.clone()
+    }
+
+    #[test]
+    fn explorer_tree_file_operations_and_sync() {
+        let root = std::env::temp_dir().join(format!("rvim-explorer-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("src/app")).unwrap();
+        std::fs::create_dir_all(root.join("target")).unwrap();
+        std::fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        std::fs::write(root.join("README.md"), "readme\n").unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() {}\n").unwrap();
+        std::fs::write(root.join("src/app/mod.rs"), "\n").unwrap();
+
+        let mut app = App::new(None, String::new());
+        app.explore_command(&root.display().to_string());
+        assert!(app.explorer_focused());
+        assert_eq!(names(&app), vec!["src", "target (ignored)", ".gitignore", "README.md"]);
+
+        press(&mut app, "\n");
+        assert_eq!(names(&app)[..4], ["src", "  app", "  main.rs", "target (ignored)"]);
+        press(&mut app, "jj\n");
+        assert!(!app.explorer_focused());
+        assert_eq!(app.file_path.as_deref(), Some(root.join("src/main.rs").as_path()));
+
+        app.open_or_switch_buffer(root.join("src/app/mod.rs"), None);
+        app.sync_explorer();
+        let explorer = app.explorer.as_ref().unwrap();
+        assert_eq!(explorer.entries[explorer.selected].path, root.join("src/app/mod.rs"));
+
+        app.focus_explorer(true);
+        press(&mut app, "anew/lib.rs\n");
+        assert!(root.join("src/app/new/lib.rs").is_file());
+        assert_eq!(selected_name(&app), "lib.rs");
+
+        press(&mut app, "j");
+        assert_eq!(selected_name(&app), "mod.rs");
+        press(&mut app, "r");
+        for _ in 0.."src/app/mod.rs".len() {
+            handle_key(&mut app, KeyEvent::new(KeyCode::Backspace, KeyModifiers::NONE)).unwrap();
+        }
+        press(&mut app, "src/lib.rs\n");
+        assert!(root.join("src/lib.rs").is_file());
+        assert!(!root.join("src/app/mod.rs").exists());
+        assert_eq!(app.file_path.as_deref(), Some(root.join("src/lib.rs").as_path()));
+
+        press(&mut app, "dn");
+        assert!(root.join("src/lib.rs").exists());
+        press(&mut app, "dy");
+        assert!(!root.join("src/lib.rs").exists());
+        let explorer = app.explorer.as_ref().unwrap();
+        assert!(!explorer.entries.iter().any(|entry| entry.path == root.join("src/lib.rs")));
+
+        press(&mut app, "q");
+        assert!(app.explorer.is_none());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,418 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+
+use super::buffer::TextBuffer;
+
+const BOM: char = '\u{feff}';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FileFormat {
+    Unix,
+    Dos,
+    Mac,
+}
+
+impl FileFormat {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            FileFormat::Unix => "unix",
+            FileFormat::Dos => "dos",
+            FileFormat::Mac => "mac",
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "unix" => Some(FileFormat::Unix),
+            "dos" => Some(FileFormat::Dos),
+            "mac" => Some(FileFormat::Mac),
+            _ => None,
+        }
+    }
+
+    fn line_ending(self) -> &'static str {
+        match self {
+            FileFormat::Unix => "\n",
+            FileFormat::Dos => "\r\n",
+            FileFormat::Mac => "\r",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FileOptions {
+    pub(crate) encoding: &'static Encoding,
+    pub(crate) format: FileFormat,
+    pub(crate) eol: bool,
+    pub(crate) bomb: bool,
+    pub(crate) decode_failed: bool,
+}
+
+impl Default for FileOptions {
+    fn default() -> Self {
+        Self {
+            encoding: UTF_8,
+            format: FileFormat::Unix,
+            eol: true,
+            bomb: false,
+            decode_failed: false,
+        }
+    }
+}
+
+impl FileOptions {
+    pub(crate) fn describe(&self) -> String {
+        let mut out = format!("{} {}", encoding_name(self.encoding), self.format.name());
+        if !self.eol {
+            out.push_str(" noeol");
+        }
+        if self.bomb {
+            out.push_str(" bomb");
+        }
+        if self.decode_failed {
+            out.push_str(" [conversion error]");
+        }
+        out
+    }
+}
+
+pub(crate) fn encoding_name(encoding: &'static Encoding) -> String {
+    if encoding == WINDOWS_1252 {
+        return "latin1".to_string();
+    }
+    encoding.name().to_ascii_lowercase()
+}
+
+pub(crate) fn encoding_for_name(name: &str) -> Option<&'static Encoding> {
+    match name.to_ascii_lowercase().as_str() {
+        "utf8" => Some(UTF_8),
+        "utf16le" | "ucs-2le" => Some(UTF_16LE),
+        "utf16be" | "utf-16" | "ucs-2" => Some(UTF_16BE),
+        "sjis" | "cp932" => Encoding::for_label(b"shift_jis"),
+        "cp1252" => Some(WINDOWS_1252),
+        other => Encoding::for_label(other.as_bytes()),
+    }
+}
+
+pub(crate) fn detect_encoding(bytes: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return encoding;
+    }
+    if std::str::from_utf8(bytes).is_ok() {
+        return UTF_8;
+    }
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    let guess = detector.guess(None, true);
+    if guess != UTF_8 && !guess.decode_without_bom_handling(bytes).1 {
+        guess
+    } else {
+        WINDOWS_1252
+    }
+}
+
+pub(crate) fn decode_bytes(
+    bytes: &[u8],
+    forced: Option<&'static Encoding>,
+) -> (TextBuffer, FileOptions) {
+    let encoding = forced.unwrap_or_else(|| detect_encoding(bytes));
+    let (content, had_errors) = encoding.decode_without_bom_handling(bytes);
+    let (text, opts) = decode_text(&content);
+    let opts = FileOptions {
+        encoding,
+        decode_failed: had_errors,
+        ..opts
+    };
+    (text, opts)
+}
+
+pub(crate) fn encode_bytes(text: &TextBuffer, opts: &FileOptions) -> Result<Vec<u8>> {
+    let content = encode_text(text, opts);
+    if opts.encoding == UTF_16LE {
+        return Ok(content.encode_utf16().flat_map(|unit| unit.to_le_bytes()).collect());
+    }
+    if opts.encoding == UTF_16BE {
+        return Ok(content.encode_utf16().flat_map(|unit| unit.to_be_bytes()).collect());
+    }
+    let (bytes, _, had_errors) = opts.encoding.encode(&content);
+    if had_errors {
+        bail!(
+            "text cannot be represented in {}",
+            encoding_name(opts.encoding)
+        );
+    }
+    Ok(bytes.into_owned())
+}
+
+pub(crate) fn read_file(
+    path: &Path,
+    forced: Option<&'static Encoding>,
+) -> Result<(TextBuffer, FileOptions)> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(decode_bytes(&bytes, forced)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            let opts = FileOptions {
+                encoding: forced.unwrap_or(UTF_8),
+                ..FileOptions::default()
+            };
+            Ok((TextBuffer::new(), opts))
+        }
+        Err(err) => Err(err).with_context(|| format!("read {}", path.display())),
+    }
+}
+
+pub(crate) fn detect_format(content: &str) -> FileFormat {
+    let lf = content.matches('\n').count();
+    let crlf = content.matches("\r\n").count();
+    if lf > 0 {
+        if crlf == lf {
+            FileFormat::Dos
+        } else {
+            FileFormat::Unix
+        }
+    } else if content.contains('\r') {
+        FileFormat::Mac
+    } else {
+        FileFormat::Unix
+    }
+}
+
+pub(crate) fn decode_text(content: &str) -> (TextBuffer, FileOptions) {
+    let (content, bomb) = match content.strip_prefix(BOM) {
+        Some(rest) => (rest, true),
+        None => (content, false),
+    };
+    let format = detect_format(content);
+    let ending = format.line_ending();
+    let (body, eol) = match content.strip_suffix(ending) {
+        Some(body) => (body, true),
+        None => (content, content.is_empty()),
+    };
+    let text = match format {
+        FileFormat::Unix => TextBuffer::from_text(body),
+        FileFormat::Dos => TextBuffer::from_text(&body.replace("\r\n", "\n")),
+        FileFormat::Mac => TextBuffer::from_text(&body.replace('\r', "\n")),
+    };
+    let opts = FileOptions {
+        format,
+        eol,
+        bomb,
+        ..FileOptions::default()
+    };
+    (text, opts)
+}
+
+pub(crate) fn encode_text(text: &TextBuffer, opts: &FileOptions) -> String {
+    let mut out = String::with_capacity(text.len_bytes() + 4);
+    if opts.bomb {
+        out.push(BOM);
+    }
+    if text.len_bytes() == 0 && text.line_count() <= 1 {
+        return out;
+    }
+    let ending = opts.format.line_ending();
+    for chunk in text.byte_range_chunks(0, text.len_bytes()) {
+        let chunk = String::from_utf8_lossy(chunk);
+        if opts.format == FileFormat::Unix {
+            out.push_str(&chunk);
+        } else {
+            out.push_str(&chunk.replace('\n', ending));
+        }
+    }
+    if opts.eol {
+        out.push_str(ending);
+    }
+    out
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct WriteOptions<'a> {
+    pub(crate) backup: bool,
+    pub(crate) backup_dir: Option<&'a Path>,
+}
+
+pub(crate) fn resolve_write_target(path: &Path) -> PathBuf {
+    let mut target = path.to_path_buf();
+    for _ in 0..32 {
+        let Ok(link) = fs::read_link(&target) else {
+            break;
+        };
+        target = match target.parent() {
+            Some(parent) if link.is_relative() => parent.join(link),
+            _ => link,
+        };
+    }
+    target
+}
+
+pub(crate) fn backup_path(target: &Path, backup_dir: Option<&Path>) -> PathBuf {
+    let name = target
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let backup_name = format!("{}~", name);
+    match backup_dir {
+        Some(dir) => dir.join(backup_name),
+        None => target.with_file_name(backup_name),
+    }
+}
+
+pub(crate) fn write_file(path: &Path, data: &[u8], opts: WriteOptions) -> Result<()> {
+    let target = resolve_write_target(path);
+    let meta = fs::metadata(&target).ok();
+    if opts.backup && meta.is_some() {
+        if let Some(dir) = opts.backup_dir {
+            fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+        }
+        let backup = backup_path(&target, opts.backup_dir);
+        fs::copy(&target, &backup).with_context(|| format!("backup to {}", backup.display()))?;
+    }
+    if meta.as_ref().is_some_and(has_hard_links) {
+        return write_in_place(&target, data);
+    }
+    let tmp = temp_path(&target);
+    let file = match File::create(&tmp) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::PermissionDenied => {
+            return write_in_place(&target, data);
+        }
+        Err(err) => return Err(err).with_context(|| format!("create {}", tmp.display())),
+    };
+    let result = write_temp(file, &tmp, data, meta.as_ref())
+        .and_then(|_| {
+            fs::rename(&tmp, &target).with_context(|| format!("rename to {}", target.display()))
+        });
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result?;
+    sync_parent(&target);
+    Ok(())
+}
+
+fn write_temp(mut file: File, tmp: &Path, data: &[u8], meta: Option<&fs::Metadata>) -> Result<()> {
+    file.write_all(data)
+        .and_then(|_| file.sync_all())
+        .with_context(|| format!("write {}", tmp.display()))?;
+    if let Some(meta) = meta {
+        fs::set_permissions(tmp, meta.permissions())
+            .with_context(|| format!("set permissions on {}", tmp.display()))?;
+    }
+    Ok(())
+}
+
+fn write_in_place(target: &Path, data: &[u8]) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .open(target)
+        .with_context(|| format!("open {}", target.display()))?;
+    file.write_all(data)
+        .and_then(|_| file.sync_all())
+        .with_context(|| format!("write {}", target.display()))
+}
+
+fn temp_path(target: &Path) -> PathBuf {
+    let name = target
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    target.with_file_name(format!(".{}.rvim-{}.tmp", name, std::process::id()))
+}
+
+#[cfg(unix)]
+fn has_hard_links(meta: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    meta.nlink() > 1
+}
+
+#[cfg(not(unix))]
+fn has_hard_links(_meta: &fs::Metadata) -> bool {
+    false
+}
+
+fn sync_parent(target: &Path) {
+    if let Some(parent) = target.parent().filter(|p| !p.as_os_str().is_empty())
+        && let Ok(dir) = File::open(parent)
+    {
+        let _ = dir.sync_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_encode_round_trips_line_endings_and_bom() {
+        for content in ["a\r\nb\r\n", "a\nb", "\u{feff}x\ny\n", "a\rb\r", "a\r\nb\n", ""] {
+            let (text, opts) = decode_text(content);
+            assert_eq!(encode_text(&text, &opts), content);
+        }
+        let (text, opts) = decode_text("\u{feff}one\r\ntwo");
+        assert_eq!(text.to_string(), "one\ntwo");
+        assert_eq!(opts.format, FileFormat::Dos);
+        assert!(!opts.eol && opts.bomb);
+    }
+
+    #[test]
+    fn non_utf8_bytes_decode_losslessly() {
+        let latin1: Vec<u8> = (0u8..=255).collect();
+        let (text, opts) = decode_bytes(&latin1, None);
+        assert_eq!(encoding_name(opts.encoding), "latin1");
+        assert!(!opts.decode_failed);
+        assert_eq!(encode_bytes(&text, &opts).unwrap(), latin1);
+
+        let sjis = b"\x82\xb1\x82\xf1\x82\xc9\x82\xbf\x82\xcd\x90\xa2\x8a\x45\n";
+        let (text, opts) = decode_bytes(sjis, None);
+        assert_eq!(text.to_string(), "こんにちは世界");
+        assert_eq!(encode_bytes(&text, &opts).unwrap(), sjis);
+
+        let (_, forced) = decode_bytes(&latin1, Some(UTF_8));
+        assert!(forced.decode_failed);
+
+        let utf16 = FileOptions {
+            encoding: encoding_for_name("utf-16le").unwrap(),
+            bomb: true,
+            ..FileOptions::default()
+        };
+        let bytes = encode_bytes(&TextBuffer::from_text("hi"), &utf16).unwrap();
+        assert_eq!(bytes, b"\xff\xfeh\x00i\x00\n\x00");
+        let (text, opts) = decode_bytes(&bytes, None);
+        assert_eq!(text.to_string(), "hi");
+        assert!(opts.bomb && opts.encoding == UTF_16LE);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_keeps_mode_follows_symlink_and_backs_up() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let dir = std::env::temp_dir().join(format!("rvim-fileio-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let real = dir.join("real.sh");
+        let link = dir.join("link.sh");
+        fs::write(&real, "old").unwrap();
+        fs::set_permissions(&real, fs::Permissions::from_mode(0o750)).unwrap();
+        symlink(&real, &link).unwrap();
+
+        let opts = WriteOptions {
+            backup: true,
+            backup_dir: None,
+        };
+        write_file(&link, b"new", opts).unwrap();
+
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(&real).unwrap(), "new");
+        assert_eq!(fs::metadata(&real).unwrap().permissions().mode() & 0o777, 0o750);
+        assert_eq!(fs::read_to_string(dir.join("real.sh~")).unwrap(), "old");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,98 @@
+fn is_boundary(prev: char, ch: char) -> bool {
+    !prev.is_alphanumeric() || (prev.is_lowercase() && ch.is_uppercase())
+}
+
+const MAX_GAP_PENALTY: usize = 8;
+
+pub(crate) fn fuzzy_score(pattern: &str, candidate: &str) -> Option<i64> {
+    if pattern.is_empty() {
+        return Some(0);
+    }
+    let case_sensitive = pattern.chars().any(char::is_uppercase);
+    let fold = |ch: char| {
+        if case_sensitive {
+            ch
+        } else {
+            ch.to_lowercase().next().unwrap_or(ch)
+        }
+    };
+    let pattern: Vec<char> = pattern.chars().map(fold).collect();
+    let chars: Vec<char> = candidate.chars().collect();
+    let folded: Vec<char> = chars.iter().copied().map(fold).collect();
+    let mut remaining = pattern.iter().peekable();
+    for ch in &folded {
+        if remaining.peek() == Some(&ch) {
+            remaining.next();
+        }
+    }
+    if remaining.peek().is_some() {
+        return None;
+    }
+
+    // best[j]: highest score with the current pattern char matched at candidate index j.
+    let n = chars.len();
+    let bonus = |idx: usize| {
+        16 + if idx == 0 {
+            12
+        } else if is_boundary(chars[idx - 1], chars[idx]) {
+            8
+        } else {
+            0
+        }
+    };
+    let mut best: Vec<Option<i64>> = (0..n)
+        .map(|idx| (folded[idx] == pattern[0]).then(|| bonus(idx) - idx.min(MAX_GAP_PENALTY) as i64))
+        .collect();
+    for &pc in &pattern[1..] {
+        let mut next = vec![None; n];
+        // Highest score at least MAX_GAP_PENALTY + 1 positions back, where the gap penalty is capped.
+        let mut far: Option<i64> = None;
+        for idx in 1..n {
+            if idx > MAX_GAP_PENALTY {
+                far = far.max(best[idx - MAX_GAP_PENALTY - 1]);
+            }
+            if folded[idx] != pc {
+                continue;
+            }
+            let mut prev = best[idx - 1].map(|score| score + 10);
+            for gap in 1..MAX_GAP_PENALTY.min(idx) {
+                prev = prev.max(best[idx - 1 - gap].map(|score| score - gap as i64));
+            }
+            prev = prev.max(far.map(|score| score - MAX_GAP_PENALTY as i64));
+            next[idx] = prev.map(|score| score + bonus(idx));
+        }
+        best = next;
+    }
+    let best = best.into_iter().flatten().max()?;
+    Some(best - (n - pattern.len().min(n)).min(20) as i64 / 2)
+}
+
+pub(crate) fn fuzzy_rank<'a>(pattern: &str, items: impl IntoIterator<Item = &'a String>) -> Vec<String> {
+    let mut scored: Vec<(i64, &String)> = items
+        .into_iter()
+        .filter_map(|item| Some((fuzzy_score(pattern, item)?, item)))
+        .collect();
+    scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    scored.into_iter().map(|(_, item)| item.clone()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranking_prefers_prefix_and_word_boundaries() {
+        let items: Vec<String> = ["format_line", "for_each", "buffer_format", "xfxoxr", "foo", "unrelated"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(
+            fuzzy_rank("fo", &items),
+            vec!["foo", "for_each", "format_line", "buffer_format", "xfxoxr"]
+        );
+        assert_eq!(fuzzy_rank("bf", &items), vec!["buffer_format"]);
+        assert!(fuzzy_score("FL", "format_line").is_none());
+        assert!(fuzzy_score("fl", "FormatLine").is_some());
+        assert_eq!(fuzzy_rank("", &items).len(), items.len());
+    }
+}
@@ -1,12 +1,15 @@
 use std::collections::HashMap;
 use std::fs;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
-use tree_sitter::{Language, Parser, Query, QueryCursor, Tree, StreamingIterator};
+use tree_sitter::{
+    InputEdit, Language, Node, Parser, Point, Query, QueryCursor, StreamingIterator, Tree,
+};
 
 use crate::logging::timestamp_prefix;
+use super::buffer::{ByteEdit, TextBuffer};
 use super::App;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -47,18 +50,15 @@ pub(crate) struct SyntaxState {
     query: Query,
     inline_parser: Option<Parser>,
     inline_query: Option<Query>,
-    source: String,
-    line_offsets: Vec<usize>,
+    source: TextBuffer,
     cache_tick: u64,
     debug_last_log_tick: u64,
-    cache: HashMap<usize, Vec<SyntaxSpan>>,
     line_cache: HashMap<usize, Vec<SyntaxSpan>>,
     line_cache_order: Vec<usize>,
     line_cache_limit: usize,
     viewport_tick: u64,
     viewport_start: usize,
     viewport_rows: usize,
-    viewport_cache: HashMap<usize, Vec<SyntaxSpan>>,
 }
 
 #[derive(Debug, Clone)]
@@ -75,12 +75,9 @@ fn detect_language(path: &Option<PathBuf>) -> Option<LanguageSpec> {
         .and_then(|p| p.extension())
         .and_then(|e| e.to_str())
         .map(|s| s.to_ascii_lowercase())?;
-    for spec in language_specs() {
-        if spec.extensions.iter().any(|e| *e == ext) {
-            return Some(spec);
-        }
-    }
-    None
+    language_specs()
+        .into_iter()
+        .find(|spec| spec.extensions.iter().any(|e| *e == ext))
 }
 
 pub fn detect_language_name(path: &Option<PathBuf>) -> Option<&'static str> {
@@ -95,16 +92,16 @@ pub fn has_query_for_language(path: &Option<PathBuf>) -> bool {
 
 pub(crate) fn syntax_spans_for_state(
     state: &mut SyntaxState,
-    lines: &[String],
+    text: &mut TextBuffer,
     start_row: usize,
     rows: usize,
     edit_tick: u64,
 ) -> HashMap<usize, Vec<SyntaxSpan>> {
-    if let Err(err) = state.sync(lines, edit_tick) {
+    if let Err(err) = state.sync(text, edit_tick) {
         debug_log(&format!("syntax: sync failed: {}", err));
         return HashMap::new();
     }
-    let line_count = lines.len();
+    let line_count = text.line_count();
     if line_count == 0 {
         return HashMap::new();
     }
@@ -119,7 +116,7 @@ pub(crate) fn syntax_spans_for_state(
         for row in start_row..end_row {
             if let Some(spans) = take_line_cache(state, row) {
                 if let Some(ms) = missing_start.take() {
-                    let partial = compute_spans_for_range(state, lines, ms, missing_len, edit_tick);
+                    let partial = compute_spans_for_range(state, ms, missing_len, edit_tick);
                     out.extend(partial);
                     missing_len = 0;
                 }
@@ -132,7 +129,7 @@ pub(crate) fn syntax_spans_for_state(
             }
         }
         if let Some(ms) = missing_start {
-            let partial = compute_spans_for_range(state, lines, ms, missing_len, edit_tick);
+            let partial = compute_spans_for_range(state, ms, missing_len, edit_tick);
             out.extend(partial);
         }
         for (row, spans) in &out {
@@ -141,7 +138,6 @@ pub(crate) fn syntax_spans_for_state(
         state.viewport_tick = edit_tick;
         state.viewport_start = start_row;
         state.viewport_rows = rows;
-        state.viewport_cache = out.clone();
         return out;
     }
     let mut out: HashMap<usize, Vec<SyntaxSpan>> = HashMap::new();
@@ -150,7 +146,7 @@ pub(crate) fn syntax_spans_for_state(
     for row in start_row..end_row {
         if let Some(spans) = take_line_cache(state, row) {
             if let Some(ms) = missing_start.take() {
-                let partial = compute_spans_for_range(state, lines, ms, missing_len, edit_tick);
+                let partial = compute_spans_for_range(state, ms, missing_len, edit_tick);
                 out.extend(partial);
                 missing_len = 0;
             }
@@ -163,7 +159,7 @@ pub(crate) fn syntax_spans_for_state(
         }
     }
     if let Some(ms) = missing_start {
-        let partial = compute_spans_for_range(state, lines, ms, missing_len, edit_tick);
+        let partial = compute_spans_for_range(state, ms, missing_len, edit_tick);
         out.extend(partial);
     }
     for (row, spans) in &out {
@@ -172,28 +168,30 @@ pub(crate) fn syntax_spans_for_state(
     state.viewport_tick = edit_tick;
     state.viewport_start = start_row;
     state.viewport_rows = rows;
-    state.viewport_cache = out.clone();
     out
 }
 
+pub(crate) fn spans_for_text(
+    path: &Path,
+    text: &mut TextBuffer,
+    rows: usize,
+) -> Option<HashMap<usize, Vec<SyntaxSpan>>> {
+    let spec = detect_language(&Some(path.to_path_buf()))?;
+    let query = load_query_for_language(&spec)?;
+    let mut state = SyntaxState::new(spec, query).ok()?;
+    Some(syntax_spans_for_state(&mut state, text, 0, rows, 1))
+}
+
 fn compute_spans_for_range(
     state: &mut SyntaxState,
-    lines: &[String],
     start_row: usize,
     rows: usize,
     edit_tick: u64,
 ) -> HashMap<usize, Vec<SyntaxSpan>> {
-    let line_count = lines.len();
-    if line_count == 0 {
-        return HashMap::new();
-    }
+    let line_count = state.source.line_count();
     let end_row = (start_row + rows).min(line_count);
-    let start_byte = state.line_offsets.get(start_row).copied().unwrap_or(0);
-    let end_byte = state
-        .line_offsets
-        .get(end_row)
-        .copied()
-        .unwrap_or(state.source.len());
+    let start_byte = state.source.line_to_byte(start_row);
+    let end_byte = state.source.line_to_byte(end_row);
     let tree = match state.tree.as_ref() {
         Some(tree) => tree,
         None => return HashMap::new(),
@@ -202,7 +200,10 @@ fn compute_spans_for_range(
     cursor.set_byte_range(start_byte..end_byte);
     let mut out: HashMap<usize, Vec<SyntaxSpan>> = HashMap::new();
     let root = tree.root_node();
-    let mut captures = cursor.captures(&state.query, root, state.source.as_bytes());
+    let source = &state.source;
+    let mut captures = cursor.captures(&state.query, root, |node: Node| {
+        source.byte_range_chunks(node.start_byte(), node.end_byte())
+    });
     loop {
         captures.advance();
         let Some((m, idx)) = captures.get() else {
@@ -219,29 +220,24 @@ fn compute_spans_for_range(
         if start >= end {
             continue;
         }
-        let start_row_cap = node.start_position().row as usize;
-        let end_row_cap = node.end_position().row as usize;
+        let start_row_cap = node.start_position().row;
+        let end_row_cap = node.end_position().row;
         for row in start_row_cap..=end_row_cap {
             if row < start_row || row >= end_row {
                 continue;
             }
-            let line = match lines.get(row) {
-                Some(line) => line,
-                None => continue,
+            let Some(line) = source.line(row) else {
+                continue;
             };
-            let line_start = state.line_offsets.get(row).copied().unwrap_or(0);
-            let line_end = state
-                .line_offsets
-                .get(row + 1)
-                .copied()
-                .unwrap_or(state.source.len());
+            let line_start = source.line_to_byte(row);
+            let line_end = source.line_to_byte(row + 1);
             let seg_start = if row == start_row_cap { start } else { line_start };
             let seg_end = if row == end_row_cap { end } else { line_end };
             if seg_end <= seg_start || seg_start < line_start || seg_end > line_end {
                 continue;
             }
             if let Some((start_col, end_col)) =
-                byte_range_to_col_range(line, line_start, seg_start, seg_end)
+                byte_range_to_col_range(&line, line_start, seg_start, seg_end)
             {
                 out.entry(row)
                     .or_default()
@@ -249,9 +245,9 @@ fn compute_spans_for_range(
             }
         }
     }
-    add_markdown_inline_spans(state, lines, start_row, end_row, &mut out);
+    add_markdown_inline_spans(state, start_row, end_row, &mut out);
     for spans in out.values_mut() {
-        spans.sort_by(|a, b| (a.start_col, a.end_col).cmp(&(b.start_col, b.end_col)));
+        spans.sort_by_key(|a| (a.start_col, a.end_col));
         normalize_spans(spans);
     }
     if std::env::var("RVIM_DEBUG_SYNTAX").ok().as_deref() == Some("1") {
@@ -269,14 +265,14 @@ fn compute_spans_for_range(
         debug_log("syntax: no spans produced for viewport");
     }
     for row in start_row..end_row {
-        out.entry(row).or_insert_with(Vec::new);
+        out.entry(row).or_default();
     }
     out
 }
 
 fn cache_line_spans(state: &mut SyntaxState, row: usize, spans: Vec<SyntaxSpan>) {
-    if state.line_cache.contains_key(&row) {
-        state.line_cache.insert(row, spans);
+    if let std::collections::hash_map::Entry::Occupied(mut e) = state.line_cache.entry(row) {
+        e.insert(spans);
         return;
     }
     state.line_cache.insert(row, spans);
@@ -312,7 +308,6 @@ fn load_markdown_inline_query() -> Option<QuerySource> {
 
 fn add_markdown_inline_spans(
     state: &mut SyntaxState,
-    lines: &[String],
     start_row: usize,
     end_row: usize,
     out: &mut HashMap<usize, Vec<SyntaxSpan>>,
@@ -335,15 +330,13 @@ fn add_markdown_inline_spans(
             if inline_start >= inline_end {
                 continue;
             }
-            let inline_start_row = node.start_position().row as usize;
-            let inline_end_row = node.end_position().row as usize;
+            let inline_start_row = node.start_position().row;
+            let inline_end_row = node.end_position().row;
             if inline_end_row < start_row || inline_start_row >= end_row {
                 continue;
             }
-            let Some(slice) = state.source.get(inline_start..inline_end) else {
-                continue;
-            };
-            let inline_tree = match inline_parser.parse(slice, None) {
+            let slice = state.source.byte_range_to_string(inline_start, inline_end);
+            let inline_tree = match inline_parser.parse(&slice, None) {
                 Some(tree) => tree,
                 None => continue,
             };
@@ -366,29 +359,24 @@ fn add_markdown_inline_spans(
                 if start >= end {
                     continue;
                 }
-                let start_row_cap = inline_start_row + node.start_position().row as usize;
-                let end_row_cap = inline_start_row + node.end_position().row as usize;
+                let start_row_cap = inline_start_row + node.start_position().row;
+                let end_row_cap = inline_start_row + node.end_position().row;
                 for row in start_row_cap..=end_row_cap {
                     if row < start_row || row >= end_row {
                         continue;
                     }
-                    let line = match lines.get(row) {
-                        Some(line) => line,
-                        None => continue,
+                    let Some(line) = state.source.line(row) else {
+                        continue;
                     };
-                    let line_start = state.line_offsets.get(row).copied().unwrap_or(0);
-                    let line_end = state
-                        .line_offsets
-                        .get(row + 1)
-                        .copied()
-                        .unwrap_or(state.source.len());
+                    let line_start = state.source.line_to_byte(row);
+                    let line_end = state.source.line_to_byte(row + 1);
                     let seg_start = if row == start_row_cap { start } else { line_start };
                     let seg_end = if row == end_row_cap { end } else { line_end };
                     if seg_end <= seg_start || seg_start < line_start || seg_end > line_end {
                         continue;
                     }
                     if let Some((start_col, end_col)) =
-                        byte_range_to_col_range(line, line_start, seg_start, seg_end)
+                        byte_range_to_col_range(&line, line_start, seg_start, seg_end)
                     {
                         out.entry(row)
                             .or_default()
@@ -425,8 +413,8 @@ impl App {
                 lang,
                 query,
                 state.tree.is_some(),
-                state.source.len(),
-                state.line_offsets.len(),
+                state.source.len_bytes(),
+                state.source.line_count(),
                 state.cache_tick,
                 self.edit_tick
             ),
@@ -475,38 +463,93 @@ impl SyntaxState {
             query,
             inline_parser,
             inline_query,
-            source: String::new(),
-            line_offsets: Vec::new(),
+            source: TextBuffer::new(),
             cache_tick: u64::MAX,
             debug_last_log_tick: u64::MAX,
-            cache: HashMap::new(),
             line_cache: HashMap::new(),
             line_cache_order: Vec::new(),
             line_cache_limit: 2000,
             viewport_tick: u64::MAX,
             viewport_start: 0,
             viewport_rows: 0,
-            viewport_cache: HashMap::new(),
         })
     }
 
-    fn sync(&mut self, lines: &[String], edit_tick: u64) -> Result<()> {
-        if self.cache_tick == edit_tick && self.tree.is_some() {
-            return Ok(());
+    fn sync(&mut self, text: &mut TextBuffer, edit_tick: u64) -> Result<()> {
+        let edits = text.take_byte_edits();
+        let (mut old_tree, edits) = match (self.tree.take(), edits) {
+            (Some(tree), Some(edits)) if edits.is_empty() => {
+                self.tree = Some(tree);
+                self.cache_tick = edit_tick;
+                return Ok(());
+            }
+            (Some(tree), Some(edits)) => (Some(tree), edits),
+            _ => (None, Vec::new()),
+        };
+        if let Some(tree) = old_tree.as_mut() {
+            for edit in &edits {
+                tree.edit(&InputEdit {
+                    start_byte: edit.start_byte,
+                    old_end_byte: edit.old_end_byte,
+                    new_end_byte: edit.new_end_byte,
+                    start_position: Point::new(edit.start_point.0, edit.start_point.1),
+                    old_end_position: Point::new(edit.old_end_point.0, edit.old_end_point.1),
+                    new_end_position: Point::new(edit.new_end_point.0, edit.new_end_point.1),
+                });
+            }
         }
-        let (source, offsets) = build_source(lines);
-        let tree = self.parser.parse(&source, None).context("parse")?;
-        self.source = source;
-        self.line_offsets = offsets;
+        let tree = self
+            .parser
+            .parse_with_options(
+                &mut |byte, _| text.chunk_from_byte(byte),
+                old_tree.as_ref(),
+                None,
+            )
+            .context("parse")?;
+        match old_tree {
+            Some(old_tree) => {
+                self.shift_rows(&edits);
+                let rows: Vec<(usize, usize)> = old_tree
+                    .changed_ranges(&tree)
+                    .map(|range| (range.start_point.row, range.end_point.row))
+                    .collect();
+                self.line_cache
+                    .retain(|row, _| !rows.iter().any(|(start, end)| row >= start && row <= end));
+                let line_cache = &self.line_cache;
+                self.line_cache_order.retain(|row| line_cache.contains_key(row));
+            }
+            None => {
+                self.line_cache.clear();
+                self.line_cache_order.clear();
+            }
+        }
+        self.source = text.snapshot();
         self.tree = Some(tree);
         self.cache_tick = edit_tick;
-        self.cache.clear();
-        self.line_cache.clear();
-        self.line_cache_order.clear();
         self.viewport_tick = u64::MAX;
-        self.viewport_cache.clear();
         Ok(())
     }
+
+    // Rows an edit touched are dropped; rows below it move by the edit's line delta.
+    fn shift_rows(&mut self, edits: &[ByteEdit]) {
+        for edit in edits {
+            let (start, old_end) = (edit.start_point.0, edit.old_end_point.0);
+            let new_end = edit.new_end_point.0;
+            let shift = |row: usize| match row {
+                row if row < start => Some(row),
+                row if row <= old_end => None,
+                row => Some(row - old_end + new_end),
+            };
+            self.line_cache = std::mem::take(&mut self.line_cache)
+                .into_iter()
+                .filter_map(|(row, spans)| shift(row).map(|row| (row, spans)))
+                .collect();
+            self.line_cache_order = std::mem::take(&mut self.line_cache_order)
+                .into_iter()
+                .filter_map(shift)
+                .collect();
+        }
+    }
 }
 
 fn language_specs() -> Vec<LanguageSpec> {
@@ -672,23 +715,6 @@ fn capture_to_kind(name: &str) -> Option<HighlightKind> {
     }
 }
 
-fn build_source(lines: &[String]) -> (String, Vec<usize>) {
-    let mut source = String::new();
-    let mut offsets = Vec::with_capacity(lines.len() + 1);
-    let mut offset = 0usize;
-    for (idx, line) in lines.iter().enumerate() {
-        offsets.push(offset);
-        source.push_str(line);
-        offset += line.len();
-        if idx + 1 < lines.len() {
-            source.push('\n');
-            offset += 1;
-        }
-    }
-    offsets.push(offset);
-    (source, offsets)
-}
-
 fn byte_range_to_col_range(
     line: &str,
     line_start: usize,
@@ -724,14 +750,13 @@ fn byte_range_to_col_range(
 fn normalize_spans(spans: &mut Vec<SyntaxSpan>) {
     let mut out: Vec<SyntaxSpan> = Vec::with_capacity(spans.len());
     for span in spans.drain(..) {
-        if let Some(last) = out.last_mut() {
-            if span.start_col < last.end_col {
+        if let Some(last) = out.last_mut()
+            && span.start_col < last.end_col {
                 if span.end_col > last.end_col {
                     *last = span;
                 }
                 continue;
             }
-        }
         out.push(span);
     }
     spans.extend(out);
@@ -761,12 +786,33 @@ impl App {
         };
         Some(syntax_spans_for_state(
             state,
-            &self.lines,
+            &mut self.text,
             start_row,
             rows,
             self.edit_tick,
         ))
     }
+
+    pub(crate) fn syntax_spans_for_buffer(
+        &mut self,
+        buffer_id: usize,
+        start_row: usize,
+        rows: usize,
+    ) -> Option<HashMap<usize, Vec<SyntaxSpan>>> {
+        if buffer_id == self.current_buffer_id {
+            return self.syntax_spans_for_viewport(start_row, rows);
+        }
+        let slot = self.buffers.iter_mut().find(|slot| slot.id == buffer_id)?;
+        let spec = detect_language(&slot.state.file_path)?;
+        let state = ensure_syntax_state(&mut self.syntax_by_buffer, buffer_id, spec)?;
+        Some(syntax_spans_for_state(
+            state,
+            &mut slot.state.text,
+            start_row,
+            rows,
+            slot.state.edit_tick,
+        ))
+    }
 }
 
 fn debug_log(message: &str) {
@@ -794,3 +840,46 @@ const RUST_QUERY: &str = r#"
 (type_identifier) @type
 (primitive_type) @type
 "#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rust_state() -> SyntaxState {
+        let spec = detect_language(&Some(PathBuf::from("main.rs"))).unwrap();
+        let query = load_query_for_language(&spec).unwrap();
+        SyntaxState::new(spec, query).unwrap()
+    }
+
+    #[test]
+    fn incremental_sync_matches_full_parse() {
+        let mut text = TextBuffer::from_text("fn main() {\n    let x = 1;\n}\n");
+        let mut state = rust_state();
+        syntax_spans_for_state(&mut state, &mut text, 0, 10, 1);
+        text.insert(1, 4, "// ");
+        text.insert_line(2, "    let s = \"hi\";");
+        let incremental = syntax_spans_for_state(&mut state, &mut text, 0, 10, 2);
+
+        let mut fresh_text = TextBuffer::from_text(&text.to_string());
+        let full = syntax_spans_for_state(&mut rust_state(), &mut fresh_text, 0, 10, 1);
+        assert_eq!(incremental, full);
+        assert_eq!(state.tree.as_ref().unwrap().root_node().to_sexp(), {
+            let mut fresh = rust_state();
+            fresh.sync(&mut fresh_text, 2).unwrap();
+            fresh.tree.unwrap().root_node().to_sexp()
+        });
+    }
+
+    #[test]
+    fn line_insert_shifts_cached_rows() {
+        let mut text = TextBuffer::from_text("fn a() {}\nfn b() {}\nfn c() {}\nlet x = 1;\n");
+        let mut state = rust_state();
+        let before = syntax_spans_for_state(&mut state, &mut text, 0, 10, 1);
+        text.insert_line(1, "// note");
+        state.sync(&mut text, 2).unwrap();
+        assert_eq!(state.line_cache.get(&3), before.get(&2));
+        assert_eq!(state.line_cache.get(&4), before.get(&3));
+        assert!(!state.line_cache.contains_key(&1));
+        assert_eq!(state.line_cache.len(), state.line_cache_order.len());
+    }
+}
@@ -1,9 +1,15 @@
 use anyhow::Result;
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use std::fs;
+use std::rc::Rc;
+
+use super::completion::{FilePaths, WholeLines};
 
 use super::edit::selection_to_last_visual;
-use super::keymap::{KeyAction, KeymapResult};
+use super::events::EditorEvent;
+use super::keymap::{key_notation, KeyAction, KeymapResult};
+use super::macros::is_macro_register;
+use super::registers::is_register_name;
 use super::types::{
     char_to_byte_idx, CommandPrompt, FindPending, FindSpec, Mode, Operator, OperatorPending,
     RepeatKey, TextObjectKind, TextObjectPending, TextObjectTarget, VisualSelectionKind,
@@ -11,10 +17,122 @@ use super::types::{
 use super::App;
 
 pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
+    let mode = app.mode;
+    let (buffer, edit_tick) = (app.current_buffer_id, app.edit_tick);
+    let was_insert = app.mode == Mode::Insert;
+    let register_key = app.insert_register_pending;
+    let motion = app.is_macro_motion(&key);
+    let cursor = (app.cursor_row, app.cursor_col);
+    app.record_macro_key(&key);
+    let result = handle_key_inner(app, key);
+    if motion && (app.cursor_row, app.cursor_col) == cursor {
+        app.fail_macro();
+    }
+    if was_insert && !register_key {
+        capture_insert_key(app, &key);
+    }
+    if app.mode == Mode::Insert && !was_insert {
+        app.insert_capture.clear();
+    } else if was_insert && app.mode != Mode::Insert {
+        app.registers.last_insert = std::mem::take(&mut app.insert_capture);
+        app.text.set_mark('^', (app.cursor_row, app.cursor_col));
+    }
+    app.update_incsearch();
+    app.guard_quickfix_buffer(buffer, edit_tick);
+    app.fire_event(EditorEvent::KeyPressed(key_notation(&key)));
+    if app.mode != mode {
+        app.fire_event(EditorEvent::ModeChanged(mode, app.mode));
+    }
+    result
+}
+
+fn capture_insert_key(app: &mut App, key: &KeyEvent) {
+    match key.code {
+        KeyCode::Char(ch)
+            if !key
+                .modifiers
+                .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+        {
+            app.insert_capture.push(ch)
+        }
+        KeyCode::Enter => app.insert_capture.push('\n'),
+        KeyCode::Tab => app.insert_capture.push_str("    "),
+        KeyCode::Backspace => {
+            app.insert_capture.pop();
+        }
+        _ => {}
+    }
+}
+
+fn handle_key_inner(app: &mut App, key: KeyEvent) -> Result<bool> {
     app.log_key_event(&format!(
         "mode={:?} code={:?} mods={:?}",
         app.mode, key.code, key.modifiers
     ));
+    app.last_input = std::time::Instant::now();
+    if app.swap_prompt.is_some() {
+        app.handle_swap_prompt(key);
+        return Ok(false);
+    }
+    if app.substitute_confirm.is_some() {
+        app.handle_substitute_confirm(key);
+        return Ok(false);
+    }
+    if app.file_picker.is_some() {
+        app.handle_file_picker_key(key);
+        return Ok(false);
+    }
+    if app.insert_register_pending {
+        app.insert_register_pending = false;
+        if app.repeat_recording
+            && !app.repeat_replaying
+            && app.repeat_buffer.last().is_some_and(|last| {
+                last.code == KeyCode::Char('r') && last.modifiers == KeyModifiers::CONTROL
+            })
+        {
+            app.repeat_buffer.pop();
+        }
+        if let KeyCode::Char(ch) = key.code
+            && is_register_name(ch)
+        {
+            app.insert_register(ch);
+        }
+        return Ok(false);
+    }
+    if app.awaiting_register {
+        app.awaiting_register = false;
+        match key.code {
+            KeyCode::Char(ch) if is_register_name(ch) => app.pending_register = Some(ch),
+            _ => app.pending_count = None,
+        }
+        return Ok(false);
+    }
+    if app.pending_macro_record {
+        app.pending_macro_record = false;
+        if let KeyCode::Char(ch) = key.code
+            && is_macro_register(ch)
+        {
+            app.start_macro_recording(ch);
+        }
+        return Ok(false);
+    }
+    if let Some(count) = app.pending_window.take() {
+        return match key.code {
+            KeyCode::Char(ch) => app.window_command(ch, count),
+            _ => Ok(false),
+        };
+    }
+    if app.mode == Mode::Normal && app.explorer_focused() && app.handle_explorer_key(key) {
+        return Ok(false);
+    }
+    if let Some(count) = app.pending_macro_play.take() {
+        return match key.code {
+            KeyCode::Char(ch) if is_macro_register(ch) || matches!(ch, '@' | ':' | '+' | '*') => {
+                app.play_macro(ch, count)
+            }
+            _ => Ok(false),
+        };
+    }
     let pre_tick = app.change_tick;
     if !app.repeat_replaying && !app.repeat_recording && should_start_repeat(app, &key) {
         app.repeat_recording = true;
@@ -35,8 +153,17 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
     if !is_quit {
         app.quit_confirm = false;
     }
+    if app.mode == Mode::Insert
+        && app.insert_completion.is_some()
+        && !keeps_insert_completion(&key)
+    {
+        app.close_insert_completion();
+    }
     if app.pending_g
-        && !matches!(key.code, KeyCode::Char('g') | KeyCode::Char('v'))
+        && !matches!(
+            key.code,
+            KeyCode::Char('g' | 'v' | '-' | '+' | 't' | 'T' | 'd' | 'r')
+        )
         && key.modifiers == KeyModifiers::NONE
     {
         app.pending_g = false;
@@ -58,8 +185,7 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
     if app.mode == Mode::Normal
         && key.modifiers == KeyModifiers::NONE
         && matches!(key.code, KeyCode::Char(ch) if ch.is_ascii_digit())
-    {
-        if let KeyCode::Char(ch) = key.code {
+        && let KeyCode::Char(ch) = key.code {
             let digit = ch.to_digit(10).unwrap_or(0) as usize;
             if app.pending_count.is_some() || digit != 0 {
                 let next = app.pending_count.unwrap_or(0) * 10 + digit;
@@ -67,12 +193,11 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
                 return Ok(false);
             }
         }
-    }
     if matches!(
         app.mode,
         Mode::Normal | Mode::VisualChar | Mode::VisualLine | Mode::VisualBlock
-    ) {
-        if let Some(pending) = app.pending_find.take() {
+    )
+        && let Some(pending) = app.pending_find.take() {
             if let KeyCode::Char(ch) = key.code {
                 let found = if pending.reverse {
                     app.find_backward(ch, pending.until)
@@ -80,6 +205,7 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
                     app.find_forward(ch, pending.until)
                 };
                 if !found {
+                    app.fail_macro();
                     app.set_status(format!(
                         "Pattern not found: {}{}",
                         if pending.reverse { "F" } else { "f" },
@@ -93,8 +219,8 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
                     });
                 }
             }
-            if app.mode == Mode::Normal {
-                if let Some(op) = app.operator_pending.take() {
+            if app.mode == Mode::Normal
+                && let Some(op) = app.operator_pending.take() {
                     app.apply_operator(
                         op.op,
                         (op.start_row, op.start_col),
@@ -105,10 +231,23 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
                         app.set_status("-- INSERT --");
                     }
                 }
-            }
             finalize_repeat(app, pre_tick);
             return Ok(false);
         }
+
+    if app.mode == Mode::Normal && app.pending_mark {
+        app.pending_mark = false;
+        if let KeyCode::Char(ch) = key.code {
+            app.set_mark(ch);
+        }
+        return Ok(false);
+    }
+    if let Some(linewise) = app.pending_mark_jump.take() {
+        if let KeyCode::Char(ch) = key.code {
+            app.jump_to_mark(ch, linewise);
+        }
+        finalize_repeat(app, pre_tick);
+        return Ok(false);
     }
 
     if let Some(pending) = app.pending_textobj.take() {
@@ -163,12 +302,15 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
         return Ok(false);
     }
 
+    let ctrl_x = std::mem::take(&mut app.pending_ctrl_x);
     match app.mode {
         Mode::Normal => match (key.code, key.modifiers) {
             (KeyCode::Esc, _) => {
                 app.operator_pending = None;
                 app.pending_textobj = None;
                 app.pending_find = None;
+                app.pending_mark = false;
+                app.pending_register = None;
                 app.pending_g = false;
                 app.last_search = None;
                 app.pending_count = None;
@@ -177,10 +319,23 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
                 replay_last_change(app)?;
                 return Ok(false);
             }
+            (KeyCode::Enter, KeyModifiers::NONE) if app.is_quickfix_buffer(app.current_buffer_id) => {
+                app.quickfix_open_at_cursor();
+            }
             (KeyCode::Char('r'), KeyModifiers::CONTROL) => app.redo(),
             (KeyCode::Char('z'), KeyModifiers::CONTROL) => app.undo(),
             (KeyCode::Char('u'), KeyModifiers::NONE) => app.undo(),
             (KeyCode::Char('U'), _) => app.undo_line(),
+            (KeyCode::Char('-'), KeyModifiers::NONE) if app.pending_g => {
+                app.pending_g = false;
+                let count = app.pending_count.take().unwrap_or(1);
+                app.undo_chrono(count, false);
+            }
+            (KeyCode::Char('+'), _) if app.pending_g => {
+                app.pending_g = false;
+                let count = app.pending_count.take().unwrap_or(1);
+                app.undo_chrono(count, true);
+            }
             (KeyCode::Char('q'), KeyModifiers::CONTROL) => {
                 if app.dirty && !app.quit_confirm {
                     app.quit_confirm = true;
@@ -189,7 +344,9 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
                 }
                 return Ok(true);
             }
-            (KeyCode::Char('s'), KeyModifiers::CONTROL) => app.save()?,
+            (KeyCode::Char('s'), KeyModifiers::CONTROL) => {
+                app.save();
+            }
             (KeyCode::Char('i'), KeyModifiers::NONE) => {
                 if app.operator_pending.is_some() {
                     app.pending_textobj = Some(TextObjectPending {
@@ -276,6 +433,7 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
                 app.command_cursor = 0;
                 app.search_history_index = None;
                 app.operator_pending = None;
+                app.begin_search_prompt();
             }
             (KeyCode::Char('?'), KeyModifiers::NONE) => {
                 app.mode = Mode::Command;
@@ -284,6 +442,50 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
                 app.command_cursor = 0;
                 app.search_history_index = None;
                 app.operator_pending = None;
+                app.begin_search_prompt();
+            }
+            (KeyCode::Char('m'), KeyModifiers::NONE) => {
+                app.pending_mark = true;
+            }
+            (KeyCode::Char('\''), _) => {
+                app.pending_mark_jump = Some(true);
+            }
+            (KeyCode::Char('`'), _) => {
+                app.pending_mark_jump = Some(false);
+            }
+            (KeyCode::Char('o'), KeyModifiers::CONTROL) => {
+                let count = app.pending_count.take().unwrap_or(1);
+                app.jump_older(count);
+            }
+            (KeyCode::Char('w'), KeyModifiers::CONTROL) => {
+                app.pending_window = Some(app.pending_count.take().unwrap_or(0));
+            }
+            (KeyCode::Char('t'), KeyModifiers::NONE) if app.pending_g => {
+                app.pending_g = false;
+                let count = app.pending_count.take().unwrap_or(0);
+                app.next_tab(count, true);
+            }
+            (KeyCode::Char('T'), _) if app.pending_g => {
+                app.pending_g = false;
+                let count = app.pending_count.take().unwrap_or(1);
+                app.next_tab(count, false);
+            }
+            (KeyCode::Tab, _) | (KeyCode::Char('i'), KeyModifiers::CONTROL) => {
+                let count = app.pending_count.take().unwrap_or(1);
+                app.jump_newer(count);
+            }
+            (KeyCode::Char('"'), _) => {
+                app.awaiting_register = true;
+            }
+            (KeyCode::Char('q'), KeyModifiers::NONE) => {
+                if app.macro_recording.is_some() {
+                    app.stop_macro_recording();
+                } else {
+                    app.pending_macro_record = true;
+                }
+            }
+            (KeyCode::Char('@'), _) => {
+                app.pending_macro_play = Some(app.pending_count.take().unwrap_or(1));
             }
             (KeyCode::Char(':'), KeyModifiers::NONE) => {
                 app.mode = Mode::Command;
@@ -293,16 +495,24 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
                 app.search_history_index = None;
                 app.operator_pending = None;
             }
+            (KeyCode::Char('d'), KeyModifiers::NONE) if app.pending_g => {
+                app.pending_g = false;
+                app.lsp_definition();
+            }
+            (KeyCode::Char('r'), KeyModifiers::NONE) if app.pending_g => {
+                app.pending_g = false;
+                app.lsp_references();
+            }
+            (KeyCode::Char('K'), _) => app.lsp_hover(),
             (KeyCode::Char('d'), KeyModifiers::NONE) => {
                 let mut handled = false;
-                if let Some(op) = app.operator_pending.take() {
-                    if op.op == Operator::Delete {
-                        app.yank_line(app.cursor_row);
+                if let Some(op) = app.operator_pending.take()
+                    && op.op == Operator::Delete {
+                        app.register_lines(app.cursor_row, app.cursor_row, true);
                         app.delete_line(app.cursor_row);
                         app.operator_pending = None;
                         handled = true;
                     }
-                }
                 if !handled {
                     app.operator_pending = Some(OperatorPending {
                         op: Operator::Delete,
@@ -313,13 +523,12 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
             }
             (KeyCode::Char('y'), KeyModifiers::NONE) => {
                 let mut handled = false;
-                if let Some(op) = app.operator_pending.take() {
-                    if op.op == Operator::Yank {
+                if let Some(op) = app.operator_pending.take()
+                    && op.op == Operator::Yank {
                         app.yank_line(app.cursor_row);
                         app.operator_pending = None;
                         handled = true;
                     }
-                }
                 if !handled {
                     app.operator_pending = Some(OperatorPending {
                         op: Operator::Yank,
@@ -330,9 +539,9 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
             }
             (KeyCode::Char('c'), KeyModifiers::NONE) => {
                 let mut handled = false;
-                if let Some(op) = app.operator_pending.take() {
-                    if op.op == Operator::Change {
-                        app.yank_line(app.cursor_row);
+                if let Some(op) = app.operator_pending.take()
+                    && op.op == Operator::Change {
+                        app.register_lines(app.cursor_row, app.cursor_row, true);
                         app.delete_line(app.cursor_row);
                         app.mode = Mode::Insert;
                         app.insert_undo_snapshot = false;
@@ -340,7 +549,6 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
                         app.operator_pending = None;
                         handled = true;
                     }
-                }
                 if !handled {
                     app.operator_pending = Some(OperatorPending {
                         op: Operator::Change,
@@ -434,12 +642,19 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
             (KeyCode::Char('0'), KeyModifiers::NONE) => app.move_line_start(),
             (KeyCode::Char('$'), _) => app.move_line_end(),
             (KeyCode::Char('%'), KeyModifiers::NONE) => {
-                if !app.percent_jump() {
+                let origin = (app.cursor_row, app.cursor_col);
+                if app.percent_jump() {
+                    let target = (app.cursor_row, app.cursor_col);
+                    (app.cursor_row, app.cursor_col) = origin;
+                    app.push_jump();
+                    (app.cursor_row, app.cursor_col) = target;
+                } else {
                     app.set_status("No matching bracket");
                 }
             }
             (KeyCode::Char('g'), KeyModifiers::NONE) => {
                 if app.pending_g {
+                    app.push_jump();
                     if let Some(count) = app.pending_count.take() {
                         app.move_to_line(count);
                     } else {
@@ -463,6 +678,7 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
                 }
             }
             (KeyCode::Char('G'), _) => {
+                app.push_jump();
                 if let Some(count) = app.pending_count.take() {
                     app.move_to_line(count);
                 } else {
@@ -471,6 +687,7 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
             }
             (KeyCode::Char('n'), KeyModifiers::NONE) => {
                 if let Some(spec) = app.last_search.clone() {
+                    app.push_jump();
                     let found = if spec.reverse {
                         app.search_backward(&spec.pattern)
                     } else {
@@ -489,6 +706,7 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
             }
             (KeyCode::Char('N'), _) => {
                 if let Some(spec) = app.last_search.clone() {
+                    app.push_jump();
                     let found = if spec.reverse {
                         app.search_forward(&spec.pattern)
                     } else {
@@ -595,7 +813,19 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
                 }
                 return Ok(true);
             }
-            (KeyCode::Char('r'), KeyModifiers::CONTROL) => app.redo(),
+            (KeyCode::Char('r'), KeyModifiers::CONTROL) => app.insert_register_pending = true,
+            (KeyCode::Char('x'), KeyModifiers::CONTROL) => app.pending_ctrl_x = true,
+            (KeyCode::Char('o'), KeyModifiers::CONTROL) if ctrl_x => app.lsp_complete(),
+            (KeyCode::Char('f'), KeyModifiers::CONTROL) if ctrl_x => {
+                app.start_insert_completion(vec![Rc::new(FilePaths)], true)
+            }
+            (KeyCode::Char('l'), KeyModifiers::CONTROL) if ctrl_x => {
+                app.start_insert_completion(vec![Rc::new(WholeLines)], true)
+            }
+            (KeyCode::Char('n'), KeyModifiers::CONTROL) => app.insert_completion_step(true),
+            (KeyCode::Char('p'), KeyModifiers::CONTROL) => app.insert_completion_step(false),
+            (KeyCode::Char('y'), KeyModifiers::CONTROL) => app.close_insert_completion(),
+            (KeyCode::Char('e'), KeyModifiers::CONTROL) => app.cancel_insert_completion(),
             (KeyCode::Char('z'), KeyModifiers::CONTROL) => app.undo(),
             (KeyCode::Esc, _) => {
                 app.mode = Mode::Normal;
@@ -603,14 +833,17 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
                 app.insert_undo_snapshot = false;
                 app.set_status("-- NORMAL --");
             }
-            (KeyCode::Char('s'), KeyModifiers::CONTROL) => app.save()?,
+            (KeyCode::Char('s'), KeyModifiers::CONTROL) => {
+                app.save();
+            }
             (KeyCode::Enter, _) => {
                 app.insert_undo_snapshot = false;
                 app.insert_newline()
             }
             (KeyCode::Backspace, _) => {
                 app.insert_undo_snapshot = false;
-                app.backspace()
+                app.backspace();
+                app.refresh_insert_completion();
             }
             (KeyCode::Delete, _) => {
                 app.insert_undo_snapshot = false;
@@ -626,13 +859,15 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
                 if super::types::is_undo_break_char(ch) {
                     app.insert_undo_snapshot = false;
                 }
-                app.insert_char(ch)
+                app.insert_char(ch);
+                app.refresh_insert_completion();
             }
             (KeyCode::Char(ch), KeyModifiers::SHIFT) => {
                 if super::types::is_undo_break_char(ch) {
                     app.insert_undo_snapshot = false;
                 }
-                app.insert_char(ch)
+                app.insert_char(ch);
+                app.refresh_insert_completion();
             }
             (KeyCode::Left, _) => {
                 app.insert_undo_snapshot = false;
@@ -653,6 +888,7 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
             _ => {}
         },
         Mode::Command => match (key.code, key.modifiers) {
+            (KeyCode::Char('r'), KeyModifiers::CONTROL) => app.insert_register_pending = true,
             (KeyCode::Esc, _) => {
                 app.mode = Mode::Normal;
                 app.command_buffer.clear();
@@ -709,7 +945,7 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
                 if app
                     .completion_cmd_prefix
                     .as_deref()
-                    .is_some_and(|p| p == "<map>")
+                    .is_some_and(|p| p == "<list>")
                     && !app.completion_candidates.is_empty()
                 {
                     let next_idx = match app.completion_index {
@@ -729,7 +965,7 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
                 if app
                     .completion_cmd_prefix
                     .as_deref()
-                    .is_some_and(|p| p == "<map>")
+                    .is_some_and(|p| p == "<list>")
                     && !app.completion_candidates.is_empty()
                 {
                     let total = app.completion_candidates.len();
@@ -800,8 +1036,8 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
                 } else if matches!(
                     app.command_prompt,
                     CommandPrompt::SearchForward | CommandPrompt::SearchBackward
-                ) {
-                    if let Some(idx) = app.search_history_index {
+                )
+                    && let Some(idx) = app.search_history_index {
                         if idx + 1 < app.search_history.len() {
                             let next_idx = idx + 1;
                             app.search_history_index = Some(next_idx);
@@ -816,7 +1052,6 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
                             app.clear_completion();
                         }
                     }
-                }
             }
             (KeyCode::Left, mods) if mods.contains(KeyModifiers::SUPER) => {
                 command_move_line_start(app);
@@ -866,6 +1101,18 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
             _ => {}
         },
         Mode::VisualChar | Mode::VisualLine | Mode::VisualBlock => match (key.code, key.modifiers) {
+            (KeyCode::Char(':'), _) => {
+                if let Some(selection) = app.visual_selection() {
+                    app.last_visual = Some(selection_to_last_visual(selection, app.mode));
+                }
+                app.mode = Mode::Command;
+                app.visual_start = None;
+                app.command_prompt = CommandPrompt::Command;
+                app.command_buffer = "'<,'>".to_string();
+                app.command_cursor = app.command_buffer.chars().count();
+                app.search_history_index = None;
+                app.command_history_index = None;
+            }
             (KeyCode::Esc, _)
             | (KeyCode::Char('v'), KeyModifiers::NONE)
             | (KeyCode::Char('V'), _) => {
@@ -876,6 +1123,9 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
                 app.visual_start = None;
                 app.set_status("-- NORMAL --");
             }
+            (KeyCode::Char('"'), _) => {
+                app.awaiting_register = true;
+            }
             (KeyCode::Char('y'), KeyModifiers::NONE) => {
                 if let Some(selection) = app.visual_selection() {
                     match selection.kind {
@@ -892,15 +1142,15 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
                 if let Some(selection) = app.visual_selection() {
                     match selection.kind {
                         VisualSelectionKind::Char(start, end) => {
-                            app.yank_range(start, end);
+                            app.register_range(start, end, true);
                             app.delete_range(start, end);
                         }
                         VisualSelectionKind::Line(start, end) => {
-                            app.yank_lines(start, end);
+                            app.register_lines(start, end, true);
                             app.delete_lines(start, end);
                         }
                         VisualSelectionKind::Block { start, end } => {
-                            app.yank_block(start, end);
+                            app.register_block(start, end, true);
                             app.delete_block(start, end);
                         }
                     }
@@ -913,15 +1163,15 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
                 if let Some(selection) = app.visual_selection() {
                     match selection.kind {
                         VisualSelectionKind::Char(start, end) => {
-                            app.yank_range(start, end);
+                            app.register_range(start, end, true);
                             app.delete_range(start, end);
                         }
                         VisualSelectionKind::Line(start, end) => {
-                            app.yank_lines(start, end);
+                            app.register_lines(start, end, true);
                             app.delete_lines(start, end);
                         }
                         VisualSelectionKind::Block { start, end } => {
-                            app.yank_block(start, end);
+                            app.register_block(start, end, true);
                             app.delete_block(start, end);
                         }
                     }
@@ -1023,9 +1273,9 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
                 app.visual_start = None;
             }
             (KeyCode::Char('I'), _) => {
-                if matches!(app.mode, Mode::VisualBlock) {
-                    if let Some(selection) = app.visual_selection() {
-                        if let VisualSelectionKind::Block { start, end } = selection.kind {
+                if matches!(app.mode, Mode::VisualBlock)
+                    && let Some(selection) = app.visual_selection()
+                        && let VisualSelectionKind::Block { start, end } = selection.kind {
                             app.block_insert = Some(super::types::BlockInsert {
                                 start_row: start.0,
                                 end_row: end.0,
@@ -1039,13 +1289,11 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
                             app.visual_start = None;
                             app.set_status("-- INSERT --");
                         }
-                    }
-                }
             }
             (KeyCode::Char('A'), _) => {
-                if matches!(app.mode, Mode::VisualBlock) {
-                    if let Some(selection) = app.visual_selection() {
-                        if let VisualSelectionKind::Block { start, end } = selection.kind {
+                if matches!(app.mode, Mode::VisualBlock)
+                    && let Some(selection) = app.visual_selection()
+                        && let VisualSelectionKind::Block { start, end } = selection.kind {
                             app.block_insert = Some(super::types::BlockInsert {
                                 start_row: start.0,
                                 end_row: end.0,
@@ -1059,8 +1307,6 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
                             app.visual_start = None;
                             app.set_status("-- INSERT --");
                         }
-                    }
-                }
             }
             (KeyCode::Char('h'), KeyModifiers::NONE) | (KeyCode::Left, _) => app.move_left(),
             (KeyCode::Char('j'), KeyModifiers::NONE) | (KeyCode::Down, _) => app.move_down(),
@@ -1181,7 +1427,8 @@ pub fn handle_key(app: &mut App, key: KeyEvent) -> Result<bool> {
 
 fn should_start_repeat(app: &App, key: &KeyEvent) -> bool {
     match app.mode {
-        Mode::Normal => match (key.code, key.modifiers) {
+        Mode::Normal => matches!(
+            (key.code, key.modifiers),
             (KeyCode::Char('i'), KeyModifiers::NONE)
             | (KeyCode::Char('a'), KeyModifiers::NONE)
             | (KeyCode::Char('I'), _)
@@ -1195,18 +1442,17 @@ fn should_start_repeat(app: &App, key: &KeyEvent) -> bool {
             | (KeyCode::Char('c'), KeyModifiers::NONE)
             | (KeyCode::Char('v'), KeyModifiers::NONE)
             | (KeyCode::Char('V'), _)
-            | (KeyCode::Char('v'), KeyModifiers::CONTROL) => true,
-            _ => false,
-        },
-        Mode::VisualChar | Mode::VisualLine | Mode::VisualBlock => match (key.code, key.modifiers) {
+            | (KeyCode::Char('v'), KeyModifiers::CONTROL)
+        ),
+        Mode::VisualChar | Mode::VisualLine | Mode::VisualBlock => matches!(
+            (key.code, key.modifiers),
             (KeyCode::Char('d'), KeyModifiers::NONE)
             | (KeyCode::Char('c'), KeyModifiers::NONE)
             | (KeyCode::Char('p'), KeyModifiers::NONE)
             | (KeyCode::Char('P'), _)
             | (KeyCode::Char('I'), _)
-            | (KeyCode::Char('A'), _) => true,
-            _ => false,
-        },
+            | (KeyCode::Char('A'), _)
+        ),
         _ => false,
     }
 }
@@ -1264,7 +1510,7 @@ fn replay_last_change(app: &mut App) -> Result<()> {
         app.repeat_recording = false;
         app.repeat_changed = false;
         app.repeat_buffer.clear();
-        app.yank_line(app.cursor_row);
+        app.register_lines(app.cursor_row, app.cursor_row, true);
         app.delete_line(app.cursor_row);
         app.repeat_replaying = false;
         return Ok(());
@@ -1358,6 +1604,49 @@ fn complete_set_in_command(app: &mut App, reverse: bool) -> bool {
             "set rnu?".to_string(),
             "set theme=".to_string(),
             "set theme?".to_string(),
+            "set undofile".to_string(),
+            "set noundofile".to_string(),
+            "set undofile?".to_string(),
+            "set undodir=".to_string(),
+            "set undodir?".to_string(),
+            "set fileencoding=".to_string(),
+            "set fileencoding?".to_string(),
+            "set fileformat=unix".to_string(),
+            "set fileformat=dos".to_string(),
+            "set fileformat=mac".to_string(),
+            "set fileformat?".to_string(),
+            "set eol".to_string(),
+            "set noeol".to_string(),
+            "set eol?".to_string(),
+            "set bomb".to_string(),
+            "set nobomb".to_string(),
+            "set bomb?".to_string(),
+            "set backup".to_string(),
+            "set nobackup".to_string(),
+            "set backup?".to_string(),
+            "set backupdir=".to_string(),
+            "set backupdir?".to_string(),
+            "set swapfile".to_string(),
+            "set noswapfile".to_string(),
+            "set swapfile?".to_string(),
+            "set directory=".to_string(),
+            "set directory?".to_string(),
+            "set updatetime=".to_string(),
+            "set updatecount=".to_string(),
+            "set readonly".to_string(),
+            "set noreadonly".to_string(),
+            "set ignorecase".to_string(),
+            "set noignorecase".to_string(),
+            "set ignorecase?".to_string(),
+            "set smartcase".to_string(),
+            "set nosmartcase".to_string(),
+            "set smartcase?".to_string(),
+            "set incsearch".to_string(),
+            "set noincsearch".to_string(),
+            "set incsearch?".to_string(),
+            "set clipboard=unnamedplus".to_string(),
+            "set clipboard=".to_string(),
+            "set clipboard?".to_string(),
         ]
     };
 
@@ -1769,6 +2058,12 @@ fn complete_command_in_command(app: &mut App, reverse: bool) -> bool {
 }
 
 fn apply_keymap_action(app: &mut App, action: KeyAction) -> Result<Option<bool>> {
+    if let KeyAction::Plugin(idx) = action {
+        let Some(command) = app.keymaps.plugin_command(idx).map(str::to_string) else {
+            return Ok(Some(false));
+        };
+        return app.run_ex_command(&command).map(Some);
+    }
     match app.mode {
         Mode::Command => {
             match action {
@@ -1893,6 +2188,10 @@ fn apply_keymap_action(app: &mut App, action: KeyAction) -> Result<Option<bool>>
                 KeyAction::NoOp => return Ok(Some(false)),
                 KeyAction::BufferNext => app.switch_next_buffer(),
                 KeyAction::BufferPrev => app.switch_prev_buffer(),
+                KeyAction::DiagnosticNext => app.goto_diagnostic(true),
+                KeyAction::DiagnosticPrev => app.goto_diagnostic(false),
+                KeyAction::FindFiles => app.open_file_picker(),
+                KeyAction::ToggleExplorer => app.toggle_explorer(),
                 KeyAction::MoveLeft => app.move_left(),
                 KeyAction::MoveRight => app.move_right(),
                 KeyAction::MoveUp => app.move_up(),
@@ -1922,6 +2221,14 @@ fn exit_visual(app: &mut App) {
     app.set_status("-- NORMAL --");
 }
 
+fn keeps_insert_completion(key: &KeyEvent) -> bool {
+    match (key.code, key.modifiers) {
+        (KeyCode::Char(_), KeyModifiers::NONE | KeyModifiers::SHIFT) | (KeyCode::Backspace, _) => true,
+        (KeyCode::Char(ch), KeyModifiers::CONTROL) => matches!(ch, 'n' | 'p' | 'y' | 'e'),
+        _ => false,
+    }
+}
+
 pub(crate) fn expand_tilde(input: &str) -> (String, bool) {
     if !input.starts_with('~') {
         return (input.to_string(), false);
@@ -1943,10 +2250,9 @@ pub(crate) fn expand_tilde_path(input: impl AsRef<str>) -> String {
     let trimmed = input.trim();
     let (quote, rest) = strip_leading_quote(trimmed);
     let (expanded, _had_tilde) = expand_tilde(rest);
-    if quote.is_some() {
-        format!("{}{}", quote.unwrap(), expanded)
-    } else {
-        expanded
+    match quote {
+        Some(quote) => format!("{}{}", quote, expanded),
+        None => expanded,
     }
 }
 
@@ -5,6 +5,8 @@ use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use super::config::KeymapConfig;
 use super::types::Mode;
 
+const DEFAULT_LEADER: char = '\\';
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub(crate) struct KeySpec {
     pub(crate) code: KeyCode,
@@ -16,6 +18,11 @@ pub(crate) enum KeyAction {
     NoOp,
     BufferNext,
     BufferPrev,
+    DiagnosticNext,
+    DiagnosticPrev,
+    FindFiles,
+    ToggleExplorer,
+    Plugin(usize),
     MoveLeft,
     MoveRight,
     MoveUp,
@@ -39,8 +46,11 @@ pub(crate) struct Keymaps {
     insert: HashMap<Vec<KeySpec>, KeyAction>,
     visual: HashMap<Vec<KeySpec>, KeyAction>,
     command: HashMap<Vec<KeySpec>, KeyAction>,
+    leader: char,
+    plugin_commands: Vec<String>,
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct KeymapEntry {
     pub mode: &'static str,
@@ -88,23 +98,31 @@ impl Keymaps {
     }
 
     pub(crate) fn from_config(cfg: Option<&KeymapConfig>) -> (Self, Vec<String>) {
-        let mut keymaps = Keymaps::default();
         let mut errors = Vec::new();
         let Some(cfg) = cfg else {
-            return (keymaps, errors);
+            return (Keymaps::default(), errors);
+        };
+        let leader = match cfg.leader.as_deref().map(parse_leader) {
+            Some(Some(leader)) => leader,
+            Some(None) => {
+                errors.push(format!("Invalid leader: {}", cfg.leader.as_deref().unwrap_or_default()));
+                DEFAULT_LEADER
+            }
+            None => DEFAULT_LEADER,
         };
+        let mut keymaps = Keymaps::with_leader(leader);
 
         if let Some(map) = cfg.normal.as_ref() {
-            parse_map(map, &mut keymaps.normal, &mut errors);
+            parse_map(map, leader, &mut keymaps.normal, &mut errors);
         }
         if let Some(map) = cfg.insert.as_ref() {
-            parse_map(map, &mut keymaps.insert, &mut errors);
+            parse_map(map, leader, &mut keymaps.insert, &mut errors);
         }
         if let Some(map) = cfg.visual.as_ref() {
-            parse_map(map, &mut keymaps.visual, &mut errors);
+            parse_map(map, leader, &mut keymaps.visual, &mut errors);
         }
         if let Some(map) = cfg.command.as_ref() {
-            parse_map(map, &mut keymaps.command, &mut errors);
+            parse_map(map, leader, &mut keymaps.command, &mut errors);
         }
 
         (keymaps, errors)
@@ -113,6 +131,12 @@ impl Keymaps {
 
 impl Default for Keymaps {
     fn default() -> Self {
+        Keymaps::with_leader(DEFAULT_LEADER)
+    }
+}
+
+impl Keymaps {
+    fn with_leader(leader: char) -> Self {
         let mut normal = HashMap::new();
         if let Some(seq) = parse_key_sequence("]b") {
             normal.insert(seq, KeyAction::BufferNext);
@@ -120,22 +144,77 @@ impl Default for Keymaps {
         if let Some(seq) = parse_key_sequence("[b") {
             normal.insert(seq, KeyAction::BufferPrev);
         }
+        if let Some(seq) = parse_key_sequence("]d") {
+            normal.insert(seq, KeyAction::DiagnosticNext);
+        }
+        if let Some(seq) = parse_key_sequence("[d") {
+            normal.insert(seq, KeyAction::DiagnosticPrev);
+        }
+        if let Some(seq) = parse_key_sequence(&expand_leader("<leader>f", leader)) {
+            normal.insert(seq, KeyAction::FindFiles);
+        }
+        if let Some(seq) = parse_key_sequence(&expand_leader("<leader>e", leader)) {
+            normal.insert(seq, KeyAction::ToggleExplorer);
+        }
         Keymaps {
             normal,
             insert: HashMap::new(),
             visual: HashMap::new(),
             command: HashMap::new(),
+            leader,
+            plugin_commands: Vec::new(),
+        }
+    }
+
+    pub(crate) fn add_plugin_map(&mut self, mode: &str, lhs: &str, command: &str) -> Result<(), String> {
+        let map = match mode {
+            "n" | "normal" => &mut self.normal,
+            "i" | "insert" => &mut self.insert,
+            "v" | "visual" => &mut self.visual,
+            "c" | "command" => &mut self.command,
+            _ => return Err(format!("Invalid mode: {}", mode)),
+        };
+        let Some(seq) = parse_key_sequence(&expand_leader(lhs, self.leader)) else {
+            return Err(format!("Invalid key: {}", lhs));
+        };
+        map.insert(seq, KeyAction::Plugin(self.plugin_commands.len()));
+        self.plugin_commands.push(command.to_string());
+        Ok(())
+    }
+
+    pub(crate) fn plugin_command(&self, idx: usize) -> Option<&str> {
+        self.plugin_commands.get(idx).map(String::as_str)
+    }
+}
+
+fn parse_leader(raw: &str) -> Option<char> {
+    match raw.to_ascii_lowercase().as_str() {
+        "space" | "<space>" => Some(' '),
+        _ => {
+            let mut chars = raw.chars();
+            let ch = chars.next()?;
+            chars.next().is_none().then_some(ch)
         }
     }
 }
 
+fn expand_leader(lhs: &str, leader: char) -> String {
+    let token = match leader {
+        ' ' => "<space>".to_string(),
+        '<' => "<lt>".to_string(),
+        ch => ch.to_string(),
+    };
+    lhs.replace("<leader>", &token).replace("<Leader>", &token)
+}
+
 fn parse_map(
     map: &HashMap<String, String>,
+    leader: char,
     out: &mut HashMap<Vec<KeySpec>, KeyAction>,
     errors: &mut Vec<String>,
 ) {
     for (lhs, rhs) in map {
-        let Some(seq) = parse_key_sequence(lhs) else {
+        let Some(seq) = parse_key_sequence(&expand_leader(lhs, leader)) else {
             errors.push(format!("Invalid key: {}", lhs));
             continue;
         };
@@ -152,6 +231,10 @@ fn parse_key_action(s: &str) -> Option<KeyAction> {
         "noop" | "no-op" => Some(KeyAction::NoOp),
         "buffer_next" | "bnext" | "bn" => Some(KeyAction::BufferNext),
         "buffer_prev" | "bprev" | "bp" => Some(KeyAction::BufferPrev),
+        "diagnostic_next" => Some(KeyAction::DiagnosticNext),
+        "diagnostic_prev" => Some(KeyAction::DiagnosticPrev),
+        "find_files" | "files" => Some(KeyAction::FindFiles),
+        "toggle_explorer" | "explore" => Some(KeyAction::ToggleExplorer),
         "left" | "move_left" => Some(KeyAction::MoveLeft),
         "right" | "move_right" => Some(KeyAction::MoveRight),
         "up" | "move_up" => Some(KeyAction::MoveUp),
@@ -171,7 +254,7 @@ fn parse_key_action(s: &str) -> Option<KeyAction> {
     }
 }
 
-fn parse_key_sequence(raw: &str) -> Option<Vec<KeySpec>> {
+pub(super) fn parse_key_sequence(raw: &str) -> Option<Vec<KeySpec>> {
     let trimmed = raw.trim();
     if trimmed.is_empty() {
         return None;
@@ -183,7 +266,7 @@ fn parse_key_sequence(raw: &str) -> Option<Vec<KeySpec>> {
             let mut token = String::new();
             token.push(ch);
             iter.next();
-            while let Some(next) = iter.next() {
+            for next in iter.by_ref() {
                 token.push(next);
                 if next == '>' {
                     break;
@@ -244,6 +327,7 @@ fn parse_named_key(raw: &str) -> Option<KeySpec> {
         "enter" | "cr" => KeyCode::Enter,
         "esc" | "escape" => KeyCode::Esc,
         "space" => KeyCode::Char(' '),
+        "lt" => KeyCode::Char('<'),
         "delete" | "del" => KeyCode::Delete,
         "insert" | "ins" => KeyCode::Insert,
         "home" => KeyCode::Home,
@@ -274,85 +358,8 @@ pub(crate) enum KeymapResult {
     NoMatch,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    fn key_event(ch: char) -> KeyEvent {
-        KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE)
-    }
-
-    #[test]
-    fn default_keymap_supports_bracket_b_sequences() {
-        let keymaps = Keymaps::default();
-        let mut seq = Vec::new();
-
-        let res = keymaps.action_for_seq(Mode::Normal, &key_event(']'), &mut seq);
-        assert!(matches!(res, KeymapResult::Pending));
-
-        let res = keymaps.action_for_seq(Mode::Normal, &key_event('b'), &mut seq);
-        assert!(matches!(res, KeymapResult::Matched(KeyAction::BufferNext)));
-
-        let res = keymaps.action_for_seq(Mode::Normal, &key_event('['), &mut seq);
-        assert!(matches!(res, KeymapResult::Pending));
-
-        let res = keymaps.action_for_seq(Mode::Normal, &key_event('b'), &mut seq);
-        assert!(matches!(res, KeymapResult::Matched(KeyAction::BufferPrev)));
-    }
-
-    #[test]
-    fn unknown_sequence_clears_pending_state() {
-        let keymaps = Keymaps::default();
-        let mut seq = Vec::new();
-
-        let res = keymaps.action_for_seq(Mode::Normal, &key_event(']'), &mut seq);
-        assert!(matches!(res, KeymapResult::Pending));
-
-        let res = keymaps.action_for_seq(Mode::Normal, &key_event('x'), &mut seq);
-        assert!(matches!(res, KeymapResult::NoMatch));
-
-        let res = keymaps.action_for_seq(Mode::Normal, &key_event('b'), &mut seq);
-        assert!(matches!(res, KeymapResult::NoMatch));
-    }
-
-    #[test]
-    fn parse_config_multi_key_sequences() {
-        let mut cfg = KeymapConfig::default();
-        let mut normal = HashMap::new();
-        normal.insert("]b".to_string(), "buffer_next".to_string());
-        normal.insert("[b".to_string(), "buffer_prev".to_string());
-        normal.insert("gh".to_string(), "left".to_string());
-        cfg.normal = Some(normal);
-
-        let (keymaps, errors) = Keymaps::from_config(Some(&cfg));
-        assert!(errors.is_empty());
-
-        let mut seq = Vec::new();
-        let res = keymaps.action_for_seq(Mode::Normal, &key_event(']'), &mut seq);
-        assert!(matches!(res, KeymapResult::Pending));
-        let res = keymaps.action_for_seq(Mode::Normal, &key_event('b'), &mut seq);
-        assert!(matches!(res, KeymapResult::Matched(KeyAction::BufferNext)));
-
-        let res = keymaps.action_for_seq(Mode::Normal, &key_event('g'), &mut seq);
-        assert!(matches!(res, KeymapResult::Pending));
-        let res = keymaps.action_for_seq(Mode::Normal, &key_event('h'), &mut seq);
-        assert!(matches!(res, KeymapResult::Matched(KeyAction::MoveLeft)));
-    }
-
-    #[test]
-    fn parse_config_reports_invalid_entries() {
-        let mut cfg = KeymapConfig::default();
-        let mut normal = HashMap::new();
-        normal.insert("<C-".to_string(), "left".to_string());
-        normal.insert("x".to_string(), "not_an_action".to_string());
-        cfg.normal = Some(normal);
-
-        let (_keymaps, errors) = Keymaps::from_config(Some(&cfg));
-        assert_eq!(errors.len(), 2);
-    }
-}
-
 impl Keymaps {
+    #[allow(dead_code)]
     pub(crate) fn describe(&self) -> String {
         let mut parts = Vec::new();
         let normal = format_map(&self.normal);
@@ -380,16 +387,17 @@ impl Keymaps {
 
     pub(crate) fn describe_lines(&self) -> Vec<String> {
         let mut lines = Vec::new();
-        lines.extend(format_map_lines("normal", &self.normal));
-        lines.extend(format_map_lines("insert", &self.insert));
-        lines.extend(format_map_lines("visual", &self.visual));
-        lines.extend(format_map_lines("command", &self.command));
+        lines.extend(format_map_lines("normal", &self.normal, &self.plugin_commands));
+        lines.extend(format_map_lines("insert", &self.insert, &self.plugin_commands));
+        lines.extend(format_map_lines("visual", &self.visual, &self.plugin_commands));
+        lines.extend(format_map_lines("command", &self.command, &self.plugin_commands));
         if lines.is_empty() {
             lines.push("no keymaps".to_string());
         }
         lines
     }
 
+    #[allow(dead_code)]
     pub fn entries(&self, mode: Option<&'static str>) -> Vec<KeymapEntry> {
         let mut out = Vec::new();
         if mode.is_none() || mode == Some("normal") {
@@ -408,6 +416,7 @@ impl Keymaps {
     }
 }
 
+#[allow(dead_code)]
 fn format_map(map: &HashMap<Vec<KeySpec>, KeyAction>) -> String {
     if map.is_empty() {
         return String::new();
@@ -424,7 +433,11 @@ fn format_map(map: &HashMap<Vec<KeySpec>, KeyAction>) -> String {
         .join(", ")
 }
 
-fn format_map_lines(label: &str, map: &HashMap<Vec<KeySpec>, KeyAction>) -> Vec<String> {
+fn format_map_lines(
+    label: &str,
+    map: &HashMap<Vec<KeySpec>, KeyAction>,
+    plugin_commands: &[String],
+) -> Vec<String> {
     if map.is_empty() {
         return Vec::new();
     }
@@ -438,11 +451,11 @@ fn format_map_lines(label: &str, map: &HashMap<Vec<KeySpec>, KeyAction>) -> Vec<
     let mut entries: Vec<(String, String, Option<&'static str>)> = map
         .iter()
         .map(|(seq, action)| {
-            (
-                format_sequence(seq),
-                action_name(*action),
-                action_description(*action),
-            )
+            let name = match action {
+                KeyAction::Plugin(idx) => format!(":{}", plugin_commands.get(*idx).map_or("", String::as_str)),
+                _ => action_name(*action),
+            };
+            (format_sequence(seq), name, action_description(*action))
         })
         .collect();
     entries.sort_by(|a, b| a.0.cmp(&b.0));
@@ -467,6 +480,7 @@ fn format_map_lines(label: &str, map: &HashMap<Vec<KeySpec>, KeyAction>) -> Vec<
         .collect()
 }
 
+#[allow(dead_code)]
 fn entries_for_mode(label: &'static str, map: &HashMap<Vec<KeySpec>, KeyAction>) -> Vec<KeymapEntry> {
     map.iter()
         .map(|(seq, action)| KeymapEntry {
@@ -478,7 +492,7 @@ fn entries_for_mode(label: &'static str, map: &HashMap<Vec<KeySpec>, KeyAction>)
         .collect()
 }
 
-fn format_sequence(seq: &[KeySpec]) -> String {
+pub(super) fn format_sequence(seq: &[KeySpec]) -> String {
     let mut out = String::new();
     for spec in seq {
         out.push_str(&format_key_spec(spec));
@@ -486,15 +500,28 @@ fn format_sequence(seq: &[KeySpec]) -> String {
     out
 }
 
+pub(super) fn key_notation(key: &KeyEvent) -> String {
+    let mods = match key.code {
+        KeyCode::Char(_) => key.modifiers - KeyModifiers::SHIFT,
+        _ => key.modifiers,
+    };
+    format_key_spec(&KeySpec {
+        code: key.code,
+        mods,
+    })
+}
+
 fn format_key_spec(spec: &KeySpec) -> String {
-    if spec.mods == KeyModifiers::NONE {
-        if let KeyCode::Char(ch) = spec.code {
+    if spec.mods == KeyModifiers::NONE
+        && let KeyCode::Char(ch) = spec.code {
             if ch == ' ' {
                 return "<Space>".to_string();
             }
+            if ch == '<' {
+                return "<lt>".to_string();
+            }
             return ch.to_string();
         }
-    }
     let mut mods = Vec::new();
     if spec.mods.contains(KeyModifiers::CONTROL) {
         mods.push("C");
@@ -543,6 +570,11 @@ fn action_name(action: KeyAction) -> String {
         KeyAction::NoOp => "noop",
         KeyAction::BufferNext => "buffer_next",
         KeyAction::BufferPrev => "buffer_prev",
+        KeyAction::DiagnosticNext => "diagnostic_next",
+        KeyAction::DiagnosticPrev => "diagnostic_prev",
+        KeyAction::FindFiles => "find_files",
+        KeyAction::ToggleExplorer => "toggle_explorer",
+        KeyAction::Plugin(_) => "plugin",
         KeyAction::MoveLeft => "left",
         KeyAction::MoveRight => "right",
         KeyAction::MoveUp => "up",
@@ -567,6 +599,11 @@ fn action_description(action: KeyAction) -> Option<&'static str> {
         KeyAction::NoOp => Some("disable"),
         KeyAction::BufferNext => Some("next buffer"),
         KeyAction::BufferPrev => Some("prev buffer"),
+        KeyAction::DiagnosticNext => Some("next diagnostic"),
+        KeyAction::DiagnosticPrev => Some("prev diagnostic"),
+        KeyAction::FindFiles => Some("find files"),
+        KeyAction::ToggleExplorer => Some("toggle explorer"),
+        KeyAction::Plugin(_) => None,
         KeyAction::MoveLeft => Some("left"),
         KeyAction::MoveRight => Some("right"),
         KeyAction::MoveUp => Some("up"),
@@ -584,3 +621,107 @@ fn action_description(action: KeyAction) -> Option<&'static str> {
         KeyAction::BackTab => Some("backtab"),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_event(ch: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn default_keymap_supports_bracket_b_sequences() {
+        let keymaps = Keymaps::default();
+        let mut seq = Vec::new();
+
+        let res = keymaps.action_for_seq(Mode::Normal, &key_event(']'), &mut seq);
+        assert!(matches!(res, KeymapResult::Pending));
+
+        let res = keymaps.action_for_seq(Mode::Normal, &key_event('b'), &mut seq);
+        assert!(matches!(res, KeymapResult::Matched(KeyAction::BufferNext)));
+
+        let res = keymaps.action_for_seq(Mode::Normal, &key_event('['), &mut seq);
+        assert!(matches!(res, KeymapResult::Pending));
+
+        let res = keymaps.action_for_seq(Mode::Normal, &key_event('b'), &mut seq);
+        assert!(matches!(res, KeymapResult::Matched(KeyAction::BufferPrev)));
+    }
+
+    #[test]
+    fn unknown_sequence_clears_pending_state() {
+        let keymaps = Keymaps::default();
+        let mut seq = Vec::new();
+
+        let res = keymaps.action_for_seq(Mode::Normal, &key_event(']'), &mut seq);
+        assert!(matches!(res, KeymapResult::Pending));
+
+        let res = keymaps.action_for_seq(Mode::Normal, &key_event('x'), &mut seq);
+        assert!(matches!(res, KeymapResult::NoMatch));
+
+        let res = keymaps.action_for_seq(Mode::Normal, &key_event('b'), &mut seq);
+        assert!(matches!(res, KeymapResult::NoMatch));
+    }
+
+    #[test]
+    fn parse_config_multi_key_sequences() {
+        let mut cfg = KeymapConfig::default();
+        let mut normal = HashMap::new();
+        normal.insert("]b".to_string(), "buffer_next".to_string());
+        normal.insert("[b".to_string(), "buffer_prev".to_string());
+        normal.insert("gh".to_string(), "left".to_string());
+        cfg.normal = Some(normal);
+
+        let (keymaps, errors) = Keymaps::from_config(Some(&cfg));
+        assert!(errors.is_empty());
+
+        let mut seq = Vec::new();
+        let res = keymaps.action_for_seq(Mode::Normal, &key_event(']'), &mut seq);
+        assert!(matches!(res, KeymapResult::Pending));
+        let res = keymaps.action_for_seq(Mode::Normal, &key_event('b'), &mut seq);
+        assert!(matches!(res, KeymapResult::Matched(KeyAction::BufferNext)));
+
+        let res = keymaps.action_for_seq(Mode::Normal, &key_event('g'), &mut seq);
+        assert!(matches!(res, KeymapResult::Pending));
+        let res = keymaps.action_for_seq(Mode::Normal, &key_event('h'), &mut seq);
+        assert!(matches!(res, KeymapResult::Matched(KeyAction::MoveLeft)));
+    }
+
+    #[test]
+    fn parse_config_reports_invalid_entries() {
+        let mut cfg = KeymapConfig::default();
+        let mut normal = HashMap::new();
+        normal.insert("<C-".to_string(), "left".to_string());
+        normal.insert("x".to_string(), "not_an_action".to_string());
+        cfg.normal = Some(normal);
+
+        let (_keymaps, errors) = Keymaps::from_config(Some(&cfg));
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn leader_expands_in_default_and_config_maps() {
+        let keymaps = Keymaps::default();
+        let mut seq = Vec::new();
+        let res = keymaps.action_for_seq(Mode::Normal, &key_event('\\'), &mut seq);
+        assert!(matches!(res, KeymapResult::Pending));
+        let res = keymaps.action_for_seq(Mode::Normal, &key_event('f'), &mut seq);
+        assert!(matches!(res, KeymapResult::Matched(KeyAction::FindFiles)));
+
+        let mut cfg = KeymapConfig {
+            leader: Some("space".to_string()),
+            ..KeymapConfig::default()
+        };
+        let mut normal = HashMap::new();
+        normal.insert("<leader>n".to_string(), "bnext".to_string());
+        cfg.normal = Some(normal);
+        let (keymaps, errors) = Keymaps::from_config(Some(&cfg));
+        assert!(errors.is_empty());
+        for (ch, action) in [('f', KeyAction::FindFiles), ('n', KeyAction::BufferNext)] {
+            let res = keymaps.action_for_seq(Mode::Normal, &key_event(' '), &mut seq);
+            assert!(matches!(res, KeymapResult::Pending));
+            let res = keymaps.action_for_seq(Mode::Normal, &key_event(ch), &mut seq);
+            assert!(matches!(res, KeymapResult::Matched(found) if found == action));
+        }
+    }
+}
@@ -0,0 +1,421 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::{json, Value};
+
+use super::config::LspServerConfig;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum LspRequest {
+    Initialize,
+    Shutdown,
+    Hover,
+    Definition,
+    References,
+    Rename,
+    CodeAction,
+    ResolveCodeAction,
+    ExecuteCommand,
+    Formatting(PathBuf),
+    Completion { path: PathBuf, pos: (usize, usize) },
+}
+
+#[derive(Debug)]
+pub(crate) enum LspEvent {
+    Response {
+        request: LspRequest,
+        result: std::result::Result<Value, String>,
+    },
+    Request {
+        id: Value,
+        method: String,
+        params: Value,
+    },
+    Notification {
+        method: String,
+        params: Value,
+    },
+}
+
+pub(crate) struct LspClient {
+    pub(crate) language: String,
+    pub(crate) command: String,
+    pub(crate) root: PathBuf,
+    pub(crate) capabilities: Value,
+    pub(crate) settings: Value,
+    pub(crate) documents: HashMap<PathBuf, i64>,
+    pub(crate) ready: bool,
+    pub(crate) exited: bool,
+    child: Child,
+    sender: Sender<Value>,
+    receiver: Receiver<Value>,
+    next_id: i64,
+    pending: HashMap<i64, LspRequest>,
+    queued: Vec<Value>,
+}
+
+impl LspClient {
+    pub(crate) fn start(language: &str, config: &LspServerConfig, root: PathBuf) -> Result<Self> {
+        let (program, args) = config
+            .command
+            .split_first()
+            .ok_or_else(|| anyhow!("empty command for {}", language))?;
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .current_dir(&root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null());
+        if let Some(env) = config.env.as_ref() {
+            command.envs(env);
+        }
+        let mut child = command
+            .spawn()
+            .with_context(|| format!("failed to start {}", program))?;
+        let mut stdin = child.stdin.take().ok_or_else(|| anyhow!("no stdin"))?;
+        let stdout = child.stdout.take().ok_or_else(|| anyhow!("no stdout"))?;
+
+        let (sender, outgoing) = mpsc::channel::<Value>();
+        thread::spawn(move || {
+            for msg in outgoing {
+                if write_message(&mut stdin, &msg).is_err() {
+                    break;
+                }
+            }
+        });
+        let (incoming, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut reader = BufReader::new(stdout);
+            while let Ok(Some(msg)) = read_message(&mut reader) {
+                if incoming.send(msg).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let settings = config
+            .settings
+            .as_ref()
+            .and_then(|value| serde_json::to_value(value).ok())
+            .unwrap_or(Value::Null);
+        let mut client = Self {
+            language: language.to_string(),
+            command: config.command.join(" "),
+            root,
+            capabilities: Value::Null,
+            settings,
+            documents: HashMap::new(),
+            ready: false,
+            exited: false,
+            child,
+            sender,
+            receiver,
+            next_id: 1,
+            pending: HashMap::new(),
+            queued: Vec::new(),
+        };
+        let root_uri = path_to_uri(&client.root);
+        let params = json!({
+            "processId": std::process::id(),
+            "rootUri": root_uri,
+            "workspaceFolders": [{ "uri": root_uri, "name": client.root.display().to_string() }],
+            "initializationOptions": client.settings,
+            "capabilities": client_capabilities(),
+        });
+        let id = client.next_request_id(LspRequest::Initialize);
+        client.send(json!({ "jsonrpc": "2.0", "id": id, "method": "initialize", "params": params }));
+        Ok(client)
+    }
+
+    fn next_request_id(&mut self, request: LspRequest) -> i64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.insert(id, request);
+        id
+    }
+
+    fn send(&mut self, msg: Value) {
+        if self.sender.send(msg).is_err() {
+            self.exited = true;
+        }
+    }
+
+    fn send_or_queue(&mut self, msg: Value) {
+        if self.ready {
+            self.send(msg);
+        } else {
+            self.queued.push(msg);
+        }
+    }
+
+    pub(crate) fn request(&mut self, request: LspRequest, method: &str, params: Value) {
+        let id = self.next_request_id(request);
+        self.send_or_queue(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+    }
+
+    pub(crate) fn notify(&mut self, method: &str, params: Value) {
+        self.send_or_queue(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    pub(crate) fn respond(&mut self, id: Value, result: std::result::Result<Value, String>) {
+        let msg = match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(message) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": -32601, "message": message },
+            }),
+        };
+        self.send(msg);
+    }
+
+    pub(crate) fn poll(&mut self) -> Vec<LspEvent> {
+        let mut events = Vec::new();
+        loop {
+            let msg = match self.receiver.try_recv() {
+                Ok(msg) => msg,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.exited = true;
+                    break;
+                }
+            };
+            let method = msg.get("method").and_then(Value::as_str).map(str::to_string);
+            let params = msg.get("params").cloned().unwrap_or(Value::Null);
+            match (msg.get("id").cloned(), method) {
+                (Some(id), Some(method)) => events.push(LspEvent::Request { id, method, params }),
+                (None, Some(method)) => events.push(LspEvent::Notification { method, params }),
+                (Some(id), None) => {
+                    let Some(request) = id.as_i64().and_then(|id| self.pending.remove(&id)) else {
+                        continue;
+                    };
+                    let result = match msg.get("error") {
+                        Some(error) => Err(error
+                            .get("message")
+                            .and_then(Value::as_str)
+                            .unwrap_or("request failed")
+                            .to_string()),
+                        None => Ok(msg.get("result").cloned().unwrap_or(Value::Null)),
+                    };
+                    if request == LspRequest::Initialize {
+                        self.finish_initialize(&result);
+                    }
+                    events.push(LspEvent::Response { request, result });
+                }
+                (None, None) => {}
+            }
+        }
+        events
+    }
+
+    fn finish_initialize(&mut self, result: &std::result::Result<Value, String>) {
+        if let Ok(result) = result {
+            self.capabilities = result.get("capabilities").cloned().unwrap_or(Value::Null);
+        }
+        self.ready = true;
+        self.send(json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }));
+        if !self.settings.is_null() {
+            let settings = self.settings.clone();
+            self.send(json!({
+                "jsonrpc": "2.0",
+                "method": "workspace/didChangeConfiguration",
+                "params": { "settings": settings },
+            }));
+        }
+        for msg in std::mem::take(&mut self.queued) {
+            self.send(msg);
+        }
+    }
+
+    pub(crate) fn sync_kind(&self) -> i64 {
+        let sync = &self.capabilities["textDocumentSync"];
+        sync.as_i64()
+            .or_else(|| sync.get("change").and_then(Value::as_i64))
+            .unwrap_or(0)
+    }
+
+    pub(crate) fn supports(&self, capability: &str) -> bool {
+        match self.capabilities.get(capability) {
+            Some(Value::Bool(enabled)) => *enabled,
+            Some(Value::Null) | None => false,
+            Some(_) => true,
+        }
+    }
+
+    pub(crate) fn shutdown(&mut self) {
+        if self.ready && !self.exited {
+            let id = self.next_request_id(LspRequest::Shutdown);
+            self.send(json!({ "jsonrpc": "2.0", "id": id, "method": "shutdown", "params": null }));
+            self.send(json!({ "jsonrpc": "2.0", "method": "exit", "params": null }));
+            let deadline = Instant::now() + Duration::from_millis(300);
+            while Instant::now() < deadline {
+                if matches!(self.child.try_wait(), Ok(Some(_))) {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+        self.exited = true;
+    }
+}
+
+impl Drop for LspClient {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn client_capabilities() -> Value {
+    json!({
+        "general": { "positionEncodings": ["utf-16"] },
+        "workspace": {
+            "applyEdit": true,
+            "configuration": true,
+            "workspaceEdit": { "documentChanges": true },
+        },
+        "textDocument": {
+            "synchronization": { "didSave": true, "dynamicRegistration": false },
+            "publishDiagnostics": { "relatedInformation": false },
+            "hover": { "contentFormat": ["plaintext", "markdown"] },
+            "definition": { "linkSupport": true },
+            "references": {},
+            "rename": { "prepareSupport": false },
+            "formatting": {},
+            "completion": {
+                "completionItem": { "snippetSupport": false },
+            },
+            "codeAction": {
+                "codeActionLiteralSupport": {
+                    "codeActionKind": {
+                        "valueSet": ["", "quickfix", "refactor", "refactor.extract", "refactor.inline", "refactor.rewrite", "source", "source.organizeImports"],
+                    },
+                },
+                "resolveSupport": { "properties": ["edit"] },
+            },
+        },
+        "window": { "workDoneProgress": true },
+    })
+}
+
+pub(crate) fn read_message(reader: &mut impl BufRead) -> Result<Option<Value>> {
+    let mut length = None;
+    let mut first = true;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            if first {
+                return Ok(None);
+            }
+            bail!("unexpected end of stream in LSP header");
+        }
+        first = false;
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            bail!("malformed LSP header: {:?}", line);
+        };
+        if name.trim().eq_ignore_ascii_case("content-length") {
+            length = Some(
+                value
+                    .trim()
+                    .parse::<usize>()
+                    .with_context(|| format!("invalid Content-Length: {:?}", value.trim()))?,
+            );
+        }
+    }
+    let length = length.context("LSP message without Content-Length")?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+pub(crate) fn write_message(writer: &mut impl Write, msg: &Value) -> Result<()> {
+    let body = serde_json::to_string(msg)?;
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()?;
+    Ok(())
+}
+
+pub(crate) fn absolute_path(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+pub(crate) fn find_root(path: &Path, markers: Option<&[String]>) -> PathBuf {
+    let path = absolute_path(path);
+    let start = path.parent().unwrap_or(Path::new("/")).to_path_buf();
+    let default_markers = [".git".to_string()];
+    let markers = markers.unwrap_or(&default_markers);
+    for dir in start.ancestors() {
+        if markers.iter().any(|marker| dir.join(marker).exists()) {
+            return dir.to_path_buf();
+        }
+    }
+    start
+}
+
+pub(crate) fn path_to_uri(path: &Path) -> String {
+    let path = absolute_path(path);
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+pub(crate) fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let raw = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::with_capacity(raw.len());
+    let mut i = 0;
+    while i < raw.len() {
+        if raw[i] == b'%'
+            && let Some(byte) = raw
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            bytes.push(byte);
+            i += 3;
+            continue;
+        }
+        bytes.push(raw[i]);
+        i += 1;
+    }
+    Some(PathBuf::from(String::from_utf8(bytes).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_round_trip_and_uris_escape() {
+        let mut out = Vec::new();
+        write_message(&mut out, &json!({ "id": 1, "result": "é" })).unwrap();
+        let mut reader = std::io::Cursor::new(out);
+        let msg = read_message(&mut reader).unwrap().unwrap();
+        assert_eq!(msg["result"], "é");
+        assert!(read_message(&mut reader).unwrap().is_none());
+
+        for bad in [&b"running 1 test\r\n\r\n{}"[..], b"Content-Type: x\r\n\r\n{}", b"Content-Length: 2\r\n"] {
+            assert!(read_message(&mut std::io::Cursor::new(bad)).is_err());
+        }
+
+        let uri = path_to_uri(Path::new("/tmp/a b/c#.rs"));
+        assert_eq!(uri, "file:///tmp/a%20b/c%23.rs");
+        assert_eq!(uri_to_path(&uri), Some(PathBuf::from("/tmp/a b/c#.rs")));
+    }
+}
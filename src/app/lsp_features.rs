@@ -0,0 +1,1001 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use serde_json::{json, Value};
+
+use super::buffer::TextBuffer;
+use super::completion::Words;
+use super::highlight::detect_language_name;
+use super::lsp::{absolute_path, find_root, path_to_uri, uri_to_path, LspClient, LspEvent, LspRequest};
+use super::types::{CommandPrompt, Mode};
+use super::App;
+
+#[derive(Debug, Clone)]
+pub(crate) struct Diagnostic {
+    pub(crate) start: (usize, usize),
+    pub(crate) end: (usize, usize),
+    pub(crate) severity: u8,
+    pub(crate) message: String,
+    pub(crate) raw: Value,
+}
+
+fn position(pos: (usize, usize)) -> Value {
+    json!({ "line": pos.0, "character": pos.1 })
+}
+
+fn parse_position(value: &Value) -> (usize, usize) {
+    (
+        value["line"].as_u64().unwrap_or(0) as usize,
+        value["character"].as_u64().unwrap_or(0) as usize,
+    )
+}
+
+fn language_id(language: &str) -> &str {
+    match language {
+        "tsx" => "typescriptreact",
+        other => other,
+    }
+}
+
+fn severity_label(severity: u8) -> &'static str {
+    match severity {
+        1 => "error",
+        2 => "warning",
+        3 => "info",
+        _ => "hint",
+    }
+}
+
+fn hover_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Array(items) => items.iter().map(hover_text).collect::<Vec<_>>().join("\n"),
+        Value::Object(_) => value["value"].as_str().unwrap_or("").to_string(),
+        _ => String::new(),
+    }
+}
+
+pub(super) fn display_path(path: &Path) -> PathBuf {
+    std::env::current_dir()
+        .ok()
+        .and_then(|cwd| path.strip_prefix(cwd).ok().map(Path::to_path_buf))
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+fn sync_document(client: &mut LspClient, path: &Path, text: &mut TextBuffer) {
+    let uri = path_to_uri(path);
+    let Some(version) = client.documents.get(path).copied() else {
+        text.take_lsp_changes();
+        client.documents.insert(path.to_path_buf(), 0);
+        let language = language_id(&client.language).to_string();
+        client.notify(
+            "textDocument/didOpen",
+            json!({ "textDocument": {
+                "uri": uri,
+                "languageId": language,
+                "version": 0,
+                "text": text.to_string(),
+            } }),
+        );
+        return;
+    };
+    let changes: Vec<Value> = match (text.take_lsp_changes(), client.sync_kind()) {
+        (_, 0) => return,
+        (Some(changes), _) if changes.is_empty() => return,
+        (Some(changes), 2) => changes
+            .into_iter()
+            .map(|change| {
+                json!({
+                    "range": { "start": position(change.start), "end": position(change.end) },
+                    "text": change.text,
+                })
+            })
+            .collect(),
+        _ => vec![json!({ "text": text.to_string() })],
+    };
+    client.documents.insert(path.to_path_buf(), version + 1);
+    client.notify(
+        "textDocument/didChange",
+        json!({
+            "textDocument": { "uri": uri, "version": version + 1 },
+            "contentChanges": changes,
+        }),
+    );
+}
+
+impl App {
+    pub fn poll_lsp(&mut self) {
+        if self.lsp_servers.is_empty() {
+            return;
+        }
+        let languages: Vec<String> = self.lsp_clients.keys().cloned().collect();
+        for language in languages {
+            let Some(client) = self.lsp_clients.get_mut(&language) else {
+                continue;
+            };
+            let events = client.poll();
+            let exited = client.exited;
+            for event in events {
+                self.handle_lsp_event(&language, event);
+            }
+            if exited {
+                self.lsp_clients.remove(&language);
+                self.lsp_failed
+                    .insert(language.clone(), "server exited".to_string());
+                self.set_status(format!("LSP: {} server exited", language));
+            }
+        }
+        self.sync_lsp_documents();
+    }
+
+    pub fn shutdown_lsp(&mut self) {
+        for client in self.lsp_clients.values_mut() {
+            client.shutdown();
+        }
+        self.lsp_clients.clear();
+    }
+
+    fn ensure_lsp_client(&mut self, language: &str, path: &Path) -> bool {
+        if self.lsp_clients.contains_key(language) {
+            return true;
+        }
+        if self.lsp_failed.contains_key(language) {
+            return false;
+        }
+        let Some(config) = self.lsp_servers.get(language) else {
+            return false;
+        };
+        let root = find_root(path, config.root_markers.as_deref());
+        match LspClient::start(language, config, root) {
+            Ok(client) => {
+                self.lsp_clients.insert(language.to_string(), client);
+                true
+            }
+            Err(err) => {
+                self.set_status(format!("LSP: {:#}", err));
+                self.lsp_failed.insert(language.to_string(), format!("{:#}", err));
+                false
+            }
+        }
+    }
+
+    fn sync_lsp_documents(&mut self) {
+        let mut docs: Vec<(Option<usize>, PathBuf)> = Vec::new();
+        if let Some(path) = self.file_path.clone() {
+            docs.push((None, path));
+        }
+        for (idx, slot) in self.buffers.iter().enumerate() {
+            if let Some(path) = slot.state.file_path.clone() {
+                docs.push((Some(idx), path));
+            }
+        }
+        let mut open = HashSet::new();
+        for (slot, path) in docs {
+            let Some(language) = detect_language_name(&Some(path.clone())) else {
+                continue;
+            };
+            if !self.ensure_lsp_client(language, &path) {
+                continue;
+            }
+            let path = absolute_path(&path);
+            let text = match slot {
+                None => &mut self.text,
+                Some(idx) => &mut self.buffers[idx].state.text,
+            };
+            if let Some(client) = self.lsp_clients.get_mut(language)
+                && client.ready
+            {
+                sync_document(client, &path, text);
+            }
+            open.insert(path);
+        }
+        for client in self.lsp_clients.values_mut() {
+            let closed: Vec<PathBuf> = client
+                .documents
+                .keys()
+                .filter(|path| !open.contains(*path))
+                .cloned()
+                .collect();
+            for path in closed {
+                client.documents.remove(&path);
+                client.notify(
+                    "textDocument/didClose",
+                    json!({ "textDocument": { "uri": path_to_uri(&path) } }),
+                );
+            }
+        }
+    }
+
+    pub(super) fn lsp_did_save(&mut self) {
+        let Some(language) = detect_language_name(&self.file_path) else {
+            return;
+        };
+        if !self.lsp_clients.contains_key(language) {
+            return;
+        }
+        self.sync_lsp_documents();
+        let Some(path) = self.file_path.as_deref().map(absolute_path) else {
+            return;
+        };
+        if let Some(client) = self.lsp_clients.get_mut(language)
+            && client.documents.contains_key(&path)
+        {
+            client.notify(
+                "textDocument/didSave",
+                json!({ "textDocument": { "uri": path_to_uri(&path) } }),
+            );
+        }
+    }
+
+    fn current_lsp_language(&mut self) -> Option<&'static str> {
+        let Some(language) = detect_language_name(&self.file_path)
+            .filter(|language| self.lsp_servers.contains_key(*language))
+        else {
+            self.set_status("No language server for this buffer");
+            return None;
+        };
+        self.sync_lsp_documents();
+        match self.lsp_clients.get(language) {
+            Some(client) if client.ready => Some(language),
+            Some(_) => {
+                self.set_status(format!("LSP: {} server is starting", language));
+                None
+            }
+            None => {
+                let reason = self
+                    .lsp_failed
+                    .get(language)
+                    .cloned()
+                    .unwrap_or_else(|| "not running".to_string());
+                self.set_status(format!("LSP: {}: {}", language, reason));
+                None
+            }
+        }
+    }
+
+    fn lsp_request(&mut self, capability: &str, request: LspRequest, method: &str, params: Value) {
+        let Some(language) = self.current_lsp_language() else {
+            return;
+        };
+        let Some(client) = self.lsp_clients.get_mut(language) else {
+            return;
+        };
+        if !client.supports(capability) {
+            let command = client.command.clone();
+            self.set_status(format!("LSP: {} does not support {}", command, method));
+            return;
+        }
+        client.request(request, method, params);
+    }
+
+    fn lsp_document(&self) -> Value {
+        let path = self.file_path.as_deref().map(absolute_path).unwrap_or_default();
+        json!({ "uri": path_to_uri(&path) })
+    }
+
+    fn lsp_position_params(&self) -> Value {
+        json!({
+            "textDocument": self.lsp_document(),
+            "position": position(self.text.pos_to_utf16((self.cursor_row, self.cursor_col))),
+        })
+    }
+
+    pub(super) fn lsp_hover(&mut self) {
+        let params = self.lsp_position_params();
+        self.lsp_request("hoverProvider", LspRequest::Hover, "textDocument/hover", params);
+    }
+
+    pub(super) fn lsp_definition(&mut self) {
+        let params = self.lsp_position_params();
+        self.lsp_request(
+            "definitionProvider",
+            LspRequest::Definition,
+            "textDocument/definition",
+            params,
+        );
+    }
+
+    pub(super) fn lsp_references(&mut self) {
+        let mut params = self.lsp_position_params();
+        params["context"] = json!({ "includeDeclaration": true });
+        self.lsp_request(
+            "referencesProvider",
+            LspRequest::References,
+            "textDocument/references",
+            params,
+        );
+    }
+
+    pub(super) fn lsp_rename(&mut self, new_name: &str) {
+        let mut params = self.lsp_position_params();
+        params["newName"] = json!(new_name);
+        self.lsp_request("renameProvider", LspRequest::Rename, "textDocument/rename", params);
+    }
+
+    pub(super) fn lsp_format(&mut self) {
+        let Some(path) = self.file_path.as_deref().map(absolute_path) else {
+            self.set_status("No file name");
+            return;
+        };
+        let params = json!({
+            "textDocument": self.lsp_document(),
+            "options": { "tabSize": self.shift_width, "insertSpaces": true },
+        });
+        self.lsp_request(
+            "documentFormattingProvider",
+            LspRequest::Formatting(path),
+            "textDocument/formatting",
+            params,
+        );
+    }
+
+    pub(super) fn lsp_complete(&mut self) {
+        let Some(path) = self.file_path.as_deref().map(absolute_path) else {
+            self.set_status("No file name");
+            return;
+        };
+        let params = self.lsp_position_params();
+        let request = LspRequest::Completion {
+            path,
+            pos: (self.cursor_row, self.cursor_col),
+        };
+        self.lsp_request("completionProvider", request, "textDocument/completion", params);
+    }
+
+    pub(super) fn lsp_code_action(&mut self, index: Option<usize>) {
+        if let Some(index) = index {
+            match self.lsp_code_actions.get(index.wrapping_sub(1)).cloned() {
+                Some(action) => self.run_code_action(action),
+                None => self.set_status(format!("No code action {}", index)),
+            }
+            return;
+        }
+        let row = self.cursor_row;
+        let diagnostics: Vec<Value> = self
+            .current_diagnostics()
+            .iter()
+            .filter(|diag| diag.start.0 <= row && row <= diag.end.0)
+            .map(|diag| diag.raw.clone())
+            .collect();
+        let pos = position(self.text.pos_to_utf16((self.cursor_row, self.cursor_col)));
+        let params = json!({
+            "textDocument": self.lsp_document(),
+            "range": { "start": pos, "end": pos },
+            "context": { "diagnostics": diagnostics },
+        });
+        self.lsp_request(
+            "codeActionProvider",
+            LspRequest::CodeAction,
+            "textDocument/codeAction",
+            params,
+        );
+    }
+
+    fn run_code_action(&mut self, action: Value) {
+        if action["command"].is_string() {
+            self.lsp_execute_command(&action);
+            return;
+        }
+        if action.get("edit").is_none() && action.get("command").is_none() {
+            let Some(language) = self.current_lsp_language() else {
+                return;
+            };
+            if let Some(client) = self.lsp_clients.get_mut(language)
+                && client.capabilities["codeActionProvider"]["resolveProvider"] == json!(true)
+            {
+                client.request(LspRequest::ResolveCodeAction, "codeAction/resolve", action);
+                return;
+            }
+            self.set_status("LSP: code action has no edit");
+            return;
+        }
+        if let Some(edit) = action.get("edit") {
+            self.apply_workspace_edit(edit);
+        }
+        if let Some(command) = action.get("command") {
+            self.lsp_execute_command(command);
+        }
+    }
+
+    fn lsp_execute_command(&mut self, command: &Value) {
+        let params = json!({
+            "command": command["command"],
+            "arguments": command.get("arguments").cloned().unwrap_or(json!([])),
+        });
+        self.lsp_request(
+            "executeCommandProvider",
+            LspRequest::ExecuteCommand,
+            "workspace/executeCommand",
+            params,
+        );
+    }
+
+    pub(super) fn lsp_restart(&mut self) {
+        self.shutdown_lsp();
+        self.lsp_failed.clear();
+        self.diagnostics.clear();
+        self.sync_lsp_documents();
+        self.set_status("LSP: restarted");
+    }
+
+    pub(super) fn lsp_info_lines(&self) -> Vec<String> {
+        let mut languages: Vec<&String> = self.lsp_servers.keys().collect();
+        languages.sort();
+        if languages.is_empty() {
+            return vec!["No language servers configured".to_string()];
+        }
+        languages
+            .into_iter()
+            .map(|language| match self.lsp_clients.get(language) {
+                Some(client) => format!(
+                    "{}: {} ({}) root={} docs={}",
+                    language,
+                    if client.ready { "running" } else { "starting" },
+                    client.command,
+                    client.root.display(),
+                    client.documents.len()
+                ),
+                None => match self.lsp_failed.get(language) {
+                    Some(reason) => format!("{}: failed: {}", language, reason),
+                    None => format!("{}: not started", language),
+                },
+            })
+            .collect()
+    }
+
+    fn handle_lsp_event(&mut self, language: &str, event: LspEvent) {
+        match event {
+            LspEvent::Response { request, result } => match result {
+                Ok(result) => self.handle_lsp_response(request, result),
+                Err(message) => {
+                    if request != LspRequest::Shutdown {
+                        self.set_status(format!("LSP: {}", message));
+                    }
+                }
+            },
+            LspEvent::Request { id, method, params } => {
+                let reply = match method.as_str() {
+                    "workspace/configuration" => Ok(self.lsp_configuration(language, &params)),
+                    "workspace/applyEdit" => {
+                        let applied = self.apply_workspace_edit(&params["edit"]);
+                        Ok(json!({ "applied": applied }))
+                    }
+                    "window/showMessageRequest" => {
+                        self.set_status(format!("LSP: {}", params["message"].as_str().unwrap_or("")));
+                        Ok(Value::Null)
+                    }
+                    "window/workDoneProgress/create"
+                    | "client/registerCapability"
+                    | "client/unregisterCapability" => Ok(Value::Null),
+                    _ => Err(format!("unsupported method {}", method)),
+                };
+                if let Some(client) = self.lsp_clients.get_mut(language) {
+                    client.respond(id, reply);
+                }
+            }
+            LspEvent::Notification { method, params } => match method.as_str() {
+                "textDocument/publishDiagnostics" => self.store_diagnostics(&params),
+                "window/showMessage" => {
+                    self.set_status(format!("LSP: {}", params["message"].as_str().unwrap_or("")));
+                }
+                _ => {}
+            },
+        }
+    }
+
+    fn lsp_configuration(&self, language: &str, params: &Value) -> Value {
+        let settings = self
+            .lsp_clients
+            .get(language)
+            .map(|client| client.settings.clone())
+            .unwrap_or(Value::Null);
+        let items = params["items"].as_array().cloned().unwrap_or_default();
+        items
+            .iter()
+            .map(|item| match item["section"].as_str() {
+                Some(section) => section
+                    .split('.')
+                    .try_fold(&settings, |value, key| value.get(key))
+                    .cloned()
+                    .unwrap_or(Value::Null),
+                None => settings.clone(),
+            })
+            .collect()
+    }
+
+    fn handle_lsp_response(&mut self, request: LspRequest, result: Value) {
+        match request {
+            LspRequest::Initialize | LspRequest::Shutdown | LspRequest::ExecuteCommand => {}
+            LspRequest::Hover => {
+                let text = hover_text(&result["contents"]);
+                let lines: Vec<String> = text
+                    .lines()
+                    .filter(|line| !line.starts_with("```"))
+                    .map(str::to_string)
+                    .collect();
+                if lines.iter().all(|line| line.trim().is_empty()) {
+                    self.set_status("No hover information");
+                } else {
+                    self.open_list(lines);
+                }
+            }
+            LspRequest::Definition => {
+                let location = match result {
+                    Value::Array(items) => items.into_iter().next(),
+                    Value::Null => None,
+                    other => Some(other),
+                };
+                let Some(location) = location else {
+                    self.set_status("No definition found");
+                    return;
+                };
+                let uri = location
+                    .get("targetUri")
+                    .or_else(|| location.get("uri"))
+                    .and_then(Value::as_str)
+                    .and_then(uri_to_path);
+                let range = location
+                    .get("targetSelectionRange")
+                    .or_else(|| location.get("range"))
+                    .cloned()
+                    .unwrap_or(Value::Null);
+                if let Some(path) = uri {
+                    self.jump_to_lsp_location(&path, parse_position(&range["start"]));
+                }
+            }
+            LspRequest::References => {
+                let locations = result.as_array().cloned().unwrap_or_default();
+                if locations.is_empty() {
+                    self.set_status("No references found");
+                    return;
+                }
+                let lines = locations
+                    .iter()
+                    .filter_map(|location| {
+                        let path = uri_to_path(location["uri"].as_str()?)?;
+                        let (row, col) = parse_position(&location["range"]["start"]);
+                        Some(format!(
+                            "{}:{}:{}: {}",
+                            display_path(&path).display(),
+                            row + 1,
+                            col + 1,
+                            self.lsp_line_text(&path, row).trim()
+                        ))
+                    })
+                    .collect();
+                self.open_list(lines);
+            }
+            LspRequest::Rename => {
+                if result.is_null() {
+                    self.set_status("LSP: nothing to rename");
+                } else {
+                    self.apply_workspace_edit(&result);
+                }
+            }
+            LspRequest::CodeAction => {
+                self.lsp_code_actions = result.as_array().cloned().unwrap_or_default();
+                if self.lsp_code_actions.is_empty() {
+                    self.set_status("No code actions available");
+                    return;
+                }
+                let lines = self
+                    .lsp_code_actions
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, action)| {
+                        format!("{}: {}", idx + 1, action["title"].as_str().unwrap_or("?"))
+                    })
+                    .collect();
+                self.open_list(lines);
+            }
+            LspRequest::ResolveCodeAction => {
+                if result.get("edit").is_some() {
+                    self.run_code_action(result);
+                } else {
+                    self.set_status("LSP: code action has no edit");
+                }
+            }
+            LspRequest::Formatting(path) => {
+                let edits = result.as_array().cloned().unwrap_or_default();
+                if edits.is_empty() {
+                    self.set_status("LSP: already formatted");
+                    return;
+                }
+                let origin = self.current_buffer_id;
+                if self.focus_lsp_path(&path) {
+                    self.apply_lsp_text_edits(&edits);
+                    self.set_status(format!("LSP: applied {} formatting edits", edits.len()));
+                }
+                self.enter_buffer(origin, false);
+            }
+            LspRequest::Completion { path, pos } => {
+                let current = self.file_path.as_deref().map(absolute_path);
+                if self.mode != Mode::Insert
+                    || current.as_ref() != Some(&path)
+                    || (self.cursor_row, self.cursor_col) != pos
+                {
+                    return;
+                }
+                let items = match result {
+                    Value::Array(items) => items,
+                    other => other["items"].as_array().cloned().unwrap_or_default(),
+                };
+                let words: Vec<String> = items
+                    .iter()
+                    .filter_map(|item| {
+                        item["textEdit"]["newText"]
+                            .as_str()
+                            .or_else(|| item["insertText"].as_str())
+                            .or_else(|| item["label"].as_str())
+                            .map(str::to_string)
+                    })
+                    .collect();
+                self.start_insert_completion(vec![Rc::new(Words { name: "LSP", words })], true);
+            }
+        }
+    }
+
+    fn open_list(&mut self, lines: Vec<String>) {
+        self.mode = Mode::Command;
+        self.command_prompt = CommandPrompt::Command;
+        self.command_buffer.clear();
+        self.command_cursor = 0;
+        self.show_list(lines);
+        self.command_keep_open = false;
+    }
+
+    fn lsp_line_text(&self, path: &Path, row: usize) -> String {
+        if self.file_path.as_deref().map(absolute_path).as_deref() == Some(path) {
+            return self.text.line(row).unwrap_or_default();
+        }
+        for slot in &self.buffers {
+            if slot.state.file_path.as_deref().map(absolute_path).as_deref() == Some(path) {
+                return slot.state.text.line(row).unwrap_or_default();
+            }
+        }
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|content| content.lines().nth(row).map(str::to_string))
+            .unwrap_or_default()
+    }
+
+    fn focus_lsp_path(&mut self, path: &Path) -> bool {
+        if self.file_path.as_deref().map(absolute_path).as_deref() == Some(path) {
+            return true;
+        }
+        let existing = self
+            .buffers
+            .iter()
+            .find(|slot| slot.state.file_path.as_deref().map(absolute_path).as_deref() == Some(path))
+            .map(|slot| slot.id);
+        match existing {
+            Some(id) => self.enter_buffer(id, false),
+            None => {
+                self.open_or_switch_buffer(display_path(path), None);
+                self.file_path.as_deref().map(absolute_path).as_deref() == Some(path)
+            }
+        }
+    }
+
+    fn jump_to_lsp_location(&mut self, path: &Path, pos: (usize, usize)) {
+        self.push_jump();
+        if !self.focus_lsp_path(path) {
+            return;
+        }
+        let (row, col) = self.text.utf16_to_pos(pos);
+        self.cursor_row = row;
+        self.cursor_col = col;
+    }
+
+    pub(super) fn apply_workspace_edit(&mut self, edit: &Value) -> bool {
+        let mut files: Vec<(PathBuf, Vec<Value>)> = Vec::new();
+        if let Some(changes) = edit["changes"].as_object() {
+            for (uri, edits) in changes {
+                if let Some(path) = uri_to_path(uri) {
+                    files.push((path, edits.as_array().cloned().unwrap_or_default()));
+                }
+            }
+        }
+        let mut skipped = 0;
+        for change in edit["documentChanges"].as_array().into_iter().flatten() {
+            let path = change["textDocument"]["uri"].as_str().and_then(uri_to_path);
+            match path {
+                Some(path) if change.get("kind").is_none() => {
+                    files.push((path, change["edits"].as_array().cloned().unwrap_or_default()));
+                }
+                _ => skipped += 1,
+            }
+        }
+        if files.is_empty() {
+            self.set_status("LSP: no edits to apply");
+            return false;
+        }
+        let origin = self.current_buffer_id;
+        let mut applied = 0;
+        for (path, edits) in files {
+            if self.focus_lsp_path(&path) {
+                self.apply_lsp_text_edits(&edits);
+                applied += 1;
+            }
+        }
+        self.enter_buffer(origin, false);
+        let mut status = format!("LSP: edited {} file(s)", applied);
+        if skipped > 0 {
+            status.push_str(&format!(", skipped {} file operation(s)", skipped));
+        }
+        self.set_status(status);
+        applied > 0
+    }
+
+    fn apply_lsp_text_edits(&mut self, edits: &[Value]) {
+        let mut resolved: Vec<_> = edits
+            .iter()
+            .enumerate()
+            .map(|(idx, edit)| {
+                (
+                    self.text.utf16_to_pos(parse_position(&edit["range"]["start"])),
+                    self.text.utf16_to_pos(parse_position(&edit["range"]["end"])),
+                    edit["newText"].as_str().unwrap_or("").to_string(),
+                    idx,
+                )
+            })
+            .collect();
+        if resolved.is_empty() {
+            return;
+        }
+        resolved.sort_by_key(|edit| std::cmp::Reverse((edit.0, edit.3)));
+        self.record_undo();
+        self.touch_edit();
+        self.clear_line_undo();
+        for (start, end, text, _) in resolved {
+            self.text.remove(start, end);
+            self.text.insert(start.0, start.1, &text);
+        }
+        self.dirty = true;
+        self.cursor_row = self.cursor_row.min(self.text.line_count().saturating_sub(1));
+        self.cursor_col = self.cursor_col.min(self.line_len(self.cursor_row));
+    }
+
+    fn store_diagnostics(&mut self, params: &Value) {
+        let Some(path) = params["uri"].as_str().and_then(uri_to_path) else {
+            return;
+        };
+        let diagnostics: Vec<Diagnostic> = params["diagnostics"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|raw| Diagnostic {
+                start: parse_position(&raw["range"]["start"]),
+                end: parse_position(&raw["range"]["end"]),
+                severity: raw["severity"].as_u64().unwrap_or(1) as u8,
+                message: raw["message"].as_str().unwrap_or("").to_string(),
+                raw: raw.clone(),
+            })
+            .collect();
+        if diagnostics.is_empty() {
+            self.diagnostics.remove(&path);
+        } else {
+            self.diagnostics.insert(path, diagnostics);
+        }
+    }
+
+    pub(crate) fn current_diagnostics(&self) -> &[Diagnostic] {
+        if self.diagnostics.is_empty() {
+            return &[];
+        }
+        self.file_path
+            .as_deref()
+            .map(absolute_path)
+            .and_then(|path| self.diagnostics.get(&path))
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    pub fn diagnostic_rows(&self) -> Option<HashMap<usize, u8>> {
+        let diagnostics = self.current_diagnostics();
+        if diagnostics.is_empty() {
+            return None;
+        }
+        let mut rows: HashMap<usize, u8> = HashMap::new();
+        for diag in diagnostics {
+            let severity = rows.entry(diag.start.0).or_insert(diag.severity);
+            *severity = (*severity).min(diag.severity);
+        }
+        Some(rows)
+    }
+
+    pub fn cursor_diagnostic(&self) -> Option<String> {
+        self.current_diagnostics()
+            .iter()
+            .filter(|diag| diag.start.0 <= self.cursor_row && self.cursor_row <= diag.end.0)
+            .min_by_key(|diag| diag.severity)
+            .map(|diag| format!("{}: {}", severity_label(diag.severity), diag.message))
+    }
+
+    pub(super) fn diagnostic_list_lines(&self) -> Vec<String> {
+        let diagnostics = self.current_diagnostics();
+        if diagnostics.is_empty() {
+            return vec!["No diagnostics".to_string()];
+        }
+        let mut sorted: Vec<&Diagnostic> = diagnostics.iter().collect();
+        sorted.sort_by_key(|diag| diag.start);
+        sorted
+            .into_iter()
+            .map(|diag| {
+                let (row, col) = self.text.utf16_to_pos(diag.start);
+                format!(
+                    "{}:{}: {}: {}",
+                    row + 1,
+                    col + 1,
+                    severity_label(diag.severity),
+                    diag.message
+                )
+            })
+            .collect()
+    }
+
+    pub(crate) fn goto_diagnostic(&mut self, forward: bool) {
+        let mut targets: Vec<((usize, usize), String)> = self
+            .current_diagnostics()
+            .iter()
+            .map(|diag| {
+                (
+                    self.text.utf16_to_pos(diag.start),
+                    format!("{}: {}", severity_label(diag.severity), diag.message),
+                )
+            })
+            .collect();
+        if targets.is_empty() {
+            self.set_status("No diagnostics");
+            return;
+        }
+        targets.sort_by_key(|(pos, _)| *pos);
+        let cursor = (self.cursor_row, self.cursor_col);
+        let target = if forward {
+            targets
+                .iter()
+                .find(|(pos, _)| *pos > cursor)
+                .or_else(|| targets.first())
+        } else {
+            targets
+                .iter()
+                .rev()
+                .find(|(pos, _)| *pos < cursor)
+                .or_else(|| targets.last())
+        };
+        if let Some(((row, col), message)) = target.cloned() {
+            self.cursor_row = row;
+            self.cursor_col = col;
+            self.set_status(message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    use super::super::config::LspServerConfig;
+    use super::super::handle_key;
+    use super::*;
+
+    fn key(app: &mut App, code: KeyCode, mods: KeyModifiers) {
+        handle_key(app, KeyEvent::new(code, mods)).unwrap();
+    }
+
+    fn wait_for(app: &mut App, what: &str, done: impl Fn(&App) -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !done(app) {
+            assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+            app.poll_lsp();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    // The scripted server is the fake_lsp example, which `cargo test` builds
+    // next to the test binary.
+    fn fake_server() -> String {
+        let exe = std::env::current_exe().unwrap();
+        let server = exe
+            .parent()
+            .and_then(Path::parent)
+            .unwrap()
+            .join("examples")
+            .join(format!("fake_lsp{}", std::env::consts::EXE_SUFFIX));
+        assert!(server.exists(), "{} missing; run `cargo test` to build it", server.display());
+        server.display().to_string()
+    }
+
+    #[test]
+    fn fake_server_drives_lsp_features() {
+        let dir = std::env::temp_dir().join(format!("rvim-lsp-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("main.rs");
+        std::fs::write(&path, "fn main() {\n    bad();\n}\n").unwrap();
+        let config = LspServerConfig {
+            command: vec![fake_server()],
+            root_markers: None,
+            env: None,
+            settings: None,
+        };
+
+        let mut app = App::new(Some(path.clone()), String::new());
+        app.swap_file = false;
+        app.lsp_servers.insert("rust".to_string(), config);
+        app.open_current_file(None);
+        wait_for(&mut app, "diagnostics", |app| app.diagnostic_rows().is_some());
+        assert_eq!(app.diagnostic_rows().unwrap().get(&1), Some(&1));
+        key(&mut app, KeyCode::Char('j'), KeyModifiers::NONE);
+        assert_eq!(app.cursor_diagnostic().as_deref(), Some("error: bad line"));
+
+        app.cursor_row = 0;
+        app.cursor_col = 0;
+        key(&mut app, KeyCode::Char(']'), KeyModifiers::NONE);
+        key(&mut app, KeyCode::Char('d'), KeyModifiers::NONE);
+        assert_eq!((app.cursor_row, app.cursor_col), (1, 0));
+
+        key(&mut app, KeyCode::Char('A'), KeyModifiers::NONE);
+        for ch in " // é".chars() {
+            key(&mut app, KeyCode::Char(ch), KeyModifiers::NONE);
+        }
+        key(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+        key(&mut app, KeyCode::Char('K'), KeyModifiers::NONE);
+        wait_for(&mut app, "hover", |app| app.mode == Mode::Command);
+        assert_eq!(app.completion_candidates[0], "mirror:");
+        assert_eq!(app.completion_candidates[2], "    bad(); // é");
+        key(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+
+        key(&mut app, KeyCode::Char('g'), KeyModifiers::NONE);
+        key(&mut app, KeyCode::Char('d'), KeyModifiers::NONE);
+        wait_for(&mut app, "definition", |app| app.cursor_row == 0);
+        assert_eq!(app.cursor_col, 3);
+
+        key(&mut app, KeyCode::Char('O'), KeyModifiers::NONE);
+        for ch in "al".chars() {
+            key(&mut app, KeyCode::Char(ch), KeyModifiers::NONE);
+        }
+        key(&mut app, KeyCode::Char('x'), KeyModifiers::CONTROL);
+        key(&mut app, KeyCode::Char('o'), KeyModifiers::CONTROL);
+        wait_for(&mut app, "completion", |app| app.cursor_col == 5);
+        assert_eq!(app.text.line(0).as_deref(), Some("alpha"));
+        key(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+
+        app.run_ex_command("LspFormat").unwrap();
+        wait_for(&mut app, "formatting", |app| app.text.line(2).is_some_and(|l| l.starts_with('\t')));
+        assert_eq!(app.text.line(2).as_deref(), Some("\tbad(); // é"));
+        app.undo();
+        assert_eq!(app.text.line(2).as_deref(), Some("    bad(); // é"));
+
+        key(&mut app, KeyCode::Char('g'), KeyModifiers::NONE);
+        key(&mut app, KeyCode::Char('r'), KeyModifiers::NONE);
+        wait_for(&mut app, "references", |app| app.mode == Mode::Command);
+        assert_eq!(
+            app.completion_candidates,
+            vec![
+                format!("{}:2:4: fn main() {{", display_path(&path).display()),
+                format!("{}:3:5: bad(); // é", display_path(&path).display()),
+            ]
+        );
+        key(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+
+        app.run_ex_command("LspRename good").unwrap();
+        wait_for(&mut app, "rename", |app| app.text.line(2).is_some_and(|l| l.contains("good")));
+        assert_eq!(app.text.line(2).as_deref(), Some("    good(); // é"));
+
+        app.run_ex_command("LspCodeAction").unwrap();
+        wait_for(&mut app, "code actions", |app| app.mode == Mode::Command);
+        assert_eq!(app.completion_candidates, vec!["1: Add header", "2: Add footer"]);
+        key(&mut app, KeyCode::Esc, KeyModifiers::NONE);
+        app.run_ex_command("LspCodeAction 1").unwrap();
+        assert_eq!(app.text.line(0).as_deref(), Some("// header"));
+        app.run_ex_command("LspCodeAction 2").unwrap();
+        wait_for(&mut app, "resolved code action", |app| app.text.line_count() == 6);
+        assert_eq!(app.text.line(4).as_deref(), Some("// footer"));
+
+        app.shutdown_lsp();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
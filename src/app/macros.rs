@@ -0,0 +1,305 @@
+use anyhow::Result;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use super::input::handle_key;
+use super::keymap::{KeySpec, format_sequence, parse_key_sequence};
+use super::registers::Register;
+use super::types::{Mode, SearchSpec, YankType};
+use super::App;
+
+const MAX_MACRO_EXPANSIONS: usize = 1000;
+
+pub(super) fn is_macro_register(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || ch == '"'
+}
+
+fn parse_let_value(input: &str) -> Result<String, String> {
+    if let Some(body) = input.strip_prefix('\'') {
+        let body = body
+            .strip_suffix('\'')
+            .ok_or_else(|| format!("Missing quote: {}", input))?;
+        return Ok(body.replace("''", "'"));
+    }
+    let body = input
+        .strip_prefix('"')
+        .and_then(|body| body.strip_suffix('"'))
+        .ok_or_else(|| format!("Invalid expression: {}", input))?;
+    let mut out = String::new();
+    let mut chars = body.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('e') => out.push_str("<Esc>"),
+            Some('r') => out.push_str("<Enter>"),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    Ok(out)
+}
+
+impl App {
+    pub(super) fn start_macro_recording(&mut self, name: char) {
+        self.macro_recording = Some(name);
+        self.macro_keys.clear();
+        self.set_status(format!("recording @{}", name));
+    }
+
+    pub(super) fn stop_macro_recording(&mut self) {
+        let Some(name) = self.macro_recording.take() else {
+            return;
+        };
+        self.macro_keys.pop();
+        let text = format_sequence(&self.macro_keys);
+        self.macro_keys.clear();
+        self.registers.set(
+            name,
+            Register {
+                text,
+                kind: YankType::Char,
+            },
+        );
+        self.set_status(format!("Recorded @{}", name.to_ascii_lowercase()));
+    }
+
+    pub(super) fn record_macro_key(&mut self, key: &KeyEvent) {
+        if self.macro_recording.is_none() || self.macro_running {
+            return;
+        }
+        let mods = match key.code {
+            KeyCode::Char(_) => key.modifiers - KeyModifiers::SHIFT,
+            _ => key.modifiers,
+        };
+        self.macro_keys.push(KeySpec {
+            code: key.code,
+            mods,
+        });
+    }
+
+    pub(super) fn fail_macro(&mut self) {
+        if self.macro_running {
+            self.macro_failed = true;
+        }
+    }
+
+    pub(super) fn is_macro_motion(&self, key: &KeyEvent) -> bool {
+        self.macro_running
+            && self.mode == Mode::Normal
+            && self.operator_pending.is_none()
+            && self.pending_find.is_none()
+            && self.pending_textobj.is_none()
+            && !self.pending_mark
+            && !self.pending_g
+            && !self.awaiting_register
+            && !self.pending_macro_record
+            && self.pending_macro_play.is_none()
+            && self.pending_window.is_none()
+            && !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+            && matches!(
+                key.code,
+                KeyCode::Char('h' | 'j' | 'k' | 'l' | 'w' | 'b' | 'e' | 'W' | 'B' | 'E')
+                    | KeyCode::Left
+                    | KeyCode::Right
+                    | KeyCode::Up
+                    | KeyCode::Down
+            )
+    }
+
+    pub(super) fn play_macro(&mut self, name: char, count: usize) -> Result<bool> {
+        let name = match (name, self.last_macro) {
+            ('@', Some(last)) => last,
+            ('@', None) => {
+                self.set_status("No previous macro");
+                self.fail_macro();
+                return Ok(false);
+            }
+            _ => name,
+        };
+        self.last_macro = Some(name);
+        if name == ':' {
+            let Some(command) = self.command_history.last().cloned() else {
+                self.set_status("No previous command line");
+                return Ok(false);
+            };
+            for _ in 0..count {
+                if self.run_ex_command(&command)? {
+                    return Ok(true);
+                }
+            }
+            return Ok(false);
+        }
+        let text = match self.register_content(name) {
+            Some(register) if !register.text.is_empty() => register.text,
+            _ => {
+                self.set_status(format!("Nothing in register {}", name));
+                self.fail_macro();
+                return Ok(false);
+            }
+        };
+        let Some(keys) = parse_key_sequence(&text) else {
+            self.set_status(format!("Invalid macro in register {}", name));
+            self.fail_macro();
+            return Ok(false);
+        };
+        if self.macro_running {
+            self.macro_expansions += 1;
+            if self.macro_expansions > MAX_MACRO_EXPANSIONS {
+                self.set_status("Macro recursion too deep");
+                self.macro_failed = true;
+                return Ok(false);
+            }
+            for _ in 0..count {
+                for key in keys.iter().rev() {
+                    self.macro_queue.push_front(*key);
+                }
+            }
+            return Ok(false);
+        }
+        self.macro_queue = (0..count).flat_map(|_| keys.iter().copied()).collect();
+        self.macro_running = true;
+        self.macro_failed = false;
+        self.macro_expansions = 0;
+        let mut result = Ok(false);
+        while let Some(key) = self.macro_queue.pop_front() {
+            result = handle_key(self, KeyEvent::new(key.code, key.mods));
+            if !matches!(result, Ok(false)) || self.macro_failed {
+                break;
+            }
+        }
+        self.macro_queue.clear();
+        self.macro_running = false;
+        result
+    }
+
+    pub(super) fn let_command(&mut self, args: &str) {
+        let Some(rest) = args.strip_prefix('@') else {
+            self.set_status("Only :let @{reg} = {value} is supported");
+            return;
+        };
+        let mut chars = rest.chars();
+        let Some(name) = chars.next() else {
+            self.set_status("Argument required");
+            return;
+        };
+        let rest = chars.as_str().trim_start();
+        let (append, value) = match rest.strip_prefix(".=") {
+            Some(value) => (true, value),
+            None => match rest.strip_prefix('=') {
+                Some(value) => (false, value),
+                None => {
+                    self.set_status(format!("Invalid expression: {}", args));
+                    return;
+                }
+            },
+        };
+        let value = value.trim();
+        let text = match value.strip_prefix('@') {
+            Some(source) => source
+                .chars()
+                .next()
+                .and_then(|source| self.register_content(source))
+                .map(|register| register.text)
+                .unwrap_or_default(),
+            None => match parse_let_value(value) {
+                Ok(text) => text,
+                Err(err) => {
+                    self.set_status(err);
+                    return;
+                }
+            },
+        };
+        // `.=` concatenates onto the register's current value, as a string the way Vim
+        // does, so a linewise register keeps its trailing newline.
+        let text = match self.register_content(name) {
+            Some(existing) if append && existing.kind == YankType::Line => {
+                format!("{}\n{}", existing.text, text)
+            }
+            Some(existing) if append => existing.text + &text,
+            _ => text,
+        };
+        let register = match text.strip_suffix('\n') {
+            Some(stripped) => Register {
+                text: stripped.to_string(),
+                kind: YankType::Line,
+            },
+            None => Register {
+                text,
+                kind: YankType::Char,
+            },
+        };
+        match name {
+            '/' => {
+                self.last_search = Some(SearchSpec {
+                    pattern: register.text,
+                    reverse: false,
+                });
+            }
+            '+' | '*' => {
+                self.clipboard_copy(&register);
+            }
+            _ if name.is_ascii_alphanumeric() || matches!(name, '"' | '-') => {
+                let name = if append { name.to_ascii_lowercase() } else { name };
+                self.registers.set(name, register);
+            }
+            _ => self.set_status(format!("Cannot write register {}", name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(app: &mut App, keys: &str) {
+        for key in parse_key_sequence(keys).unwrap() {
+            handle_key(app, KeyEvent::new(key.code, key.mods)).unwrap();
+        }
+    }
+
+    #[test]
+    fn records_and_replays_with_count() {
+        let mut app = App::new(None, "a\nb\nc\nd\ne".to_string());
+        feed(&mut app, "qaA;<Esc>jq");
+        assert_eq!(app.registers.get('a').unwrap().text, "A;<Esc>j");
+        feed(&mut app, "2@a@@");
+        assert_eq!(app.text.to_string(), "a;\nb;\nc;\nd;\ne");
+        feed(&mut app, ":let @b='x'<Enter>gg@b");
+        assert_eq!(app.text.line(0).unwrap(), ";");
+    }
+
+    #[test]
+    fn recursive_macro_stops_when_motion_fails() {
+        let mut app = App::new(None, "1\n2\n3\n4".to_string());
+        feed(&mut app, ":let @q=\"A!\\ej@q\"<Enter>");
+        feed(&mut app, "@q");
+        assert_eq!(app.text.to_string(), "1!\n2!\n3!\n4!");
+        assert!(!app.macro_running);
+    }
+
+    #[test]
+    fn let_append_concatenates_for_every_writable_register() {
+        let mut app = App::new(None, String::new());
+        for command in [
+            ":let @\"='a'<Enter>:let @\" .= 'b'<Enter>",
+            ":let @-='c'<Enter>:let @- .= 'd'<Enter>",
+            ":let @4='e'<Enter>:let @4 .= 'f'<Enter>",
+            ":let @a=\"one\\n\"<Enter>:let @A .= 'two'<Enter>",
+            ":let @/='x'<Enter>:let @/ .= 'y'<Enter>",
+        ] {
+            feed(&mut app, command);
+        }
+        assert_eq!(app.registers.get('"').unwrap().text, "ab");
+        assert_eq!(app.registers.get('-').unwrap().text, "cd");
+        assert_eq!(app.registers.get('4').unwrap().text, "ef");
+        assert_eq!(app.registers.get('a').unwrap(), &Register {
+            text: "one\ntwo".to_string(),
+            kind: YankType::Char,
+        });
+        assert_eq!(app.last_search.as_ref().unwrap().pattern, "xy");
+    }
+}
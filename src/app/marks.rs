@@ -0,0 +1,367 @@
+use std::path::PathBuf;
+
+use super::registers::Register;
+use super::types::{Mode, Operator, YankType};
+use super::App;
+
+const MAX_JUMPS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Jump {
+    pub(crate) buffer_id: usize,
+    pub(crate) row: usize,
+    pub(crate) col: usize,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct GlobalMark {
+    pub(crate) buffer_id: Option<usize>,
+    pub(crate) path: Option<PathBuf>,
+    pub(crate) pos: (usize, usize),
+}
+
+impl App {
+    pub(super) fn set_mark(&mut self, name: char) {
+        let pos = (self.cursor_row, self.cursor_col);
+        match name {
+            'a'..='z' | '[' | ']' => self.text.set_mark(name, pos),
+            '\'' | '`' => self.text.set_mark('\'', pos),
+            'A'..='Z' => {
+                if let Some(old) = self.global_marks.get(&name).and_then(|mark| mark.buffer_id)
+                    && let Some(slot) = self.buffers.iter_mut().find(|slot| slot.id == old)
+                {
+                    slot.state.text.remove_mark(name);
+                }
+                self.text.set_mark(name, pos);
+                self.global_marks.insert(
+                    name,
+                    GlobalMark {
+                        buffer_id: Some(self.current_buffer_id),
+                        path: self.file_path.clone(),
+                        pos,
+                    },
+                );
+            }
+            _ => self.set_status(format!("Invalid mark: {}", name)),
+        }
+    }
+
+    pub(super) fn mark_position(&self, name: char) -> Option<(usize, usize)> {
+        let pos = match name {
+            '<' | '>' => {
+                let visual = self.last_visual?;
+                let (low, high) = if visual.start <= visual.end {
+                    (visual.start, visual.end)
+                } else {
+                    (visual.end, visual.start)
+                };
+                if name == '<' { low } else { high }
+            }
+            '`' => self.text.mark('\'')?,
+            'A'..='Z' => {
+                let mark = self.global_marks.get(&name)?;
+                if mark.buffer_id != Some(self.current_buffer_id) {
+                    return None;
+                }
+                self.text.mark(name)?
+            }
+            _ => self.text.mark(name)?,
+        };
+        let row = pos.0.min(self.text.line_count().saturating_sub(1));
+        Some((row, pos.1.min(self.line_len(row))))
+    }
+
+    pub(super) fn set_change_marks(&mut self, start: (usize, usize), end: (usize, usize)) {
+        let (start, end) = if start <= end { (start, end) } else { (end, start) };
+        self.text.set_mark('[', start);
+        self.text.set_mark(']', end);
+    }
+
+    pub(super) fn mark_put(&mut self, register: &Register, start: (usize, usize)) {
+        let lines: Vec<&str> = register.text.split('\n').collect();
+        let last_row = start.0 + lines.len() - 1;
+        let end = match register.kind {
+            YankType::Line => (last_row, 0),
+            YankType::Block => {
+                let width = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+                (last_row, (start.1 + width).saturating_sub(1))
+            }
+            YankType::Char if lines.len() == 1 => {
+                (start.0, (start.1 + lines[0].chars().count()).saturating_sub(1))
+            }
+            YankType::Char => (last_row, lines[lines.len() - 1].chars().count().saturating_sub(1)),
+        };
+        self.set_change_marks(start, end);
+    }
+
+    pub(super) fn jump_to_mark(&mut self, name: char, linewise: bool) {
+        if name.is_ascii_uppercase() && !self.enter_global_mark(name) {
+            return;
+        }
+        let Some((row, col)) = self.mark_position(name) else {
+            self.set_status(format!("Mark not set: {}", name));
+            self.fail_macro();
+            return;
+        };
+        if let Some(op) = self.operator_pending.take() {
+            self.apply_mark_operator(op.op, (row, col), linewise);
+            return;
+        }
+        self.push_jump();
+        if row != self.cursor_row {
+            self.clear_line_undo();
+        }
+        self.cursor_row = row;
+        self.cursor_col = col;
+        if linewise {
+            self.move_line_first_non_blank();
+        }
+    }
+
+    fn enter_global_mark(&mut self, name: char) -> bool {
+        let Some(mark) = self.global_marks.get(&name).cloned() else {
+            self.set_status(format!("Mark not set: {}", name));
+            return false;
+        };
+        match (mark.buffer_id, mark.path) {
+            (Some(id), _) if id == self.current_buffer_id => true,
+            (Some(id), _) => self.switch_to_buffer(id),
+            (None, Some(path)) => {
+                self.open_or_switch_buffer(path.clone(), None);
+                if self.file_path.as_ref() != Some(&path) {
+                    return false;
+                }
+                self.text.set_mark(name, mark.pos);
+                if let Some(global) = self.global_marks.get_mut(&name) {
+                    global.buffer_id = Some(self.current_buffer_id);
+                }
+                true
+            }
+            (None, None) => {
+                self.set_status(format!("Mark not set: {}", name));
+                false
+            }
+        }
+    }
+
+    pub(super) fn forget_buffer_marks(&mut self, id: usize, text: &super::buffer::TextBuffer) {
+        for (name, mark) in self.global_marks.iter_mut() {
+            if mark.buffer_id == Some(id) {
+                mark.buffer_id = None;
+                if let Some(pos) = text.mark(*name) {
+                    mark.pos = pos;
+                }
+            }
+        }
+        self.global_marks.retain(|_, mark| mark.buffer_id.is_some() || mark.path.is_some());
+        self.jumplist.retain(|jump| jump.buffer_id != id);
+        self.jump_index = self.jump_index.min(self.jumplist.len());
+    }
+
+    fn apply_mark_operator(&mut self, op: Operator, target: (usize, usize), linewise: bool) {
+        let cursor = (self.cursor_row, self.cursor_col);
+        let (start, end) = if cursor <= target { (cursor, target) } else { (target, cursor) };
+        if linewise {
+            match op {
+                Operator::Yank => self.yank_lines(start.0, end.0),
+                Operator::Delete | Operator::Change => {
+                    self.register_lines(start.0, end.0, true);
+                    self.delete_lines(start.0, end.0);
+                    if op == Operator::Change {
+                        if self.cursor_row >= start.0 && self.text.line_count() > start.0 {
+                            self.open_line_above();
+                        } else {
+                            self.open_line_below();
+                        }
+                        self.mode = Mode::Insert;
+                        self.insert_undo_snapshot = false;
+                    } else {
+                        self.move_line_first_non_blank();
+                    }
+                }
+            }
+            return;
+        }
+        if start == end {
+            return;
+        }
+        let end = if end.1 > 0 {
+            (end.0, end.1 - 1)
+        } else {
+            (end.0 - 1, self.line_len(end.0 - 1).saturating_sub(1))
+        };
+        self.apply_operator(op, start, end);
+        if op == Operator::Change {
+            self.mode = Mode::Insert;
+            self.insert_undo_snapshot = false;
+        }
+    }
+
+    pub(super) fn push_jump(&mut self) {
+        let jump = Jump {
+            buffer_id: self.current_buffer_id,
+            row: self.cursor_row,
+            col: self.cursor_col,
+        };
+        self.text.set_mark('\'', (jump.row, jump.col));
+        self.jumplist
+            .retain(|old| old.buffer_id != jump.buffer_id || old.row != jump.row);
+        self.jumplist.push(jump);
+        if self.jumplist.len() > MAX_JUMPS {
+            self.jumplist.remove(0);
+        }
+        self.jump_index = self.jumplist.len();
+    }
+
+    pub(super) fn jump_older(&mut self, count: usize) {
+        let mut current = self.jump_index;
+        if current >= self.jumplist.len() {
+            self.push_jump();
+            current = self.jumplist.len() - 1;
+        }
+        match current.checked_sub(count) {
+            Some(target) => self.go_to_jump(target),
+            None => self.fail_macro(),
+        }
+    }
+
+    pub(super) fn jump_newer(&mut self, count: usize) {
+        let target = self.jump_index + count;
+        if target < self.jumplist.len() {
+            self.go_to_jump(target);
+        } else {
+            self.fail_macro();
+        }
+    }
+
+    fn go_to_jump(&mut self, idx: usize) {
+        let jump = self.jumplist[idx];
+        if jump.buffer_id != self.current_buffer_id && !self.enter_buffer(jump.buffer_id, false) {
+            self.jumplist.remove(idx);
+            self.jump_index = self.jump_index.min(self.jumplist.len());
+            return;
+        }
+        self.jump_index = idx;
+        if jump.row != self.cursor_row {
+            self.clear_line_undo();
+        }
+        self.cursor_row = jump.row.min(self.text.line_count().saturating_sub(1));
+        self.cursor_col = jump.col.min(self.line_len(self.cursor_row));
+    }
+
+    fn mark_line_text(&self, row: usize) -> String {
+        self.text.line(row).unwrap_or_default().trim().to_string()
+    }
+
+    pub(super) fn marks_list_lines(&self, filter: &str) -> Vec<String> {
+        let mut entries: Vec<(char, (usize, usize), String)> = Vec::new();
+        let names = ['\'']
+            .into_iter()
+            .chain('a'..='z')
+            .chain('A'..='Z')
+            .chain(['"', '[', ']', '^', '.', '<', '>']);
+        for name in names {
+            if !filter.is_empty() && !filter.contains(name) {
+                continue;
+            }
+            if name.is_ascii_uppercase() {
+                let Some(mark) = self.global_marks.get(&name) else {
+                    continue;
+                };
+                if mark.buffer_id != Some(self.current_buffer_id) {
+                    let pos = self
+                        .buffers
+                        .iter()
+                        .find(|slot| Some(slot.id) == mark.buffer_id)
+                        .and_then(|slot| slot.state.text.mark(name))
+                        .unwrap_or(mark.pos);
+                    let file = mark
+                        .path
+                        .as_ref()
+                        .map(|path| path.display().to_string())
+                        .unwrap_or_else(|| "[No Name]".to_string());
+                    entries.push((name, pos, file));
+                    continue;
+                }
+            }
+            if let Some(pos) = self.mark_position(name) {
+                entries.push((name, pos, self.mark_line_text(pos.0)));
+            }
+        }
+        let mut lines = vec!["mark line  col file/text".to_string()];
+        for (name, (row, col), text) in entries {
+            lines.push(format!(" {}   {:>5} {:>4} {}", name, row + 1, col, text));
+        }
+        lines
+    }
+
+    pub(super) fn jumps_list_lines(&self) -> Vec<String> {
+        let mut lines = vec![" jump line  col file/text".to_string()];
+        for (idx, jump) in self.jumplist.iter().enumerate() {
+            let distance = idx.abs_diff(self.jump_index);
+            let text = if jump.buffer_id == self.current_buffer_id {
+                self.mark_line_text(jump.row)
+            } else {
+                self.buffers
+                    .iter()
+                    .find(|slot| slot.id == jump.buffer_id)
+                    .and_then(|slot| slot.state.file_path.as_ref())
+                    .map(|path| path.display().to_string())
+                    .unwrap_or_else(|| "[No Name]".to_string())
+            };
+            lines.push(format!(
+                " {:>4} {:>5} {:>4} {}",
+                distance,
+                jump.row + 1,
+                jump.col,
+                text
+            ));
+        }
+        if self.jump_index >= self.jumplist.len() {
+            lines.push(">".to_string());
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    fn feed(app: &mut App, keys: &str) {
+        for ch in keys.chars() {
+            super::super::handle_key(app, KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE))
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn marks_jump_and_jumplist_walks_back() {
+        let mut app = App::new(None, "a\n  b\nc\nd\ne".to_string());
+        feed(&mut app, "jlmaG");
+        assert_eq!(app.cursor_row, 4);
+        feed(&mut app, "'a");
+        assert_eq!((app.cursor_row, app.cursor_col), (1, 2));
+        feed(&mut app, "gg`a");
+        assert_eq!((app.cursor_row, app.cursor_col), (1, 1));
+        let back = KeyEvent::new(KeyCode::Char('o'), KeyModifiers::CONTROL);
+        super::super::handle_key(&mut app, back).unwrap();
+        assert_eq!(app.cursor_row, 0);
+        super::super::handle_key(&mut app, back).unwrap();
+        assert_eq!(app.cursor_row, 4);
+        super::super::handle_key(&mut app, KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.cursor_row, 0);
+
+        feed(&mut app, "ggOnew");
+        super::super::handle_key(&mut app, KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE))
+            .unwrap();
+        assert_eq!(app.mark_position('a'), Some((2, 1)));
+        assert_eq!(app.mark_position('^'), Some((0, 3)));
+
+        feed(&mut app, "jd'a");
+        assert_eq!(app.text.to_string(), "new\nc\nd\ne");
+        assert_eq!(app.mark_position('a'), None);
+    }
+}
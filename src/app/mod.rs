@@ -1,19 +1,44 @@
+mod buffer;
+mod clipboard;
 mod command;
+mod completion;
 mod config;
 mod edit;
+mod events;
+mod ex;
+mod explorer;
+mod fileio;
+mod fuzzy;
 mod highlight;
 mod input;
 mod keymap;
+mod lsp;
+mod lsp_features;
+mod macros;
+mod marks;
 mod motion;
+mod picker;
+mod plugin;
+mod quickfix;
+mod range;
+mod registers;
+mod search;
+mod substitute;
+mod swap;
 mod theme;
 mod types;
+mod undo;
+mod undofile;
+mod wasm_plugin;
+mod window;
 
 pub use input::handle_key;
 pub use theme::Theme;
 pub use highlight::{HighlightKind, SyntaxSpan, total_spans};
-pub use keymap::KeymapEntry;
 pub use types::{
     App, CommandPrompt, Mode, VisualSelection, VisualSelectionKind, char_display_width,
     char_to_screen_col, line_screen_width,
 };
 pub use config::load_config;
+pub use search::SearchPattern;
+pub use swap::recoverable_swap_lines;
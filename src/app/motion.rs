@@ -3,7 +3,7 @@ use super::App;
 
 impl App {
     pub(super) fn char_at(&self, row: usize, col: usize) -> Option<char> {
-        self.lines.get(row).and_then(|l| l.chars().nth(col))
+        self.text.char_at(row, col)
     }
 
     pub(super) fn class_at(&self, row: usize, col: usize) -> Option<CharClass> {
@@ -21,7 +21,7 @@ impl App {
         let len = self.line_len(row);
         if col < len {
             Some((row, col + 1))
-        } else if row + 1 < self.lines.len() {
+        } else if row + 1 < self.text.line_count() {
             Some((row + 1, 0))
         } else {
             None
@@ -65,7 +65,7 @@ impl App {
         let len = self.line_len(self.cursor_row);
         if self.cursor_col < len {
             self.cursor_col += 1;
-        } else if self.cursor_row + 1 < self.lines.len() {
+        } else if self.cursor_row + 1 < self.text.line_count() {
             self.cursor_row += 1;
             self.cursor_col = 0;
         }
@@ -84,7 +84,7 @@ impl App {
     }
 
     pub(super) fn move_down(&mut self) {
-        if self.cursor_row + 1 < self.lines.len() {
+        if self.cursor_row + 1 < self.text.line_count() {
             self.cursor_row += 1;
             let len = self.line_len(self.cursor_row);
             self.cursor_col = self.cursor_col.min(len);
@@ -98,7 +98,7 @@ impl App {
 
     pub(super) fn move_line_first_non_blank(&mut self) {
         let mut col = 0;
-        if let Some(line) = self.lines.get(self.cursor_row) {
+        if let Some(line) = self.text.line_slice(self.cursor_row) {
             for ch in line.chars() {
                 if !ch.is_whitespace() {
                     break;
@@ -125,22 +125,12 @@ impl App {
     }
 
     pub(super) fn move_to_bottom(&mut self) {
-        if self.lines.is_empty() {
-            self.cursor_row = 0;
-            self.cursor_col = 0;
-            return;
-        }
-        self.cursor_row = self.lines.len() - 1;
+        self.cursor_row = self.text.line_count() - 1;
         self.cursor_col = 0;
     }
 
     pub(super) fn move_to_line(&mut self, line: usize) {
-        if self.lines.is_empty() {
-            self.cursor_row = 0;
-            self.cursor_col = 0;
-            return;
-        }
-        let target = line.saturating_sub(1).min(self.lines.len() - 1);
+        let target = line.saturating_sub(1).min(self.text.line_count() - 1);
         self.cursor_row = target;
         self.cursor_col = 0;
     }
@@ -411,19 +401,18 @@ impl App {
         let mut row = self.cursor_row;
         let mut col = self.cursor_col + 1;
 
-        while row < self.lines.len() {
-            let line = &self.lines[row];
+        while row < self.text.line_count() {
+            let line = self.text.line(row).unwrap_or_default();
             for (idx, ch) in line.chars().enumerate() {
                 if row == self.cursor_row && idx < col {
                     continue;
                 }
                 if ch == target {
                     let mut target_pos = (row, idx);
-                    if until {
-                        if let Some(prev) = self.prev_pos(row, idx) {
+                    if until
+                        && let Some(prev) = self.prev_pos(row, idx) {
                             target_pos = prev;
                         }
-                    }
                     self.cursor_row = target_pos.0;
                     self.cursor_col = target_pos.1;
                     if self.cursor_row != prev_row {
@@ -442,15 +431,12 @@ impl App {
     }
 
     pub(super) fn find_backward(&mut self, target: char, until: bool) -> bool {
-        if self.lines.is_empty() {
-            return false;
-        }
         let prev_row = self.cursor_row;
         let mut row = self.cursor_row;
         let mut col = self.cursor_col;
 
         loop {
-            let line = &self.lines[row];
+            let line = self.text.line(row).unwrap_or_default();
             let mut last_match: Option<usize> = None;
             for (idx, ch) in line.chars().enumerate() {
                 if row == self.cursor_row && idx >= col {
@@ -462,11 +448,10 @@ impl App {
             }
             if let Some(idx) = last_match {
                 let mut target_pos = (row, idx);
-                if until {
-                    if let Some(next) = self.advance_pos(row, idx) {
+                if until
+                    && let Some(next) = self.advance_pos(row, idx) {
                         target_pos = next;
                     }
-                }
                 self.cursor_row = target_pos.0;
                 self.cursor_col = target_pos.1;
                 if self.cursor_row != prev_row {
@@ -483,61 +468,6 @@ impl App {
         false
     }
 
-    pub(super) fn search_forward(&mut self, pattern: &str) -> bool {
-        if pattern.is_empty() || self.lines.is_empty() {
-            return false;
-        }
-        let prev_row = self.cursor_row;
-        let needle: Vec<char> = pattern.chars().collect();
-        let mut row = self.cursor_row;
-        let mut col = self.cursor_col + 1;
-        while row < self.lines.len() {
-            let line = &self.lines[row];
-            if let Some(idx) = find_in_line(line, &needle, col) {
-                self.cursor_row = row;
-                self.cursor_col = idx;
-                if self.cursor_row != prev_row {
-                    self.clear_line_undo();
-                }
-                return true;
-            }
-            if !self.find_cross_line {
-                break;
-            }
-            row += 1;
-            col = 0;
-        }
-        false
-    }
-
-    pub(super) fn search_backward(&mut self, pattern: &str) -> bool {
-        if pattern.is_empty() || self.lines.is_empty() {
-            return false;
-        }
-        let prev_row = self.cursor_row;
-        let needle: Vec<char> = pattern.chars().collect();
-        let mut row = self.cursor_row;
-        let mut col = self.cursor_col.saturating_sub(1);
-        loop {
-            let line = &self.lines[row];
-            if let Some(idx) = find_in_line_rev(line, &needle, col) {
-                self.cursor_row = row;
-                self.cursor_col = idx;
-                if self.cursor_row != prev_row {
-                    self.clear_line_undo();
-                }
-                return true;
-            }
-            if row == 0 || !self.find_cross_line {
-                break;
-            }
-            row -= 1;
-            let len = self.line_len(row);
-            col = len.saturating_sub(1);
-        }
-        false
-    }
-
     pub(super) fn percent_jump(&mut self) -> bool {
         let prev_row = self.cursor_row;
         let (open, close, forward) = if let Some(ch) = self.char_at(self.cursor_row, self.cursor_col)
@@ -588,13 +518,9 @@ impl App {
         let mut depth = 0i32;
         let mut r = self.cursor_row;
         let mut c = self.cursor_col;
-        loop {
-            if let Some((nr, nc)) = self.advance_pos(r, c) {
-                r = nr;
-                c = nc;
-            } else {
-                break;
-            }
+        while let Some((nr, nc)) = self.advance_pos(r, c) {
+            r = nr;
+            c = nc;
             if let Some(ch) = self.char_at(r, c) {
                 if ch == open {
                     depth += 1;
@@ -613,13 +539,9 @@ impl App {
         let mut depth = 0i32;
         let mut r = self.cursor_row;
         let mut c = self.cursor_col;
-        loop {
-            if let Some((pr, pc)) = self.prev_pos(r, c) {
-                r = pr;
-                c = pc;
-            } else {
-                break;
-            }
+        while let Some((pr, pc)) = self.prev_pos(r, c) {
+            r = pr;
+            c = pc;
             if let Some(ch) = self.char_at(r, c) {
                 if ch == close {
                     depth += 1;
@@ -637,13 +559,9 @@ impl App {
     fn find_next_bracket(&self) -> Option<(usize, usize, char, char, bool)> {
         let mut r = self.cursor_row;
         let mut c = self.cursor_col;
-        loop {
-            if let Some((nr, nc)) = self.advance_pos(r, c) {
-                r = nr;
-                c = nc;
-            } else {
-                break;
-            }
+        while let Some((nr, nc)) = self.advance_pos(r, c) {
+            r = nr;
+            c = nc;
             if let Some(ch) = self.char_at(r, c) {
                 match ch {
                     '(' => return Some((r, c, '(', ')', true)),
@@ -662,45 +580,6 @@ impl App {
     }
 }
 
-fn find_in_line(line: &str, needle: &[char], start_col: usize) -> Option<usize> {
-    if needle.is_empty() {
-        return None;
-    }
-    let chars: Vec<char> = line.chars().collect();
-    if needle.len() > chars.len() || start_col >= chars.len() {
-        return None;
-    }
-    let max_start = chars.len().saturating_sub(needle.len());
-    for i in start_col..=max_start {
-        if chars[i..i + needle.len()] == *needle {
-            return Some(i);
-        }
-    }
-    None
-}
-
-fn find_in_line_rev(line: &str, needle: &[char], end_col: usize) -> Option<usize> {
-    if needle.is_empty() {
-        return None;
-    }
-    let chars: Vec<char> = line.chars().collect();
-    if chars.is_empty() || needle.len() > chars.len() {
-        return None;
-    }
-    let max_start = chars.len().saturating_sub(needle.len());
-    let mut i = end_col.min(max_start);
-    loop {
-        if chars[i..i + needle.len()] == *needle {
-            return Some(i);
-        }
-        if i == 0 {
-            break;
-        }
-        i -= 1;
-    }
-    None
-}
-
 pub(super) fn char_count_in_range(app: &App, start: (usize, usize), end: (usize, usize)) -> usize {
     let (start, end) = super::types::normalize_range(start, end);
     if start.0 == end.0 {
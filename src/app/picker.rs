@@ -0,0 +1,346 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ignore::WalkBuilder;
+
+use super::App;
+use super::buffer::TextBuffer;
+use super::fuzzy::fuzzy_score;
+use super::highlight::{SyntaxSpan, spans_for_text};
+use super::window::SplitDir;
+
+const FILE_LIMIT: usize = 200_000;
+const BATCH_SIZE: usize = 512;
+const PREVIEW_BYTES: u64 = 64 * 1024;
+const PREVIEW_LINES: usize = 200;
+
+pub(crate) struct FilePicker {
+    pub(crate) query: String,
+    pub(crate) files: Vec<String>,
+    pub(crate) matches: Vec<(i64, usize)>,
+    pub(crate) selected: usize,
+    pub(crate) scanning: bool,
+    pub(crate) preview: Option<FilePreview>,
+    ranked_query: String,
+    root: PathBuf,
+    receiver: Receiver<Vec<String>>,
+}
+
+pub(crate) struct FilePreview {
+    pub(crate) file: String,
+    pub(crate) lines: Vec<String>,
+    pub(crate) spans: HashMap<usize, Vec<SyntaxSpan>>,
+}
+
+enum PickerTarget {
+    Current,
+    Split(SplitDir),
+    Tab,
+}
+
+fn walk_files(root: PathBuf) -> Receiver<Vec<String>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let walker = WalkBuilder::new(&root)
+            .hidden(true)
+            .git_ignore(true)
+            .require_git(false)
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .build();
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        let mut total = 0;
+        for entry in walker.flatten() {
+            if !entry.file_type().is_some_and(|kind| kind.is_file()) {
+                continue;
+            }
+            let Ok(rel) = entry.path().strip_prefix(&root) else {
+                continue;
+            };
+            batch.push(rel.to_string_lossy().into_owned());
+            total += 1;
+            if batch.len() >= BATCH_SIZE && sender.send(std::mem::take(&mut batch)).is_err() {
+                return;
+            }
+            if total >= FILE_LIMIT {
+                break;
+            }
+        }
+        if !batch.is_empty() {
+            let _ = sender.send(batch);
+        }
+    });
+    receiver
+}
+
+fn read_preview(path: &Path) -> Vec<String> {
+    let mut bytes = Vec::new();
+    if let Err(err) = std::fs::File::open(path).and_then(|file| file.take(PREVIEW_BYTES).read_to_end(&mut bytes)) {
+        return vec![format!("[{}]", err)];
+    }
+    if bytes.contains(&0) {
+        return vec!["[binary file]".to_string()];
+    }
+    String::from_utf8_lossy(&bytes)
+        .lines()
+        .take(PREVIEW_LINES)
+        .map(str::to_string)
+        .collect()
+}
+
+impl FilePicker {
+    fn path(&self, file: &str) -> PathBuf {
+        if self.root == Path::new(".") {
+            PathBuf::from(file)
+        } else {
+            self.root.join(file)
+        }
+    }
+
+    pub(crate) fn matched_file(&self, idx: usize) -> Option<&str> {
+        self.matches.get(idx).map(|(_, file)| self.files[*file].as_str())
+    }
+
+    fn score(&self, indices: impl Iterator<Item = usize>) -> Vec<(i64, usize)> {
+        let mut scored: Vec<(i64, usize)> = indices
+            .filter_map(|idx| Some((fuzzy_score(&self.query, &self.files[idx])?, idx)))
+            .collect();
+        scored.sort_by_key(|&(score, idx)| (Reverse(score), idx));
+        scored
+    }
+
+    fn rank(&mut self) {
+        self.matches = self.score(0..self.files.len());
+        self.ranked_query = self.query.clone();
+        self.selected = self.selected.min(self.matches.len().saturating_sub(1));
+    }
+
+    // A longer query can only match files the shorter one matched.
+    fn refine(&mut self) {
+        if !self.query.starts_with(&self.ranked_query) {
+            self.rank();
+            return;
+        }
+        let matches = std::mem::take(&mut self.matches);
+        self.matches = self.score(matches.into_iter().map(|(_, idx)| idx));
+        self.ranked_query = self.query.clone();
+        self.selected = self.selected.min(self.matches.len().saturating_sub(1));
+    }
+
+    fn add_files(&mut self, batch: Vec<String>) {
+        let first = self.files.len();
+        self.files.extend(batch);
+        let added = self.score(first..self.files.len());
+        let existing = std::mem::take(&mut self.matches);
+        let mut merged = Vec::with_capacity(existing.len() + added.len());
+        let (mut old, mut new) = (existing.into_iter().peekable(), added.into_iter().peekable());
+        while let (Some(a), Some(b)) = (old.peek(), new.peek()) {
+            if (Reverse(a.0), a.1) <= (Reverse(b.0), b.1) {
+                merged.extend(old.next());
+            } else {
+                merged.extend(new.next());
+            }
+        }
+        merged.extend(old);
+        merged.extend(new);
+        self.matches = merged;
+    }
+}
+
+impl App {
+    pub(crate) fn open_file_picker(&mut self) {
+        self.open_file_picker_at(PathBuf::from("."));
+    }
+
+    fn open_file_picker_at(&mut self, root: PathBuf) {
+        self.file_picker = Some(FilePicker {
+            query: String::new(),
+            files: Vec::new(),
+            matches: Vec::new(),
+            ranked_query: String::new(),
+            selected: 0,
+            scanning: true,
+            preview: None,
+            receiver: walk_files(root.clone()),
+            root,
+        });
+    }
+
+    pub(crate) fn poll_file_picker(&mut self) {
+        let Some(picker) = &mut self.file_picker else {
+            return;
+        };
+        if !picker.scanning {
+            return;
+        }
+        let mut received = false;
+        loop {
+            match picker.receiver.try_recv() {
+                Ok(batch) => {
+                    picker.add_files(batch);
+                    received = true;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    picker.scanning = false;
+                    break;
+                }
+            }
+        }
+        if received {
+            self.update_file_preview();
+        }
+    }
+
+    fn update_file_preview(&mut self) {
+        let Some(picker) = &mut self.file_picker else {
+            return;
+        };
+        let Some(file) = picker.matched_file(picker.selected).map(str::to_string) else {
+            picker.preview = None;
+            return;
+        };
+        if picker.preview.as_ref().is_some_and(|preview| preview.file == file) {
+            return;
+        }
+        let path = picker.path(&file);
+        let lines = read_preview(&path);
+        let mut text = TextBuffer::from_text(&lines.join("\n"));
+        let spans = spans_for_text(&path, &mut text, lines.len()).unwrap_or_default();
+        picker.preview = Some(FilePreview { file, lines, spans });
+    }
+
+    pub(crate) fn handle_file_picker_key(&mut self, key: KeyEvent) {
+        let Some(picker) = &mut self.file_picker else {
+            return;
+        };
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => self.file_picker = None,
+            KeyCode::Char('c') if ctrl => self.file_picker = None,
+            KeyCode::Enter => self.accept_file_picker(PickerTarget::Current),
+            KeyCode::Char('x') | KeyCode::Char('s') if ctrl => {
+                self.accept_file_picker(PickerTarget::Split(SplitDir::Horizontal))
+            }
+            KeyCode::Char('v') if ctrl => self.accept_file_picker(PickerTarget::Split(SplitDir::Vertical)),
+            KeyCode::Char('t') if ctrl => self.accept_file_picker(PickerTarget::Tab),
+            KeyCode::Up => picker.selected = picker.selected.saturating_sub(1),
+            KeyCode::Char('p') | KeyCode::Char('k') if ctrl => {
+                picker.selected = picker.selected.saturating_sub(1)
+            }
+            KeyCode::Down => {
+                picker.selected = (picker.selected + 1).min(picker.matches.len().saturating_sub(1))
+            }
+            KeyCode::Char('n') | KeyCode::Char('j') if ctrl => {
+                picker.selected = (picker.selected + 1).min(picker.matches.len().saturating_sub(1))
+            }
+            KeyCode::Char('u') if ctrl => {
+                picker.query.clear();
+                picker.selected = 0;
+                picker.rank();
+            }
+            KeyCode::Backspace => {
+                picker.query.pop();
+                picker.selected = 0;
+                picker.rank();
+            }
+            KeyCode::Char(ch) if !ctrl && !key.modifiers.contains(KeyModifiers::ALT) => {
+                picker.query.push(ch);
+                picker.selected = 0;
+                picker.refine();
+            }
+            _ => {}
+        }
+        self.update_file_preview();
+    }
+
+    fn accept_file_picker(&mut self, target: PickerTarget) {
+        let Some(picker) = self.file_picker.take() else {
+            return;
+        };
+        let Some(file) = picker.matched_file(picker.selected) else {
+            self.set_status("No matching files");
+            return;
+        };
+        let path = picker.path(file);
+        match target {
+            PickerTarget::Current => self.open_or_switch_buffer(path, None),
+            PickerTarget::Split(dir) => self.split_window(dir, Some(path), false),
+            PickerTarget::Tab => self.new_tab(Some(path)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::handle_key;
+
+    fn press(app: &mut App, code: KeyCode, modifiers: KeyModifiers) {
+        handle_key(app, KeyEvent::new(code, modifiers)).unwrap();
+    }
+
+    #[test]
+    fn picker_respects_ignores_ranks_previews_and_opens_split() {
+        let root = std::env::temp_dir().join(format!("rvim-picker-{}", std::process::id()));
+        for dir in ["src", "target", ".hidden"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        std::fs::write(root.join(".gitignore"), "target/\n*.log\n").unwrap();
+        std::fs::write(root.join("README.md"), "# readme\n").unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() {\n    let x = 1;\n}\n").unwrap();
+        std::fs::write(root.join("src/lib.rs"), "pub mod app;\n").unwrap();
+        std::fs::write(root.join("target/out.txt"), "").unwrap();
+        std::fs::write(root.join("debug.log"), "").unwrap();
+        std::fs::write(root.join(".hidden/x.txt"), "").unwrap();
+
+        let mut app = App::new(None, String::new());
+        app.open_file_picker_at(root.clone());
+        let start = std::time::Instant::now();
+        while app.file_picker.as_ref().unwrap().scanning && start.elapsed().as_secs() < 5 {
+            app.poll_file_picker();
+            thread::sleep(std::time::Duration::from_millis(5));
+        }
+        let picker = app.file_picker.as_ref().unwrap();
+        assert_eq!(picker.files, vec!["README.md", "src/lib.rs", "src/main.rs"]);
+
+        for ch in "mai".chars() {
+            press(&mut app, KeyCode::Char(ch), KeyModifiers::NONE);
+        }
+        let picker = app.file_picker.as_ref().unwrap();
+        assert_eq!(picker.matched_file(0), Some("src/main.rs"));
+        assert_eq!(picker.matches.len(), 1);
+        let mut batched = FilePicker {
+            query: "ma".to_string(),
+            files: Vec::new(),
+            matches: Vec::new(),
+            ranked_query: "ma".to_string(),
+            selected: 0,
+            scanning: false,
+            preview: None,
+            root: root.clone(),
+            receiver: mpsc::channel().1,
+        };
+        for batch in [["src/main.rs", "a/ma.txt"], ["mark.md", "zz"], ["x/y/m_a", "main"]] {
+            batched.add_files(batch.iter().map(|file| file.to_string()).collect());
+        }
+        let merged = batched.matches.clone();
+        batched.rank();
+        assert_eq!(merged, batched.matches);
+        assert_eq!(merged.len(), 5);
+        let preview = picker.preview.as_ref().unwrap();
+        assert_eq!(preview.lines[0], "fn main() {");
+        assert!(!preview.spans.is_empty());
+
+        press(&mut app, KeyCode::Char('v'), KeyModifiers::CONTROL);
+        assert!(app.file_picker.is_none());
+        assert_eq!(app.window_count(), 2);
+        assert_eq!(app.file_path.as_deref(), Some(root.join("src/main.rs").as_path()));
+        assert_eq!(app.text.line(1).unwrap(), "    let x = 1;");
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
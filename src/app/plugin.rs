@@ -0,0 +1,617 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use super::App;
+use super::buffer::TextBuffer;
+use super::config::Config;
+use super::events::EditorEvent;
+use super::input::expand_tilde_path;
+use super::types::BufferState;
+use super::wasm_plugin::WasmPlugin;
+
+pub(crate) const PLUGIN_API_VERSION: &str = "0.1";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct BufferInfo {
+    pub(crate) id: usize,
+    pub(crate) path: Option<PathBuf>,
+    pub(crate) dirty: bool,
+    pub(crate) lines: usize,
+    pub(crate) current: bool,
+}
+
+pub(crate) trait PluginApi {
+    fn version(&self) -> &'static str {
+        PLUGIN_API_VERSION
+    }
+    fn list_buffers(&self) -> Vec<BufferInfo>;
+    fn current_buffer(&self) -> usize;
+    fn get_buffer_text(&self, buffer: usize, start: usize, end: Option<usize>) -> Option<Vec<String>>;
+    fn buffer_snapshot(&self, buffer: usize) -> Option<TextBuffer>;
+    fn edit_tick(&self) -> u64;
+    fn apply_edit(&mut self, buffer: usize, start: usize, end: usize, lines: &[String]) -> Result<(), String>;
+    fn set_status(&mut self, message: &str);
+}
+
+pub(crate) trait Plugin {
+    fn name(&self) -> &str;
+    fn api_version(&self) -> &str;
+    fn register(&mut self, registry: &mut PluginRegistry);
+    fn run_command(&mut self, command: &str, args: &str, api: &mut dyn PluginApi) -> Result<(), String>;
+    fn status(&mut self, _item: &str, _api: &dyn PluginApi) -> Option<String> {
+        None
+    }
+    fn on_event(&mut self, _event: &EditorEvent, _api: &mut dyn PluginApi) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct PluginRegistry {
+    commands: Vec<(String, String)>,
+    keymaps: Vec<(String, String, String)>,
+    status_items: Vec<(String, i32)>,
+}
+
+impl PluginRegistry {
+    pub(crate) fn register_command(&mut self, name: &str, description: &str) {
+        self.commands.push((name.to_string(), description.to_string()));
+    }
+
+    pub(crate) fn register_keymap(&mut self, mode: &str, lhs: &str, command: &str) {
+        self.keymaps.push((mode.to_string(), lhs.to_string(), command.to_string()));
+    }
+
+    pub(crate) fn register_status(&mut self, id: &str, priority: i32) {
+        self.status_items.push((id.to_string(), priority));
+    }
+}
+
+struct LoadedPlugin {
+    plugin: Box<dyn Plugin>,
+    name: String,
+    version: String,
+    errors: Vec<String>,
+}
+
+struct PluginCommand {
+    name: String,
+    description: String,
+    plugin: usize,
+}
+
+struct PluginKeymap {
+    mode: String,
+    lhs: String,
+    command: String,
+    plugin: usize,
+}
+
+struct StatusItem {
+    id: String,
+    priority: i32,
+    plugin: usize,
+}
+
+#[derive(Default)]
+pub(crate) struct PluginHost {
+    plugins: Vec<LoadedPlugin>,
+    rejected: Vec<(String, String)>,
+    commands: Vec<PluginCommand>,
+    keymaps: Vec<PluginKeymap>,
+    status_items: Vec<StatusItem>,
+    enabled: Vec<String>,
+    settings: HashMap<String, HashMap<String, String>>,
+}
+
+impl PluginHost {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.plugins.is_empty()
+    }
+
+    pub(crate) fn configure(&mut self, config: &Config) {
+        self.enabled = config
+            .plugins
+            .as_ref()
+            .and_then(|plugins| plugins.enabled.clone())
+            .unwrap_or_default();
+        self.settings = config
+            .plugin_settings
+            .iter()
+            .flatten()
+            .map(|(name, table)| {
+                let values = table
+                    .iter()
+                    .map(|(key, value)| match value {
+                        toml::Value::String(text) => (key.clone(), text.clone()),
+                        other => (key.clone(), other.to_string()),
+                    })
+                    .collect();
+                (name.clone(), values)
+            })
+            .collect();
+    }
+}
+
+pub(crate) fn builtin_plugins() -> Vec<Box<dyn Plugin>> {
+    Vec::new()
+}
+
+fn parse_version(version: &str) -> Option<(u32, u32)> {
+    let mut parts = version.trim().split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next().unwrap_or("0").parse().ok()?;
+    Some((major, minor))
+}
+
+fn compatible(host: &str, plugin: &str) -> bool {
+    match (parse_version(host), parse_version(plugin)) {
+        (Some((0, host_minor)), Some((0, minor))) => host_minor == minor,
+        (Some((host_major, host_minor)), Some((major, minor))) => host_major == major && minor <= host_minor,
+        _ => false,
+    }
+}
+
+fn valid_command_name(name: &str) -> bool {
+    name.chars().next().is_some_and(|ch| ch.is_ascii_alphabetic())
+        && name.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+}
+
+impl PluginApi for App {
+    fn list_buffers(&self) -> Vec<BufferInfo> {
+        let mut out = vec![BufferInfo {
+            id: self.current_buffer_id,
+            path: self.file_path.clone(),
+            dirty: self.dirty,
+            lines: self.text.line_count(),
+            current: true,
+        }];
+        out.extend(self.buffers.iter().map(|slot| BufferInfo {
+            id: slot.id,
+            path: slot.state.file_path.clone(),
+            dirty: slot.state.dirty,
+            lines: slot.state.text.line_count(),
+            current: false,
+        }));
+        out.sort_by_key(|info| info.id);
+        out
+    }
+
+    fn current_buffer(&self) -> usize {
+        self.current_buffer_id
+    }
+
+    fn get_buffer_text(&self, buffer: usize, start: usize, end: Option<usize>) -> Option<Vec<String>> {
+        Some(self.buffer_text(buffer)?.lines(start, end))
+    }
+
+    fn buffer_snapshot(&self, buffer: usize) -> Option<TextBuffer> {
+        Some(self.buffer_text(buffer)?.snapshot())
+    }
+
+    fn edit_tick(&self) -> u64 {
+        self.edit_tick
+    }
+
+    fn apply_edit(&mut self, buffer: usize, start: usize, end: usize, lines: &[String]) -> Result<(), String> {
+        if buffer != self.current_buffer_id {
+            let limit = self.undo_limit;
+            let Some(slot) = self.buffers.iter_mut().find(|slot| slot.id == buffer) else {
+                return Err(format!("No such buffer: {}", buffer));
+            };
+            return edit_background_buffer(&mut slot.state, start, end, lines, limit);
+        }
+        if self.read_only {
+            return Err("Buffer is read-only".to_string());
+        }
+        let count = self.text.line_count();
+        if start > end || end > count {
+            return Err(format!("Invalid line range {}..{} ({} lines)", start, end, count));
+        }
+        self.record_undo();
+        self.touch_edit();
+        self.clear_line_undo();
+        self.text.replace_lines(start, end, lines);
+        self.dirty = true;
+        self.cursor_row = self.cursor_row.min(self.text.line_count().saturating_sub(1));
+        self.cursor_col = self.cursor_col.min(self.line_len(self.cursor_row));
+        Ok(())
+    }
+
+    fn set_status(&mut self, message: &str) {
+        App::set_status(self, message);
+    }
+}
+
+// Edits a buffer that is not on screen in place, as its own undo step, so the
+// current buffer, mode and pending state are left alone.
+fn edit_background_buffer(
+    state: &mut BufferState,
+    start: usize,
+    end: usize,
+    lines: &[String],
+    undo_limit: usize,
+) -> Result<(), String> {
+    if state.read_only {
+        return Err("Buffer is read-only".to_string());
+    }
+    let count = state.text.line_count();
+    if start > end || end > count {
+        return Err(format!("Invalid line range {}..{} ({} lines)", start, end, count));
+    }
+    let cursor = (state.cursor_row, state.cursor_col);
+    state.undo_tree.commit(state.text.take_changes(), cursor, undo_limit);
+    state.undo_tree.begin(cursor);
+    state.text.replace_lines(start, end, lines);
+    state.undo_tree.commit(state.text.take_changes(), cursor, undo_limit);
+    state.text.set_mark('.', (start.min(state.text.line_count().saturating_sub(1)), 0));
+    state.line_undo = None;
+    state.dirty = true;
+    state.change_tick = state.change_tick.wrapping_add(1);
+    state.edit_tick = state.edit_tick.wrapping_add(1);
+    state.cursor_row = state.cursor_row.min(state.text.line_count().saturating_sub(1));
+    state.cursor_col = state.cursor_col.min(state.text.line_len(state.cursor_row));
+    Ok(())
+}
+
+impl App {
+    fn buffer_text(&self, buffer: usize) -> Option<&TextBuffer> {
+        if buffer == self.current_buffer_id {
+            Some(&self.text)
+        } else {
+            Some(&self.buffers.iter().find(|slot| slot.id == buffer)?.state.text)
+        }
+    }
+
+    pub fn load_plugins(&mut self) {
+        for plugin in builtin_plugins() {
+            self.load_plugin(plugin);
+        }
+        for entry in self.plugins.enabled.clone() {
+            let path = PathBuf::from(expand_tilde_path(&entry));
+            let name = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or(entry);
+            let settings = self.plugins.settings.get(&name).cloned().unwrap_or_default();
+            match WasmPlugin::load(&path, settings) {
+                Ok(plugin) => {
+                    self.load_plugin(Box::new(plugin));
+                }
+                Err(err) => {
+                    self.set_status(format!("Plugin {} failed to load: {}", name, err));
+                    self.plugins.rejected.push((name, err));
+                }
+            }
+        }
+    }
+
+    pub(crate) fn load_plugin(&mut self, mut plugin: Box<dyn Plugin>) -> bool {
+        let name = plugin.name().to_string();
+        let version = plugin.api_version().to_string();
+        let host_version = PluginApi::version(self);
+        let reject = if !compatible(host_version, &version) {
+            Some(format!(
+                "requires plugin API {}, host provides {}",
+                version, host_version
+            ))
+        } else if self.plugins.plugins.iter().any(|loaded| loaded.name == name) {
+            Some("a plugin with this name is already loaded".to_string())
+        } else {
+            None
+        };
+        if let Some(reason) = reject {
+            self.set_status(format!("Plugin {} rejected: {}", name, reason));
+            self.plugins.rejected.push((name, reason));
+            return false;
+        }
+
+        let mut registry = PluginRegistry::default();
+        plugin.register(&mut registry);
+        let idx = self.plugins.plugins.len();
+        let mut errors = Vec::new();
+        for (command, description) in registry.commands {
+            if !valid_command_name(&command) {
+                errors.push(format!("invalid command name: {}", command));
+            } else if let Some(owner) = self.plugins.commands.iter().find(|existing| existing.name == command) {
+                let owner = &self.plugins.plugins[owner.plugin].name;
+                errors.push(format!("command {} is already provided by {}", command, owner));
+            } else {
+                self.register_command_candidate(command.clone());
+                self.plugins.commands.push(PluginCommand {
+                    name: command,
+                    description,
+                    plugin: idx,
+                });
+            }
+        }
+        for (mode, lhs, command) in registry.keymaps {
+            match self.keymaps.add_plugin_map(&mode, &lhs, &command) {
+                Ok(()) => self.plugins.keymaps.push(PluginKeymap {
+                    mode,
+                    lhs,
+                    command,
+                    plugin: idx,
+                }),
+                Err(err) => errors.push(err),
+            }
+        }
+        for (id, priority) in registry.status_items {
+            self.plugins.status_items.push(StatusItem { id, priority, plugin: idx });
+        }
+        self.plugins.status_items.sort_by_key(|item| std::cmp::Reverse(item.priority));
+        if let Some(err) = errors.first() {
+            self.set_status(format!("Plugin {}: {}", name, err));
+        }
+        self.plugins.plugins.push(LoadedPlugin {
+            plugin,
+            name,
+            version,
+            errors,
+        });
+        true
+    }
+
+    pub(super) fn apply_plugin_keymaps(&mut self) {
+        for keymap in &self.plugins.keymaps {
+            let _ = self.keymaps.add_plugin_map(&keymap.mode, &keymap.lhs, &keymap.command);
+        }
+    }
+
+    pub(super) fn run_plugin_command(&mut self, input: &str) -> bool {
+        let (name, args) = match input.split_once(char::is_whitespace) {
+            Some((name, args)) => (name, args.trim()),
+            None => (input, ""),
+        };
+        let Some(idx) = self
+            .plugins
+            .commands
+            .iter()
+            .find(|command| command.name == name)
+            .map(|command| command.plugin)
+        else {
+            return false;
+        };
+        let mut plugins = std::mem::take(&mut self.plugins.plugins);
+        let result = plugins[idx].plugin.run_command(name, args, self);
+        let plugin_name = plugins[idx].name.clone();
+        self.plugins.plugins = plugins;
+        if let Err(err) = result {
+            App::set_status(self, format!("{}: {}", plugin_name, err));
+        }
+        true
+    }
+
+    pub(super) fn dispatch_plugin_event(&mut self, event: &EditorEvent) {
+        let mut plugins = std::mem::take(&mut self.plugins.plugins);
+        for loaded in &mut plugins {
+            if let Err(err) = loaded.plugin.on_event(event, self) {
+                App::set_status(self, format!("{}: {}: {}", loaded.name, event.name(), err));
+            }
+        }
+        self.plugins.plugins = plugins;
+    }
+
+    pub(crate) fn plugin_status_items(&mut self) -> Vec<String> {
+        if self.plugins.status_items.is_empty() {
+            return Vec::new();
+        }
+        let items: Vec<(String, usize)> = self
+            .plugins
+            .status_items
+            .iter()
+            .map(|item| (item.id.clone(), item.plugin))
+            .collect();
+        let mut plugins = std::mem::take(&mut self.plugins.plugins);
+        let out = items
+            .iter()
+            .filter_map(|(id, idx)| plugins[*idx].plugin.status(id, self))
+            .filter(|text| !text.is_empty())
+            .collect();
+        self.plugins.plugins = plugins;
+        out
+    }
+
+    pub(super) fn plugin_list_lines(&self) -> Vec<String> {
+        let host = &self.plugins;
+        let mut lines = Vec::new();
+        for (idx, loaded) in host.plugins.iter().enumerate() {
+            lines.push(format!("{} (API {})", loaded.name, loaded.version));
+            for command in host.commands.iter().filter(|command| command.plugin == idx) {
+                lines.push(format!("  :{:<16} {}", command.name, command.description));
+            }
+            for keymap in host.keymaps.iter().filter(|keymap| keymap.plugin == idx) {
+                lines.push(format!("  {} {:<14} :{}", keymap.mode, keymap.lhs, keymap.command));
+            }
+            for item in host.status_items.iter().filter(|item| item.plugin == idx) {
+                lines.push(format!("  status {} (priority {})", item.id, item.priority));
+            }
+            for err in &loaded.errors {
+                lines.push(format!("  error: {}", err));
+            }
+        }
+        for (name, reason) in &host.rejected {
+            lines.push(format!("{} rejected: {}", name, reason));
+        }
+        if lines.is_empty() {
+            lines.push(format!("No plugins loaded (plugin API {})", PluginApi::version(self)));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::types::Mode;
+    use super::*;
+    use crate::app::handle_key;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    struct WordCount {
+        runs: usize,
+    }
+
+    impl Plugin for WordCount {
+        fn name(&self) -> &str {
+            "wordcount"
+        }
+
+        fn api_version(&self) -> &str {
+            "0.1"
+        }
+
+        fn register(&mut self, registry: &mut PluginRegistry) {
+            registry.register_command("WordCount", "count words in the current buffer");
+            registry.register_command("Upcase", "uppercase a line range of a buffer");
+            registry.register_command("bad name", "");
+            registry.register_keymap("n", "<leader>w", "WordCount");
+            registry.register_keymap("n", "]b", "Upcase 0 1");
+            registry.register_status("words", 10);
+            registry.register_status("runs", 20);
+        }
+
+        fn run_command(&mut self, command: &str, args: &str, api: &mut dyn PluginApi) -> Result<(), String> {
+            self.runs += 1;
+            let current = api.current_buffer();
+            match command {
+                "WordCount" => {
+                    let lines = api.get_buffer_text(current, 0, None).ok_or("no buffer")?;
+                    let words: usize = lines.iter().map(|line| line.split_whitespace().count()).sum();
+                    api.set_status(&format!("{} words", words));
+                }
+                _ => {
+                    let mut parts = args.split_whitespace().map(|part| part.parse::<usize>());
+                    let (Some(Ok(start)), Some(Ok(end))) = (parts.next(), parts.next()) else {
+                        return Err("usage: Upcase START END [BUFFER]".to_string());
+                    };
+                    let buffer = parts.next().and_then(Result::ok).unwrap_or(current);
+                    let lines: Vec<String> = api
+                        .get_buffer_text(buffer, start, Some(end))
+                        .ok_or("no buffer")?
+                        .iter()
+                        .map(|line| line.to_uppercase())
+                        .collect();
+                    api.apply_edit(buffer, start, end, &lines)?;
+                }
+            }
+            Ok(())
+        }
+
+        fn status(&mut self, item: &str, api: &dyn PluginApi) -> Option<String> {
+            match item {
+                "runs" => Some(format!("runs:{}", self.runs)),
+                _ => Some(format!("bufs:{}", api.list_buffers().len())),
+            }
+        }
+    }
+
+    struct Future;
+
+    impl Plugin for Future {
+        fn name(&self) -> &str {
+            "future"
+        }
+
+        fn api_version(&self) -> &str {
+            "1.0"
+        }
+
+        fn register(&mut self, _registry: &mut PluginRegistry) {}
+
+        fn run_command(&mut self, _: &str, _: &str, _: &mut dyn PluginApi) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    struct Journal {
+        buffer: usize,
+    }
+
+    impl Plugin for Journal {
+        fn name(&self) -> &str {
+            "journal"
+        }
+
+        fn api_version(&self) -> &str {
+            "0.1"
+        }
+
+        fn register(&mut self, _registry: &mut PluginRegistry) {}
+
+        fn run_command(&mut self, _: &str, _: &str, _: &mut dyn PluginApi) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn on_event(&mut self, event: &EditorEvent, api: &mut dyn PluginApi) -> Result<(), String> {
+            if let EditorEvent::ModeChanged(_, Mode::Insert) = event {
+                api.apply_edit(self.buffer, 0, 0, &["entered insert".to_string()])?;
+            }
+            Ok(())
+        }
+    }
+
+    fn press(app: &mut App, ch: char) {
+        handle_key(app, KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE)).unwrap();
+    }
+
+    #[test]
+    fn plugin_registers_commands_keymaps_and_status() {
+        let mut app = App::new(None, "one two\nthree\nfour five six".to_string());
+        assert!(app.load_plugin(Box::new(WordCount { runs: 0 })));
+        assert!(!app.load_plugin(Box::new(Future)));
+        assert!(!app.load_plugin(Box::new(WordCount { runs: 0 })));
+
+        app.run_ex_command("WordCount").unwrap();
+        assert_eq!(app.status_message, "6 words");
+        press(&mut app, '\\');
+        press(&mut app, 'w');
+        assert_eq!(app.status_message, "6 words");
+
+        press(&mut app, ']');
+        press(&mut app, 'b');
+        assert_eq!(app.text.line(0).unwrap(), "ONE TWO");
+        app.run_ex_command("Upcase 1 3").unwrap();
+        assert_eq!(app.text.line(2).unwrap(), "FOUR FIVE SIX");
+        app.undo();
+        assert_eq!(app.text.line(2).unwrap(), "four five six");
+        app.run_ex_command("Upcase 2 9").unwrap();
+        assert_eq!(app.status_message, "wordcount: Invalid line range 2..9 (3 lines)");
+
+        let origin = app.current_buffer_id;
+        app.open_empty_buffer();
+        app.run_ex_command(&format!("Upcase 2 3 {}", origin)).unwrap();
+        assert_ne!(app.current_buffer_id, origin);
+        let text = app.get_buffer_text(origin, 0, None).unwrap();
+        assert_eq!(text, vec!["ONE TWO", "three", "FOUR FIVE SIX"]);
+
+        assert_eq!(app.plugin_status_items(), vec!["runs:6", "bufs:2"]);
+        let lines = app.plugin_list_lines();
+        assert_eq!(lines[0], "wordcount (API 0.1)");
+        assert!(lines.iter().any(|line| line.contains(":WordCount")));
+        assert!(lines.iter().any(|line| line.contains("error: invalid command name: bad name")));
+        assert!(lines.contains(&"future rejected: requires plugin API 1.0, host provides 0.1".to_string()));
+        assert!(app.keymaps.describe_lines().iter().any(|line| line.contains(":Upcase 0 1")));
+    }
+
+    #[test]
+    fn editing_another_buffer_from_an_event_keeps_the_current_mode() {
+        let mut app = App::new(None, "log".to_string());
+        let journal = app.current_buffer_id;
+        assert!(app.load_plugin(Box::new(Journal { buffer: journal })));
+        app.open_empty_buffer();
+        let scratch = app.current_buffer_id;
+        press(&mut app, 'i');
+        press(&mut app, 'x');
+        assert_eq!(app.mode, Mode::Insert);
+        assert_eq!(app.current_buffer_id, scratch);
+        assert_eq!(app.text.to_string(), "x");
+        let text = app.get_buffer_text(journal, 0, None).unwrap();
+        assert_eq!(text, vec!["entered insert", "log"]);
+
+        handle_key(&mut app, KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)).unwrap();
+        app.enter_buffer(journal, false);
+        assert!(app.dirty);
+        app.undo();
+        assert_eq!(app.text.to_string(), "log");
+    }
+}
@@ -0,0 +1,621 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use anyhow::Result;
+use ignore::WalkBuilder;
+use regex::Regex;
+
+use super::buffer::TextBuffer;
+use super::types::Mode;
+use super::undo::UndoTree;
+use super::App;
+use super::ex::split_command;
+use super::input::expand_tilde_path;
+use super::search::SearchPattern;
+use super::substitute::split_delimited;
+use super::window::SplitDir;
+
+const MATCH_LIMIT: usize = 10_000;
+const QUICKFIX_HEIGHT: u16 = 10;
+const GREP_FORMAT: &[&str] = &["%f:%l:%c:%m", "%f:%l:%m"];
+pub(crate) const DEFAULT_ERROR_FORMAT: &[&str] = &["%f:%l:%c: %m", "%f:%l: %m", "--> %f:%l:%c"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct QuickfixEntry {
+    pub(crate) path: PathBuf,
+    pub(crate) row: usize,
+    pub(crate) col: usize,
+    pub(crate) kind: Option<char>,
+    pub(crate) text: String,
+}
+
+pub(crate) struct QuickfixJob {
+    title: String,
+    jump: bool,
+    receiver: Receiver<Result<Vec<QuickfixEntry>, String>>,
+}
+
+pub(crate) struct ErrorFormat {
+    regex: Regex,
+    fields: Vec<char>,
+}
+
+impl ErrorFormat {
+    pub(crate) fn new(format: &str) -> Result<Self, String> {
+        let mut source = String::from("^");
+        let mut fields = Vec::new();
+        let mut chars = format.chars();
+        while let Some(ch) = chars.next() {
+            if ch != '%' {
+                source.push_str(&regex::escape(&ch.to_string()));
+                continue;
+            }
+            let field = chars.next().unwrap_or('%');
+            let group = match field {
+                'f' => "(.+?)",
+                'l' | 'c' => "(\\d+)",
+                'm' => "(.*)",
+                't' => "([A-Za-z])",
+                '%' => {
+                    source.push('%');
+                    continue;
+                }
+                other => return Err(format!("Unsupported errorformat item: %{}", other)),
+            };
+            source.push_str(group);
+            fields.push(field);
+        }
+        source.push('$');
+        let regex = Regex::new(&source).map_err(|err| err.to_string())?;
+        Ok(Self { regex, fields })
+    }
+
+    fn parse(&self, line: &str) -> Option<QuickfixEntry> {
+        let caps = self.regex.captures(line)?;
+        let mut entry = QuickfixEntry {
+            path: PathBuf::new(),
+            row: 0,
+            col: 0,
+            kind: None,
+            text: String::new(),
+        };
+        for (idx, field) in self.fields.iter().enumerate() {
+            let value = caps.get(idx + 1)?.as_str();
+            match field {
+                'f' => entry.path = PathBuf::from(value),
+                'l' => entry.row = value.parse::<usize>().ok()?.saturating_sub(1),
+                'c' => entry.col = value.parse::<usize>().ok()?.saturating_sub(1),
+                't' => entry.kind = value.chars().next().map(|ch| ch.to_ascii_uppercase()),
+                _ => entry.text = value.trim().to_string(),
+            }
+        }
+        Some(entry)
+    }
+}
+
+pub(crate) fn parse_errors(output: &str, formats: &[ErrorFormat]) -> Vec<QuickfixEntry> {
+    let mut out = Vec::new();
+    let mut last_message = String::new();
+    for line in output.lines() {
+        let line = line.trim();
+        match formats.iter().find_map(|format| format.parse(line)) {
+            Some(mut entry) => {
+                if entry.text.is_empty() {
+                    entry.text = last_message.clone();
+                }
+                out.push(entry);
+            }
+            None if !line.is_empty() => last_message = line.to_string(),
+            None => {}
+        }
+    }
+    out
+}
+
+fn compile_formats(formats: &[String]) -> Result<Vec<ErrorFormat>, String> {
+    formats.iter().map(|format| ErrorFormat::new(format)).collect()
+}
+
+fn clean_path(path: &Path) -> PathBuf {
+    path.strip_prefix(".").unwrap_or(path).to_path_buf()
+}
+
+fn native_grep(pattern: &SearchPattern, roots: &[PathBuf]) -> Vec<QuickfixEntry> {
+    let mut builder = WalkBuilder::new(&roots[0]);
+    for root in &roots[1..] {
+        builder.add(root);
+    }
+    let walker = builder
+        .hidden(true)
+        .git_ignore(true)
+        .require_git(false)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build();
+    let mut out = Vec::new();
+    for entry in walker.flatten() {
+        if !entry.file_type().is_some_and(|kind| kind.is_file()) {
+            continue;
+        }
+        let Ok(bytes) = std::fs::read(entry.path()) else {
+            continue;
+        };
+        if bytes.contains(&0) {
+            continue;
+        }
+        let content = String::from_utf8_lossy(&bytes);
+        for (row, line) in content.lines().enumerate() {
+            if let Some(col) = pattern.find_from(line, 0) {
+                out.push(QuickfixEntry {
+                    path: clean_path(entry.path()),
+                    row,
+                    col,
+                    kind: None,
+                    text: line.trim().to_string(),
+                });
+                if out.len() >= MATCH_LIMIT {
+                    return out;
+                }
+            }
+        }
+    }
+    out
+}
+
+fn run_shell(command: &str) -> Result<String, String> {
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|err| format!("{}: {}", command, err))?;
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    Ok(text)
+}
+
+fn split_pattern(args: &str) -> (String, &str) {
+    let args = args.trim_start();
+    if let Some(delim) = args.chars().next().filter(|ch| !ch.is_alphanumeric() && !ch.is_whitespace())
+    {
+        let (pattern, rest) = split_delimited(&args[delim.len_utf8()..], delim);
+        return (pattern, rest.unwrap_or("").trim_start_matches(['g', 'j']));
+    }
+    match args.split_once(char::is_whitespace) {
+        Some((pattern, rest)) => (pattern.to_string(), rest),
+        None => (args.to_string(), ""),
+    }
+}
+
+impl QuickfixEntry {
+    fn describe(&self) -> String {
+        let kind = match self.kind {
+            Some('E') => " error",
+            Some('W') => " warning",
+            Some('I') => " info",
+            Some('N') => " note",
+            _ => "",
+        };
+        format!(
+            "{}|{} col {}{}| {}",
+            self.path.display(),
+            self.row + 1,
+            self.col + 1,
+            kind,
+            self.text
+        )
+    }
+}
+
+impl App {
+    pub(super) fn execute_quickfix_command(&mut self, input: &str) -> Result<bool> {
+        let (name, bang, args) = split_command(input);
+        match name {
+            "grep" | "gr" => match self.grepprg.clone() {
+                Some(prg) => {
+                    let formats = GREP_FORMAT.iter().map(|format| format.to_string()).collect();
+                    self.start_shell_job(format!("{} {}", prg, args), "grep", formats, !bang);
+                }
+                None => self.start_native_grep(args, !bang),
+            },
+            "vimgrep" | "vim" => self.start_native_grep(args, !bang),
+            "make" | "mak" => {
+                let command = format!("{} {}", self.makeprg, args);
+                self.start_shell_job(command, "make", self.errorformat.clone(), !bang);
+            }
+            "copen" | "cope" | "cw" | "cwindow" => self.open_quickfix_window(),
+            "cclose" | "ccl" => self.close_quickfix_window(),
+            "clist" | "cl" => {
+                if self.quickfix.is_empty() {
+                    self.set_status("No quickfix list");
+                } else {
+                    let lines = self
+                        .quickfix
+                        .iter()
+                        .enumerate()
+                        .map(|(idx, entry)| format!("{:>3} {}", idx + 1, entry.describe()))
+                        .collect();
+                    self.show_list(lines);
+                }
+            }
+            "cnext" | "cn" => self.quickfix_step(1),
+            "cprevious" | "cprev" | "cp" | "cNext" | "cN" => self.quickfix_step(-1),
+            "cfirst" | "cfir" | "crewind" | "cr" => self.quickfix_goto(0),
+            "clast" | "cla" => self.quickfix_goto(self.quickfix.len().saturating_sub(1)),
+            "cc" => match args.parse::<usize>() {
+                Ok(n) => self.quickfix_goto(n.saturating_sub(1)),
+                Err(_) if args.is_empty() => self.quickfix_goto(self.quickfix_index),
+                Err(_) => self.set_status("Usage: :cc [N]"),
+            },
+            "cdo" | "cfdo" => {
+                if args.is_empty() {
+                    self.set_status("Argument required");
+                } else {
+                    self.quickfix_do(args, name == "cfdo")?;
+                }
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn start_native_grep(&mut self, args: &str, jump: bool) {
+        let (pattern, rest) = split_pattern(args);
+        if pattern.is_empty() {
+            self.set_status("Usage: :vimgrep /pattern/ [path ...]");
+            return;
+        }
+        let compiled = match self.compile_search(&pattern) {
+            Ok(compiled) => compiled,
+            Err(err) => {
+                self.set_status(format!("Invalid pattern: {}", err));
+                return;
+            }
+        };
+        let mut roots: Vec<PathBuf> = rest
+            .split_whitespace()
+            .map(|path| PathBuf::from(expand_tilde_path(path)))
+            .collect();
+        if roots.is_empty() {
+            roots.push(PathBuf::from("."));
+        }
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(Ok(native_grep(&compiled, &roots)));
+        });
+        self.quickfix_job = Some(QuickfixJob {
+            title: format!("grep {}", pattern),
+            jump,
+            receiver,
+        });
+        self.set_status(format!("Searching for {}...", pattern));
+    }
+
+    fn start_shell_job(&mut self, command: String, title: &str, formats: Vec<String>, jump: bool) {
+        if let Err(err) = compile_formats(&formats) {
+            self.set_status(err);
+            return;
+        }
+        let (sender, receiver) = mpsc::channel();
+        let shown = command.trim().to_string();
+        thread::spawn(move || {
+            let result = run_shell(&command).map(|output| {
+                let formats = compile_formats(&formats).unwrap_or_default();
+                parse_errors(&output, &formats)
+            });
+            let _ = sender.send(result);
+        });
+        self.quickfix_job = Some(QuickfixJob {
+            title: title.to_string(),
+            jump,
+            receiver,
+        });
+        self.set_status(format!(":!{}", shown));
+    }
+
+    pub(crate) fn poll_quickfix(&mut self) {
+        let Some(job) = &self.quickfix_job else {
+            return;
+        };
+        let result = match job.receiver.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => Err("quickfix job failed".to_string()),
+        };
+        let Some(job) = self.quickfix_job.take() else {
+            return;
+        };
+        match result {
+            Ok(entries) => self.set_quickfix(job.title, entries, job.jump),
+            Err(err) => self.set_status(err),
+        }
+    }
+
+    fn set_quickfix(&mut self, title: String, entries: Vec<QuickfixEntry>, jump: bool) {
+        self.quickfix = entries;
+        self.quickfix_index = 0;
+        self.quickfix_title = title;
+        self.refresh_quickfix_buffer();
+        if self.quickfix.is_empty() {
+            self.set_status(format!("{}: no matches", self.quickfix_title));
+        } else if jump {
+            self.quickfix_goto(0);
+        } else {
+            self.set_status(format!("{}: {} entries", self.quickfix_title, self.quickfix.len()));
+        }
+    }
+
+    fn quickfix_lines(&self) -> String {
+        self.quickfix
+            .iter()
+            .map(QuickfixEntry::describe)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub(crate) fn is_quickfix_buffer(&self, id: usize) -> bool {
+        self.quickfix_buffer == Some(id)
+    }
+
+    fn refresh_quickfix_buffer(&mut self) {
+        let Some(id) = self.quickfix_buffer else {
+            return;
+        };
+        let content = self.quickfix_lines();
+        let row = self.quickfix_index;
+        // Rebuilt from scratch so the list keeps no edit or undo history.
+        if id == self.current_buffer_id {
+            self.text = TextBuffer::from_text(&content);
+            self.undo_tree = UndoTree::new();
+            self.line_undo = None;
+            self.dirty = false;
+            self.cursor_row = row.min(self.text.line_count().saturating_sub(1));
+            self.cursor_col = 0;
+        } else if let Some(slot) = self.buffers.iter_mut().find(|slot| slot.id == id) {
+            slot.state.text = TextBuffer::from_text(&content);
+            slot.state.undo_tree = UndoTree::new();
+            slot.state.line_undo = None;
+            slot.state.dirty = false;
+            slot.state.cursor_row = row;
+            slot.state.cursor_col = 0;
+        } else {
+            self.quickfix_buffer = None;
+            return;
+        }
+        for window in self.windows.iter_mut().filter(|window| window.buffer_id == id) {
+            window.cursor_row = row;
+            window.cursor_col = 0;
+        }
+    }
+
+    pub(crate) fn guard_quickfix_buffer(&mut self, buffer: usize, edit_tick: u64) {
+        if !self.is_quickfix_buffer(self.current_buffer_id) {
+            return;
+        }
+        let edited = buffer == self.current_buffer_id && self.edit_tick != edit_tick;
+        if edited || self.mode == Mode::Insert {
+            self.mode = Mode::Normal;
+            self.refresh_quickfix_buffer();
+            self.set_status("Cannot make changes, the quickfix list is not modifiable");
+        }
+    }
+
+    fn quickfix_window(&self) -> Option<usize> {
+        let id = self.quickfix_buffer?;
+        self.tabs[self.current_tab]
+            .layout
+            .window_ids()
+            .into_iter()
+            .find(|window_id| {
+                if *window_id == self.current_window_id() {
+                    return self.current_buffer_id == id;
+                }
+                self.windows
+                    .iter()
+                    .any(|window| window.id == *window_id && window.buffer_id == id)
+            })
+    }
+
+    fn open_quickfix_window(&mut self) {
+        if let Some(window) = self.quickfix_window() {
+            self.enter_window(window);
+        } else {
+            self.open_bottom_window(QUICKFIX_HEIGHT);
+            match self.quickfix_buffer.filter(|id| self.sorted_buffer_ids().contains(id)) {
+                Some(id) => {
+                    self.enter_buffer(id, false);
+                }
+                None => {
+                    self.open_empty_buffer();
+                    self.quickfix_buffer = Some(self.current_buffer_id);
+                }
+            }
+        }
+        self.read_only = true;
+        self.refresh_quickfix_buffer();
+        self.dirty = false;
+    }
+
+    fn close_quickfix_window(&mut self) {
+        if let Some(window) = self.quickfix_window() {
+            self.close_window(window);
+        }
+    }
+
+    fn leave_quickfix_window(&mut self) {
+        if !self.is_quickfix_buffer(self.current_buffer_id) {
+            return;
+        }
+        let current = self.current_window_id();
+        let tab = &self.tabs[self.current_tab];
+        let ids = tab.layout.window_ids();
+        let target = tab
+            .previous
+            .filter(|id| *id != current && ids.contains(id))
+            .or_else(|| {
+                ids.iter().copied().find(|id| {
+                    *id != current
+                        && self
+                            .windows
+                            .iter()
+                            .any(|window| window.id == *id && !self.is_quickfix_buffer(window.buffer_id))
+                })
+            });
+        match target {
+            Some(id) => self.enter_window(id),
+            None => self.split_window(SplitDir::Horizontal, None, false),
+        }
+    }
+
+    pub(crate) fn quickfix_open_at_cursor(&mut self) {
+        self.quickfix_goto(self.cursor_row);
+    }
+
+    fn quickfix_step(&mut self, delta: isize) {
+        if self.quickfix.is_empty() {
+            self.set_status("No quickfix list");
+            return;
+        }
+        let target = self.quickfix_index as isize + delta;
+        if target < 0 || target as usize >= self.quickfix.len() {
+            self.set_status("No more items");
+            return;
+        }
+        self.quickfix_goto(target as usize);
+    }
+
+    fn quickfix_goto(&mut self, idx: usize) {
+        let Some(entry) = self.quickfix.get(idx).cloned() else {
+            self.set_status("No quickfix list");
+            return;
+        };
+        self.quickfix_index = idx;
+        self.leave_quickfix_window();
+        if self.file_path.as_ref() == Some(&entry.path) {
+            self.push_jump();
+        } else {
+            self.open_or_switch_buffer(entry.path.clone(), None);
+            if self.file_path.as_ref() != Some(&entry.path) {
+                return;
+            }
+        }
+        self.clear_line_undo();
+        self.cursor_row = entry.row.min(self.text.line_count().saturating_sub(1));
+        self.cursor_col = entry.col.min(self.line_len(self.cursor_row));
+        self.refresh_quickfix_buffer();
+        self.set_status(format!("({} of {}): {}", idx + 1, self.quickfix.len(), entry.text));
+    }
+
+    fn quickfix_do(&mut self, command: &str, per_file: bool) -> Result<()> {
+        let mut seen = Vec::new();
+        for idx in 0..self.quickfix.len() {
+            if per_file {
+                let path = &self.quickfix[idx].path;
+                if seen.contains(path) {
+                    continue;
+                }
+                seen.push(path.clone());
+            }
+            self.quickfix_goto(idx);
+            if self.file_path.as_ref() != Some(&self.quickfix[idx].path) {
+                break;
+            }
+            self.run_ex_command(command)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::handle_key;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    fn wait_for_job(app: &mut App) {
+        let start = std::time::Instant::now();
+        while app.quickfix_job.is_some() && start.elapsed().as_secs() < 5 {
+            app.poll_quickfix();
+            thread::sleep(std::time::Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn errorformat_parses_compiler_output() {
+        let formats: Vec<ErrorFormat> = DEFAULT_ERROR_FORMAT
+            .iter()
+            .chain(["%f(%l): %t%*: %m"].iter())
+            .filter_map(|format| ErrorFormat::new(format).ok())
+            .collect();
+        let output = "\
+src/lib.c:3:7: error: expected ';'
+error[E0425]: cannot find value `y` in this scope
+  --> src/main.rs:2:13
+   |
+make: *** [all] Error 1
+";
+        let entries = parse_errors(output, &formats);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].path, PathBuf::from("src/lib.c"));
+        assert_eq!((entries[0].row, entries[0].col), (2, 6));
+        assert_eq!(entries[0].text, "error: expected ';'");
+        assert_eq!(entries[1].path, PathBuf::from("src/main.rs"));
+        assert_eq!((entries[1].row, entries[1].col), (1, 12));
+        assert_eq!(entries[1].text, "error[E0425]: cannot find value `y` in this scope");
+        assert!(ErrorFormat::new("%f:%q").is_err());
+    }
+
+    #[test]
+    fn vimgrep_fills_quickfix_and_cdo_edits_every_hit() {
+        let root = std::env::temp_dir().join(format!("rvim-grep-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("ignored")).unwrap();
+        std::fs::write(root.join(".gitignore"), "ignored/\n").unwrap();
+        std::fs::write(root.join("a.txt"), "one foo\ntwo\nfoo three\n").unwrap();
+        std::fs::write(root.join("b.txt"), "nothing\n  foo four\n").unwrap();
+        std::fs::write(root.join("ignored/c.txt"), "foo\n").unwrap();
+
+        let mut app = App::new(None, String::new());
+        app.run_ex_command(&format!("vimgrep /fo+/ {}", root.display())).unwrap();
+        wait_for_job(&mut app);
+        assert_eq!(app.quickfix.len(), 3);
+        assert_eq!(app.file_path.as_deref(), Some(root.join("a.txt").as_path()));
+        assert_eq!((app.cursor_row, app.cursor_col), (0, 4));
+        app.run_ex_command("cn").unwrap();
+        assert_eq!((app.cursor_row, app.cursor_col), (2, 0));
+        app.run_ex_command("cn").unwrap();
+        assert_eq!(app.file_path.as_deref(), Some(root.join("b.txt").as_path()));
+        assert_eq!(app.status_message, "(3 of 3): foo four");
+        app.run_ex_command("cp").unwrap();
+        assert_eq!(app.file_path.as_deref(), Some(root.join("a.txt").as_path()));
+
+        app.run_ex_command("copen").unwrap();
+        assert_eq!(app.window_count(), 2);
+        assert!(app.is_quickfix_buffer(app.current_buffer_id));
+        assert_eq!(app.cursor_row, 1);
+        assert!(app.text.line(2).unwrap().ends_with("b.txt|2 col 3| foo four"));
+        for ch in ['d', 'd', 'u', 'i'] {
+            handle_key(&mut app, KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE)).unwrap();
+        }
+        assert_eq!(app.mode, Mode::Normal);
+        assert_eq!(app.text.line_count(), 3);
+        assert!(app.text.take_changes().is_empty());
+        assert!(!app.dirty);
+        app.cursor_row = 2;
+        app.quickfix_open_at_cursor();
+        assert!(!app.is_quickfix_buffer(app.current_buffer_id));
+        assert_eq!(app.file_path.as_deref(), Some(root.join("b.txt").as_path()));
+        assert_eq!(app.cursor_row, 1);
+
+        app.run_ex_command("cdo s/foo/bar/").unwrap();
+        app.run_ex_command("cfdo w").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(root.join("a.txt")).unwrap(),
+            "one bar\ntwo\nbar three\n"
+        );
+        assert_eq!(std::fs::read_to_string(root.join("b.txt")).unwrap(), "nothing\n  bar four\n");
+        app.run_ex_command("cclose").unwrap();
+        assert_eq!(app.window_count(), 1);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
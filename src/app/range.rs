@@ -0,0 +1,195 @@
+use super::App;
+
+pub(super) type LineRange = (usize, usize);
+
+impl App {
+    pub(super) fn parse_line_range<'a>(
+        &self,
+        input: &'a str,
+    ) -> Result<(Option<LineRange>, &'a str), String> {
+        let input = input.trim_start();
+        if let Some(rest) = input.strip_prefix('%') {
+            return Ok((Some((0, self.text.line_count().saturating_sub(1))), rest));
+        }
+        let mut base = self.cursor_row;
+        let mut addresses: Vec<usize> = Vec::new();
+        let mut rest = input;
+        loop {
+            let (address, after) = self.parse_line_address(rest, base)?;
+            let after = after.trim_start();
+            match (address, after.chars().next()) {
+                (Some(line), Some(',')) => {
+                    addresses.push(line);
+                    rest = &after[1..];
+                }
+                (Some(line), Some(';')) => {
+                    addresses.push(line);
+                    base = line.saturating_sub(1);
+                    rest = &after[1..];
+                }
+                (Some(line), _) => {
+                    addresses.push(line);
+                    rest = after;
+                    break;
+                }
+                (None, _) if addresses.is_empty() => return Ok((None, input)),
+                (None, _) => {
+                    addresses.push(*addresses.last().unwrap_or(&0));
+                    rest = after;
+                    break;
+                }
+            }
+        }
+        let end = addresses.pop().unwrap_or(0);
+        let start = addresses.pop().unwrap_or(end);
+        let (start, end) = if start > end { (end, start) } else { (start, end) };
+        Ok((
+            Some((start.saturating_sub(1), end.saturating_sub(1))),
+            rest,
+        ))
+    }
+
+    pub(super) fn parse_target_line(&self, input: &str) -> Result<usize, String> {
+        let input = input.trim();
+        if input.is_empty() {
+            return Err("Argument required".to_string());
+        }
+        match self.parse_line_address(input, self.cursor_row)? {
+            (Some(line), rest) if rest.trim().is_empty() => Ok(line),
+            _ => Err(format!("Invalid address: {}", input)),
+        }
+    }
+
+    fn parse_line_address<'a>(
+        &self,
+        input: &'a str,
+        base: usize,
+    ) -> Result<(Option<usize>, &'a str), String> {
+        let line_count = self.text.line_count();
+        let current = base + 1;
+        let (mut line, mut rest) = if let Some(rest) = input.strip_prefix('.') {
+            (Some(current), rest)
+        } else if let Some(rest) = input.strip_prefix('$') {
+            (Some(line_count), rest)
+        } else if let Some(rest) = input.strip_prefix('\'') {
+            let mut chars = rest.chars();
+            let name = chars.next().ok_or_else(|| "Mark not set".to_string())?;
+            let row = self
+                .mark_row(name)
+                .ok_or_else(|| format!("Mark not set: {}", name))?;
+            (Some(row + 1), chars.as_str())
+        } else if let Some(rest) = input.strip_prefix('/') {
+            let (pattern, rest) = split_address_pattern(rest, '/');
+            (Some(self.search_address(pattern, base, false)? + 1), rest)
+        } else if let Some(rest) = input.strip_prefix('?') {
+            let (pattern, rest) = split_address_pattern(rest, '?');
+            (Some(self.search_address(pattern, base, true)? + 1), rest)
+        } else {
+            let digits = input.chars().take_while(|ch| ch.is_ascii_digit()).count();
+            if digits > 0 {
+                let number = input[..digits]
+                    .parse::<usize>()
+                    .map_err(|_| "Invalid range".to_string())?;
+                (Some(number), &input[digits..])
+            } else {
+                (None, input)
+            }
+        };
+        loop {
+            let trimmed = rest.trim_start();
+            let sign = match trimmed.chars().next() {
+                Some('+') => 1i64,
+                Some('-') => -1i64,
+                _ => break,
+            };
+            let after = &trimmed[1..];
+            let digits = after.chars().take_while(|ch| ch.is_ascii_digit()).count();
+            let offset = if digits == 0 {
+                1
+            } else {
+                after[..digits]
+                    .parse::<i64>()
+                    .map_err(|_| "Invalid range".to_string())?
+            };
+            let value = line.unwrap_or(current) as i64 + sign * offset;
+            if value < 0 {
+                return Err("Invalid range".to_string());
+            }
+            line = Some(value as usize);
+            rest = &after[digits..];
+        }
+        if line.is_some_and(|line| line > line_count) {
+            return Err("Invalid range".to_string());
+        }
+        Ok((line, rest))
+    }
+
+    fn mark_row(&self, name: char) -> Option<usize> {
+        self.mark_position(name).map(|(row, _)| row)
+    }
+
+    fn search_address(&self, pattern: &str, base: usize, reverse: bool) -> Result<usize, String> {
+        let source = if pattern.is_empty() {
+            self.last_search
+                .as_ref()
+                .map(|spec| spec.pattern.clone())
+                .ok_or_else(|| "No previous regular expression".to_string())?
+        } else {
+            pattern.to_string()
+        };
+        let compiled = self
+            .compile_search(&source)
+            .map_err(|err| format!("Invalid pattern: {}", err))?;
+        let count = self.text.line_count();
+        for step in 1..=count {
+            let row = if reverse {
+                (base + count - step % count) % count
+            } else {
+                (base + step) % count
+            };
+            let line = self.text.line(row).unwrap_or_default();
+            if compiled.find_from(&line, 0).is_some() {
+                return Ok(row);
+            }
+        }
+        Err(format!("Pattern not found: {}", source))
+    }
+}
+
+fn split_address_pattern(input: &str, delim: char) -> (&str, &str) {
+    let mut escaped = false;
+    for (idx, ch) in input.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if ch == '\\' {
+            escaped = true;
+        } else if ch == delim {
+            return (&input[..idx], &input[idx + 1..]);
+        }
+    }
+    (input, "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_addresses_offsets_and_patterns() {
+        let mut app = App::new(None, "a\nfn one\nb\nfn two\nc".to_string());
+        app.cursor_row = 1;
+        assert_eq!(app.parse_line_range("%d").unwrap(), (Some((0, 4)), "d"));
+        assert_eq!(app.parse_line_range(".,$").unwrap(), (Some((1, 4)), ""));
+        assert_eq!(app.parse_line_range(".+1,$-1y").unwrap(), (Some((2, 3)), "y"));
+        assert_eq!(app.parse_line_range("/fn/").unwrap(), (Some((3, 3)), ""));
+        assert_eq!(app.parse_line_range("?fn?").unwrap(), (Some((3, 3)), ""));
+        assert_eq!(app.parse_line_range("2;+2").unwrap(), (Some((1, 3)), ""));
+        assert_eq!(app.parse_line_range("4,2").unwrap(), (Some((1, 3)), ""));
+        assert_eq!(app.parse_line_range("normal x").unwrap(), (None, "normal x"));
+        assert!(app.parse_line_range("9").is_err());
+        assert!(app.parse_line_range("'a").is_err());
+        app.text.set_mark('a', (4, 0));
+        assert_eq!(app.parse_line_range("'a-").unwrap(), (Some((3, 3)), ""));
+        assert_eq!(app.parse_target_line("0").unwrap(), 0);
+    }
+}
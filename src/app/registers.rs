@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+
+use super::types::{Mode, YankType};
+use super::App;
+
+const LIST_WIDTH: usize = 72;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Register {
+    pub(crate) text: String,
+    pub(crate) kind: YankType,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Registers {
+    unnamed: Option<Register>,
+    slots: HashMap<char, Register>,
+    pub(crate) last_insert: String,
+}
+
+pub(crate) fn is_register_name(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || matches!(ch, '"' | '-' | '_' | '.' | ':' | '/' | '%' | '+' | '*')
+}
+
+fn append_register(existing: &Register, added: Register) -> Register {
+    if existing.kind == YankType::Line || added.kind == YankType::Line {
+        return Register {
+            text: format!("{}\n{}", existing.text, added.text),
+            kind: YankType::Line,
+        };
+    }
+    Register {
+        text: format!("{}{}", existing.text, added.text),
+        kind: existing.kind,
+    }
+}
+
+impl Registers {
+    pub(crate) fn get(&self, name: char) -> Option<&Register> {
+        match name {
+            '"' => self.unnamed.as_ref(),
+            _ => self.slots.get(&name.to_ascii_lowercase()),
+        }
+    }
+
+    pub(crate) fn set(&mut self, name: char, register: Register) {
+        if name == '"' {
+            self.unnamed = Some(register);
+            return;
+        }
+        let register = match self.slots.get(&name.to_ascii_lowercase()) {
+            Some(existing) if name.is_ascii_uppercase() => append_register(existing, register),
+            _ => register,
+        };
+        self.slots.insert(name.to_ascii_lowercase(), register);
+    }
+
+    pub(crate) fn store(&mut self, target: Option<char>, register: Register, delete: bool) {
+        match target {
+            Some('_') => return,
+            Some('+' | '*') => {}
+            Some(name) if name.is_ascii_uppercase() => {
+                self.set(name, register);
+                self.unnamed = self.get(name).cloned();
+                return;
+            }
+            Some(name) if name.is_ascii_alphanumeric() || name == '-' => {
+                self.slots.insert(name, register.clone());
+            }
+            _ if !delete => {
+                self.slots.insert('0', register.clone());
+            }
+            _ if register.kind == YankType::Line || register.text.contains('\n') => {
+                for idx in (1..9).rev() {
+                    let from = char::from(b'0' + idx);
+                    if let Some(shifted) = self.slots.remove(&from) {
+                        self.slots.insert(char::from(b'0' + idx + 1), shifted);
+                    }
+                }
+                self.slots.insert('1', register.clone());
+            }
+            _ => {
+                self.slots.insert('-', register.clone());
+            }
+        }
+        self.unnamed = Some(register);
+    }
+}
+
+impl App {
+    pub(super) fn store_register(&mut self, text: String, kind: YankType, delete: bool) {
+        let target = self.pending_register.take();
+        let register = Register { text, kind };
+        let to_clipboard = match target {
+            Some('+' | '*') => true,
+            None => self.clipboard_unnamed,
+            _ => false,
+        };
+        if to_clipboard {
+            self.clipboard_copy(&register);
+        }
+        self.registers.store(target, register, delete);
+    }
+
+    pub(super) fn register_content(&self, name: char) -> Option<Register> {
+        let text = match name {
+            '.' => self.registers.last_insert.clone(),
+            ':' => self.command_history.last()?.clone(),
+            '/' => self.last_search.as_ref()?.pattern.clone(),
+            '%' => self.file_path.as_ref()?.display().to_string(),
+            '+' | '*' => return self.clipboard_paste().ok().flatten(),
+            _ => return self.registers.get(name).cloned(),
+        };
+        Some(Register {
+            text,
+            kind: YankType::Char,
+        })
+    }
+
+    pub(super) fn take_paste_register(&mut self) -> Option<Register> {
+        let default = if self.clipboard_unnamed { '+' } else { '"' };
+        let name = self.pending_register.take().unwrap_or(default);
+        let content = match name {
+            '+' | '*' => match self.clipboard_paste() {
+                Ok(register) => register,
+                Err(err) => {
+                    self.set_status(format!("Clipboard: {}", err));
+                    return None;
+                }
+            },
+            _ => self.register_content(name),
+        };
+        match content {
+            Some(register) if !register.text.is_empty() => Some(register),
+            _ => {
+                self.set_status(format!("Nothing in register {}", name));
+                None
+            }
+        }
+    }
+
+    pub(super) fn insert_register(&mut self, name: char) {
+        let Some(register) = self.register_content(name) else {
+            return;
+        };
+        if self.mode == Mode::Command {
+            self.insert_command_text(&register.text);
+            return;
+        }
+        let mut text = register.text;
+        if register.kind == YankType::Line {
+            text.push('\n');
+        }
+        self.insert_capture.push_str(&text);
+        self.insert_text(&text);
+    }
+
+    pub(super) fn register_list_lines(&self, filter: &str) -> Vec<String> {
+        let names = std::iter::once('"')
+            .chain('0'..='9')
+            .chain('a'..='z')
+            .chain(['-', '.', ':', '/', '%', '+', '*']);
+        let mut lines = vec!["Type Name Content".to_string()];
+        for name in names {
+            if !filter.is_empty() && !filter.contains(name) {
+                continue;
+            }
+            let Some(register) = self.register_content(name) else {
+                continue;
+            };
+            if register.text.is_empty() {
+                continue;
+            }
+            let kind = match register.kind {
+                YankType::Char => 'c',
+                YankType::Line => 'l',
+                YankType::Block => 'b',
+            };
+            let content: String = register
+                .text
+                .replace('\n', "^J")
+                .replace('\t', "^I")
+                .chars()
+                .take(LIST_WIDTH)
+                .collect();
+            lines.push(format!("  {}  \"{}   {}", kind, name, content));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn register(text: &str, kind: YankType) -> Register {
+        Register {
+            text: text.to_string(),
+            kind,
+        }
+    }
+
+    #[test]
+    fn store_routes_yanks_deletes_and_appends() {
+        let mut regs = Registers::default();
+        regs.store(None, register("yanked", YankType::Char), false);
+        regs.store(None, register("word", YankType::Char), true);
+        regs.store(None, register("line one", YankType::Line), true);
+        regs.store(None, register("line two", YankType::Line), true);
+        assert_eq!(regs.get('0').unwrap().text, "yanked");
+        assert_eq!(regs.get('-').unwrap().text, "word");
+        assert_eq!(regs.get('1').unwrap().text, "line two");
+        assert_eq!(regs.get('2').unwrap().text, "line one");
+        assert_eq!(regs.get('"').unwrap().text, "line two");
+
+        regs.store(Some('a'), register("foo", YankType::Char), false);
+        regs.store(Some('A'), register("bar", YankType::Char), false);
+        assert_eq!(regs.get('a').unwrap().text, "foobar");
+        regs.store(Some('A'), register("baz", YankType::Line), false);
+        assert_eq!(regs.get('a').unwrap(), &register("foobar\nbaz", YankType::Line));
+
+        regs.store(Some('_'), register("gone", YankType::Line), true);
+        assert_eq!(regs.get('"').unwrap().text, "foobar\nbaz");
+        assert_eq!(regs.get('1').unwrap().text, "line two");
+    }
+}
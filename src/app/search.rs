@@ -0,0 +1,344 @@
+use std::time::{Duration, Instant};
+
+use regex::{Regex, RegexBuilder};
+
+use super::types::{CommandPrompt, Mode};
+use super::App;
+
+const SEARCH_COUNT_MAX: usize = 99;
+const SEARCH_COUNT_BUDGET: Duration = Duration::from_millis(20);
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SearchCount {
+    tick: u64,
+    current: Option<usize>,
+    total: usize,
+    complete: bool,
+}
+
+pub struct SearchPattern {
+    regex: Regex,
+}
+
+impl SearchPattern {
+    pub(crate) fn new(pattern: &str, ignore_case: bool, smart_case: bool) -> Result<Self, String> {
+        let mut source = String::with_capacity(pattern.len());
+        let mut case_flag = None;
+        let mut has_upper = false;
+        let mut chars = pattern.chars();
+        while let Some(ch) = chars.next() {
+            if ch != '\\' {
+                has_upper |= ch.is_uppercase();
+                source.push(ch);
+                continue;
+            }
+            match chars.next() {
+                Some('c') => case_flag = Some(true),
+                Some('C') => case_flag = Some(false),
+                Some(next) => {
+                    source.push('\\');
+                    source.push(next);
+                }
+                None => source.push_str("\\\\"),
+            }
+        }
+        let ignore = case_flag.unwrap_or(ignore_case && !(smart_case && has_upper));
+        let regex = RegexBuilder::new(&source)
+            .case_insensitive(ignore)
+            .build()
+            .map_err(|err| {
+                err.to_string()
+                    .lines()
+                    .last()
+                    .unwrap_or_default()
+                    .trim()
+                    .trim_start_matches("error: ")
+                    .to_string()
+            })?;
+        Ok(Self { regex })
+    }
+
+    pub(super) fn regex(&self) -> &Regex {
+        &self.regex
+    }
+
+    pub(crate) fn find_from(&self, line: &str, col: usize) -> Option<usize> {
+        let start = char_to_byte(line, col)?;
+        self.regex
+            .find_at(line, start)
+            .map(|m| byte_to_char(line, m.start()))
+    }
+
+    pub(crate) fn find_before(&self, line: &str, col: usize) -> Option<usize> {
+        let limit = char_to_byte(line, col).unwrap_or(line.len());
+        self.match_starts(line)
+            .take_while(|start| *start < limit)
+            .last()
+            .map(|start| byte_to_char(line, start))
+    }
+
+    pub(crate) fn match_ranges(&self, line: &str) -> Vec<(usize, usize)> {
+        self.regex
+            .find_iter(line)
+            .filter(|m| !m.is_empty())
+            .map(|m| (byte_to_char(line, m.start()), byte_to_char(line, m.end())))
+            .collect()
+    }
+
+    fn match_starts<'a>(&'a self, line: &'a str) -> impl Iterator<Item = usize> + 'a {
+        self.regex.find_iter(line).filter(|m| !m.is_empty()).map(|m| m.start())
+    }
+}
+
+fn char_to_byte(line: &str, col: usize) -> Option<usize> {
+    if col == 0 {
+        return Some(0);
+    }
+    match line.char_indices().nth(col) {
+        Some((idx, _)) => Some(idx),
+        None if line.chars().count() == col => Some(line.len()),
+        None => None,
+    }
+}
+
+fn byte_to_char(line: &str, byte: usize) -> usize {
+    line[..byte].chars().count()
+}
+
+impl App {
+    pub(crate) fn compile_search(&self, pattern: &str) -> Result<SearchPattern, String> {
+        SearchPattern::new(pattern, self.ignore_case, self.smart_case)
+    }
+
+    pub(super) fn search_forward(&mut self, pattern: &str) -> bool {
+        self.search_text(pattern, false)
+    }
+
+    pub(super) fn search_backward(&mut self, pattern: &str) -> bool {
+        self.search_text(pattern, true)
+    }
+
+    fn search_text(&mut self, pattern: &str, reverse: bool) -> bool {
+        if pattern.is_empty() {
+            return false;
+        }
+        match self.compile_search(pattern) {
+            Ok(compiled) => self.search_with(&compiled, reverse),
+            Err(_) => false,
+        }
+    }
+
+    pub(crate) fn search_with(&mut self, pattern: &SearchPattern, reverse: bool) -> bool {
+        let found = if reverse {
+            self.find_search_backward(pattern)
+        } else {
+            self.find_search_forward(pattern)
+        };
+        let Some((row, col)) = found else {
+            self.fail_macro();
+            return false;
+        };
+        if row != self.cursor_row {
+            self.clear_line_undo();
+        }
+        self.cursor_row = row;
+        self.cursor_col = col;
+        self.update_search_count(pattern);
+        true
+    }
+
+    fn find_search_forward(&self, pattern: &SearchPattern) -> Option<(usize, usize)> {
+        let mut row = self.cursor_row;
+        let mut col = self.cursor_col + 1;
+        while row < self.text.line_count() {
+            let line = self.text.line(row).unwrap_or_default();
+            if let Some(idx) = pattern.find_from(&line, col) {
+                return Some((row, idx));
+            }
+            if !self.find_cross_line {
+                break;
+            }
+            row += 1;
+            col = 0;
+        }
+        None
+    }
+
+    fn find_search_backward(&self, pattern: &SearchPattern) -> Option<(usize, usize)> {
+        let mut row = self.cursor_row;
+        let mut col = self.cursor_col;
+        loop {
+            let line = self.text.line(row).unwrap_or_default();
+            if let Some(idx) = pattern.find_before(&line, col) {
+                return Some((row, idx));
+            }
+            if row == 0 || !self.find_cross_line {
+                break;
+            }
+            row -= 1;
+            col = usize::MAX;
+        }
+        None
+    }
+
+    // Like vim, counting stops past 99 matches (or after a short time budget) so that
+    // every n/N on a large file does not rescan the whole buffer.
+    fn update_search_count(&mut self, pattern: &SearchPattern) {
+        let cursor = (self.cursor_row, self.cursor_col);
+        let started = Instant::now();
+        let (mut current, mut total) = (0, 0);
+        let mut passed_cursor = false;
+        let mut complete = true;
+        'rows: for row in 0..self.text.line_count() {
+            if row % 256 == 255 && started.elapsed() > SEARCH_COUNT_BUDGET {
+                complete = false;
+                break;
+            }
+            let line = self.text.line(row).unwrap_or_default();
+            for start in pattern.match_starts(&line) {
+                if total == SEARCH_COUNT_MAX {
+                    complete = false;
+                    break 'rows;
+                }
+                total += 1;
+                if (row, byte_to_char(&line, start)) <= cursor {
+                    current = total;
+                } else {
+                    passed_cursor = true;
+                }
+            }
+            passed_cursor |= row >= cursor.0;
+        }
+        self.search_count = Some(SearchCount {
+            tick: self.edit_tick,
+            current: (complete || passed_cursor).then_some(current),
+            total,
+            complete,
+        });
+    }
+
+    pub(crate) fn search_count_label(&self) -> Option<String> {
+        let count = self.search_count?;
+        if count.tick != self.edit_tick || (self.last_search.is_none() && !self.is_search_prompt()) {
+            return None;
+        }
+        let current = count.current.map_or("?".to_string(), |current| current.to_string());
+        if count.complete {
+            Some(format!("[{}/{}]", current, count.total))
+        } else {
+            Some(format!("[{}/>{}]", current, count.total))
+        }
+    }
+
+    pub(crate) fn is_search_prompt(&self) -> bool {
+        self.mode == Mode::Command
+            && matches!(
+                self.command_prompt,
+                CommandPrompt::SearchForward | CommandPrompt::SearchBackward
+            )
+    }
+
+    pub fn search_highlight(&self) -> Option<SearchPattern> {
+        if self.is_search_prompt() && self.incsearch {
+            if self.command_buffer.is_empty() {
+                return None;
+            }
+            return self.compile_search(&self.command_buffer).ok();
+        }
+        let spec = self.last_search.as_ref()?;
+        self.compile_search(&spec.pattern).ok()
+    }
+
+    pub(crate) fn begin_search_prompt(&mut self) {
+        self.incsearch_origin = Some((self.cursor_row, self.cursor_col));
+        self.incsearch_pattern = None;
+    }
+
+    pub fn update_incsearch(&mut self) {
+        if !self.is_search_prompt() {
+            if let Some((row, col)) = self.incsearch_origin.take() {
+                self.cursor_row = row;
+                self.cursor_col = col;
+            }
+            self.incsearch_pattern = None;
+            return;
+        }
+        if !self.incsearch || self.incsearch_pattern.as_deref() == Some(&self.command_buffer) {
+            return;
+        }
+        self.incsearch_pattern = Some(self.command_buffer.clone());
+        let Some((row, col)) = self.incsearch_origin else {
+            return;
+        };
+        self.cursor_row = row;
+        self.cursor_col = col;
+        self.search_count = None;
+        if self.command_buffer.is_empty() {
+            return;
+        }
+        let reverse = matches!(self.command_prompt, CommandPrompt::SearchBackward);
+        if let Ok(pattern) = self.compile_search(&self.command_buffer) {
+            self.search_with(&pattern, reverse);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::types::SearchSpec;
+
+    #[test]
+    fn pattern_supports_word_bounds_and_case_flags() {
+        let word = SearchPattern::new(r"\<cat\>", false, false).unwrap();
+        assert_eq!(word.match_ranges("concat cat cats"), vec![(7, 10)]);
+
+        let smart = SearchPattern::new("Foo", true, true).unwrap();
+        assert_eq!(smart.find_from("foo Foo", 0), Some(4));
+        let forced = SearchPattern::new(r"Foo\c", true, true).unwrap();
+        assert_eq!(forced.find_from("foo Foo", 0), Some(0));
+        let ignore = SearchPattern::new("foo", true, true).unwrap();
+        assert_eq!(ignore.find_before("FOO héllo foo", 13), Some(10));
+
+        assert_eq!(
+            SearchPattern::new(r"^\s*[0-9]+$", false, false)
+                .unwrap()
+                .find_from("  42", 0),
+            Some(0)
+        );
+        assert!(SearchPattern::new("(", false, false).is_err());
+    }
+
+    #[test]
+    fn search_moves_cursor_and_counts_matches() {
+        let mut app = App::new(None, "one two\ntwo one\nthree two".to_string());
+        let pattern = app.compile_search("two").unwrap();
+        assert!(app.search_with(&pattern, false));
+        assert_eq!((app.cursor_row, app.cursor_col), (0, 4));
+        assert!(app.search_with(&pattern, false));
+        assert_eq!((app.cursor_row, app.cursor_col), (1, 0));
+        app.last_search = Some(SearchSpec {
+            pattern: "two".to_string(),
+            reverse: false,
+        });
+        assert_eq!(app.search_count_label().as_deref(), Some("[2/3]"));
+        assert!(app.search_with(&pattern, true));
+        assert_eq!((app.cursor_row, app.cursor_col), (0, 4));
+        assert!(!app.search_with(&pattern, true));
+
+        let text: Vec<String> = (0..150).map(|idx| format!("line {}", idx)).collect();
+        let mut app = App::new(None, text.join("\n"));
+        app.last_search = Some(SearchSpec {
+            pattern: "line".to_string(),
+            reverse: false,
+        });
+        let pattern = app.compile_search("line").unwrap();
+        assert!(app.search_with(&pattern, false));
+        assert_eq!(app.search_count_label().as_deref(), Some("[2/>99]"));
+        app.cursor_row = 120;
+        assert!(app.search_with(&pattern, false));
+        assert_eq!(app.search_count_label().as_deref(), Some("[?/>99]"));
+        let empty = app.compile_search("x*").unwrap();
+        assert_eq!(empty.match_starts("axxb").count(), empty.match_ranges("axxb").len());
+    }
+}
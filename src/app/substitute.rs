@@ -0,0 +1,433 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use regex::Captures;
+
+use super::search::SearchPattern;
+use super::types::{SearchSpec, VisualSelection, VisualSelectionKind};
+use super::App;
+
+pub(crate) struct SubstituteConfirm {
+    pattern: SearchPattern,
+    replacement: String,
+    global: bool,
+    end_row: usize,
+    next: (usize, usize),
+    after_match: Option<(usize, usize)>,
+    current: Option<(usize, usize, usize)>,
+    substitutions: usize,
+    last_row: Option<usize>,
+    lines: usize,
+}
+
+struct SubstituteSpec {
+    pattern: String,
+    replacement: String,
+    global: bool,
+    confirm: bool,
+    case: Option<bool>,
+}
+
+fn parse_substitute(args: &str) -> Result<SubstituteSpec, String> {
+    let mut chars = args.chars();
+    let delim = match chars.next() {
+        Some(ch) if !ch.is_alphanumeric() && !ch.is_whitespace() && ch != '\\' && ch != '"' => ch,
+        _ => return Err("Usage: :[range]s/pattern/replacement/[gciI]".to_string()),
+    };
+    let rest = chars.as_str();
+    let (pattern, rest) = split_delimited(rest, delim);
+    let (replacement, flags) = match rest {
+        Some(rest) => {
+            let (replacement, flags) = split_delimited(rest, delim);
+            (replacement, flags.unwrap_or(""))
+        }
+        None => (String::new(), ""),
+    };
+    let mut spec = SubstituteSpec {
+        pattern,
+        replacement,
+        global: false,
+        confirm: false,
+        case: None,
+    };
+    for flag in flags.trim().chars() {
+        match flag {
+            'g' => spec.global = true,
+            'c' => spec.confirm = true,
+            'i' => spec.case = Some(true),
+            'I' => spec.case = Some(false),
+            _ => return Err(format!("Invalid flag: {}", flag)),
+        }
+    }
+    Ok(spec)
+}
+
+pub(super) fn split_delimited(input: &str, delim: char) -> (String, Option<&str>) {
+    let mut out = String::new();
+    let mut chars = input.char_indices();
+    while let Some((idx, ch)) = chars.next() {
+        if ch == delim {
+            return (out, Some(&input[idx + ch.len_utf8()..]));
+        }
+        if ch == '\\' {
+            match chars.next() {
+                Some((_, next)) if next == delim => out.push(next),
+                Some((_, next)) => {
+                    out.push('\\');
+                    out.push(next);
+                }
+                None => out.push('\\'),
+            }
+            continue;
+        }
+        out.push(ch);
+    }
+    (out, None)
+}
+
+fn expand_replacement(template: &str, caps: &Captures) -> String {
+    let group = |idx: usize| caps.get(idx).map_or("", |m| m.as_str());
+    let mut out = String::new();
+    let mut chars = template.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '&' => out.push_str(group(0)),
+            '\\' => match chars.next() {
+                Some(digit @ '0'..='9') => {
+                    out.push_str(group(digit.to_digit(10).unwrap_or(0) as usize));
+                }
+                Some('r') | Some('n') => out.push('\n'),
+                Some('t') => out.push('\t'),
+                Some(other) => out.push(other),
+                None => out.push('\\'),
+            },
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+fn substitute_line(
+    pattern: &SearchPattern,
+    replacement: &str,
+    line: &str,
+    global: bool,
+) -> Option<(String, usize)> {
+    let mut out = String::new();
+    let mut last = 0;
+    let mut count = 0;
+    for caps in pattern.regex().captures_iter(line) {
+        let Some(whole) = caps.get(0) else {
+            continue;
+        };
+        if whole.is_empty() && count > 0 && whole.start() == last {
+            continue;
+        }
+        out.push_str(&line[last..whole.start()]);
+        out.push_str(&expand_replacement(replacement, &caps));
+        last = whole.end();
+        count += 1;
+        if !global {
+            break;
+        }
+    }
+    if count == 0 {
+        return None;
+    }
+    out.push_str(&line[last..]);
+    Some((out, count))
+}
+
+impl App {
+    pub(super) fn substitute(&mut self, range: (usize, usize), args: &str) {
+        let spec = match parse_substitute(args) {
+            Ok(spec) => spec,
+            Err(err) => {
+                self.set_status(err);
+                return;
+            }
+        };
+        let source = if spec.pattern.is_empty() {
+            match self.last_search.as_ref() {
+                Some(last) => last.pattern.clone(),
+                None => {
+                    self.set_status("No previous regular expression");
+                    return;
+                }
+            }
+        } else {
+            spec.pattern.clone()
+        };
+        let compiled = match spec.case {
+            Some(ignore) => SearchPattern::new(&source, ignore, false),
+            None => self.compile_search(&source),
+        };
+        let pattern = match compiled {
+            Ok(pattern) => pattern,
+            Err(err) => {
+                self.set_status(format!("Invalid pattern: {}", err));
+                return;
+            }
+        };
+        self.last_search = Some(SearchSpec {
+            pattern: source.clone(),
+            reverse: false,
+        });
+        let (start, end) = range;
+        if spec.confirm {
+            self.record_undo();
+            self.clear_line_undo();
+            self.substitute_confirm = Some(SubstituteConfirm {
+                pattern,
+                replacement: spec.replacement,
+                global: spec.global,
+                end_row: end,
+                next: (start, 0),
+                after_match: None,
+                current: None,
+                substitutions: 0,
+                last_row: None,
+                lines: 0,
+            });
+            self.advance_substitute_confirm();
+            return;
+        }
+
+        let mut row = start;
+        let mut end = end;
+        let mut substitutions = 0;
+        let mut lines = 0;
+        let mut last_row = None;
+        while row <= end && row < self.text.line_count() {
+            let line = self.text.line(row).unwrap_or_default();
+            let Some((updated, count)) =
+                substitute_line(&pattern, &spec.replacement, &line, spec.global)
+            else {
+                row += 1;
+                continue;
+            };
+            if lines == 0 {
+                self.record_undo();
+                self.clear_line_undo();
+            }
+            let added = updated.matches('\n').count();
+            self.text.set_line(row, &updated);
+            self.touch_edit();
+            substitutions += count;
+            lines += 1;
+            last_row = Some(row + added);
+            row += added + 1;
+            end += added;
+        }
+        let Some(last_row) = last_row else {
+            self.set_status(format!("Pattern not found: {}", source));
+            return;
+        };
+        self.commit_undo();
+        self.dirty = true;
+        self.cursor_row = last_row;
+        self.move_line_first_non_blank();
+        self.report_substitutions(substitutions, lines);
+    }
+
+    fn report_substitutions(&mut self, substitutions: usize, lines: usize) {
+        self.set_status(format!(
+            "{} substitution{} on {} line{}",
+            substitutions,
+            if substitutions == 1 { "" } else { "s" },
+            lines,
+            if lines == 1 { "" } else { "s" }
+        ));
+    }
+
+    fn advance_substitute_confirm(&mut self) {
+        let Some(state) = self.substitute_confirm.as_mut() else {
+            return;
+        };
+        state.current = None;
+        let (mut row, mut byte) = state.next;
+        while row <= state.end_row && row < self.text.line_count() {
+            let line = self.text.line(row).unwrap_or_default();
+            let found = (byte <= line.len())
+                .then(|| state.pattern.regex().find_at(&line, byte))
+                .flatten();
+            if let Some(found) = found {
+                if found.is_empty() && state.after_match == Some((row, found.start())) {
+                    let step = line[byte..].chars().next().map_or(1, char::len_utf8);
+                    byte += step;
+                    continue;
+                }
+                state.current = Some((row, found.start(), found.end()));
+                break;
+            }
+            row += 1;
+            byte = 0;
+        }
+        let Some((row, start, _)) = state.current else {
+            self.finish_substitute_confirm();
+            return;
+        };
+        let line = self.text.line(row).unwrap_or_default();
+        let replacement = state.replacement.clone();
+        self.cursor_row = row;
+        self.cursor_col = line[..start].chars().count();
+        self.set_status(format!("replace with {} (y/n/a/q/l)?", replacement));
+    }
+
+    fn replace_confirm_match(&mut self) {
+        let Some(state) = self.substitute_confirm.as_mut() else {
+            return;
+        };
+        let Some((row, start, end)) = state.current else {
+            return;
+        };
+        let line = self.text.line(row).unwrap_or_default();
+        let Some(caps) = state.pattern.regex().captures_at(&line, start) else {
+            return;
+        };
+        let expanded = expand_replacement(&state.replacement, &caps);
+        let start_col = line[..start].chars().count();
+        let end_col = line[..end].chars().count();
+        let added = expanded.matches('\n').count();
+        let tail_bytes = match expanded.rfind('\n') {
+            Some(idx) => expanded.len() - idx - 1,
+            None => start + expanded.len(),
+        };
+        state.substitutions += 1;
+        if state.last_row != Some(row) {
+            state.lines += 1;
+        }
+        state.last_row = Some(row + added);
+        state.end_row += added;
+        let global = state.global;
+        self.text.remove((row, start_col), (row, end_col));
+        self.text.insert(row, start_col, &expanded);
+        self.touch_edit();
+        self.dirty = true;
+        let next = if !global {
+            (row + added + 1, 0)
+        } else if start == end {
+            let updated = self.text.line(row + added).unwrap_or_default();
+            let step = updated[tail_bytes..].chars().next().map_or(1, char::len_utf8);
+            (row + added, tail_bytes + step)
+        } else {
+            (row + added, tail_bytes)
+        };
+        if let Some(state) = self.substitute_confirm.as_mut() {
+            state.next = next;
+            state.after_match = (start != end).then_some(next);
+        }
+    }
+
+    fn skip_confirm_match(&mut self) {
+        let Some(state) = self.substitute_confirm.as_mut() else {
+            return;
+        };
+        let Some((row, start, end)) = state.current else {
+            return;
+        };
+        state.next = if state.global {
+            let line = self.text.line(row).unwrap_or_default();
+            let step = line[end..].chars().next().map_or(1, char::len_utf8);
+            (row, if start == end { end + step } else { end })
+        } else {
+            (row + 1, 0)
+        };
+        state.after_match = (start != end).then_some(state.next);
+    }
+
+    fn finish_substitute_confirm(&mut self) {
+        let Some(state) = self.substitute_confirm.take() else {
+            return;
+        };
+        self.commit_undo();
+        if let Some(row) = state.last_row {
+            self.cursor_row = row.min(self.text.line_count().saturating_sub(1));
+            self.move_line_first_non_blank();
+        }
+        if state.substitutions == 0 {
+            self.set_status("No substitutions");
+        } else {
+            self.report_substitutions(state.substitutions, state.lines);
+        }
+    }
+
+    pub(crate) fn handle_substitute_confirm(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Char('y') => {
+                self.replace_confirm_match();
+                self.advance_substitute_confirm();
+            }
+            KeyCode::Char('n') => {
+                self.skip_confirm_match();
+                self.advance_substitute_confirm();
+            }
+            KeyCode::Char('a') => {
+                while self
+                    .substitute_confirm
+                    .as_ref()
+                    .is_some_and(|state| state.current.is_some())
+                {
+                    self.replace_confirm_match();
+                    self.advance_substitute_confirm();
+                }
+            }
+            KeyCode::Char('l') => {
+                self.replace_confirm_match();
+                self.finish_substitute_confirm();
+            }
+            KeyCode::Char('q') | KeyCode::Esc => self.finish_substitute_confirm(),
+            _ => {}
+        }
+    }
+
+    pub fn substitute_match_selection(&self) -> Option<VisualSelection> {
+        let (row, start, end) = self.substitute_confirm.as_ref()?.current?;
+        let line = self.text.line(row)?;
+        let start_col = line[..start].chars().count();
+        let end_col = line[..end].chars().count().max(start_col + 1) - 1;
+        Some(VisualSelection {
+            kind: VisualSelectionKind::Char((row, start_col), (row, end_col)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::KeyModifiers;
+
+    fn run(app: &mut App, command: &str) {
+        app.command_buffer = command.to_string();
+        app.execute_command().unwrap();
+    }
+
+    #[test]
+    fn substitute_uses_groups_and_single_undo() {
+        let mut app = App::new(None, "let foo = foo_bar;\nfoo(1, 2)\nbar".to_string());
+        run(&mut app, r"%s/\<foo\>/[&]/g");
+        assert_eq!(app.text.to_string(), "let [foo] = foo_bar;\n[foo](1, 2)\nbar");
+        run(&mut app, r"2s/(\d), (\d)/\2, \1/");
+        assert_eq!(app.text.line(1).as_deref(), Some("[foo](2, 1)"));
+        app.undo();
+        app.undo();
+        assert_eq!(app.text.to_string(), "let foo = foo_bar;\nfoo(1, 2)\nbar");
+        run(&mut app, "s/nomatch/x/");
+        assert_eq!(app.status_message, "Pattern not found: nomatch");
+    }
+
+    #[test]
+    fn confirm_mode_replaces_selected_matches() {
+        let mut app = App::new(None, "a a\na".to_string());
+        run(&mut app, "%s/a/bb/gc");
+        let key = |ch| KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE);
+        assert_eq!(app.substitute_match_selection().map(|_| app.cursor_col), Some(0));
+        app.handle_substitute_confirm(key('y'));
+        app.handle_substitute_confirm(key('n'));
+        assert_eq!((app.cursor_row, app.cursor_col), (1, 0));
+        app.handle_substitute_confirm(key('a'));
+        assert!(app.substitute_confirm.is_none());
+        assert_eq!(app.text.to_string(), "bb a\nbb");
+        assert_eq!(app.status_message, "2 substitutions on 2 lines");
+        app.undo();
+        assert_eq!(app.text.to_string(), "a a\na");
+    }
+}
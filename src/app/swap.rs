@@ -0,0 +1,406 @@
+use std::cell::OnceCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use chrono::{Local, TimeZone};
+use crossterm::event::{KeyCode, KeyEvent};
+use serde::{Deserialize, Serialize};
+
+use super::buffer::TextBuffer;
+use super::config::Config;
+use super::input::expand_tilde_path;
+use super::undo::now_secs;
+use super::undofile::{absolute_path, state_file_path};
+use super::App;
+use crate::logging::append_log;
+
+const SWAP_FILE_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct SwapFile {
+    version: u32,
+    pub(crate) path: String,
+    pub(crate) pid: u32,
+    pub(crate) modified: u64,
+    pub(crate) cursor: (usize, usize),
+    pub(crate) text: String,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct SwapPrompt {
+    pub(crate) swap_path: PathBuf,
+}
+
+enum SwapJob {
+    Write {
+        dir: PathBuf,
+        file: PathBuf,
+        text: Box<TextBuffer>,
+        cursor: (usize, usize),
+    },
+    Remove(PathBuf),
+    Flush(Sender<()>),
+}
+
+// Swap files are serialized and written on a worker thread from a rope snapshot, so
+// large buffers do not stall input. Jobs run in order, so a removal after :w is never
+// overtaken by an older write.
+#[derive(Default)]
+pub(crate) struct SwapWriter {
+    sender: OnceCell<Sender<SwapJob>>,
+}
+
+impl SwapWriter {
+    fn send(&self, job: SwapJob) {
+        let sender = self.sender.get_or_init(|| {
+            let (sender, receiver) = mpsc::channel();
+            thread::spawn(move || {
+                for job in receiver {
+                    run_swap_job(job);
+                }
+            });
+            sender
+        });
+        let _ = sender.send(job);
+    }
+
+    pub(crate) fn flush(&self) {
+        if self.sender.get().is_none() {
+            return;
+        }
+        let (ack, done) = mpsc::channel();
+        self.send(SwapJob::Flush(ack));
+        let _ = done.recv();
+    }
+}
+
+fn run_swap_job(job: SwapJob) {
+    match job {
+        SwapJob::Write {
+            dir,
+            file,
+            text,
+            cursor,
+        } => {
+            if let Err(err) = write_swap_file(&dir, &file, &text, cursor) {
+                append_log(&format!("swapfile: {}: {}", file.display(), err));
+            }
+        }
+        SwapJob::Remove(swap) => {
+            if read_swap_file(&swap).is_ok_and(|record| record.pid == std::process::id()) {
+                let _ = fs::remove_file(swap);
+            }
+        }
+        SwapJob::Flush(ack) => {
+            let _ = ack.send(());
+        }
+    }
+}
+
+pub(crate) fn default_swap_dir() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME")?;
+    Some(PathBuf::from(home).join(".config/rvim/swap"))
+}
+
+pub(crate) fn swap_file_path(dir: &Path, file: &Path) -> PathBuf {
+    state_file_path(dir, file, "swp")
+}
+
+pub(crate) fn write_swap_file(
+    dir: &Path,
+    file: &Path,
+    text: &TextBuffer,
+    cursor: (usize, usize),
+) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+    let record = SwapFile {
+        version: SWAP_FILE_VERSION,
+        path: absolute_path(file).to_string_lossy().to_string(),
+        pid: std::process::id(),
+        modified: now_secs(),
+        cursor,
+        text: text.to_string(),
+    };
+    let target = swap_file_path(dir, file);
+    let tmp = target.with_extension("swp.tmp");
+    fs::write(&tmp, serde_json::to_vec(&record)?)
+        .with_context(|| format!("write {}", tmp.display()))?;
+    fs::rename(&tmp, &target).with_context(|| format!("rename {}", target.display()))?;
+    Ok(())
+}
+
+pub(crate) fn read_swap_file(path: &Path) -> Result<SwapFile> {
+    let data = fs::read(path).with_context(|| format!("read {}", path.display()))?;
+    let record: SwapFile = serde_json::from_slice(&data)?;
+    if record.version != SWAP_FILE_VERSION {
+        bail!("unsupported swap file version {}", record.version);
+    }
+    Ok(record)
+}
+
+pub(crate) fn list_swap_files(dir: &Path) -> Vec<(PathBuf, SwapFile)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut out: Vec<(PathBuf, SwapFile)> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "swp"))
+        .filter_map(|path| read_swap_file(&path).ok().map(|record| (path, record)))
+        .collect();
+    out.sort_by(|a, b| a.1.path.cmp(&b.1.path));
+    out
+}
+
+pub(crate) fn describe_swap(record: &SwapFile) -> String {
+    let modified = Local
+        .timestamp_opt(record.modified as i64, 0)
+        .single()
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default();
+    let running = if process_running(record.pid) {
+        ", still running"
+    } else {
+        ""
+    };
+    format!(
+        "{} (modified {}, pid {}{})",
+        record.path, modified, record.pid, running
+    )
+}
+
+pub fn recoverable_swap_lines(config: &Config) -> Vec<String> {
+    let dir = config
+        .directory
+        .as_deref()
+        .map(|dir| PathBuf::from(expand_tilde_path(dir)))
+        .or_else(default_swap_dir);
+    let Some(dir) = dir else {
+        return vec!["No swap directory (HOME is not set)".to_string()];
+    };
+    let swaps = list_swap_files(&dir);
+    if swaps.is_empty() {
+        return vec![format!("No swap files found in {}", dir.display())];
+    }
+    let mut lines = vec![format!("Swap files found in {}:", dir.display())];
+    for (idx, (_, record)) in swaps.iter().enumerate() {
+        lines.push(format!("{:>3}. {}", idx + 1, describe_swap(record)));
+    }
+    lines.push("Recover with: rvim -r <file>".to_string());
+    lines
+}
+
+fn process_running(pid: u32) -> bool {
+    if pid == std::process::id() {
+        return true;
+    }
+    if Path::new("/proc/self").exists() {
+        return Path::new("/proc").join(pid.to_string()).exists();
+    }
+    // Without procfs only a definite "no such process" counts as dead: kill -0 also
+    // fails with EPERM for another user's live process, and may not exist at all.
+    match Command::new("kill")
+        .arg("-0")
+        .arg(pid.to_string())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+    {
+        Ok(output) if !output.status.success() => !String::from_utf8_lossy(&output.stderr)
+            .to_ascii_lowercase()
+            .contains("no such process"),
+        _ => true,
+    }
+}
+
+impl App {
+    fn swap_dir(&self) -> Option<PathBuf> {
+        self.swap_dir.clone().or_else(default_swap_dir)
+    }
+
+    pub fn maybe_write_swap(&mut self, force: bool) {
+        if !self.swap_file || self.read_only || !self.dirty || self.edit_tick == self.swap_tick {
+            return;
+        }
+        let due = force
+            || self.last_input.elapsed() >= Duration::from_millis(self.update_time)
+            || (self.update_count > 0
+                && self.edit_tick.wrapping_sub(self.swap_tick) >= self.update_count);
+        if !due {
+            return;
+        }
+        let (Some(path), Some(dir)) = (self.file_path.clone(), self.swap_dir()) else {
+            return;
+        };
+        self.swap_tick = self.edit_tick;
+        self.swap_writer.send(SwapJob::Write {
+            dir,
+            file: path,
+            text: Box::new(self.text.snapshot()),
+            cursor: (self.cursor_row, self.cursor_col),
+        });
+    }
+
+    pub(crate) fn remove_swap_file(&self, path: &Path) {
+        let Some(dir) = self.swap_dir() else {
+            return;
+        };
+        self.swap_writer.send(SwapJob::Remove(swap_file_path(&dir, path)));
+    }
+
+    pub fn remove_swap_files(&self) {
+        let paths = std::iter::once(&self.file_path)
+            .chain(self.buffers.iter().map(|slot| &slot.state.file_path))
+            .flatten();
+        for path in paths {
+            self.remove_swap_file(path);
+        }
+        self.swap_writer.flush();
+    }
+
+    pub fn check_swap_file(&mut self) {
+        if !self.swap_file {
+            return;
+        }
+        let (Some(path), Some(dir)) = (self.file_path.clone(), self.swap_dir()) else {
+            return;
+        };
+        let swap_path = swap_file_path(&dir, &path);
+        if !swap_path.exists() {
+            return;
+        }
+        let record = match read_swap_file(&swap_path) {
+            Ok(record) => record,
+            Err(err) => {
+                append_log(&format!("swapfile: {}: {}", swap_path.display(), err));
+                return;
+            }
+        };
+        if record.pid == std::process::id() {
+            return;
+        }
+        self.set_status(format!(
+            "Swap file found for {}: [r]ecover, [o]pen read-only, [d]elete swap",
+            describe_swap(&record)
+        ));
+        self.swap_prompt = Some(SwapPrompt { swap_path });
+    }
+
+    pub(crate) fn handle_swap_prompt(&mut self, key: KeyEvent) {
+        let Some(prompt) = self.swap_prompt.take() else {
+            return;
+        };
+        match key.code {
+            KeyCode::Char('r') | KeyCode::Char('R') => self.recover_from_swap(&prompt.swap_path),
+            KeyCode::Char('d') | KeyCode::Char('D') => match fs::remove_file(&prompt.swap_path) {
+                Ok(()) => self.set_status("Deleted swap file"),
+                Err(err) => self.set_status(format!("Delete swap failed: {}", err)),
+            },
+            KeyCode::Char('o') | KeyCode::Char('O') | KeyCode::Esc => {
+                self.read_only = true;
+                self.set_status("Opened read-only (use :w! to write anyway)");
+            }
+            _ => self.swap_prompt = Some(prompt),
+        }
+    }
+
+    pub fn recover_swap_file(&mut self) {
+        self.swap_prompt = None;
+        let (Some(path), Some(dir)) = (self.file_path.clone(), self.swap_dir()) else {
+            self.set_status("No swap file to recover");
+            return;
+        };
+        let swap_path = swap_file_path(&dir, &path);
+        if !swap_path.exists() {
+            self.set_status(format!("No swap file found for {}", path.display()));
+            return;
+        }
+        self.recover_from_swap(&swap_path);
+    }
+
+    fn recover_from_swap(&mut self, swap_path: &Path) {
+        let record = match read_swap_file(swap_path) {
+            Ok(record) => record,
+            Err(err) => {
+                self.set_status(format!("Recovery failed: {}", err));
+                return;
+            }
+        };
+        if record.text == self.text.to_string() {
+            self.set_status("Swap file matches the file on disk");
+            return;
+        }
+        self.record_undo();
+        self.text.replace_all(&record.text);
+        self.commit_undo();
+        self.touch_edit();
+        self.dirty = true;
+        self.cursor_row = record.cursor.0.min(self.text.line_count().saturating_sub(1));
+        self.cursor_col = record.cursor.1.min(self.text.line_len(self.cursor_row));
+        self.set_status(format!(
+            "Recovered {} from swap; :w to keep the changes",
+            record.path
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn process_running_sees_other_users_and_exited_processes() {
+        assert!(process_running(std::process::id()));
+        assert!(process_running(1));
+        let mut child = Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        assert!(!process_running(pid));
+    }
+
+    #[test]
+    fn swap_file_round_trips_and_lists() {
+        let dir = std::env::temp_dir().join(format!("rvim-swap-{}", std::process::id()));
+        let file = dir.join("draft.txt");
+        let text = TextBuffer::from_text("unsaved\nwork");
+        write_swap_file(&dir, &file, &text, (1, 2)).unwrap();
+
+        let record = read_swap_file(&swap_file_path(&dir, &file)).unwrap();
+        assert_eq!(record.text, "unsaved\nwork");
+        assert_eq!(record.cursor, (1, 2));
+        assert_eq!(record.pid, std::process::id());
+
+        let listed = list_swap_files(&dir);
+        assert_eq!(listed.len(), 1);
+        assert!(listed[0].1.path.ends_with("draft.txt"));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn swap_writes_and_removals_run_in_order_off_the_ui_thread() {
+        let dir = std::env::temp_dir().join(format!("rvim-swap-writer-{}", std::process::id()));
+        let file = dir.join("notes.txt");
+        let mut app = App::new(Some(file.clone()), "draft".to_string());
+        app.swap_dir = Some(dir.clone());
+        app.text.insert(0, 5, " two");
+        app.touch_edit();
+        app.dirty = true;
+        app.maybe_write_swap(true);
+        app.text.insert(0, 9, " three");
+        app.swap_writer.flush();
+        let swap = swap_file_path(&dir, &file);
+        assert_eq!(read_swap_file(&swap).unwrap().text, "draft two");
+
+        app.touch_edit();
+        app.maybe_write_swap(true);
+        app.remove_swap_file(&file);
+        app.swap_writer.flush();
+        assert!(!swap.exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -24,6 +24,10 @@ pub struct Theme {
     pub(crate) syntax_macro: Color,
     pub(crate) syntax_attribute: Color,
     pub(crate) syntax_punctuation: Color,
+    pub(crate) diagnostic_error: Color,
+    pub(crate) diagnostic_warning: Color,
+    pub(crate) diagnostic_info: Color,
+    pub(crate) diagnostic_hint: Color,
 }
 
 impl Theme {
@@ -55,6 +59,10 @@ impl Theme {
             syntax_macro: Color::Rgb(128, 0, 128),
             syntax_attribute: Color::Rgb(153, 76, 0),
             syntax_punctuation: Color::Rgb(80, 80, 80),
+            diagnostic_error: Color::Red,
+            diagnostic_warning: Color::Rgb(204, 102, 0),
+            diagnostic_info: Color::Blue,
+            diagnostic_hint: Color::DarkGray,
         }
     }
 
@@ -82,6 +90,10 @@ impl Theme {
             syntax_macro: Color::Rgb(197, 134, 192),
             syntax_attribute: Color::Rgb(214, 157, 133),
             syntax_punctuation: Color::Rgb(212, 212, 212),
+            diagnostic_error: Color::Rgb(244, 71, 71),
+            diagnostic_warning: Color::Rgb(255, 204, 0),
+            diagnostic_info: Color::Rgb(55, 148, 255),
+            diagnostic_hint: Color::Rgb(160, 160, 160),
         }
     }
 
@@ -109,6 +121,10 @@ impl Theme {
             syntax_macro: Color::Rgb(211, 54, 130),
             syntax_attribute: Color::Rgb(133, 153, 0),
             syntax_punctuation: Color::Rgb(88, 110, 117),
+            diagnostic_error: Color::Rgb(220, 50, 47),
+            diagnostic_warning: Color::Rgb(181, 137, 0),
+            diagnostic_info: Color::Rgb(38, 139, 210),
+            diagnostic_hint: Color::Rgb(147, 161, 161),
         }
     }
 
@@ -1,12 +1,31 @@
 use std::path::PathBuf;
 use std::time::Instant;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 
 use crossterm::event::{KeyCode, KeyModifiers};
 
+use super::buffer::TextBuffer;
+use super::clipboard::Clipboard;
+use super::fileio::FileOptions;
 use super::theme::Theme;
+use super::undo::UndoTree;
 use super::highlight::SyntaxState;
+use super::swap::{SwapPrompt, SwapWriter};
+use super::registers::Registers;
+use super::substitute::SubstituteConfirm;
 use super::keymap::{Keymaps, KeySpec};
+use super::marks::{GlobalMark, Jump};
+use super::window::{TabPage, Window};
+use super::config::LspServerConfig;
+use super::lsp::LspClient;
+use super::lsp_features::Diagnostic;
+use super::completion::{CompletionSource, InsertCompletion};
+use super::picker::FilePicker;
+use super::events::Autocmd;
+use super::explorer::Explorer;
+use super::search::SearchCount;
+use super::plugin::PluginHost;
+use super::quickfix::{QuickfixEntry, QuickfixJob};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Mode {
@@ -47,18 +66,18 @@ pub(crate) struct OperatorPending {
 }
 
 pub struct App {
-    pub(crate) lines: Vec<String>,
+    pub(crate) text: TextBuffer,
     pub(crate) cursor_row: usize,
     pub(crate) cursor_col: usize,
     pub(crate) scroll_row: usize,
     pub(crate) scroll_col: usize,
     pub(crate) mode: Mode,
     pub(crate) file_path: Option<PathBuf>,
+    pub(crate) file_options: FileOptions,
     pub(crate) dirty: bool,
     pub(crate) status_message: String,
     pub(crate) command_buffer: String,
-    pub(crate) undo_stack: Vec<EditorState>,
-    pub(crate) redo_stack: Vec<EditorState>,
+    pub(crate) undo_tree: UndoTree,
     pub(crate) find_cross_line: bool,
     pub(crate) shift_width: usize,
     pub(crate) indent_colon: bool,
@@ -67,12 +86,32 @@ pub struct App {
     pub(crate) theme: Theme,
     pub(crate) theme_name: String,
     pub(crate) theme_overrides: Option<HashMap<String, super::config::ThemeOverride>>,
-    pub(crate) yank_buffer: String,
-    pub(crate) yank_type: YankType,
+    pub(crate) registers: Registers,
+    pub(crate) clipboard: Clipboard,
+    pub(crate) clipboard_unnamed: bool,
+    pub(crate) pending_register: Option<char>,
+    pub(crate) awaiting_register: bool,
+    pub(crate) insert_register_pending: bool,
+    pub(crate) insert_capture: String,
     pub(crate) visual_start: Option<(usize, usize)>,
     pub(crate) block_insert: Option<BlockInsert>,
     pub(crate) last_visual: Option<LastVisual>,
+    pub(crate) global_marks: HashMap<char, GlobalMark>,
+    pub(crate) jumplist: Vec<Jump>,
+    pub(crate) jump_index: usize,
+    pub(crate) pending_mark_jump: Option<bool>,
+    pub(crate) pending_mark: bool,
+    pub(crate) pending_macro_record: bool,
+    pub(crate) pending_macro_play: Option<usize>,
+    pub(crate) macro_recording: Option<char>,
+    pub(crate) macro_keys: Vec<KeySpec>,
+    pub(crate) macro_queue: VecDeque<KeySpec>,
+    pub(crate) macro_running: bool,
+    pub(crate) macro_failed: bool,
+    pub(crate) macro_expansions: usize,
+    pub(crate) last_macro: Option<char>,
     pub(crate) insert_undo_snapshot: bool,
+    pub(crate) undo_grouped: bool,
     pub(crate) pending_find: Option<FindPending>,
     pub(crate) pending_g: bool,
     pub(crate) operator_pending: Option<OperatorPending>,
@@ -81,8 +120,21 @@ pub struct App {
     pub(crate) quit_confirm: bool,
     pub(crate) status_time: Option<Instant>,
     pub(crate) undo_limit: usize,
+    pub(crate) undo_file: bool,
+    pub(crate) undo_dir: Option<PathBuf>,
+    pub(crate) backup: bool,
+    pub(crate) backup_dir: Option<PathBuf>,
+    pub(crate) swap_file: bool,
+    pub(crate) swap_dir: Option<PathBuf>,
+    pub(crate) update_time: u64,
+    pub(crate) update_count: u64,
+    pub(crate) swap_tick: u64,
+    pub(crate) swap_prompt: Option<SwapPrompt>,
+    pub(crate) swap_writer: SwapWriter,
+    pub(crate) substitute_confirm: Option<SubstituteConfirm>,
+    pub(crate) last_input: Instant,
+    pub(crate) read_only: bool,
     pub(crate) line_undo: Option<LineUndo>,
-    pub(crate) is_restoring: bool,
     pub(crate) command_prompt: CommandPrompt,
     pub(crate) command_history: Vec<String>,
     pub(crate) command_history_index: Option<usize>,
@@ -102,6 +154,12 @@ pub struct App {
     pub(crate) last_search: Option<SearchSpec>,
     pub(crate) search_history: Vec<String>,
     pub(crate) search_history_index: Option<usize>,
+    pub(crate) search_count: Option<SearchCount>,
+    pub(crate) ignore_case: bool,
+    pub(crate) smart_case: bool,
+    pub(crate) incsearch: bool,
+    pub(crate) incsearch_origin: Option<(usize, usize)>,
+    pub(crate) incsearch_pattern: Option<String>,
     pub(crate) repeat_recording: bool,
     pub(crate) repeat_replaying: bool,
     pub(crate) repeat_changed: bool,
@@ -118,6 +176,33 @@ pub struct App {
     pub(crate) completion_anchor_col: Option<u16>,
     pub(crate) edit_tick: u64,
     pub(crate) syntax_by_buffer: HashMap<usize, SyntaxState>,
+    pub(crate) windows: Vec<Window>,
+    pub(crate) tabs: Vec<TabPage>,
+    pub(crate) current_tab: usize,
+    pub(crate) next_window_id: usize,
+    pub(crate) window_area: ratatui::layout::Rect,
+    pub(crate) pending_window: Option<usize>,
+    pub(crate) lsp_servers: HashMap<String, LspServerConfig>,
+    pub(crate) lsp_clients: HashMap<String, LspClient>,
+    pub(crate) lsp_failed: HashMap<String, String>,
+    pub(crate) lsp_code_actions: Vec<serde_json::Value>,
+    pub(crate) diagnostics: HashMap<PathBuf, Vec<Diagnostic>>,
+    pub(crate) pending_ctrl_x: bool,
+    pub(crate) completion_sources: Vec<std::rc::Rc<dyn CompletionSource>>,
+    pub(crate) insert_completion: Option<InsertCompletion>,
+    pub(crate) file_picker: Option<FilePicker>,
+    pub(crate) quickfix: Vec<QuickfixEntry>,
+    pub(crate) quickfix_index: usize,
+    pub(crate) quickfix_title: String,
+    pub(crate) quickfix_job: Option<QuickfixJob>,
+    pub(crate) quickfix_buffer: Option<usize>,
+    pub(crate) grepprg: Option<String>,
+    pub(crate) makeprg: String,
+    pub(crate) errorformat: Vec<String>,
+    pub(crate) explorer: Option<Explorer>,
+    pub(crate) plugins: PluginHost,
+    pub(crate) autocmds: Vec<Autocmd>,
+    pub(crate) firing_event: bool,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -132,31 +217,22 @@ pub enum VisualSelectionKind {
     Block { start: (usize, usize), end: (usize, usize) },
 }
 
-#[derive(Debug, Clone)]
-pub(crate) struct EditorState {
-    pub(super) lines: Vec<String>,
-    pub(super) cursor_row: usize,
-    pub(super) cursor_col: usize,
-    pub(super) scroll_row: usize,
-    pub(super) scroll_col: usize,
-    pub(super) dirty: bool,
-}
-
 #[derive(Debug, Clone)]
 pub(crate) struct BufferState {
-    pub(crate) lines: Vec<String>,
+    pub(crate) text: TextBuffer,
     pub(crate) cursor_row: usize,
     pub(crate) cursor_col: usize,
     pub(crate) scroll_row: usize,
     pub(crate) scroll_col: usize,
     pub(crate) file_path: Option<PathBuf>,
+    pub(crate) file_options: FileOptions,
     pub(crate) dirty: bool,
-    pub(crate) undo_stack: Vec<EditorState>,
-    pub(crate) redo_stack: Vec<EditorState>,
+    pub(crate) undo_tree: UndoTree,
     pub(crate) line_undo: Option<LineUndo>,
-    pub(crate) is_restoring: bool,
     pub(crate) change_tick: u64,
     pub(crate) edit_tick: u64,
+    pub(crate) swap_tick: u64,
+    pub(crate) read_only: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -305,6 +381,21 @@ pub fn char_display_width(ch: char, col: usize, tab_width: usize) -> usize {
     unicode_width::UnicodeWidthChar::width(ch).unwrap_or(0).max(1)
 }
 
+pub(super) fn normalize_range(
+    a: (usize, usize),
+    b: (usize, usize),
+) -> ((usize, usize), (usize, usize)) {
+    if pos_le(a, b) {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+fn pos_le(a: (usize, usize), b: (usize, usize)) -> bool {
+    a.0 < b.0 || (a.0 == b.0 && a.1 <= b.1)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -336,18 +427,3 @@ mod tests {
         assert_eq!(line_screen_width(line, 4), 5);
     }
 }
-
-pub(super) fn normalize_range(
-    a: (usize, usize),
-    b: (usize, usize),
-) -> ((usize, usize), (usize, usize)) {
-    if pos_le(a, b) {
-        (a, b)
-    } else {
-        (b, a)
-    }
-}
-
-fn pos_le(a: (usize, usize), b: (usize, usize)) -> bool {
-    a.0 < b.0 || (a.0 == b.0 && a.1 <= b.1)
-}
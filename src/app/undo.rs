@@ -0,0 +1,315 @@
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use super::buffer::TextEdit;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct UndoNode {
+    pub(crate) seq: usize,
+    pub(crate) parent: Option<usize>,
+    pub(crate) children: Vec<usize>,
+    pub(crate) cur_child: Option<usize>,
+    pub(crate) edits: Vec<TextEdit>,
+    pub(crate) cursor: (usize, usize),
+    pub(crate) time: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum UndoStep {
+    Revert(usize),
+    Apply(usize),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct UndoTree {
+    nodes: BTreeMap<usize, UndoNode>,
+    root: usize,
+    current: usize,
+    next_seq: usize,
+    saved: Option<usize>,
+    #[serde(skip)]
+    pending_cursor: Option<(usize, usize)>,
+    #[serde(skip)]
+    pending_time: Option<u64>,
+}
+
+impl Default for UndoTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UndoTree {
+    pub(crate) fn new() -> Self {
+        let mut nodes = BTreeMap::new();
+        nodes.insert(
+            0,
+            UndoNode {
+                seq: 0,
+                parent: None,
+                children: Vec::new(),
+                cur_child: None,
+                edits: Vec::new(),
+                cursor: (0, 0),
+                time: now_secs(),
+            },
+        );
+        Self {
+            nodes,
+            root: 0,
+            current: 0,
+            next_seq: 1,
+            saved: Some(0),
+            pending_cursor: None,
+            pending_time: None,
+        }
+    }
+
+    pub(crate) fn current(&self) -> usize {
+        self.current
+    }
+
+    pub(crate) fn node(&self, seq: usize) -> Option<&UndoNode> {
+        self.nodes.get(&seq)
+    }
+
+    pub(crate) fn begin(&mut self, cursor: (usize, usize)) {
+        if self.pending_cursor.is_none() {
+            self.pending_cursor = Some(cursor);
+            self.pending_time = Some(now_secs());
+        }
+    }
+
+    pub(crate) fn commit(&mut self, edits: Vec<TextEdit>, cursor: (usize, usize), limit: usize) {
+        let cursor = self.pending_cursor.take().unwrap_or(cursor);
+        let time = self.pending_time.take().unwrap_or_else(now_secs);
+        if edits.is_empty() {
+            return;
+        }
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let parent = self.current;
+        if let Some(node) = self.nodes.get_mut(&parent) {
+            node.children.push(seq);
+            node.cur_child = Some(seq);
+        }
+        self.nodes.insert(
+            seq,
+            UndoNode {
+                seq,
+                parent: Some(parent),
+                children: Vec::new(),
+                cur_child: None,
+                edits,
+                cursor,
+                time,
+            },
+        );
+        self.current = seq;
+        self.prune(limit);
+    }
+
+    pub(crate) fn mark_saved(&mut self) {
+        self.saved = Some(self.current);
+    }
+
+    pub(crate) fn is_saved(&self) -> bool {
+        self.saved == Some(self.current)
+    }
+
+    pub(crate) fn undo_target(&self) -> Option<usize> {
+        self.nodes.get(&self.current).and_then(|node| node.parent)
+    }
+
+    pub(crate) fn redo_target(&self) -> Option<usize> {
+        self.nodes.get(&self.current).and_then(|node| node.cur_child)
+    }
+
+    pub(crate) fn prev_seq_from(&self, seq: usize) -> Option<usize> {
+        self.nodes.range(..seq).next_back().map(|(seq, _)| *seq)
+    }
+
+    pub(crate) fn next_seq_from(&self, seq: usize) -> Option<usize> {
+        self.nodes.range(seq + 1..).next().map(|(seq, _)| *seq)
+    }
+
+    pub(crate) fn seq_before_time(&self, time: u64) -> usize {
+        self.nodes
+            .values()
+            .filter(|node| node.time <= time)
+            .map(|node| node.seq)
+            .max()
+            .unwrap_or(self.root)
+    }
+
+    pub(crate) fn undo_depth(&self) -> usize {
+        self.ancestors(self.current).len().saturating_sub(1)
+    }
+
+    pub(crate) fn redo_depth(&self) -> usize {
+        let mut depth = 0;
+        let mut seq = self.current;
+        while let Some(next) = self.nodes.get(&seq).and_then(|node| node.cur_child) {
+            depth += 1;
+            seq = next;
+        }
+        depth
+    }
+
+    pub(crate) fn leaves(&self) -> Vec<&UndoNode> {
+        self.nodes
+            .values()
+            .filter(|node| node.children.is_empty() && node.seq != self.root)
+            .collect()
+    }
+
+    pub(crate) fn steps_to(&self, target: usize) -> Vec<UndoStep> {
+        if !self.nodes.contains_key(&target) || target == self.current {
+            return Vec::new();
+        }
+        let from = self.ancestors(self.current);
+        let to = self.ancestors(target);
+        let common = from
+            .iter()
+            .copied()
+            .find(|seq| to.contains(seq))
+            .unwrap_or(self.root);
+        let mut steps: Vec<UndoStep> = from
+            .iter()
+            .take_while(|seq| **seq != common)
+            .map(|seq| UndoStep::Revert(*seq))
+            .collect();
+        let downs: Vec<usize> = to.iter().copied().take_while(|seq| *seq != common).collect();
+        steps.extend(downs.into_iter().rev().map(UndoStep::Apply));
+        steps
+    }
+
+    pub(crate) fn set_current(&mut self, target: usize) {
+        if !self.nodes.contains_key(&target) {
+            return;
+        }
+        self.current = target;
+        self.pending_cursor = None;
+        self.pending_time = None;
+        let mut child = target;
+        while let Some(parent) = self.nodes.get(&child).and_then(|node| node.parent) {
+            if let Some(node) = self.nodes.get_mut(&parent) {
+                node.cur_child = Some(child);
+            }
+            child = parent;
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn set_time(&mut self, seq: usize, time: u64) {
+        if let Some(node) = self.nodes.get_mut(&seq) {
+            node.time = time;
+        }
+    }
+
+    fn ancestors(&self, seq: usize) -> Vec<usize> {
+        let mut out = Vec::new();
+        let mut cur = Some(seq);
+        while let Some(seq) = cur {
+            out.push(seq);
+            cur = self.nodes.get(&seq).and_then(|node| node.parent);
+        }
+        out
+    }
+
+    fn prune(&mut self, limit: usize) {
+        let limit = limit.max(1);
+        while self.undo_depth() > limit {
+            let path = self.ancestors(self.current);
+            let Some(&new_root) = path.iter().rev().nth(1) else {
+                return;
+            };
+            let old_root = self.root;
+            let mut stack: Vec<usize> = self
+                .nodes
+                .get(&old_root)
+                .map(|node| node.children.clone())
+                .unwrap_or_default();
+            stack.retain(|seq| *seq != new_root);
+            self.nodes.remove(&old_root);
+            while let Some(seq) = stack.pop() {
+                if let Some(node) = self.nodes.remove(&seq) {
+                    stack.extend(node.children);
+                }
+            }
+            if let Some(node) = self.nodes.get_mut(&new_root) {
+                node.parent = None;
+                node.edits.clear();
+            }
+            if self.saved.is_some_and(|seq| !self.nodes.contains_key(&seq)) {
+                self.saved = None;
+            }
+            self.root = new_root;
+        }
+    }
+}
+
+pub(crate) fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(char_idx: usize, inserted: &str) -> Vec<TextEdit> {
+        vec![TextEdit {
+            char_idx,
+            removed: String::new(),
+            inserted: inserted.to_string(),
+        }]
+    }
+
+    #[test]
+    fn new_change_after_undo_keeps_old_branch() {
+        let mut tree = UndoTree::new();
+        tree.commit(edit(0, "a"), (0, 0), 100);
+        tree.commit(edit(1, "b"), (0, 1), 100);
+        tree.set_current(tree.undo_target().unwrap());
+        tree.commit(edit(1, "c"), (0, 1), 100);
+        assert_eq!(tree.current(), 3);
+        assert_eq!(tree.leaves().len(), 2);
+        assert_eq!(tree.prev_seq_from(tree.current()), Some(2));
+        assert_eq!(
+            tree.steps_to(2),
+            vec![UndoStep::Revert(3), UndoStep::Apply(2)]
+        );
+        tree.set_current(2);
+        assert_eq!(tree.redo_depth(), 0);
+        tree.set_current(0);
+        assert_eq!(tree.redo_target(), Some(1));
+        assert_eq!(tree.redo_depth(), 2);
+    }
+
+    #[test]
+    fn commit_keeps_the_time_the_change_began() {
+        let mut tree = UndoTree::new();
+        tree.begin((0, 0));
+        tree.pending_time = Some(1_000);
+        tree.commit(edit(0, "a"), (0, 1), 100);
+        assert_eq!(tree.node(1).map(|node| node.time), Some(1_000));
+        tree.commit(edit(1, "b"), (0, 2), 100);
+        assert!(tree.node(2).is_some_and(|node| node.time > 1_000));
+    }
+
+    #[test]
+    fn depth_limit_drops_oldest_states() {
+        let mut tree = UndoTree::new();
+        for idx in 0..5 {
+            tree.commit(edit(idx, "x"), (0, idx), 3);
+        }
+        assert_eq!(tree.undo_depth(), 3);
+        assert!(tree.node(0).is_none());
+        assert!(!tree.is_saved());
+    }
+}
@@ -0,0 +1,152 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use super::buffer::TextBuffer;
+use super::undo::UndoTree;
+use super::App;
+
+const UNDO_FILE_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct UndoFile {
+    version: u32,
+    path: String,
+    content_len: usize,
+    content_hash: u64,
+    tree: UndoTree,
+}
+
+pub(crate) fn default_undo_dir() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME")?;
+    Some(PathBuf::from(home).join(".config/rvim/undo"))
+}
+
+pub(crate) fn absolute_path(file: &Path) -> PathBuf {
+    fs::canonicalize(file)
+        .or_else(|_| std::path::absolute(file))
+        .unwrap_or_else(|_| file.to_path_buf())
+}
+
+pub(crate) fn state_file_path(dir: &Path, file: &Path, ext: &str) -> PathBuf {
+    let abs = absolute_path(file);
+    let name = abs
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let key = fnv1a(abs.to_string_lossy().as_bytes(), FNV_OFFSET);
+    dir.join(format!("{}.{:016x}.{}", name, key, ext))
+}
+
+pub(crate) fn undo_file_path(dir: &Path, file: &Path) -> PathBuf {
+    state_file_path(dir, file, "json")
+}
+
+pub(crate) fn content_hash(text: &TextBuffer) -> u64 {
+    text.byte_range_chunks(0, text.len_bytes())
+        .fold(FNV_OFFSET, |hash, chunk| fnv1a(chunk, hash))
+}
+
+pub(crate) fn write_undo_file(dir: &Path, file: &Path, text: &TextBuffer, tree: &UndoTree) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+    let record = UndoFile {
+        version: UNDO_FILE_VERSION,
+        path: file.to_string_lossy().to_string(),
+        content_len: text.len_bytes(),
+        content_hash: content_hash(text),
+        tree: tree.clone(),
+    };
+    let target = undo_file_path(dir, file);
+    let tmp = target.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_vec(&record)?)
+        .with_context(|| format!("write {}", tmp.display()))?;
+    fs::rename(&tmp, &target).with_context(|| format!("rename {}", target.display()))?;
+    Ok(())
+}
+
+pub(crate) fn read_undo_file(dir: &Path, file: &Path, text: &TextBuffer) -> Result<Option<UndoTree>> {
+    let target = undo_file_path(dir, file);
+    if !target.exists() {
+        return Ok(None);
+    }
+    let data = fs::read(&target).with_context(|| format!("read {}", target.display()))?;
+    let record: UndoFile = serde_json::from_slice(&data)?;
+    if record.version != UNDO_FILE_VERSION
+        || record.content_len != text.len_bytes()
+        || record.content_hash != content_hash(text)
+    {
+        return Ok(None);
+    }
+    let mut tree = record.tree;
+    tree.mark_saved();
+    Ok(Some(tree))
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+fn fnv1a(bytes: &[u8], mut hash: u64) -> u64 {
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+impl App {
+    fn undo_dir(&self) -> Option<PathBuf> {
+        self.undo_dir.clone().or_else(default_undo_dir)
+    }
+
+    pub(crate) fn load_undo_history(&self, path: &Path, text: &TextBuffer) -> UndoTree {
+        if !self.undo_file {
+            return UndoTree::new();
+        }
+        let Some(dir) = self.undo_dir() else {
+            return UndoTree::new();
+        };
+        match read_undo_file(&dir, path, text) {
+            Ok(Some(tree)) => tree,
+            Ok(None) => UndoTree::new(),
+            Err(err) => {
+                crate::logging::append_log(&format!("undofile: {}: {}", path.display(), err));
+                UndoTree::new()
+            }
+        }
+    }
+
+    pub(crate) fn save_undo_history(&self, path: &Path) -> Result<()> {
+        if !self.undo_file {
+            return Ok(());
+        }
+        let Some(dir) = self.undo_dir() else {
+            return Ok(());
+        };
+        write_undo_file(&dir, path, &self.text, &self.undo_tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_file_round_trips_only_for_matching_content() {
+        let dir = std::env::temp_dir().join(format!("rvim-undofile-{}", std::process::id()));
+        let file = dir.join("notes.txt");
+        let mut text = TextBuffer::from_text("one");
+        text.insert(0, 3, " two");
+        let mut tree = UndoTree::new();
+        tree.commit(text.take_changes(), (0, 3), 100);
+        write_undo_file(&dir, &file, &text, &tree).unwrap();
+
+        let loaded = read_undo_file(&dir, &file, &text).unwrap().unwrap();
+        assert_eq!(loaded.undo_depth(), 1);
+        assert!(loaded.is_saved());
+
+        let changed = TextBuffer::from_text("one three");
+        assert!(read_undo_file(&dir, &file, &changed).unwrap().is_none());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,462 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+
+use wasmtime::{
+    Caller, Config, Engine, Extern, Instance, Linker, Memory, Module, Store, StoreLimits,
+    StoreLimitsBuilder, Trap,
+};
+
+use super::buffer::TextBuffer;
+use super::events::EditorEvent;
+use super::plugin::{BufferInfo, Plugin, PluginApi, PluginRegistry};
+
+const FUEL_PER_CALL: u64 = 50_000_000;
+const EPOCH_TICK: Duration = Duration::from_millis(10);
+const TIMEOUT_TICKS: u64 = 50;
+const MEMORY_LIMIT: usize = 16 * 1024 * 1024;
+const MAX_STRING: usize = 4 * 1024 * 1024;
+
+// Current buffer, its edit tick and the number of guest calls so far.
+type StatusKey = (usize, u64, u64);
+
+enum Request {
+    Status(String),
+    Edit {
+        buffer: usize,
+        start: usize,
+        end: usize,
+        lines: Vec<String>,
+    },
+}
+
+struct HostState {
+    limits: StoreLimits,
+    settings: HashMap<String, String>,
+    registry: PluginRegistry,
+    buffers: Vec<BufferInfo>,
+    current: usize,
+    texts: HashMap<usize, TextBuffer>,
+    requests: Vec<Request>,
+    reply: Vec<u8>,
+    status_text: Option<String>,
+}
+
+pub(crate) struct WasmPlugin {
+    name: String,
+    version: String,
+    store: Store<HostState>,
+    instance: Instance,
+    registry: Option<PluginRegistry>,
+    status_failed: bool,
+    handles_events: bool,
+    runs: u64,
+    status_cache: HashMap<String, (StatusKey, Option<String>)>,
+}
+
+fn engine() -> Result<&'static Engine, String> {
+    static ENGINE: OnceLock<Result<Engine, String>> = OnceLock::new();
+    ENGINE
+        .get_or_init(|| {
+            let mut config = Config::new();
+            config.consume_fuel(true).epoch_interruption(true);
+            let engine = Engine::new(&config).map_err(|err| err.to_string())?;
+            let ticker = engine.clone();
+            thread::spawn(move || {
+                loop {
+                    thread::sleep(EPOCH_TICK);
+                    ticker.increment_epoch();
+                }
+            });
+            Ok(engine)
+        })
+        .as_ref()
+        .map_err(Clone::clone)
+}
+
+fn describe_error(err: &wasmtime::Error) -> String {
+    match err.downcast_ref::<Trap>() {
+        Some(Trap::OutOfFuel) => "exceeded its instruction budget".to_string(),
+        Some(Trap::Interrupt) => "timed out".to_string(),
+        Some(trap) => format!("trapped: {}", trap),
+        None => format!("{:#}", err),
+    }
+}
+
+fn memory(caller: &mut Caller<'_, HostState>) -> wasmtime::Result<Memory> {
+    match caller.get_export("memory") {
+        Some(Extern::Memory(memory)) => Ok(memory),
+        _ => Err(wasmtime::Error::msg("plugin does not export its memory")),
+    }
+}
+
+fn read_string(caller: &mut Caller<'_, HostState>, ptr: i32, len: i32) -> wasmtime::Result<String> {
+    let len = usize::try_from(len).map_err(|_| wasmtime::Error::msg("negative string length"))?;
+    if len > MAX_STRING {
+        return Err(wasmtime::Error::msg("string too long"));
+    }
+    let memory = memory(caller)?;
+    let mut bytes = vec![0; len];
+    memory.read(&*caller, ptr as u32 as usize, &mut bytes)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn reply(caller: &mut Caller<'_, HostState>, text: String) -> i32 {
+    let len = text.len() as i32;
+    caller.data_mut().reply = text.into_bytes();
+    len
+}
+
+fn text_lines(text: &str) -> Vec<String> {
+    text.split_terminator('\n').map(str::to_string).collect()
+}
+
+fn link(linker: &mut Linker<HostState>) -> wasmtime::Result<()> {
+    linker.func_wrap("rvim", "set_status", |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| {
+        let message = read_string(&mut caller, ptr, len)?;
+        caller.data_mut().requests.push(Request::Status(message));
+        Ok(())
+    })?;
+    linker.func_wrap(
+        "rvim",
+        "register_command",
+        |mut caller: Caller<'_, HostState>, name: i32, name_len: i32, desc: i32, desc_len: i32| {
+            let name = read_string(&mut caller, name, name_len)?;
+            let description = read_string(&mut caller, desc, desc_len)?;
+            caller.data_mut().registry.register_command(&name, &description);
+            Ok(())
+        },
+    )?;
+    linker.func_wrap(
+        "rvim",
+        "register_keymap",
+        |mut caller: Caller<'_, HostState>,
+         mode: i32,
+         mode_len: i32,
+         lhs: i32,
+         lhs_len: i32,
+         command: i32,
+         command_len: i32| {
+            let mode = read_string(&mut caller, mode, mode_len)?;
+            let lhs = read_string(&mut caller, lhs, lhs_len)?;
+            let command = read_string(&mut caller, command, command_len)?;
+            caller.data_mut().registry.register_keymap(&mode, &lhs, &command);
+            Ok(())
+        },
+    )?;
+    linker.func_wrap(
+        "rvim",
+        "register_status",
+        |mut caller: Caller<'_, HostState>, id: i32, id_len: i32, priority: i32| {
+            let id = read_string(&mut caller, id, id_len)?;
+            caller.data_mut().registry.register_status(&id, priority);
+            Ok(())
+        },
+    )?;
+    linker.func_wrap("rvim", "current_buffer", |caller: Caller<'_, HostState>| {
+        caller.data().current as i32
+    })?;
+    linker.func_wrap("rvim", "list_buffers", |mut caller: Caller<'_, HostState>| {
+        let buffers: Vec<serde_json::Value> = caller
+            .data()
+            .buffers
+            .iter()
+            .map(|info| {
+                serde_json::json!({
+                    "id": info.id,
+                    "path": info.path.as_ref().map(|path| path.display().to_string()),
+                    "dirty": info.dirty,
+                    "lines": info.lines,
+                    "current": info.current,
+                })
+            })
+            .collect();
+        reply(&mut caller, serde_json::Value::Array(buffers).to_string())
+    })?;
+    linker.func_wrap(
+        "rvim",
+        "get_buffer_text",
+        |mut caller: Caller<'_, HostState>, buffer: i32, start: i32, end: i32| {
+            let Some(text) = caller.data().texts.get(&(buffer as usize)) else {
+                return -1;
+            };
+            let end = usize::try_from(end).ok();
+            let lines = text.lines(start.max(0) as usize, end);
+            let text: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+            reply(&mut caller, text)
+        },
+    )?;
+    linker.func_wrap(
+        "rvim",
+        "apply_edit",
+        |mut caller: Caller<'_, HostState>, buffer: i32, start: i32, end: i32, ptr: i32, len: i32| {
+            let lines = text_lines(&read_string(&mut caller, ptr, len)?);
+            let buffer = buffer as usize;
+            let state = caller.data_mut();
+            let Some(text) = state.texts.get_mut(&buffer) else {
+                return Ok(-1);
+            };
+            let (Ok(start), Ok(end)) = (usize::try_from(start), usize::try_from(end)) else {
+                return Ok(-1);
+            };
+            if start > end || end > text.line_count() {
+                return Ok(-1);
+            }
+            text.replace_lines(start, end, &lines);
+            state.requests.push(Request::Edit { buffer, start, end, lines });
+            Ok(0)
+        },
+    )?;
+    linker.func_wrap(
+        "rvim",
+        "get_setting",
+        |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| {
+            let key = read_string(&mut caller, ptr, len)?;
+            Ok(match caller.data().settings.get(&key).cloned() {
+                Some(value) => reply(&mut caller, value),
+                None => -1,
+            })
+        },
+    )?;
+    linker.func_wrap("rvim", "read_reply", |mut caller: Caller<'_, HostState>, ptr: i32| {
+        let bytes = std::mem::take(&mut caller.data_mut().reply);
+        memory(&mut caller)?.write(&mut caller, ptr as u32 as usize, &bytes)?;
+        Ok(())
+    })?;
+    linker.func_wrap(
+        "rvim",
+        "status_text",
+        |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| {
+            let text = read_string(&mut caller, ptr, len)?;
+            caller.data_mut().status_text = Some(text);
+            Ok(())
+        },
+    )?;
+    Ok(())
+}
+
+impl WasmPlugin {
+    pub(crate) fn load(path: &Path, settings: HashMap<String, String>) -> Result<Self, String> {
+        let engine = engine()?;
+        let module = Module::from_file(engine, path).map_err(|err| format!("{:#}", err))?;
+        let mut linker = Linker::new(engine);
+        link(&mut linker).map_err(|err| err.to_string())?;
+        let state = HostState {
+            limits: StoreLimitsBuilder::new()
+                .memory_size(MEMORY_LIMIT)
+                .instances(1)
+                .tables(4)
+                .memories(1)
+                .build(),
+            settings,
+            registry: PluginRegistry::default(),
+            buffers: Vec::new(),
+            current: 0,
+            texts: HashMap::new(),
+            requests: Vec::new(),
+            reply: Vec::new(),
+            status_text: None,
+        };
+        let mut store = Store::new(engine, state);
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(FUEL_PER_CALL).map_err(|err| err.to_string())?;
+        store.set_epoch_deadline(TIMEOUT_TICKS);
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .map_err(|err| describe_error(&err))?;
+        let mut plugin = Self {
+            name: path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| path.display().to_string()),
+            version: String::new(),
+            store,
+            instance,
+            registry: None,
+            status_failed: false,
+            handles_events: false,
+            runs: 0,
+            status_cache: HashMap::new(),
+        };
+        let version = plugin
+            .call::<(), i32>("rvim_api_version", ())
+            .map_err(|err| format!("rvim_api_version: {}", err))?;
+        plugin.version = format!("{}.{}", version >> 16, version & 0xffff);
+        if plugin.instance.get_func(&mut plugin.store, "init").is_some() {
+            plugin.call::<(), ()>("init", ()).map_err(|err| format!("init: {}", err))?;
+        }
+        plugin.registry = Some(std::mem::take(&mut plugin.store.data_mut().registry));
+        plugin.handles_events = plugin.instance.get_func(&mut plugin.store, "on_event").is_some();
+        Ok(plugin)
+    }
+
+    fn call<P: wasmtime::WasmParams, R: wasmtime::WasmResults>(&mut self, name: &str, params: P) -> Result<R, String> {
+        let func = self
+            .instance
+            .get_typed_func::<P, R>(&mut self.store, name)
+            .map_err(|err| err.to_string())?;
+        self.store.set_fuel(FUEL_PER_CALL).map_err(|err| err.to_string())?;
+        self.store.set_epoch_deadline(TIMEOUT_TICKS);
+        func.call(&mut self.store, params).map_err(|err| describe_error(&err))
+    }
+
+    fn write_string(&mut self, text: &str) -> Result<(i32, i32), String> {
+        let len = text.len() as i32;
+        let ptr = self.call::<i32, i32>("alloc", len)?;
+        let memory = self
+            .instance
+            .get_memory(&mut self.store, "memory")
+            .ok_or("plugin does not export its memory")?;
+        memory
+            .write(&mut self.store, ptr as u32 as usize, text.as_bytes())
+            .map_err(|err| err.to_string())?;
+        Ok((ptr, len))
+    }
+
+    // Edits and status messages queued by the guest are applied only once it returns cleanly.
+    fn invoke(&mut self, export: &str, first: &str, second: &str, api: &mut dyn PluginApi) -> Result<i32, String> {
+        self.runs += 1;
+        let (first_ptr, first_len) = self.write_string(first)?;
+        let (second_ptr, second_len) = self.write_string(second)?;
+        let code = self.call::<(i32, i32, i32, i32), i32>(export, (first_ptr, first_len, second_ptr, second_len))?;
+        for request in std::mem::take(&mut self.store.data_mut().requests) {
+            match request {
+                Request::Status(message) => api.set_status(&message),
+                Request::Edit { buffer, start, end, lines } => api.apply_edit(buffer, start, end, &lines)?,
+            }
+        }
+        Ok(code)
+    }
+
+    // Buffer snapshots share the editor's ropes, so text is only copied for the ranges read.
+    fn snapshot(&mut self, api: &dyn PluginApi) {
+        let state = self.store.data_mut();
+        state.buffers = api.list_buffers();
+        state.current = api.current_buffer();
+        state.texts = state
+            .buffers
+            .iter()
+            .filter_map(|info| Some((info.id, api.buffer_snapshot(info.id)?)))
+            .collect();
+        state.requests.clear();
+        state.reply.clear();
+        state.status_text = None;
+    }
+}
+
+impl Plugin for WasmPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn api_version(&self) -> &str {
+        &self.version
+    }
+
+    fn register(&mut self, registry: &mut PluginRegistry) {
+        if let Some(registered) = self.registry.take() {
+            *registry = registered;
+        }
+    }
+
+    fn run_command(&mut self, command: &str, args: &str, api: &mut dyn PluginApi) -> Result<(), String> {
+        self.snapshot(&*api);
+        let code = self.invoke("run_command", command, args, api)?;
+        if code != 0 {
+            return Err(format!("{} failed with code {}", command, code));
+        }
+        Ok(())
+    }
+
+    fn on_event(&mut self, event: &EditorEvent, api: &mut dyn PluginApi) -> Result<(), String> {
+        if !self.handles_events {
+            return Ok(());
+        }
+        self.snapshot(&*api);
+        let code = self.invoke("on_event", event.name(), &event.subject(), api)?;
+        if code != 0 {
+            return Err(format!("on_event failed with code {}", code));
+        }
+        Ok(())
+    }
+
+    fn status(&mut self, item: &str, api: &dyn PluginApi) -> Option<String> {
+        if self.status_failed || self.instance.get_func(&mut self.store, "status").is_none() {
+            return None;
+        }
+        // The status line is redrawn constantly; only ask the guest again after an edit,
+        // a buffer switch or one of its own calls.
+        let key = (api.current_buffer(), api.edit_tick(), self.runs);
+        if let Some((cached, text)) = self.status_cache.get(item)
+            && *cached == key
+        {
+            return text.clone();
+        }
+        self.snapshot(api);
+        let result = self
+            .write_string(item)
+            .and_then(|(ptr, len)| self.call::<(i32, i32), ()>("status", (ptr, len)));
+        if result.is_err() {
+            self.status_failed = true;
+            return None;
+        }
+        let text = self.store.data_mut().status_text.take();
+        self.status_cache.insert(item.to_string(), (key, text.clone()));
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{App, handle_key};
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    fn sample() -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/plugins/sample.wat")
+    }
+
+    #[test]
+    fn sample_wasm_plugin_runs_sandboxed() {
+        let mut app = App::new(None, "hello world\nsecond".to_string());
+        let settings = HashMap::from([("greeting".to_string(), "hi there".to_string())]);
+        let plugin = WasmPlugin::load(&sample(), settings).unwrap();
+        assert_eq!(plugin.api_version(), "0.1");
+        assert!(app.load_plugin(Box::new(plugin)));
+
+        app.run_ex_command("Greet").unwrap();
+        assert_eq!(app.status_message, "hi there");
+        for ch in ['\\', 'u'] {
+            handle_key(&mut app, KeyEvent::new(KeyCode::Char(ch), KeyModifiers::NONE)).unwrap();
+        }
+        assert_eq!(app.text.line(0).unwrap(), "HELLO WORLD");
+        assert_eq!(app.text.line(1).unwrap(), "second");
+        assert!(app.dirty);
+
+        app.run_ex_command("Spin").unwrap();
+        assert_eq!(app.status_message, "sample: exceeded its instruction budget");
+        app.run_ex_command("Crash").unwrap();
+        assert!(app.status_message.starts_with("sample: trapped:"), "{}", app.status_message);
+        app.run_ex_command("Hog").unwrap();
+        assert!(app.status_message.starts_with("sample: trapped:"), "{}", app.status_message);
+        app.run_ex_command("Greet").unwrap();
+        assert_eq!(app.status_message, "hi there");
+        assert_eq!(app.plugin_status_items(), vec!["runs:6"]);
+        assert_eq!(app.plugin_status_items(), vec!["runs:6"]);
+        app.run_ex_command("Greet").unwrap();
+        assert_eq!(app.plugin_status_items(), vec!["runs:7"]);
+
+        let broken = std::env::temp_dir().join(format!("rvim-broken-{}.wat", std::process::id()));
+        app.file_path = Some(broken.clone());
+        assert!(app.save());
+        assert_eq!(app.status_message, broken.display().to_string());
+        std::fs::write(&broken, "(module (func (export \"rvim_api_version\") (result i32) (i32.const 65536)))").unwrap();
+        let plugin = WasmPlugin::load(&broken, HashMap::new()).unwrap();
+        assert!(!app.load_plugin(Box::new(plugin)));
+        std::fs::write(&broken, "(module (import \"rvim\" \"format_disk\" (func)))").unwrap();
+        assert!(WasmPlugin::load(&broken, HashMap::new()).is_err());
+        std::fs::remove_file(&broken).unwrap();
+    }
+}
@@ -0,0 +1,839 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use ratatui::layout::Rect;
+
+use super::buffer::TextBuffer;
+use super::types::{char_to_screen_col, screen_col_to_char_idx};
+use super::App;
+
+pub(crate) const DEFAULT_WINDOW_AREA: Rect = Rect {
+    x: 0,
+    y: 0,
+    width: 80,
+    height: 23,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SplitDir {
+    Horizontal,
+    Vertical,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum LayoutNode {
+    Leaf(usize),
+    Split {
+        dir: SplitDir,
+        children: Vec<LayoutNode>,
+        sizes: Vec<u16>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Window {
+    pub(crate) id: usize,
+    pub(crate) buffer_id: usize,
+    pub(crate) cursor_row: usize,
+    pub(crate) cursor_col: usize,
+    pub(crate) scroll_row: usize,
+    pub(crate) scroll_col: usize,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct TabPage {
+    pub(crate) layout: LayoutNode,
+    pub(crate) current: usize,
+    pub(crate) previous: Option<usize>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct WindowLayout {
+    pub(crate) panes: Vec<(usize, Rect)>,
+    pub(crate) separators: Vec<Rect>,
+}
+
+impl Window {
+    pub(crate) fn new(id: usize, buffer_id: usize) -> Self {
+        Self {
+            id,
+            buffer_id,
+            cursor_row: 0,
+            cursor_col: 0,
+            scroll_row: 0,
+            scroll_col: 0,
+        }
+    }
+}
+
+impl TabPage {
+    pub(crate) fn new(window_id: usize) -> Self {
+        Self {
+            layout: LayoutNode::Leaf(window_id),
+            current: window_id,
+            previous: None,
+        }
+    }
+}
+
+fn scale_sizes(sizes: &[u16], avail: u16) -> Vec<u16> {
+    let total = sizes.iter().map(|&size| size as u32).sum::<u32>().max(1);
+    let mut out: Vec<u16> = sizes
+        .iter()
+        .map(|&size| ((size as u32 * avail as u32 / total) as u16).max(1))
+        .collect();
+    let mut diff = avail as i32 - out.iter().map(|&size| size as i32).sum::<i32>();
+    while diff < 0 {
+        let Some(idx) = (0..out.len()).filter(|&idx| out[idx] > 1).max_by_key(|&idx| out[idx])
+        else {
+            break;
+        };
+        out[idx] -= 1;
+        diff += 1;
+    }
+    if diff > 0
+        && let Some(last) = out.last_mut()
+    {
+        *last += diff as u16;
+    }
+    out
+}
+
+fn child_rects(dir: SplitDir, area: Rect, sizes: &[u16]) -> (Vec<Rect>, Vec<Rect>) {
+    let seps = match dir {
+        SplitDir::Horizontal => 0,
+        SplitDir::Vertical => sizes.len().saturating_sub(1) as u16,
+    };
+    let avail = match dir {
+        SplitDir::Horizontal => area.height,
+        SplitDir::Vertical => area.width.saturating_sub(seps),
+    };
+    let mut rects = Vec::with_capacity(sizes.len());
+    let mut separators = Vec::new();
+    let mut offset = 0u16;
+    let count = sizes.len();
+    for (idx, size) in scale_sizes(sizes, avail).into_iter().enumerate() {
+        let rect = match dir {
+            SplitDir::Horizontal => Rect {
+                y: area.y.saturating_add(offset),
+                height: size,
+                ..area
+            },
+            SplitDir::Vertical => Rect {
+                x: area.x.saturating_add(offset),
+                width: size,
+                ..area
+            },
+        };
+        rects.push(rect.intersection(area));
+        offset = offset.saturating_add(size);
+        if dir == SplitDir::Vertical && idx + 1 < count {
+            let sep = Rect {
+                x: area.x.saturating_add(offset),
+                width: 1,
+                ..area
+            };
+            separators.push(sep.intersection(area));
+            offset = offset.saturating_add(1);
+        }
+    }
+    (rects, separators)
+}
+
+impl LayoutNode {
+    fn contains(&self, id: usize) -> bool {
+        match self {
+            LayoutNode::Leaf(leaf) => *leaf == id,
+            LayoutNode::Split { children, .. } => children.iter().any(|child| child.contains(id)),
+        }
+    }
+
+    pub(crate) fn window_ids(&self) -> Vec<usize> {
+        match self {
+            LayoutNode::Leaf(id) => vec![*id],
+            LayoutNode::Split { children, .. } => {
+                children.iter().flat_map(|child| child.window_ids()).collect()
+            }
+        }
+    }
+
+    fn split(&mut self, target: usize, new_id: usize, dir: SplitDir) -> bool {
+        match self {
+            LayoutNode::Leaf(id) if *id == target => {
+                *self = LayoutNode::Split {
+                    dir,
+                    children: vec![LayoutNode::Leaf(new_id), LayoutNode::Leaf(target)],
+                    sizes: vec![1, 1],
+                };
+                true
+            }
+            LayoutNode::Leaf(_) => false,
+            LayoutNode::Split {
+                dir: split_dir,
+                children,
+                sizes,
+            } => {
+                if *split_dir == dir
+                    && let Some(idx) = children
+                        .iter()
+                        .position(|child| *child == LayoutNode::Leaf(target))
+                {
+                    let half = (sizes[idx] / 2).max(1);
+                    sizes[idx] = sizes[idx].saturating_sub(half).max(1);
+                    children.insert(idx, LayoutNode::Leaf(new_id));
+                    sizes.insert(idx, half);
+                    return true;
+                }
+                children
+                    .iter_mut()
+                    .any(|child| child.split(target, new_id, dir))
+            }
+        }
+    }
+
+    fn remove(&mut self, target: usize) -> bool {
+        let LayoutNode::Split {
+            children, sizes, ..
+        } = self
+        else {
+            return false;
+        };
+        let Some(idx) = children
+            .iter()
+            .position(|child| *child == LayoutNode::Leaf(target))
+        else {
+            return children.iter_mut().any(|child| child.remove(target));
+        };
+        children.remove(idx);
+        let freed = sizes.remove(idx);
+        let neighbor = idx.saturating_sub(1);
+        sizes[neighbor] = sizes[neighbor].saturating_add(freed);
+        if children.len() == 1 {
+            let only = children.remove(0);
+            *self = only;
+        }
+        true
+    }
+
+    fn rects(&self, area: Rect, out: &mut WindowLayout) {
+        match self {
+            LayoutNode::Leaf(id) => out.panes.push((*id, area)),
+            LayoutNode::Split {
+                dir,
+                children,
+                sizes,
+            } => {
+                let (rects, separators) = child_rects(*dir, area, sizes);
+                for (child, rect) in children.iter().zip(rects) {
+                    child.rects(rect, out);
+                }
+                out.separators.extend(separators);
+            }
+        }
+    }
+
+    fn normalize(&mut self, area: Rect) {
+        if let LayoutNode::Split {
+            dir,
+            children,
+            sizes,
+        } = self
+        {
+            let (rects, _) = child_rects(*dir, area, sizes);
+            for ((child, size), rect) in children.iter_mut().zip(sizes.iter_mut()).zip(rects) {
+                *size = match dir {
+                    SplitDir::Horizontal => rect.height,
+                    SplitDir::Vertical => rect.width,
+                };
+                child.normalize(rect);
+            }
+        }
+    }
+
+    fn equalize(&mut self) {
+        if let LayoutNode::Split {
+            children, sizes, ..
+        } = self
+        {
+            sizes.iter_mut().for_each(|size| *size = 1);
+            children.iter_mut().for_each(LayoutNode::equalize);
+        }
+    }
+
+    fn resize(&mut self, target: usize, dir: SplitDir, size_for: &dyn Fn(u16) -> i32) -> bool {
+        let LayoutNode::Split {
+            dir: split_dir,
+            children,
+            sizes,
+        } = self
+        else {
+            return false;
+        };
+        let Some(idx) = children.iter().position(|child| child.contains(target)) else {
+            return false;
+        };
+        if children[idx].resize(target, dir, size_for) {
+            return true;
+        }
+        if *split_dir != dir {
+            return false;
+        }
+        let other = if idx + 1 < children.len() { idx + 1 } else { idx - 1 };
+        let total = sizes[idx] as i32 + sizes[other] as i32;
+        let size = size_for(sizes[idx]).clamp(1, (total - 1).max(1));
+        sizes[idx] = size as u16;
+        sizes[other] = (total - size).max(1) as u16;
+        true
+    }
+}
+
+impl App {
+    pub(crate) fn current_window_id(&self) -> usize {
+        self.tabs[self.current_tab].current
+    }
+
+    pub(crate) fn window_count(&self) -> usize {
+        self.tabs[self.current_tab].layout.window_ids().len()
+    }
+
+    pub(crate) fn window_layout(&self, area: Rect) -> WindowLayout {
+        let mut out = WindowLayout::default();
+        self.tabs[self.current_tab].layout.rects(area, &mut out);
+        out
+    }
+
+    pub(crate) fn sync_current_window(&mut self) {
+        let id = self.current_window_id();
+        if let Some(window) = self.windows.iter_mut().find(|window| window.id == id) {
+            window.buffer_id = self.current_buffer_id;
+            window.cursor_row = self.cursor_row;
+            window.cursor_col = self.cursor_col;
+            window.scroll_row = self.scroll_row;
+            window.scroll_col = self.scroll_col;
+            self.text.set_anchor(id, (self.cursor_row, self.cursor_col));
+        }
+    }
+
+    // Moves a window's saved cursor to where edits made from other windows pushed it,
+    // scrolling by the same number of lines so the view stays on the same text.
+    fn follow_buffer_edits(&mut self, idx: usize) {
+        let window = &self.windows[idx];
+        let Some((row, col)) = self
+            .window_text(window.buffer_id)
+            .and_then(|text| text.anchor(window.id))
+        else {
+            return;
+        };
+        let window = &mut self.windows[idx];
+        if row >= window.cursor_row {
+            window.scroll_row += row - window.cursor_row;
+        } else {
+            window.scroll_row = window.scroll_row.saturating_sub(window.cursor_row - row);
+        }
+        window.cursor_row = row;
+        window.cursor_col = col;
+    }
+
+    fn load_window(&mut self, id: usize) {
+        let Some(idx) = self.windows.iter().position(|window| window.id == id) else {
+            return;
+        };
+        self.follow_buffer_edits(idx);
+        let window = self.windows[idx].clone();
+        if window.buffer_id != self.current_buffer_id {
+            self.enter_buffer(window.buffer_id, false);
+        }
+        self.tabs[self.current_tab].current = id;
+        if window.cursor_row != self.cursor_row {
+            self.clear_line_undo();
+        }
+        self.cursor_row = window.cursor_row.min(self.text.line_count().saturating_sub(1));
+        self.cursor_col = window.cursor_col.min(self.line_len(self.cursor_row));
+        self.scroll_row = window.scroll_row.min(self.cursor_row);
+        self.scroll_col = window.scroll_col;
+    }
+
+    pub(super) fn enter_window(&mut self, id: usize) {
+        let current = self.current_window_id();
+        if id == current {
+            return;
+        }
+        self.sync_current_window();
+        self.tabs[self.current_tab].previous = Some(current);
+        self.load_window(id);
+    }
+
+    pub(super) fn split_window(&mut self, dir: SplitDir, path: Option<PathBuf>, empty: bool) {
+        self.sync_current_window();
+        self.tabs[self.current_tab].layout.normalize(self.window_area);
+        let current = self.current_window_id();
+        let Some(mut window) = self.windows.iter().find(|window| window.id == current).cloned()
+        else {
+            return;
+        };
+        window.id = self.next_window_id;
+        self.next_window_id += 1;
+        self.windows.push(window.clone());
+        let tab = &mut self.tabs[self.current_tab];
+        tab.layout.split(current, window.id, dir);
+        tab.previous = Some(current);
+        tab.current = window.id;
+        if empty {
+            self.open_empty_buffer();
+        } else if let Some(path) = path {
+            self.open_or_switch_buffer(path, None);
+        }
+    }
+
+    pub(super) fn open_bottom_window(&mut self, rows: u16) {
+        self.sync_current_window();
+        let current = self.current_window_id();
+        let Some(mut window) = self.windows.iter().find(|window| window.id == current).cloned()
+        else {
+            return;
+        };
+        window.id = self.next_window_id;
+        self.next_window_id += 1;
+        self.windows.push(window.clone());
+        let area = self.window_area;
+        let tab = &mut self.tabs[self.current_tab];
+        tab.layout.normalize(area);
+        let rows = rows.min(area.height / 2).max(1);
+        let root = std::mem::replace(&mut tab.layout, LayoutNode::Leaf(window.id));
+        tab.layout = LayoutNode::Split {
+            dir: SplitDir::Horizontal,
+            children: vec![root, LayoutNode::Leaf(window.id)],
+            sizes: vec![area.height.saturating_sub(rows).max(1), rows],
+        };
+        tab.previous = Some(current);
+        tab.current = window.id;
+    }
+
+    pub(super) fn close_window(&mut self, id: usize) {
+        if self.window_count() == 1 {
+            if self.tabs.len() == 1 {
+                self.set_status("Cannot close last window");
+            } else {
+                self.close_tab(self.current_tab);
+            }
+            return;
+        }
+        let tab = &mut self.tabs[self.current_tab];
+        tab.layout.remove(id);
+        self.windows.retain(|window| window.id != id);
+        if tab.previous == Some(id) {
+            tab.previous = None;
+        }
+        if tab.current != id {
+            return;
+        }
+        let ids = tab.layout.window_ids();
+        let next = tab
+            .previous
+            .take()
+            .filter(|prev| ids.contains(prev))
+            .unwrap_or(ids[0]);
+        self.load_window(next);
+    }
+
+    pub(super) fn only_window(&mut self) {
+        let current = self.current_window_id();
+        let closing: Vec<usize> = self.tabs[self.current_tab]
+            .layout
+            .window_ids()
+            .into_iter()
+            .filter(|id| *id != current)
+            .collect();
+        if closing.is_empty() {
+            self.set_status("Already only one window");
+            return;
+        }
+        self.windows.retain(|window| !closing.contains(&window.id));
+        let tab = &mut self.tabs[self.current_tab];
+        tab.layout = LayoutNode::Leaf(current);
+        tab.previous = None;
+    }
+
+    pub(super) fn quit_window(&mut self) -> bool {
+        if self.window_count() == 1 && self.tabs.len() == 1 {
+            return true;
+        }
+        self.close_window(self.current_window_id());
+        false
+    }
+
+    fn neighbor_window(&self, direction: char) -> Option<usize> {
+        let layout = self.window_layout(self.window_area);
+        let current = self.current_window_id();
+        let (_, cur) = *layout.panes.iter().find(|(id, _)| *id == current)?;
+        let cursor_y = cur.y + (self.cursor_row.saturating_sub(self.scroll_row) as u16).min(cur.height);
+        let cursor_x = cur.x;
+        layout
+            .panes
+            .iter()
+            .filter(|(id, rect)| {
+                *id != current
+                    && match direction {
+                        'h' => rect.right() <= cur.x && overlaps(rect.y, rect.bottom(), cur.y, cur.bottom()),
+                        'l' => rect.x >= cur.right() && overlaps(rect.y, rect.bottom(), cur.y, cur.bottom()),
+                        'k' => rect.bottom() <= cur.y && overlaps(rect.x, rect.right(), cur.x, cur.right()),
+                        _ => rect.y >= cur.bottom() && overlaps(rect.x, rect.right(), cur.x, cur.right()),
+                    }
+            })
+            .min_by_key(|(_, rect)| {
+                let distance = match direction {
+                    'h' => cur.x - rect.right(),
+                    'l' => rect.x - cur.right(),
+                    'k' => cur.y - rect.bottom(),
+                    _ => rect.y - cur.bottom(),
+                };
+                let aligned = match direction {
+                    'h' | 'l' => rect.y <= cursor_y && cursor_y < rect.bottom(),
+                    _ => rect.x <= cursor_x && cursor_x < rect.right(),
+                };
+                (distance, !aligned)
+            })
+            .map(|(id, _)| *id)
+    }
+
+    pub(super) fn window_command(&mut self, ch: char, count: usize) -> Result<bool> {
+        let ids = self.tabs[self.current_tab].layout.window_ids();
+        let current = self.current_window_id();
+        let pos = ids.iter().position(|id| *id == current).unwrap_or(0);
+        if self.explorer_focused() {
+            if matches!(ch, 'l' | 'w' | 'W' | 'p') {
+                self.focus_explorer(false);
+            } else if ch == 'c' || ch == 'q' {
+                self.explorer = None;
+            }
+            return Ok(false);
+        }
+        match ch {
+            'h' | 'j' | 'k' | 'l' => {
+                for _ in 0..count.max(1) {
+                    match self.neighbor_window(ch) {
+                        Some(id) => self.enter_window(id),
+                        None if ch == 'h' => {
+                            self.focus_explorer(true);
+                            break;
+                        }
+                        None => break,
+                    }
+                }
+            }
+            'w' | 'W' => {
+                let target = match (count, ch) {
+                    (0, 'w') => (pos + 1) % ids.len(),
+                    (0, _) => (pos + ids.len() - 1) % ids.len(),
+                    (n, _) => (n - 1).min(ids.len() - 1),
+                };
+                self.enter_window(ids[target]);
+            }
+            't' => self.enter_window(ids[0]),
+            'b' => self.enter_window(ids[ids.len() - 1]),
+            'p' => match self.tabs[self.current_tab].previous {
+                Some(prev) if ids.contains(&prev) => self.enter_window(prev),
+                _ => self.set_status("No previous window"),
+            },
+            's' | 'S' => self.split_window(SplitDir::Horizontal, None, false),
+            'v' => self.split_window(SplitDir::Vertical, None, false),
+            'n' => self.split_window(SplitDir::Horizontal, None, true),
+            'c' => self.close_window(current),
+            'q' => return self.run_ex_command("quit"),
+            'o' => self.only_window(),
+            '=' => self.tabs[self.current_tab].layout.equalize(),
+            '+' => self.resize_window(SplitDir::Horizontal, &|size| size as i32 + count.max(1) as i32),
+            '-' => self.resize_window(SplitDir::Horizontal, &|size| size as i32 - count.max(1) as i32),
+            '>' => self.resize_window(SplitDir::Vertical, &|size| size as i32 + count.max(1) as i32),
+            '<' => self.resize_window(SplitDir::Vertical, &|size| size as i32 - count.max(1) as i32),
+            '_' => {
+                let size = if count == 0 { i32::MAX } else { count as i32 + 1 };
+                self.resize_window(SplitDir::Horizontal, &|_| size);
+            }
+            '|' => {
+                let size = if count == 0 { i32::MAX } else { count as i32 };
+                self.resize_window(SplitDir::Vertical, &|_| size);
+            }
+            _ => self.set_status(format!("Unknown window command: {}", ch)),
+        }
+        Ok(false)
+    }
+
+    pub(super) fn resize_window(&mut self, dir: SplitDir, size_for: &dyn Fn(u16) -> i32) {
+        let current = self.current_window_id();
+        let layout = &mut self.tabs[self.current_tab].layout;
+        layout.normalize(self.window_area);
+        layout.resize(current, dir, size_for);
+    }
+
+    pub(super) fn resize_command(&mut self, dir: SplitDir, arg: Option<&str>) {
+        let status_row = if dir == SplitDir::Horizontal { 1 } else { 0 };
+        let parsed = match arg {
+            None => Some(i32::MAX),
+            Some(arg) => arg.parse::<i32>().ok(),
+        };
+        let Some(value) = parsed else {
+            self.set_status("Usage: :resize [+-]N");
+            return;
+        };
+        match arg {
+            Some(arg) if arg.starts_with(['+', '-']) => {
+                self.resize_window(dir, &|size| size as i32 + value)
+            }
+            _ => self.resize_window(dir, &|_| value.saturating_add(status_row)),
+        }
+    }
+
+    pub(crate) fn window_text(&self, buffer_id: usize) -> Option<&TextBuffer> {
+        if buffer_id == self.current_buffer_id {
+            return Some(&self.text);
+        }
+        self.buffers
+            .iter()
+            .find(|slot| slot.id == buffer_id)
+            .map(|slot| &slot.state.text)
+    }
+
+    pub(crate) fn window_label(&self, buffer_id: usize) -> String {
+        if self.is_quickfix_buffer(buffer_id) {
+            return "[Quickfix List]".to_string();
+        }
+        let (path, dirty) = if buffer_id == self.current_buffer_id {
+            (&self.file_path, self.dirty)
+        } else {
+            match self.buffers.iter().find(|slot| slot.id == buffer_id) {
+                Some(slot) => (&slot.state.file_path, slot.state.dirty),
+                None => return "[No Name]".to_string(),
+            }
+        };
+        let name = App::buffer_display_name(path);
+        if dirty { format!("{} [+]", name) } else { name }
+    }
+
+    pub(crate) fn prepare_window(&mut self, id: usize, rows: usize, cols: usize) -> Option<Window> {
+        let idx = self.windows.iter().position(|window| window.id == id)?;
+        self.follow_buffer_edits(idx);
+        let window = self.windows[idx].clone();
+        let text = self.window_text(window.buffer_id)?;
+        let cursor_row = window.cursor_row.min(text.line_count().saturating_sub(1));
+        let line = text.line(cursor_row).unwrap_or_default();
+        let cursor_col = window.cursor_col.min(line.chars().count());
+        let mut scroll_row = window.scroll_row.min(cursor_row);
+        if cursor_row >= scroll_row + rows {
+            scroll_row = cursor_row + 1 - rows;
+        }
+        let cursor_screen = char_to_screen_col(&line, cursor_col, self.shift_width);
+        let mut scroll_col = window.scroll_col;
+        let scroll_screen = char_to_screen_col(&line, scroll_col, self.shift_width);
+        if cursor_screen < scroll_screen {
+            scroll_col = cursor_col;
+        } else if cursor_screen >= scroll_screen + cols {
+            let target = cursor_screen + 1 - cols;
+            scroll_col = screen_col_to_char_idx(&line, target, self.shift_width);
+        }
+        let window = &mut self.windows[idx];
+        window.cursor_row = cursor_row;
+        window.cursor_col = cursor_col;
+        window.scroll_row = scroll_row;
+        window.scroll_col = scroll_col;
+        Some(window.clone())
+    }
+
+    pub(super) fn forget_buffer_windows(&mut self, buffer_id: usize) {
+        let replacement = self.current_buffer_id;
+        for window in self.windows.iter_mut() {
+            if window.buffer_id == buffer_id {
+                *window = Window::new(window.id, replacement);
+            }
+        }
+    }
+
+    pub(super) fn new_tab(&mut self, path: Option<PathBuf>) {
+        self.sync_current_window();
+        let id = self.next_window_id;
+        self.next_window_id += 1;
+        self.windows.push(Window::new(id, self.current_buffer_id));
+        self.current_tab += 1;
+        self.tabs.insert(self.current_tab, TabPage::new(id));
+        match path {
+            Some(path) => self.open_or_switch_buffer(path, None),
+            None => self.open_empty_buffer(),
+        }
+    }
+
+    pub(super) fn goto_tab(&mut self, idx: usize) {
+        if idx >= self.tabs.len() {
+            self.set_status(format!("No tab page {}", idx + 1));
+            return;
+        }
+        if idx == self.current_tab {
+            return;
+        }
+        self.sync_current_window();
+        self.current_tab = idx;
+        self.load_window(self.tabs[idx].current);
+    }
+
+    pub(super) fn next_tab(&mut self, count: usize, forward: bool) {
+        let len = self.tabs.len();
+        let target = match (count, forward) {
+            (0, true) => (self.current_tab + 1) % len,
+            (n, true) => n - 1,
+            (n, false) => (self.current_tab + len - n.max(1) % len) % len,
+        };
+        self.goto_tab(target);
+    }
+
+    pub(super) fn close_tab(&mut self, idx: usize) {
+        if self.tabs.len() == 1 {
+            self.set_status("Cannot close last tab page");
+            return;
+        }
+        self.sync_current_window();
+        let tab = self.tabs.remove(idx);
+        let closing = tab.layout.window_ids();
+        self.windows.retain(|window| !closing.contains(&window.id));
+        if idx < self.current_tab {
+            self.current_tab -= 1;
+        } else if idx == self.current_tab {
+            self.current_tab = idx.min(self.tabs.len() - 1);
+            self.load_window(self.tabs[self.current_tab].current);
+        }
+    }
+
+    pub(super) fn only_tab(&mut self) {
+        let current = self.tabs.remove(self.current_tab);
+        let keep = current.layout.window_ids();
+        self.windows.retain(|window| keep.contains(&window.id));
+        self.tabs = vec![current];
+        self.current_tab = 0;
+    }
+
+    pub(crate) fn tab_labels(&self) -> Vec<String> {
+        self.tabs
+            .iter()
+            .enumerate()
+            .map(|(idx, tab)| {
+                let buffer_id = if idx == self.current_tab {
+                    self.current_buffer_id
+                } else {
+                    self.windows
+                        .iter()
+                        .find(|window| window.id == tab.current)
+                        .map(|window| window.buffer_id)
+                        .unwrap_or(self.current_buffer_id)
+                };
+                let count = tab.layout.window_ids().len();
+                let label = self.window_label(buffer_id);
+                if count > 1 {
+                    format!("{} {}", count, label)
+                } else {
+                    label
+                }
+            })
+            .collect()
+    }
+
+    pub(super) fn tab_list_lines(&mut self) -> Vec<String> {
+        self.sync_current_window();
+        let mut lines = Vec::new();
+        for (idx, tab) in self.tabs.iter().enumerate() {
+            lines.push(format!("Tab page {}", idx + 1));
+            for id in tab.layout.window_ids() {
+                let marker = if idx == self.current_tab && id == tab.current { '>' } else { ' ' };
+                let buffer_id = self
+                    .windows
+                    .iter()
+                    .find(|window| window.id == id)
+                    .map(|window| window.buffer_id)
+                    .unwrap_or(self.current_buffer_id);
+                lines.push(format!("{}   {}", marker, self.window_label(buffer_id)));
+            }
+        }
+        lines
+    }
+}
+
+fn overlaps(a_start: u16, a_end: u16, b_start: u16, b_end: u16) -> bool {
+    a_start < b_end && b_start < a_end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::handle_key;
+    use crossterm::event::KeyEvent;
+
+    use super::super::keymap::parse_key_sequence;
+
+    fn feed(app: &mut App, keys: &str) {
+        for key in parse_key_sequence(keys).unwrap() {
+            handle_key(app, KeyEvent::new(key.code, key.mods)).unwrap();
+        }
+    }
+
+    #[test]
+    fn layout_splits_and_collapses() {
+        let mut layout = LayoutNode::Leaf(1);
+        assert!(layout.split(1, 2, SplitDir::Vertical));
+        assert!(layout.split(2, 3, SplitDir::Horizontal));
+        let mut out = WindowLayout::default();
+        layout.rects(Rect::new(0, 0, 81, 20), &mut out);
+        assert_eq!(
+            out.panes,
+            vec![
+                (3, Rect::new(0, 0, 40, 10)),
+                (2, Rect::new(0, 10, 40, 10)),
+                (1, Rect::new(41, 0, 40, 20)),
+            ]
+        );
+        assert_eq!(out.separators, vec![Rect::new(40, 0, 1, 20)]);
+        layout.normalize(Rect::new(0, 0, 81, 20));
+        assert!(layout.resize(3, SplitDir::Horizontal, &|size| size as i32 + 3));
+        let mut out = WindowLayout::default();
+        layout.rects(Rect::new(0, 0, 81, 20), &mut out);
+        assert_eq!(out.panes[0].1.height, 13);
+        assert!(layout.remove(2));
+        assert!(layout.remove(3));
+        assert_eq!(layout, LayoutNode::Leaf(1));
+    }
+
+    #[test]
+    fn windows_share_buffer_and_keep_own_cursor() {
+        let mut app = App::new(None, "one\ntwo\nthree".to_string());
+        feed(&mut app, ":vsplit<Enter>Gx<C-w>l");
+        assert_eq!(app.window_count(), 2);
+        assert_eq!(app.cursor_row, 0);
+        assert_eq!(app.text.line(2).unwrap(), "hree");
+        feed(&mut app, "<C-w>h");
+        assert_eq!(app.cursor_row, 2);
+
+        feed(&mut app, ":tabnew<Enter>");
+        assert_eq!((app.tabs.len(), app.current_tab), (2, 1));
+        assert_eq!(app.text.to_string(), "");
+        feed(&mut app, "gt");
+        assert_eq!(app.current_tab, 0);
+        assert_eq!((app.cursor_row, app.text.line(2).unwrap()), (2, "hree".to_string()));
+        feed(&mut app, "<C-w>o:q<Enter>");
+        assert_eq!(app.window_count(), 1);
+        assert_eq!(app.tabs.len(), 1);
+        assert_eq!(app.text.to_string(), "");
+    }
+
+    #[test]
+    fn other_windows_follow_edits_to_their_buffer() {
+        let text: String = (0..40).map(|idx| format!("line {}\n", idx)).collect();
+        let mut app = App::new(None, text);
+        feed(&mut app, ":split<Enter>30G<C-w>j");
+        let other = app.tabs[0].previous.unwrap();
+        app.windows.iter_mut().find(|window| window.id == other).unwrap().scroll_row = 25;
+        feed(&mut app, "ggOa<Enter>b<Enter>c<Esc>");
+        let window = app.prepare_window(other, 10, 80).unwrap();
+        assert_eq!((window.cursor_row, window.scroll_row), (32, 28));
+
+        feed(&mut app, "5Gdd<C-w>k");
+        assert_eq!(app.cursor_row, 31);
+        assert_eq!(app.text.line(app.cursor_row).unwrap(), "line 29");
+        assert_eq!(app.scroll_row, 27);
+    }
+}
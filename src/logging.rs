@@ -1,5 +1,22 @@
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
 use chrono::Local;
 
 pub fn timestamp_prefix() -> String {
     Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
 }
+
+pub fn append_log(message: &str) {
+    let Some(home) = std::env::var_os("HOME") else {
+        return;
+    };
+    let mut path = PathBuf::from(home);
+    path.push(".config/rvim");
+    let _ = fs::create_dir_all(&path);
+    path.push("rvim.log");
+    if let Ok(mut file) = fs::OpenOptions::new().create(true).append(true).open(path) {
+        let _ = writeln!(file, "{} {}", timestamp_prefix(), message);
+    }
+}
@@ -14,8 +14,8 @@ use crossterm::terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScree
 use crossterm::{execute, event::EnableBracketedPaste, event::DisableBracketedPaste};
 use ratatui::prelude::*;
 
-use crate::app::{handle_key, load_config, App, Mode};
-use crate::logging::timestamp_prefix;
+use crate::app::{handle_key, load_config, recoverable_swap_lines, App, Mode};
+use crate::logging::{append_log, timestamp_prefix};
 use crate::ui::apply_cursor_style;
 
 struct TerminalGuard;
@@ -37,20 +37,33 @@ impl Drop for TerminalGuard {
 
 fn main() -> Result<()> {
     install_panic_logger();
-    let path = std::env::args().nth(1).map(PathBuf::from);
-    let content = match &path {
-        Some(p) => fs::read_to_string(p).unwrap_or_default(),
-        None => String::new(),
-    };
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let recover = args.first().is_some_and(|arg| arg == "-r");
+    if recover {
+        args.remove(0);
+    }
+    let path = args.first().map(PathBuf::from);
+    if recover && path.is_none() {
+        let cfg = load_config().unwrap_or_default();
+        for line in recoverable_swap_lines(&cfg) {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
 
     let _guard = TerminalGuard::enter()?;
     let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
     terminal.clear()?;
 
-    let mut app = App::new(path, content);
+    let mut app = App::new(path, String::new());
     if let Ok(cfg) = load_config() {
         app.apply_config(&cfg);
     }
+    app.load_plugins();
+    app.open_current_file(None);
+    if recover {
+        app.recover_swap_file();
+    }
     apply_cursor_style(&app)?;
 
     loop {
@@ -74,14 +87,22 @@ fn main() -> Result<()> {
                         app.clear_completion();
                         app.search_history_index = None;
                         app.command_history_index = None;
+                        app.update_incsearch();
                     }
                 }
                 _ => {}
             }
             apply_cursor_style(&app)?;
         }
+        app.maybe_write_swap(false);
+        app.poll_lsp();
+        app.poll_file_picker();
+        app.poll_quickfix();
+        app.sync_explorer();
     }
 
+    app.shutdown_lsp();
+    app.remove_swap_files();
     Ok(())
 }
 
@@ -100,19 +121,6 @@ fn install_panic_logger() {
     }));
 }
 
-fn append_log(message: &str) {
-    let Some(home) = std::env::var_os("HOME") else {
-        return;
-    };
-    let mut path = PathBuf::from(home);
-    path.push(".config/rvim");
-    let _ = fs::create_dir_all(&path);
-    path.push("rvim.log");
-    if let Ok(mut file) = fs::OpenOptions::new().create(true).append(true).open(path) {
-        let _ = writeln!(file, "{} error: {}", timestamp_prefix(), message);
-    }
-}
-
 fn with_error_logging<T>(
     app: &mut App,
     result: Result<T>,
@@ -121,7 +129,7 @@ fn with_error_logging<T>(
     match result {
         Ok(value) => Ok(value),
         Err(err) => {
-            append_log(&format!("{}: {}", context, err));
+            append_log(&format!("error: {}: {}", context, err));
             app.set_status(format!("{}: {}", context, err));
             Err(err)
         }
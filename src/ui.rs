@@ -9,7 +9,9 @@ use ratatui::prelude::*;
 use ratatui::widgets::{Block, Borders, Clear, Paragraph};
 
 use crate::app::{App, CommandPrompt, HighlightKind, Mode, SyntaxSpan, VisualSelection, VisualSelectionKind, total_spans};
-use crate::app::{char_display_width, char_to_screen_col, line_screen_width};
+use crate::app::{char_display_width, char_to_screen_col, line_screen_width, SearchPattern};
+
+const EXPLORER_WIDTH: u16 = 30;
 
 pub fn apply_cursor_style(app: &App) -> Result<()> {
     match app.mode {
@@ -27,29 +29,89 @@ pub fn ui(f: &mut Frame<'_>, app: &mut App) {
     let perf_start = if app.perf_enabled { Some(Instant::now()) } else { None };
     let perf_highlight_start = if app.perf_enabled { Some(Instant::now()) } else { None };
     let size = f.area();
+    let tabline_height = if app.tabs.len() > 1 { 1 } else { 0 };
     let rows = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([Constraint::Min(1), Constraint::Length(1), Constraint::Length(1)])
+        .constraints([
+            Constraint::Length(tabline_height),
+            Constraint::Min(1),
+            Constraint::Length(1),
+        ])
         .split(size);
 
-    let main_area = rows[0];
-    let status_area = rows[1];
+    let mut layout_area = rows[1];
     let message_area = rows[2];
+    if tabline_height > 0 {
+        render_tabline(f, app, rows[0]);
+    }
+    let mut explorer_area = None;
+    if app.explorer.is_some() && layout_area.width > 20 {
+        let width = EXPLORER_WIDTH.min(layout_area.width / 3);
+        explorer_area = Some(Rect { width, ..layout_area });
+        let separator = Rect {
+            x: layout_area.x + width,
+            width: 1,
+            ..layout_area
+        };
+        let bar = vec![Line::from("│"); separator.height as usize];
+        f.render_widget(
+            Paragraph::new(bar).style(Style::default().fg(app.theme.line_number_fg)),
+            separator,
+        );
+        layout_area.x += width + 1;
+        layout_area.width -= width + 1;
+    }
+
+    app.window_area = layout_area;
+    app.sync_current_window();
+    let layout = app.window_layout(layout_area);
+    let current_window = app.current_window_id();
+    for &(id, area) in &layout.panes {
+        if id != current_window {
+            render_window(f, app, id, area);
+        }
+    }
+    for &area in &layout.separators {
+        let bar = vec![Line::from("│"); area.height as usize];
+        f.render_widget(
+            Paragraph::new(bar).style(Style::default().fg(app.theme.line_number_fg)),
+            area,
+        );
+    }
+    let pane = layout
+        .panes
+        .iter()
+        .find(|(id, _)| *id == current_window)
+        .map(|(_, area)| *area)
+        .unwrap_or(layout_area);
+    let main_area = Rect {
+        height: pane.height.saturating_sub(1),
+        ..pane
+    };
+    let status_area = Rect {
+        y: main_area.bottom(),
+        height: pane.height.min(1),
+        ..pane
+    };
 
     let viewport_rows = main_area.height as usize;
     if app.perf_enabled {
         app.push_perf_line_sample(viewport_rows);
     }
-    let line_count = app.lines.len().max(1);
-    let gutter_width = line_count.to_string().len() + 1;
+    let line_count = app.text.line_count().max(1);
+    let signs = app.diagnostic_rows();
+    let sign_width = if signs.is_some() { 2 } else { 0 };
+    let gutter_width = line_count.to_string().len() + 1 + sign_width;
     let viewport_cols = main_area
         .width
         .saturating_sub(gutter_width as u16)
         .max(1) as usize;
-    app.ensure_cursor_visible(viewport_rows, viewport_cols);
+    app.ensure_cursor_visible(viewport_rows.max(1), viewport_cols);
 
     let mut text_lines: Vec<Line> = Vec::with_capacity(viewport_rows);
-    let selection = app.visual_selection();
+    let selection = app
+        .visual_selection()
+        .or_else(|| app.substitute_match_selection());
     let syntax = if app.syntax_enabled {
         app.syntax_spans_for_viewport(app.scroll_row, viewport_rows)
     } else {
@@ -59,23 +121,25 @@ pub fn ui(f: &mut Frame<'_>, app: &mut App) {
         app.push_perf_highlight_sample(start.elapsed().as_micros());
     }
     let debug_syntax = std::env::var("RVIM_DEBUG_SYNTAX").ok().as_deref() == Some("1");
+    let search_pattern = app.search_highlight();
     for i in 0..viewport_rows {
         let idx = app.scroll_row + i;
-        if let Some(line) = app.lines.get(idx) {
-            let scroll_screen = char_to_screen_col(line, app.scroll_col, app.shift_width);
+        if let Some(line) = app.text.line(idx) {
+            let scroll_screen = char_to_screen_col(&line, app.scroll_col, app.shift_width);
             let syntax_spans = syntax.as_ref().and_then(|m| m.get(&idx)).map(|v| v.as_slice());
             text_lines.push(render_line_with_selection(
-                line,
+                &line,
                 idx,
                 scroll_screen,
                 viewport_cols,
                 selection,
                 syntax_spans,
-                app.last_search.as_ref().map(|s| s.pattern.as_str()),
+                search_pattern.as_ref(),
                 gutter_width,
                 idx == app.cursor_row,
                 app.relative_number,
                 app.cursor_row,
+                signs.as_ref().map(|rows| diagnostic_sign(app, rows.get(&idx).copied())),
                 app,
             ));
         } else {
@@ -97,27 +161,43 @@ pub fn ui(f: &mut Frame<'_>, app: &mut App) {
         Mode::VisualLine => "VISUAL LINE",
         Mode::VisualBlock => "VISUAL BLOCK",
     };
-    let file_label = app
-        .file_path
-        .as_ref()
-        .map(|p| p.display().to_string())
-        .unwrap_or_else(|| "[No Name]".to_string());
+    let file_label = if app.is_quickfix_buffer(app.current_buffer_id) {
+        "[Quickfix List]".to_string()
+    } else {
+        app.file_path
+            .as_ref()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|| "[No Name]".to_string())
+    };
     let dirty = if app.dirty { " [+]" } else { "" };
+    let read_only = if app.read_only { " [RO]" } else { "" };
     let mut status = format!(
-        "{} | {}{} | buf:{}/{} | {}:{}",
+        "{} | {}{}{} | buf:{}/{} | {}:{}",
         mode_label,
         file_label,
         dirty,
+        read_only,
         app.current_buffer_id,
         app.buffer_count(),
         app.cursor_row + 1,
         app.cursor_col + 1
     );
+    if let Some(count) = app.search_count_label() {
+        status.push_str(&format!(" | {}", count));
+    }
+    if let Some(name) = app.macro_recording {
+        status.push_str(&format!(" | recording @{}", name.to_ascii_lowercase()));
+    }
+    status.push_str(&format!(" | {}", app.file_options.describe()));
     status.push_str(&format!(" | undo:{} redo:{}", app.undo_len(), app.redo_len()));
     status.push_str(&format!(" | theme:{}", app.theme_name));
     if app.mode == Mode::Command && app.command_buffer.starts_with("set theme=") {
         status.push_str(" | themes: light dark solarized");
     }
+    for item in app.plugin_status_items() {
+        status.push_str(" | ");
+        status.push_str(&item);
+    }
     if debug_syntax {
         status.push_str(&format!(" | {} spans:{}", app.syntax_debug_summary(), total_spans(&syntax)));
     }
@@ -129,12 +209,11 @@ pub fn ui(f: &mut Frame<'_>, app: &mut App) {
         let idx = app.completion_index.unwrap_or(0) + 1;
         status.push_str(&format!(" | tab:{}/{}", idx, total));
     }
-    if matches!(app.mode, Mode::VisualChar | Mode::VisualLine | Mode::VisualBlock) {
-        if let Some(summary) = app.selection_summary() {
+    if matches!(app.mode, Mode::VisualChar | Mode::VisualLine | Mode::VisualBlock)
+        && let Some(summary) = app.selection_summary() {
             status.push_str(" | ");
             status.push_str(&summary);
         }
-    }
     if let Some(start) = perf_start {
         let micros = start.elapsed().as_micros();
         status.push_str(&format!(" | render:{}us", micros));
@@ -149,13 +228,19 @@ pub fn ui(f: &mut Frame<'_>, app: &mut App) {
     );
     f.render_widget(status_paragraph, status_area);
 
-    let message = if app.mode == Mode::Command {
+    let message = if let Some(prompt) = app.explorer_prompt_line() {
+        Paragraph::new(prompt)
+    } else if app.mode == Mode::Command {
         let prefix = match app.command_prompt {
             CommandPrompt::Command => ':',
             CommandPrompt::SearchForward => '/',
             CommandPrompt::SearchBackward => '?',
         };
         Paragraph::new(format!("{}{}", prefix, app.command_buffer))
+    } else if app.status_message.is_empty()
+        && let Some(diagnostic) = app.cursor_diagnostic()
+    {
+        Paragraph::new(diagnostic).style(Style::default().fg(app.theme.line_number_fg))
     } else {
         Paragraph::new(app.status_message.clone())
     };
@@ -165,7 +250,14 @@ pub fn ui(f: &mut Frame<'_>, app: &mut App) {
         && app.command_prompt == CommandPrompt::Command
         && !app.completion_candidates.is_empty()
     {
-        render_completion_popover(f, app, main_area, message_area);
+        render_completion_popover(f, app, layout_area, message_area);
+    }
+
+    if app.mode == Mode::Insert
+        && !app.completion_candidates.is_empty()
+        && let Some(anchor) = app.insert_completion_anchor()
+    {
+        render_insert_completion(f, app, anchor, main_area, gutter_width as u16);
     }
 
     if app.mode == Mode::Command {
@@ -175,9 +267,9 @@ pub fn ui(f: &mut Frame<'_>, app: &mut App) {
             f.set_cursor_position(Position::new(cursor_x, cursor_y));
         }
     } else {
-        let line = app.lines.get(app.cursor_row).map(|s| s.as_str()).unwrap_or("");
-        let cursor_screen = char_to_screen_col(line, app.cursor_col, app.shift_width);
-        let scroll_screen = char_to_screen_col(line, app.scroll_col, app.shift_width);
+        let line = app.text.line(app.cursor_row).unwrap_or_default();
+        let cursor_screen = char_to_screen_col(&line, app.cursor_col, app.shift_width);
+        let scroll_screen = char_to_screen_col(&line, app.scroll_col, app.shift_width);
         let cursor_x = cursor_screen.saturating_sub(scroll_screen) as u16
             + main_area.x
             + gutter_width as u16;
@@ -186,6 +278,272 @@ pub fn ui(f: &mut Frame<'_>, app: &mut App) {
             f.set_cursor_position(Position::new(cursor_x, cursor_y));
         }
     }
+
+    if let Some(area) = explorer_area {
+        render_explorer(f, app, area, message_area);
+    }
+
+    if app.file_picker.is_some() {
+        render_file_picker(f, app, size);
+    }
+}
+
+fn render_explorer(f: &mut Frame<'_>, app: &mut App, area: Rect, message_area: Rect) {
+    let prompt = app.explorer_prompt_line();
+    let command_mode = app.mode == Mode::Command;
+    let Some(explorer) = &mut app.explorer else {
+        return;
+    };
+    let rows = area.height.saturating_sub(1) as usize;
+    if explorer.selected < explorer.scroll {
+        explorer.scroll = explorer.selected;
+    } else if rows > 0 && explorer.selected >= explorer.scroll + rows {
+        explorer.scroll = explorer.selected + 1 - rows;
+    }
+    let Some(explorer) = app.explorer.as_ref() else {
+        return;
+    };
+    let width = area.width as usize;
+    let root = explorer
+        .root
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| explorer.root.display().to_string());
+    let header: String = format!(" {}/", root).chars().take(width).collect();
+    let mut lines = vec![Line::from(Span::styled(
+        format!("{:width$}", header, width = width),
+        Style::default().fg(app.theme.status_fg).bg(app.theme.status_bg),
+    ))];
+    for (idx, entry) in explorer.entries.iter().enumerate().skip(explorer.scroll).take(rows) {
+        let marker = match (entry.is_dir, explorer.is_expanded(&entry.path)) {
+            (true, true) => "▾ ",
+            (true, false) => "▸ ",
+            _ => "  ",
+        };
+        let suffix = if entry.is_dir { "/" } else { "" };
+        let text: String = format!(" {}{}{}{}", "  ".repeat(entry.depth), marker, entry.name, suffix)
+            .chars()
+            .take(width)
+            .collect();
+        let mut style = if entry.ignored {
+            Style::default().fg(app.theme.line_number_fg)
+        } else if entry.is_dir {
+            Style::default().fg(app.theme.syntax_type)
+        } else {
+            Style::default()
+        };
+        if idx == explorer.selected {
+            style = if explorer.focused {
+                style.fg(app.theme.selection_fg).bg(app.theme.selection_bg)
+            } else {
+                style.bg(app.theme.current_line_bg)
+            };
+        }
+        lines.push(Line::from(Span::styled(format!("{:width$}", text, width = width), style)));
+    }
+    f.render_widget(Clear, area);
+    f.render_widget(Paragraph::new(lines), area);
+
+    if let Some(prompt) = prompt {
+        let cursor_x = message_area.x + prompt.chars().count() as u16;
+        if cursor_x < message_area.right() {
+            f.set_cursor_position(Position::new(cursor_x, message_area.y));
+        }
+    } else if explorer.focused && !command_mode {
+        let cursor_y = area.y + 1 + explorer.selected.saturating_sub(explorer.scroll) as u16;
+        if cursor_y < area.bottom() {
+            f.set_cursor_position(Position::new(area.x, cursor_y));
+        }
+    }
+}
+
+fn render_file_picker(f: &mut Frame<'_>, app: &App, size: Rect) {
+    let Some(picker) = &app.file_picker else {
+        return;
+    };
+    let width = (size.width * 9 / 10).max(20).min(size.width);
+    let height = (size.height * 4 / 5).max(5).min(size.height);
+    let area = Rect {
+        x: size.x + (size.width - width) / 2,
+        y: size.y + (size.height - height) / 2,
+        width,
+        height,
+    };
+    f.render_widget(Clear, area);
+    let panes = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
+        .split(area);
+
+    let scanning = if picker.scanning { "…" } else { "" };
+    let list_block = Block::default().borders(Borders::ALL).title(format!(
+        " Files {}/{}{} ",
+        picker.matches.len(),
+        picker.files.len(),
+        scanning
+    ));
+    let list_area = list_block.inner(panes[0]);
+    f.render_widget(list_block, panes[0]);
+    let rows = list_area.height.saturating_sub(1) as usize;
+    let window_start = picker.selected.saturating_sub(rows.saturating_sub(1));
+    let mut lines = vec![Line::from(format!("> {}", picker.query))];
+    for (idx, (_, file)) in picker.matches.iter().enumerate().skip(window_start).take(rows) {
+        let file = &picker.files[*file];
+        let text: String = format!(" {}", file).chars().take(list_area.width as usize).collect();
+        if idx == picker.selected {
+            let pad = (list_area.width as usize).saturating_sub(text.chars().count());
+            lines.push(Line::from(Span::styled(
+                format!("{}{}", text, " ".repeat(pad)),
+                Style::default()
+                    .fg(app.theme.selection_fg)
+                    .bg(app.theme.selection_bg),
+            )));
+        } else {
+            lines.push(Line::from(text));
+        }
+    }
+    f.render_widget(Paragraph::new(lines), list_area);
+    let cursor_x = list_area.x + 2 + picker.query.chars().count() as u16;
+    if cursor_x < list_area.right() {
+        f.set_cursor_position(Position::new(cursor_x, list_area.y));
+    }
+
+    let title = picker
+        .preview
+        .as_ref()
+        .map(|preview| format!(" {} ", preview.file))
+        .unwrap_or_default();
+    let preview_block = Block::default().borders(Borders::ALL).title(title);
+    let preview_area = preview_block.inner(panes[1]);
+    f.render_widget(preview_block, panes[1]);
+    let Some(preview) = &picker.preview else {
+        return;
+    };
+    let gutter_width = preview.lines.len().max(1).to_string().len() + 1;
+    let cols = (preview_area.width as usize).saturating_sub(gutter_width).max(1);
+    let lines: Vec<Line> = preview
+        .lines
+        .iter()
+        .take(preview_area.height as usize)
+        .enumerate()
+        .map(|(idx, line)| {
+            render_line_with_selection(
+                line,
+                idx,
+                0,
+                cols,
+                None,
+                preview.spans.get(&idx).map(|v| v.as_slice()),
+                None,
+                gutter_width,
+                false,
+                false,
+                0,
+                None,
+                app,
+            )
+        })
+        .collect();
+    f.render_widget(Paragraph::new(lines), preview_area);
+}
+
+fn render_tabline(f: &mut Frame<'_>, app: &App, area: Rect) {
+    let mut spans = Vec::new();
+    for (idx, label) in app.tab_labels().into_iter().enumerate() {
+        let style = if idx == app.current_tab {
+            Style::default()
+                .fg(app.theme.status_fg)
+                .bg(app.theme.status_bg)
+        } else {
+            Style::default().bg(app.theme.current_line_bg)
+        };
+        spans.push(Span::styled(format!(" {} {} ", idx + 1, label), style));
+    }
+    let tabline = Paragraph::new(Line::from(spans))
+        .style(Style::default().bg(app.theme.current_line_bg));
+    f.render_widget(tabline, area);
+}
+
+fn render_window(f: &mut Frame<'_>, app: &mut App, id: usize, area: Rect) {
+    let text_area = Rect {
+        height: area.height.saturating_sub(1),
+        ..area
+    };
+    let status_area = Rect {
+        y: text_area.bottom(),
+        height: area.height.min(1),
+        ..area
+    };
+    let Some(buffer_id) = app
+        .windows
+        .iter()
+        .find(|window| window.id == id)
+        .map(|window| window.buffer_id)
+    else {
+        return;
+    };
+    let line_count = app
+        .window_text(buffer_id)
+        .map(|text| text.line_count())
+        .unwrap_or(1)
+        .max(1);
+    let gutter_width = line_count.to_string().len() + 1;
+    let rows = text_area.height as usize;
+    let cols = text_area
+        .width
+        .saturating_sub(gutter_width as u16)
+        .max(1) as usize;
+    let Some(window) = app.prepare_window(id, rows.max(1), cols) else {
+        return;
+    };
+    let syntax = if app.syntax_enabled {
+        app.syntax_spans_for_buffer(buffer_id, window.scroll_row, rows)
+    } else {
+        None
+    };
+    let search_pattern = app.search_highlight();
+    let app: &App = app;
+    let Some(text) = app.window_text(buffer_id) else {
+        return;
+    };
+    let mut lines: Vec<Line> = Vec::with_capacity(rows);
+    for i in 0..rows {
+        let idx = window.scroll_row + i;
+        if let Some(line) = text.line(idx) {
+            let scroll_screen = char_to_screen_col(&line, window.scroll_col, app.shift_width);
+            let syntax_spans = syntax.as_ref().and_then(|m| m.get(&idx)).map(|v| v.as_slice());
+            lines.push(render_line_with_selection(
+                &line,
+                idx,
+                scroll_screen,
+                cols,
+                None,
+                syntax_spans,
+                search_pattern.as_ref(),
+                gutter_width,
+                false,
+                app.relative_number,
+                window.cursor_row,
+                None,
+                app,
+            ));
+        } else {
+            lines.push(render_empty_line(gutter_width));
+        }
+    }
+    f.render_widget(Paragraph::new(lines), text_area);
+    let status = format!(
+        "{} | {}:{}",
+        app.window_label(buffer_id),
+        window.cursor_row + 1,
+        window.cursor_col + 1
+    );
+    let status_paragraph = Paragraph::new(status).style(
+        Style::default()
+            .fg(app.theme.line_number_fg)
+            .bg(app.theme.current_line_bg),
+    );
+    f.render_widget(status_paragraph, status_area);
 }
 
 fn render_completion_popover(f: &mut Frame<'_>, app: &App, main_area: Rect, message_area: Rect) {
@@ -209,7 +567,8 @@ fn render_completion_popover(f: &mut Frame<'_>, app: &App, main_area: Rect, mess
     let y = message_area.y.saturating_sub(height).max(main_area.y);
 
     let area = Rect { x, y, width, height };
-    let lines = completion_window(app, &labels, width as usize, height as usize);
+    let selected = Some(app.completion_index.unwrap_or(0));
+    let lines = completion_window(app, &labels, selected, width as usize, height as usize);
     f.render_widget(Clear, area);
     let widget = Paragraph::new(lines).style(
         Style::default()
@@ -219,6 +578,36 @@ fn render_completion_popover(f: &mut Frame<'_>, app: &App, main_area: Rect, mess
     f.render_widget(widget, area);
 }
 
+fn render_insert_completion(
+    f: &mut Frame<'_>,
+    app: &App,
+    (row, col): (usize, usize),
+    main_area: Rect,
+    gutter_width: u16,
+) {
+    let labels = &app.completion_candidates;
+    let max_len = labels.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+    let width = (max_len + 2).min(main_area.width as usize).max(4) as u16;
+    let height = 8.min(labels.len()) as u16;
+    let line = app.text.line(row).unwrap_or_default();
+    let start_screen = char_to_screen_col(&line, col, app.shift_width);
+    let scroll_screen = char_to_screen_col(&line, app.scroll_col, app.shift_width);
+    let x = (main_area.x + gutter_width + start_screen.saturating_sub(scroll_screen) as u16)
+        .min(main_area.right().saturating_sub(width))
+        .max(main_area.x);
+    let cursor_y = main_area.y + row.saturating_sub(app.scroll_row) as u16;
+    let y = if cursor_y + 1 + height <= main_area.bottom() {
+        cursor_y + 1
+    } else {
+        cursor_y.saturating_sub(height).max(main_area.y)
+    };
+    let area = Rect { x, y, width, height: height.min(main_area.height) };
+    let lines = completion_window(app, labels, app.completion_index, width as usize, area.height as usize);
+    f.render_widget(Clear, area);
+    let widget = Paragraph::new(lines).style(Style::default().bg(app.theme.current_line_bg));
+    f.render_widget(widget, area);
+}
+
 fn completion_labels(app: &App) -> Vec<String> {
     let total = app.completion_candidates.len();
     if total == 0 {
@@ -242,6 +631,7 @@ fn completion_max_label_len(app: &App) -> usize {
 fn completion_window(
     app: &App,
     labels: &[String],
+    selected: Option<usize>,
     width: usize,
     window_size: usize,
 ) -> Vec<Line<'static>> {
@@ -251,13 +641,10 @@ fn completion_window(
     let text_width = width.saturating_sub(1);
     let total = labels.len();
     let window = window_size.min(total).max(1);
-    let selected = app.completion_index.unwrap_or(0).min(total.saturating_sub(1));
+    let highlight = selected.is_some();
+    let selected = selected.unwrap_or(0).min(total.saturating_sub(1));
     let anchor = window / 2;
-    let mut window_start = if selected <= anchor {
-        0
-    } else {
-        selected - anchor
-    };
+    let mut window_start = selected.saturating_sub(anchor);
     if window_start + window > total {
         window_start = total - window;
     }
@@ -274,7 +661,7 @@ fn completion_window(
             text = text.chars().take(text_width).collect();
         }
         let bar = if scroll.contains(&i) { '█' } else { ' ' };
-        let line = if i == selected_pos {
+        let line = if highlight && i == selected_pos {
             Line::from(vec![
                 Span::styled(
                     text,
@@ -307,11 +694,10 @@ fn completion_scrollbar(total: usize, window: usize, selected: usize) -> std::op
 
 fn completion_item_label(candidate: &str) -> String {
     let mut s = candidate.to_string();
-    if let Some(quote) = s.chars().next() {
-        if quote == '"' || quote == '\'' {
+    if let Some(quote) = s.chars().next()
+        && (quote == '"' || quote == '\'') {
             s = s[1..].to_string();
         }
-    }
     let unescaped = unescape_display(&s);
     let is_dir = s.ends_with('/');
     let mut base = match unescaped.rfind('/') {
@@ -349,11 +735,10 @@ fn unescape_display(input: &str) -> String {
 
 fn completion_anchor_x(app: &App, message_area: Rect) -> u16 {
     let cmd = app.command_buffer.as_str();
-    if app.completion_anchor_fixed {
-        if let Some(offset) = app.completion_anchor_col {
+    if app.completion_anchor_fixed
+        && let Some(offset) = app.completion_anchor_col {
             return message_area.x + 1 + offset;
         }
-    }
     let path_part = if cmd == "e" {
         ""
     } else if let Some(rest) = cmd.strip_prefix("e ") {
@@ -382,6 +767,7 @@ fn completion_anchor_x(app: &App, message_area: Rect) -> u16 {
     message_area.x + 1 + offset as u16
 }
 
+#[allow(clippy::too_many_arguments)]
 fn render_line_with_selection(
     line: &str,
     line_idx: usize,
@@ -389,15 +775,15 @@ fn render_line_with_selection(
     max_cols: usize,
     selection: Option<VisualSelection>,
     syntax_spans: Option<&[SyntaxSpan]>,
-    search_pattern: Option<&str>,
+    search_pattern: Option<&SearchPattern>,
     gutter_width: usize,
     is_current_line: bool,
     relative_number: bool,
     cursor_row: usize,
+    sign: Option<Span<'static>>,
     app: &App,
 ) -> Line<'static> {
     let mut spans: Vec<Span> = Vec::new();
-    let mut col = 0usize;
     let mut screen_col = 0usize;
     let mut buf = String::new();
     let mut buf_state = 0u8;
@@ -412,9 +798,9 @@ fn render_line_with_selection(
     let block_range = match selection {
         Some(sel) => match sel.kind {
             VisualSelectionKind::Block { start, end } => {
-                let base_line = app.lines.get(start.0).map(|s| s.as_str()).unwrap_or("");
-                let start_sc = char_to_screen_col(base_line, start.1, app.shift_width);
-                let end_sc = char_to_screen_col(base_line, end.1, app.shift_width);
+                let base_line = app.text.line(start.0).unwrap_or_default();
+                let start_sc = char_to_screen_col(&base_line, start.1, app.shift_width);
+                let end_sc = char_to_screen_col(&base_line, end.1, app.shift_width);
                 let end_char = base_line.chars().nth(end.1).unwrap_or(' ');
                 let end_w = char_display_width(end_char, end_sc, app.shift_width);
                 let (a, b) = if start_sc <= end_sc {
@@ -434,7 +820,12 @@ fn render_line_with_selection(
     } else {
         line_idx + 1
     };
-    let line_label = format!("{:>width$} ", number, width = gutter_width - 1);
+    let mut number_width = gutter_width - 1;
+    if let Some(sign) = sign {
+        number_width -= sign.width();
+        spans.push(sign);
+    }
+    let line_label = format!("{:>width$} ", number, width = number_width);
     spans.push(Span::styled(
         line_label,
         if is_current_line {
@@ -482,7 +873,7 @@ fn render_line_with_selection(
         }
     };
 
-    for ch in line.chars() {
+    for (col, ch) in line.chars().enumerate() {
         while syntax_idx < syntax.len() && col >= syntax[syntax_idx].end_col {
             syntax_idx += 1;
         }
@@ -524,7 +915,6 @@ fn render_line_with_selection(
                 buf.push(ch);
             }
         }
-        col += 1;
         screen_col += width;
         if screen_col >= start_col + max_cols {
             break;
@@ -591,27 +981,31 @@ fn syntax_color(kind: HighlightKind, app: &App) -> Color {
     }
 }
 
-fn build_search_mask(line: &str, pattern: &str) -> Option<Vec<bool>> {
-    if pattern.is_empty() {
-        return None;
-    }
-    let chars: Vec<char> = line.chars().collect();
-    let needle: Vec<char> = pattern.chars().collect();
-    if needle.is_empty() || needle.len() > chars.len() {
+fn build_search_mask(line: &str, pattern: &SearchPattern) -> Option<Vec<bool>> {
+    let ranges = pattern.match_ranges(line);
+    if ranges.is_empty() {
         return None;
     }
-    let mut mask = vec![false; chars.len()];
-    let max_start = chars.len().saturating_sub(needle.len());
-    for i in 0..=max_start {
-        if chars[i..i + needle.len()] == *needle {
-            for j in 0..needle.len() {
-                mask[i + j] = true;
-            }
+    let mut mask = vec![false; line.chars().count()];
+    for (start, end) in ranges {
+        for slot in &mut mask[start..end] {
+            *slot = true;
         }
     }
     Some(mask)
 }
 
+fn diagnostic_sign(app: &App, severity: Option<u8>) -> Span<'static> {
+    let (label, color) = match severity {
+        Some(1) => ("E ", app.theme.diagnostic_error),
+        Some(2) => ("W ", app.theme.diagnostic_warning),
+        Some(3) => ("I ", app.theme.diagnostic_info),
+        Some(_) => ("H ", app.theme.diagnostic_hint),
+        None => return Span::raw("  "),
+    };
+    Span::styled(label, Style::default().fg(color))
+}
+
 fn render_empty_line(gutter_width: usize) -> Line<'static> {
     let gutter = " ".repeat(gutter_width);
     Line::from(format!("{}~", gutter))